@@ -0,0 +1,55 @@
+use std::collections::HashSet;
+
+use globset::GlobSet;
+
+use git_istage_rs::{
+    backend::GitBackend,
+    git::{FileStatusKind, Location},
+    paths::RepoPath,
+    status::StatusEntry,
+};
+
+use crate::{glob, journal, watcher::Watcher};
+
+// Stages files matching the configured globs whenever the watcher sees them change.
+pub struct AutoStage {
+    pub globs: Vec<String>,
+    matcher: GlobSet,
+    watcher: Watcher,
+}
+
+impl AutoStage {
+    pub fn start(location: &Location, globs: &[String]) -> anyhow::Result<AutoStage> {
+        Ok(AutoStage {
+            globs: globs.to_vec(),
+            matcher: glob::set(globs)?,
+            watcher: Watcher::new(location.workdir())?,
+        })
+    }
+
+    // Returns true if anything was staged.
+    pub fn poll(&mut self, location: &Location, backend: &dyn GitBackend) -> anyhow::Result<bool> {
+        let changed: HashSet<RepoPath> = self
+            .watcher
+            .changed_paths()
+            .into_iter()
+            .filter(|p| self.matcher.is_match(p.to_path()))
+            .collect();
+        if changed.is_empty() {
+            return Ok(false);
+        }
+
+        let entries: Vec<StatusEntry> = backend
+            .statuses(FileStatusKind::Unstaged)?
+            .into_iter()
+            .filter(|e| changed.contains(&e.new_file))
+            .collect();
+        if entries.is_empty() {
+            return Ok(false);
+        }
+
+        backend.stage(&entries)?;
+        journal::record(location, "auto-stage", &entries)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,839 @@
+//! The git operations the staging engine is built on.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use git2::{
+    build::CheckoutBuilder, ApplyLocation, ApplyOptions, Diff, DiffOptions, Index, IndexAddOption,
+    IndexEntry, IndexEntryExtendedFlag, IndexEntryFlag, IndexTime, Oid, Repository,
+};
+
+use crate::{
+    config::{BackendKind, DiscardUntracked, ShowUntracked, Whitespace},
+    diff::{self, DiffSettings, FileDiff},
+    git::{self, FileStatusKind, Location},
+    paths::RepoPath,
+    signing,
+    status::{Status, StatusEntry},
+};
+
+/// Everything the engine and the TUI need from git, so that other implementations (or test
+/// doubles) can stand in for the default one.
+///
+/// Entries passed to `stage` and `discard` come from `statuses(FileStatusKind::Unstaged)`, and
+/// entries passed to `unstage` from `statuses(FileStatusKind::Staged)`.
+pub trait GitBackend {
+    /// Unstaged entries compare the index to the working directory, staged ones HEAD to the index.
+    fn statuses(&self, kind: FileStatusKind) -> anyhow::Result<Vec<StatusEntry>>;
+
+    /// Sets how diffs are computed from then on, by `diff` and by `apply_hunks` and `apply_lines`
+    /// for the hunks they're given the indices of.
+    fn set_diff_settings(&self, settings: DiffSettings);
+
+    /// Restricts `statuses` to the paths matching `pathspecs`, which git matches from the root of
+    /// the working tree. With none, the default, every path is listed.
+    fn set_pathspecs(&self, pathspecs: &[String]);
+
+    /// Has `statuses` list the files of these collapsed untracked directories ("dir/", as listed
+    /// when the untracked files aren't all shown) one by one.
+    fn set_expanded_dirs(&self, dirs: &[RepoPath]);
+
+    /// The diff behind a status entry of the given kind.
+    fn diff(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<FileDiff>;
+
+    /// Like `diff`, but leaves the hunks out if they'd make more than `max_lines` lines, headers
+    /// included, saying how many in `held_back` instead. 0 is no limit.
+    fn diff_up_to(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        max_lines: usize,
+    ) -> anyhow::Result<FileDiff> {
+        let mut file = self.diff(entry, kind)?;
+        file.hold_back(max_lines);
+        Ok(file)
+    }
+
+    /// Stages (for an unstaged entry) or unstages (for a staged one) just the hunks with the given
+    /// indices into `diff(entry, kind).hunks`.
+    fn apply_hunks(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunks: &[usize],
+    ) -> anyhow::Result<()>;
+
+    /// Stages (for an unstaged entry) or unstages (for a staged one) just the added and removed
+    /// lines with the given indices into the `lines` of hunk `hunk` of `diff(entry, kind)`.
+    fn apply_lines(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()>;
+
+    /// Stages (for an unstaged entry) or unstages (for a staged one) just the change of the file's
+    /// executable bit, `diff(entry, kind).modes`, leaving its content in the index as it is.
+    fn apply_mode(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<()>;
+
+    /// Throws away just the hunks with the given indices into `diff(entry, Unstaged).hunks` from
+    /// the working directory, like `git checkout -p`.
+    fn discard_hunks(&self, entry: &StatusEntry, hunks: &[usize]) -> anyhow::Result<()>;
+
+    /// Throws away just the added and removed lines with the given indices into the `lines` of
+    /// hunk `hunk` of `diff(entry, Unstaged)` from the working directory.
+    fn discard_lines(
+        &self,
+        entry: &StatusEntry,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()>;
+
+    /// Stages the entire changes of the entries.
+    fn stage(&self, entries: &[StatusEntry]) -> anyhow::Result<()>;
+
+    /// Adds untracked entries to the index without their content, like `git add -N`, so that
+    /// their content shows up as an unstaged diff whose hunks can be staged.
+    fn intent_to_add(&self, entries: &[StatusEntry]) -> anyhow::Result<()>;
+
+    /// Unstages the entire changes of the entries.
+    fn unstage(&self, entries: &[StatusEntry]) -> anyhow::Result<()>;
+
+    /// Throws away the working directory changes of the entries.
+    fn discard(&self, entries: &[StatusEntry], untracked: DiscardUntracked) -> anyhow::Result<()>;
+
+    /// Commits the index on top of HEAD (or as the root commit of an unborn branch).
+    fn commit(&self, message: &str) -> anyhow::Result<Oid>;
+}
+
+/// Opens the repository at `location` with the given kind of backend.
+pub fn open(kind: BackendKind, location: &Location) -> anyhow::Result<Box<dyn GitBackend>> {
+    match kind {
+        BackendKind::Git2 => Ok(Box::new(Git2Backend::at(location)?)),
+        #[cfg(feature = "gix")]
+        BackendKind::Gix => Ok(Box::new(crate::gix_backend::GixBackend::at(location)?)),
+        #[cfg(not(feature = "gix"))]
+        BackendKind::Gix => anyhow::bail!("the gix backend needs a build with the `gix` feature"),
+    }
+}
+
+/// Stages the hunks of an unstaged entry that change more than whitespace, and leaves those that
+/// only change whitespace, such as an autoformatter's reindenting, unstaged. They're told apart by diffing the entry again with whitespace changes left out:
+/// a hunk with none of its changes in that diff only changes whitespace. `settings` are those
+/// `backend` computes diffs with, which it's set back to. Returns how many hunks were staged and
+/// how many were left.
+pub fn stage_ignoring_whitespace(
+    backend: &dyn GitBackend,
+    entry: &StatusEntry,
+    settings: DiffSettings,
+) -> anyhow::Result<(usize, usize)> {
+    let with = |whitespace| DiffSettings {
+        whitespace,
+        ..settings
+    };
+    backend.set_diff_settings(with(Whitespace::IgnoreAll));
+    let ignoring = backend.diff(entry, FileStatusKind::Unstaged);
+    // Only diffs with every change in them apply.
+    backend.set_diff_settings(with(Whitespace::Show));
+    let result = ignoring.and_then(|ignoring| {
+        let file = backend.diff(entry, FileStatusKind::Unstaged)?;
+        if file.hunks.is_empty() {
+            anyhow::bail!("{} has no hunks to stage", entry.new_file);
+        }
+        let hunks = diff::substantive_hunks(&file, &ignoring);
+        if hunks.is_empty() {
+            anyhow::bail!(
+                "{} only changes whitespace, nothing was staged",
+                entry.new_file
+            );
+        }
+        backend.apply_hunks(entry, FileStatusKind::Unstaged, &hunks)?;
+        Ok((hunks.len(), file.hunks.len() - hunks.len()))
+    });
+    backend.set_diff_settings(settings);
+    result
+}
+
+/// Does everything through libgit2 and the index, without needing `git` on the PATH. Each
+/// whole-file operation writes the index (or checks out the files) once for all its entries.
+pub struct Git2Backend {
+    repo: Repository,
+    root: PathBuf,
+    diff_settings: Cell<DiffSettings>,
+    pathspecs: RefCell<Vec<String>>,
+    expanded_dirs: RefCell<Vec<RepoPath>>,
+}
+
+impl Git2Backend {
+    /// Opens the repository containing `path`.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Git2Backend> {
+        Git2Backend::at(&Location::discover(path)?)
+    }
+
+    /// Opens the repository at `location`, staging into the index it names.
+    pub fn at(location: &Location) -> anyhow::Result<Git2Backend> {
+        Ok(Git2Backend {
+            repo: location.open()?,
+            root: location.workdir().to_path_buf(),
+            diff_settings: Cell::new(DiffSettings::default()),
+            pathspecs: RefCell::new(Vec::new()),
+            expanded_dirs: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
+
+    // The staged changes, with the files copied from others in HEAD found among them. Statuses
+    // don't look for copies, so this diffs HEAD with the index instead, unmodified files and all
+    // for the copies to be found from.
+    fn staged_with_copies(&self) -> anyhow::Result<Vec<StatusEntry>> {
+        let head = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(_) => None,
+        };
+        let mut opts = DiffOptions::new();
+        opts.include_unmodified(true);
+        for pathspec in self.pathspecs.borrow().iter() {
+            opts.pathspec(pathspec);
+        }
+        let mut d = self
+            .repo
+            .diff_tree_to_index(head.as_ref(), None, Some(&mut opts))?;
+        let renames = self.diff_settings.get().renames;
+        d.find_similar(Some(&mut diff::find_options(&renames, true)))?;
+
+        let abs_path = self.root.clone();
+        let index = self.repo.index()?;
+        let submodules = self.submodule_paths()?;
+        Ok(d.deltas()
+            .filter(|d| !intent_to_add(&index, d.new_file().path()))
+            .map(|d| StatusEntry::from((abs_path.clone(), d)))
+            .map(|mut e| {
+                e.submodule = submodules.contains(&e.new_file);
+                e
+            })
+            .collect())
+    }
+
+    // The untracked (and ignored, if they're shown) files in the collapsed untracked directory
+    // `dir`, among those matching the pathspecs.
+    fn untracked_in(&self, dir: &StatusEntry) -> anyhow::Result<Vec<StatusEntry>> {
+        let settings = DiffSettings {
+            untracked: ShowUntracked::All,
+            max_untracked: 0,
+            ..self.diff_settings.get()
+        };
+        let path = dir.new_path().to_string_lossy().into_owned();
+        let mut opts = git::status_options(FileStatusKind::Unstaged, settings, &[path]);
+        let pathspecs = self.pathspecs.borrow();
+        let pathspec = git2::Pathspec::new(pathspecs.iter())?;
+        let d = self.repo.statuses(Some(&mut opts))?;
+        Ok(d.iter()
+            .filter_map(|st| st.index_to_workdir())
+            .map(|st| StatusEntry::from((self.root.clone(), st)))
+            .filter(|e| matches!(e.status, Status::Untracked | Status::Ignored))
+            .filter(|e| {
+                pathspecs.is_empty()
+                    || pathspec.matches_path(&e.new_path(), git2::PathspecFlags::DEFAULT)
+            })
+            .collect())
+    }
+
+    // Stages a rename without any of its content changes: the file's index entry moves to the
+    // new path as it is.
+    fn stage_rename(&self, entry: &StatusEntry) -> anyhow::Result<()> {
+        let mut index = self.fresh_index()?;
+        let mut moved = index
+            .get_path(&entry.old_path(), 0)
+            .ok_or(anyhow::anyhow!("{} isn't in the index", entry.old_file))?;
+        moved.path = entry.new_file.as_bytes().to_vec();
+        index
+            .add(&moved)
+            .and_then(|_| index.remove_path(&entry.old_path()))
+            .and_then(|_| index.write())
+            .map_err(|e| {
+                anyhow::anyhow!("staging the rename of {}: {}", entry.old_file, e.message())
+            })
+    }
+
+    // Applies a patch rendered from one of the diffs to the index, or to the working directory.
+    fn apply_patch(&self, patch: &str, location: ApplyLocation) -> Result<(), git2::Error> {
+        let diff = Diff::from_buffer(patch.as_bytes())?;
+        self.repo.apply(&diff, location, None)
+    }
+
+    fn submodule_paths(&self) -> anyhow::Result<HashSet<RepoPath>> {
+        Ok(self
+            .repo
+            .submodules()?
+            .iter()
+            .map(|s| RepoPath::from(s.path()))
+            .collect())
+    }
+
+    // The index as it is on disk, to be changed and written back. The repository keeps the copy
+    // it read last, which misses whatever another program (`git add` in another terminal, or a
+    // `git` subprocess) has staged since, and writing that copy back would undo it.
+    fn fresh_index(&self) -> anyhow::Result<Index> {
+        let mut index = self.repo.index()?;
+        index.read(false)?;
+        Ok(index)
+    }
+}
+
+// Nothing of an intent-to-add entry is staged yet, like `git diff --cached` has it.
+fn intent_to_add(index: &Index, path: Option<&Path>) -> bool {
+    path.and_then(|p| index.get_path(p, 0))
+        .is_some_and(|e| e.flags_extended & IndexEntryExtendedFlag::INTENT_TO_ADD.bits() != 0)
+}
+
+impl GitBackend for Git2Backend {
+    fn statuses(&self, kind: FileStatusKind) -> anyhow::Result<Vec<StatusEntry>> {
+        let settings = self.diff_settings.get();
+        let renames = settings.renames;
+        if renames.enabled && renames.copies && kind == FileStatusKind::Staged {
+            return self.staged_with_copies();
+        }
+        let pathspecs = self.pathspecs.borrow();
+        let mut opts = git::status_options(kind, settings, &pathspecs);
+        let d = self.repo.statuses(Some(&mut opts))?;
+
+        let abs_path = self.root.clone();
+        let index = self.repo.index()?;
+        let submodules = self.submodule_paths()?;
+
+        let entries = d
+            .iter()
+            .filter_map(|st| match kind {
+                FileStatusKind::Unstaged => st.index_to_workdir(),
+                FileStatusKind::Staged => st.head_to_index(),
+            })
+            .filter(|st| {
+                !matches!(kind, FileStatusKind::Staged)
+                    || !intent_to_add(&index, st.new_file().path())
+            })
+            .map(|st| (abs_path.clone(), st))
+            .map(StatusEntry::from)
+            .map(|mut e| {
+                e.submodule = submodules.contains(&e.new_file);
+                e
+            })
+            .collect();
+        expand_untracked_dirs(entries, settings, &self.expanded_dirs.borrow(), |dir| {
+            self.untracked_in(dir)
+        })
+    }
+
+    fn set_diff_settings(&self, settings: DiffSettings) {
+        self.diff_settings.set(settings);
+    }
+
+    fn set_pathspecs(&self, pathspecs: &[String]) {
+        *self.pathspecs.borrow_mut() = pathspecs.to_vec();
+    }
+
+    fn set_expanded_dirs(&self, dirs: &[RepoPath]) {
+        *self.expanded_dirs.borrow_mut() = dirs.to_vec();
+    }
+
+    fn diff(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<FileDiff> {
+        self.diff_up_to(entry, kind, 0)
+    }
+
+    // The hunks of a diff held back aren't collected from libgit2's patch at all.
+    fn diff_up_to(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        max_lines: usize,
+    ) -> anyhow::Result<FileDiff> {
+        if let (Status::Unreadable, FileStatusKind::Unstaged) = (entry.status, kind) {
+            return Err(unreadable(entry));
+        }
+        if entry.is_dir() {
+            return Err(untracked_dir(entry));
+        }
+        let diff = diff::entry_diff(&self.repo, entry, kind, false, self.diff_settings.get())?;
+        let files = diff::file_diffs_up_to(&diff, max_lines)?;
+        let mut file = match entry.status {
+            Status::Typechange => diff::join_typechange(files),
+            _ => files.into_iter().next(),
+        }
+        .ok_or(anyhow::anyhow!("no changes to {}", entry.new_file))?;
+        // Each side of a type change may be under the limit while both aren't.
+        file.hold_back(max_lines);
+        Ok(file)
+    }
+
+    fn apply_hunks(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunks: &[usize],
+    ) -> anyhow::Result<()> {
+        check_hunk_apply(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let applying = |e: git2::Error| {
+            anyhow::anyhow!("applying hunks to {}: {}", entry.new_file, e.message())
+        };
+        match (entry.status, kind) {
+            // libgit2 can't apply a diff adding an untracked file to the index, but it can apply
+            // the same diff parsed from a patch.
+            (Status::Untracked | Status::Ignored, FileStatusKind::Unstaged) => {
+                let patch = diff::render_patch(&self.diff(entry, kind)?, entry.status, hunks);
+                return self
+                    .apply_patch(&patch, ApplyLocation::Index)
+                    .map_err(applying);
+            }
+            // The rename goes in first, and then just the selected hunks on top of it.
+            (Status::Renamed, FileStatusKind::Unstaged) => {
+                let file = diff::renamed_content(&self.diff(entry, kind)?);
+                let patch = diff::render_patch(&file, Status::Modified, hunks);
+                self.stage_rename(entry)?;
+                return self
+                    .apply_patch(&patch, ApplyLocation::Index)
+                    .map_err(applying);
+            }
+            (Status::Renamed | Status::Copied, FileStatusKind::Staged) => {
+                let file = self.diff(entry, kind)?;
+                for patch in diff::render_reverse_patches(&file, hunks) {
+                    self.apply_patch(&patch, ApplyLocation::Index)
+                        .map_err(applying)?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        // Unstaging applies the staged changes backwards.
+        let reverse = matches!(kind, FileStatusKind::Staged);
+        let diff = diff::entry_diff(&self.repo, entry, kind, reverse, self.diff_settings.get())?;
+
+        // libgit2 offers the hunks in order, and adjusts the positions of later hunks for the
+        // ones that were skipped.
+        let next = Cell::new(0);
+        let mut opts = ApplyOptions::new();
+        opts.hunk_callback(|_| {
+            let idx = next.get();
+            next.set(idx + 1);
+            hunks.contains(&idx)
+        });
+
+        self.repo
+            .apply(&diff, ApplyLocation::Index, Some(&mut opts))
+            .map_err(applying)
+    }
+
+    fn apply_lines(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()> {
+        check_hunk_apply(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, kind)?;
+        let reverse = matches!(kind, FileStatusKind::Staged);
+        let patch = match (entry.status, kind) {
+            (Status::Renamed, FileStatusKind::Unstaged) => {
+                let file = diff::renamed_content(&file);
+                let patch = diff::render_line_patch(&file, Status::Modified, hunk, lines, false);
+                self.stage_rename(entry)?;
+                patch
+            }
+            _ => diff::render_line_patch(&file, entry.status, hunk, lines, reverse),
+        };
+        self.apply_patch(&patch, ApplyLocation::Index)
+            .map_err(|e| anyhow::anyhow!("applying lines to {}: {}", entry.new_file, e.message()))
+    }
+
+    // Rewrites the mode of the file's index entry, which keeps its content.
+    fn apply_mode(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<()> {
+        let mode = mode_to_apply(entry, kind, &self.diff(entry, kind)?)?;
+        let mut index = self.fresh_index()?;
+        let mut indexed = index
+            .get_path(&entry.new_path(), 0)
+            .ok_or(anyhow::anyhow!("{} isn't in the index", entry.new_file))?;
+        indexed.mode = mode;
+        index
+            .add(&indexed)
+            .and_then(|_| index.write())
+            .map_err(|e| {
+                anyhow::anyhow!("changing the mode of {}: {}", entry.new_file, e.message())
+            })
+    }
+
+    fn discard_hunks(&self, entry: &StatusEntry, hunks: &[usize]) -> anyhow::Result<()> {
+        check_hunk_discard(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, FileStatusKind::Unstaged)?;
+        for patch in diff::render_reverse_patches(&file, hunks) {
+            self.apply_patch(&patch, ApplyLocation::WorkDir)
+                .map_err(|e| discarding(entry, e.message()))?;
+        }
+        Ok(())
+    }
+
+    fn discard_lines(
+        &self,
+        entry: &StatusEntry,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()> {
+        check_hunk_discard(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, FileStatusKind::Unstaged)?;
+        let patch = diff::render_line_patch(&file, Status::Modified, hunk, lines, true);
+        self.apply_patch(&patch, ApplyLocation::WorkDir)
+            .map_err(|e| discarding(entry, e.message()))
+    }
+
+    fn stage(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        // Assumption: these StatusEntries were obtained by comparing the index to the working
+        // directory.
+        let mut index = self.fresh_index()?;
+        for e in entries {
+            let staged = match e.status {
+                Status::Renamed => index
+                    .remove_path(&e.old_path())
+                    .and_then(|_| index.add_path(&e.new_path())),
+                Status::Deleted => index.remove_path(&e.new_path()),
+                Status::Unreadable => return Err(unreadable(e)),
+                // A collapsed untracked directory, whose files are added as `git add dir` would.
+                _ if e.is_dir() => index.add_all([e.new_path()], IndexAddOption::DEFAULT, None),
+                // Points the gitlink at the commit checked out in the submodule, in the same index.
+                _ if e.submodule => self
+                    .repo
+                    .find_submodule(&e.new_file.to_str_lossy())
+                    .and_then(|mut s| s.add_to_index(false)),
+                _ => index.add_path(&e.new_path()),
+            };
+            staged.map_err(|err| anyhow::anyhow!("staging {}: {}", e.new_file, err.message()))?;
+        }
+        index
+            .write()
+            .map_err(|e| anyhow::anyhow!("writing the index: {}", e.message()))
+    }
+
+    fn intent_to_add(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        // An intent-to-add entry is an empty blob with a flag telling git that the file isn't
+        // really staged yet. The blob has to exist for libgit2 to add the entry.
+        let empty = self.repo.blob(&[])?;
+        let mut index = self.fresh_index()?;
+        let mut files = Vec::new();
+        for e in entries {
+            match e.is_dir() {
+                true => files.extend(
+                    self.untracked_in(e)?
+                        .into_iter()
+                        .filter(|f| f.status == Status::Untracked),
+                ),
+                false => files.push(e.clone()),
+            }
+        }
+        for e in &files {
+            if !matches!(e.status, Status::Untracked) {
+                anyhow::bail!("{} is already tracked", e.new_file);
+            }
+            let entry = IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: file_mode(&e.abs_path_new()),
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: empty,
+                flags: IndexEntryFlag::EXTENDED.bits(),
+                flags_extended: IndexEntryExtendedFlag::INTENT_TO_ADD.bits(),
+                path: e.new_file.as_bytes().to_vec(),
+            };
+            index
+                .add(&entry)
+                .map_err(|err| anyhow::anyhow!("adding {}: {}", e.new_file, err.message()))?;
+        }
+        index
+            .write()
+            .map_err(|e| anyhow::anyhow!("writing the index: {}", e.message()))
+    }
+
+    fn unstage(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        // Assumption: these StatusEntries were obtained by comparing HEAD to the index.
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut paths = Vec::new();
+        let mut typechanges = Vec::new();
+        for e in entries {
+            match e.status {
+                Status::Renamed => paths.extend([e.old_path(), e.new_path()]),
+                Status::Typechange => typechanges.push(e),
+                _ => paths.push(e.new_path()),
+            }
+        }
+
+        // Without a HEAD (an unborn branch), the entries are removed from the index altogether.
+        let head = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?.into_object()),
+            Err(_) => None,
+        };
+        if !paths.is_empty() {
+            // Resets the repository's own copy of the index, which is read again first.
+            self.fresh_index()?;
+            self.repo
+                .reset_default(head.as_ref(), paths)
+                .map_err(|e| anyhow::anyhow!("unstaging: {}", e.message()))?;
+        }
+        if typechanges.is_empty() {
+            return Ok(());
+        }
+
+        // `reset_default` sees a type change as the old file deleted and the new one added, and
+        // undoing the addition after the deletion leaves the path out of the index altogether. So
+        // HEAD's entry is put back as it is, with its mode.
+        let tree = match &head {
+            Some(head) => head.peel_to_tree()?,
+            None => anyhow::bail!("there's no HEAD to unstage type changes to"),
+        };
+        let mut index = self.fresh_index()?;
+        for e in typechanges {
+            let committed = tree
+                .get_path(&e.new_path())
+                .map_err(|err| anyhow::anyhow!("unstaging {}: {}", e.new_file, err.message()))?;
+            let entry = IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: committed.filemode() as u32,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: committed.id(),
+                flags: 0,
+                flags_extended: 0,
+                path: e.new_file.as_bytes().to_vec(),
+            };
+            index
+                .add(&entry)
+                .map_err(|err| anyhow::anyhow!("unstaging {}: {}", e.new_file, err.message()))?;
+        }
+        index
+            .write()
+            .map_err(|e| anyhow::anyhow!("writing the index: {}", e.message()))
+    }
+
+    fn discard(&self, entries: &[StatusEntry], untracked: DiscardUntracked) -> anyhow::Result<()> {
+        // Assumption: these StatusEntries were obtained by comparing the index to the working
+        // directory.
+        refuse_submodules(entries)?;
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force();
+        let mut any = false;
+        for e in entries {
+            match e.status {
+                Status::Untracked | Status::Ignored => {
+                    remove_untracked(&e.abs_path_new(), untracked)?
+                }
+                Status::Renamed => {
+                    remove_untracked(&e.abs_path_new(), untracked)?;
+                    checkout.path(e.old_path());
+                    any = true;
+                }
+                _ => {
+                    checkout.path(e.new_path());
+                    any = true;
+                }
+            }
+        }
+
+        if any {
+            self.repo
+                .checkout_index(None, Some(&mut checkout))
+                .map_err(|e| anyhow::anyhow!("checking out: {}", e.message()))?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self, message: &str) -> anyhow::Result<Oid> {
+        let sig = self.repo.signature()?;
+        let mut index = self.fresh_index()?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+        let parent = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(_) => None,
+        };
+        let parents: Vec<_> = parent.iter().collect();
+        let reflog = match parent {
+            Some(_) => "commit",
+            None => "commit (initial)",
+        };
+        signing::commit(&self.repo, &sig, &sig, message, &tree, &parents, reflog)
+    }
+}
+
+// The mode `apply_mode` gives the file's index entry: the new one to stage the change, the old one
+// to unstage it. A rename's mode goes with its content, as the index only has the file at one path.
+pub(crate) fn mode_to_apply(
+    entry: &StatusEntry,
+    kind: FileStatusKind,
+    file: &FileDiff,
+) -> anyhow::Result<u32> {
+    let (old, new) = match file.modes {
+        Some(modes) if entry.status == Status::Modified => modes,
+        _ => anyhow::bail!("{} has no mode change of its own", entry.new_file),
+    };
+    Ok(match kind {
+        FileStatusKind::Unstaged => new,
+        FileStatusKind::Staged => old,
+    })
+}
+
+// The index mode of the file at `path`: a symlink, or a file executable or not, like git records
+// it. A symlink's own mode, not its target's.
+#[cfg(unix)]
+pub(crate) fn file_mode(path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    match fs::symlink_metadata(path) {
+        Ok(m) if m.file_type().is_symlink() => 0o120000,
+        Ok(m) if m.permissions().mode() & 0o111 != 0 => 0o100755,
+        _ => 0o100644,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_mode(_path: &Path) -> u32 {
+    0o100644
+}
+
+// Checking out a gitlink leaves the submodule's own checkout alone, so its changes can't be
+// discarded from here.
+pub(crate) fn refuse_submodules(entries: &[StatusEntry]) -> anyhow::Result<()> {
+    match entries.iter().find(|e| e.submodule) {
+        Some(e) => anyhow::bail!(
+            "{} is a submodule; run `git submodule update` to check out the commit it records",
+            e.new_file
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Fails unless hunks of the unstaged entry can be discarded: they can only be taken out of a file
+/// that's there in the working directory, with a version in the index to go back to, and not out
+/// of a symlink, which would write them to the file it points to.
+pub fn check_hunk_discard(entry: &StatusEntry) -> anyhow::Result<()> {
+    match entry.status {
+        _ if entry.submodule => anyhow::bail!(
+            "{} is a submodule and can only be discarded as a whole",
+            entry.new_file
+        ),
+        _ if is_symlink(entry) => anyhow::bail!(
+            "{} is a symlink and can only be discarded as a whole",
+            entry.new_file
+        ),
+        Status::Modified | Status::Renamed => Ok(()),
+        status => anyhow::bail!(
+            "{} is {} and can only be discarded as a whole",
+            entry.new_file,
+            status.name()
+        ),
+    }
+}
+
+/// Fails unless hunks and lines of the entry can be staged or unstaged on their own. A type
+/// change, such as a symlink replaced by a file, goes into the index as a whole: the old content
+/// goes with the old type. So does a symlink, whose content is the path it points to.
+pub fn check_hunk_apply(entry: &StatusEntry) -> anyhow::Result<()> {
+    match entry.status {
+        Status::Typechange => anyhow::bail!(
+            "{} changed type and can only be staged or unstaged as a whole",
+            entry.new_file
+        ),
+        _ if is_symlink(entry) => anyhow::bail!(
+            "{} is a symlink and can only be staged or unstaged as a whole",
+            entry.new_file
+        ),
+        Status::Unreadable => Err(unreadable(entry)),
+        _ => Ok(()),
+    }
+}
+
+// Whether the entry's file in the working directory is a symlink, rather than the file it points
+// to.
+fn is_symlink(entry: &StatusEntry) -> bool {
+    fs::symlink_metadata(entry.abs_path_new()).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+// The error for an entry libgit2 couldn't read, with the reason the file system gives for it.
+pub(crate) fn unreadable(entry: &StatusEntry) -> anyhow::Error {
+    let path = entry.abs_path_new();
+    let read = fs::metadata(&path).and_then(|m| match m.is_dir() {
+        true => fs::read_dir(&path).map(drop),
+        false => fs::File::open(&path).map(drop),
+    });
+    match read {
+        Err(e) => anyhow::anyhow!("{} can't be read: {}", entry.new_file, e),
+        Ok(()) => anyhow::anyhow!("{} couldn't be read; refresh to try again", entry.new_file),
+    }
+}
+
+// Replaces the collapsed untracked directories among `entries` that were expanded with the files
+// `list` finds in them. With every untracked file to be shown but a limit on how many are looked
+// for, the others are expanded too, in the order they're listed, until the limit is reached: the
+// rest stay collapsed, so that only so many are walked through.
+pub(crate) fn expand_untracked_dirs(
+    entries: Vec<StatusEntry>,
+    settings: DiffSettings,
+    expanded: &[RepoPath],
+    mut list: impl FnMut(&StatusEntry) -> anyhow::Result<Vec<StatusEntry>>,
+) -> anyhow::Result<Vec<StatusEntry>> {
+    let all = settings.untracked == ShowUntracked::All;
+    let mut count = entries
+        .iter()
+        .filter(|e| e.status == Status::Untracked)
+        .count();
+    let mut listed = Vec::with_capacity(entries.len());
+    for e in entries {
+        let expand = e.status == Status::Untracked
+            && e.is_dir()
+            && (expanded.contains(&e.new_file) || all && count < settings.max_untracked);
+        if !expand {
+            listed.push(e);
+            continue;
+        }
+        let files = list(&e)?;
+        count = count - 1 + files.len();
+        listed.extend(files);
+    }
+    Ok(listed)
+}
+
+pub(crate) fn untracked_dir(entry: &StatusEntry) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} is an untracked directory: expand it to diff its files",
+        entry.new_file
+    )
+}
+
+pub(crate) fn discarding(entry: &StatusEntry, e: impl std::fmt::Display) -> anyhow::Error {
+    anyhow::anyhow!("discarding hunks of {}: {}", entry.new_file, e)
+}
+
+pub(crate) fn remove_untracked(path: &Path, mode: DiscardUntracked) -> anyhow::Result<()> {
+    match mode {
+        DiscardUntracked::Trash => trash::delete(path)
+            .map_err(|e| anyhow::anyhow!("moving {} to the trash: {}", path.display(), e)),
+        DiscardUntracked::Delete if path.is_dir() => Ok(fs::remove_dir_all(path)?),
+        DiscardUntracked::Delete => Ok(fs::remove_file(path)?),
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use git2::{Blame, BlameOptions, ErrorCode, Oid, Repository};
+
+use git_istage_rs::{git::Location, paths::RepoPath};
+
+use crate::journal;
+
+// The commit that last changed a line.
+pub struct LineBlame {
+    pub id: Oid,
+    pub author: String,
+    // When it was committed, in seconds since the epoch.
+    pub time: i64,
+}
+
+// How wide the annotation in front of each line is.
+pub const WIDTH: usize = 28;
+
+impl LineBlame {
+    // E.g. "1a2b3c4 Jane Doe 2024-03-01 ", with the author cut or padded to the same width for
+    // every line.
+    pub fn label(&self) -> String {
+        let id = self.id.to_string();
+        let date = journal::format_utc(self.time.max(0) as u64);
+        format!("{} {:<8.8} {} ", &id[..7], self.author, &date[..10])
+    }
+}
+
+// Who last changed each line of the old side of a file's diff: HEAD's version for staged changes,
+// and the index's for unstaged ones. Lines that are staged but not committed have no commit.
+#[derive(Default)]
+pub struct FileBlame {
+    lines: Vec<Option<LineBlame>>,
+}
+
+impl FileBlame {
+    // Blames `path` as it is in HEAD, or in the index unless `staged`. A file that isn't in HEAD
+    // yet has nothing to blame.
+    pub fn load(location: &Location, path: &RepoPath, staged: bool) -> anyhow::Result<FileBlame> {
+        let repo = location.open()?;
+        if repo.head().is_err() {
+            return Ok(FileBlame::default());
+        }
+        let path = path.to_path();
+        let blame = match repo.blame_file(&path, Some(&mut BlameOptions::new())) {
+            Ok(blame) => blame,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(FileBlame::default()),
+            Err(e) => return Err(e.into()),
+        };
+        if staged {
+            let lines = blamed_lines(&repo, &blame)?;
+            return Ok(FileBlame { lines });
+        }
+        let index = repo.index()?;
+        let Some(entry) = index.get_path(&path, 0) else {
+            return Ok(FileBlame::default());
+        };
+        let content = repo.find_blob(entry.id)?;
+        let blame = blame.blame_buffer(content.content())?;
+        let lines = blamed_lines(&repo, &blame)?;
+        Ok(FileBlame { lines })
+    }
+
+    // The commit that last changed line `lineno`, counted from 1, if it's committed.
+    pub fn line(&self, lineno: u32) -> Option<&LineBlame> {
+        let index = (lineno as usize).checked_sub(1)?;
+        self.lines.get(index)?.as_ref()
+    }
+}
+
+// The commit of each line `blame` found one for. The commits are looked up rather than taken from
+// the hunks, as libgit2 leaves the signatures out of hunks split by `blame_buffer`.
+fn blamed_lines(repo: &Repository, blame: &Blame) -> anyhow::Result<Vec<Option<LineBlame>>> {
+    let mut authors = HashMap::new();
+    let mut lines = Vec::new();
+    for hunk in blame.iter() {
+        let start = hunk.final_start_line().saturating_sub(1);
+        let end = start + hunk.lines_in_hunk();
+        if lines.len() < end {
+            lines.resize_with(end, || None);
+        }
+        let id = hunk.final_commit_id();
+        if id.is_zero() {
+            continue;
+        }
+        let (author, time) = match authors.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let commit = repo.find_commit(id)?;
+                let author = commit.author();
+                let name = author.name().unwrap_or("?").to_string();
+                entry.insert((name, author.when().seconds()))
+            }
+        };
+        for line in &mut lines[start..end] {
+            *line = Some(LineBlame {
+                id,
+                author: author.clone(),
+                time: *time,
+            });
+        }
+    }
+    Ok(lines)
+}
+
+// The blames loaded so far, by whether they're of the staged side and by the file's path. A refresh
+// marks them stale rather than dropping them, so that the overlay stays until it's loaded again.
+#[derive(Default)]
+pub struct BlameCache {
+    blames: HashMap<(bool, RepoPath), (FileBlame, bool)>,
+}
+
+impl BlameCache {
+    // The blame of a file, if it's been loaded, and whether it's stale.
+    pub fn get(&self, staged: bool, path: &RepoPath) -> Option<(&FileBlame, bool)> {
+        self.blames
+            .get(&(staged, path.clone()))
+            .map(|(blame, stale)| (blame, *stale))
+    }
+
+    pub fn insert(&mut self, staged: bool, path: RepoPath, blame: FileBlame) {
+        self.blames.insert((staged, path), (blame, false));
+    }
+
+    pub fn invalidate(&mut self) {
+        for (_, stale) in self.blames.values_mut() {
+            *stale = true;
+        }
+    }
+}
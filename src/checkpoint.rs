@@ -0,0 +1,118 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{Commit, Oid, Repository, ResetType};
+
+use git_istage_rs::{console, git::Location};
+
+const CHECKPOINT_REF: &str = "refs/istage/checkpoint";
+// The state from before the last restore, so that restoring can be undone too.
+pub const BEFORE_RESTORE_REF: &str = "refs/istage/before-restore";
+
+// Saves HEAD, the index and the whole worktree, untracked files included, as a checkpoint.
+// Returns the checkpoint's id.
+pub fn create(location: &Location) -> anyhow::Result<Oid> {
+    let repo = location.open()?;
+    save(location, &repo, CHECKPOINT_REF)
+}
+
+// Returns HEAD, the index and the worktree to the last checkpoint. Files created since are removed
+// and the branch is moved back if commits were made. Returns the checkpoint's id, or None if there
+// is no checkpoint.
+pub fn restore(location: &Location) -> anyhow::Result<Option<Oid>> {
+    let repo = location.open()?;
+    let checkpoint = match repo.find_reference(CHECKPOINT_REF) {
+        Ok(r) => r.peel_to_commit()?,
+        Err(_) => return Ok(None),
+    };
+    save(location, &repo, BEFORE_RESTORE_REF)?;
+
+    // Like a stash: the commit holds the worktree, its last parent the index, and its first parent
+    // HEAD, unless the branch was unborn.
+    let index = checkpoint.parent(checkpoint.parent_count() - 1)?;
+    if checkpoint.parent_count() == 2 {
+        repo.reset(checkpoint.parent(0)?.as_object(), ResetType::Soft, None)?;
+    }
+
+    // Resetting a scratch index that holds the current worktree, untracked files included, removes
+    // whatever isn't in the checkpoint too.
+    let scratch = location.with_index(scratch_index(location, &repo)?);
+    run_git(&scratch, &["add", "-A"])?;
+    run_git(
+        &scratch,
+        &[
+            "read-tree",
+            "--reset",
+            "-u",
+            &checkpoint.tree_id().to_string(),
+        ],
+    )?;
+    fs::remove_file(scratch.index().unwrap())?;
+
+    run_git(location, &["read-tree", &index.tree_id().to_string()])?;
+    Ok(Some(checkpoint.id()))
+}
+
+fn save(location: &Location, repo: &Repository, refname: &str) -> anyhow::Result<Oid> {
+    let sig = repo.signature()?;
+    let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let head_parents: Vec<&Commit> = head.iter().collect();
+
+    let mut index = repo.index()?;
+    // The index may have been changed on disk by a `git` subprocess since it was last read.
+    index.read(false)?;
+    let index_tree = repo.find_tree(index.write_tree()?)?;
+    let index_commit = repo.find_commit(repo.commit(
+        None,
+        &sig,
+        &sig,
+        "istage checkpoint index",
+        &index_tree,
+        &head_parents,
+    )?)?;
+
+    let scratch = location.with_index(scratch_index(location, repo)?);
+    run_git(&scratch, &["add", "-A"])?;
+    let worktree_tree = Oid::from_str(run_git(&scratch, &["write-tree"])?.trim())?;
+    fs::remove_file(scratch.index().unwrap())?;
+
+    let mut parents = head_parents;
+    parents.push(&index_commit);
+    let id = repo.commit(
+        None,
+        &sig,
+        &sig,
+        "istage checkpoint",
+        &repo.find_tree(worktree_tree)?,
+        &parents,
+    )?;
+    repo.reference(refname, id, true, "istage checkpoint")?;
+    Ok(id)
+}
+
+// A copy of the index to stage the whole worktree into without touching the real one.
+fn scratch_index(location: &Location, repo: &Repository) -> anyhow::Result<PathBuf> {
+    let dir = repo.path().join("istage");
+    fs::create_dir_all(&dir)?;
+    let scratch = dir.join("checkpoint-index");
+    let index = location
+        .index()
+        .map_or_else(|| repo.path().join("index"), Path::to_path_buf);
+    if index.exists() {
+        fs::copy(index, &scratch)?;
+    } else if scratch.exists() {
+        fs::remove_file(&scratch)?;
+    }
+    Ok(scratch)
+}
+
+// Runs `git` in the repo root, against the index `location` stages into.
+fn run_git(location: &Location, args: &[&str]) -> anyhow::Result<String> {
+    let output = console::output(location.git().args(args))?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,134 @@
+use std::{env, ffi::OsString, io, path::PathBuf};
+
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+/// Interactively stage, unstage and discard changes in a git repository.
+#[derive(Parser)]
+#[command(name = "git-istage", bin_name = "git-istage", version)]
+pub struct Cli {
+    /// Override a configuration setting, e.g. `-c confirm=all`. Can be given several times.
+    #[arg(short = 'c', value_name = "KEY=VALUE", global = true)]
+    pub config_overrides: Vec<String>,
+
+    /// Read this config file instead of the one in the user's config directory.
+    #[arg(long = "config", value_name = "FILE", global = true)]
+    pub config_file: Option<PathBuf>,
+
+    /// Draw without colors, telling statuses and changes apart by text, as NO_COLOR does.
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// The repository to open, or any directory inside it. Defaults to the current directory.
+    pub path: Option<PathBuf>,
+
+    /// Only list changes to paths matching these pathspecs (given after `--`, relative to the
+    /// current directory), e.g. `git-istage -- src/`.
+    #[arg(last = true, value_name = "PATHSPEC")]
+    pub pathspecs: Vec<String>,
+
+    /// Create a repository in the directory if it isn't in one, rather than asking first.
+    #[arg(long)]
+    pub init: bool,
+
+    /// Start in the staged view rather than the unstaged one.
+    #[arg(long)]
+    pub staged: bool,
+
+    /// Start on this file's diff, in whichever view has changes to it.
+    #[arg(long, value_name = "FILE")]
+    pub file: Option<PathBuf>,
+
+    /// Apply this patch to the worktree (or to the index with `--staged`) before starting. Nothing
+    /// is applied if any file of it doesn't apply, and those files are reported.
+    #[arg(long, value_name = "FILE", conflicts_with = "list")]
+    pub apply_patch: Option<PathBuf>,
+
+    /// Stage into a copy of the index rather than the index itself, and print what was staged as a
+    /// patch on quitting, e.g. `git-istage --emit-patch | git apply --cached`. Nothing can be
+    /// committed meanwhile.
+    #[arg(long, conflicts_with_all = ["list", "apply_patch"])]
+    pub emit_patch: bool,
+
+    /// Run these `:` commands or actions, separated by `;`, on starting, e.g. `--exec 'stage *.rs;
+    /// commit --message "wip"'`. Ending with `quit`, they run without the app ever showing:
+    /// confirmations of what isn't destructive are answered, and the first command to fail, or to
+    /// wait for an answer, fails the run.
+    #[arg(long, value_name = "COMMANDS", conflicts_with = "list")]
+    pub exec: Option<String>,
+
+    /// With `--exec` ending in `quit`, answer every confirmation, destructive ones included.
+    #[arg(long, requires = "exec")]
+    pub yes: bool,
+
+    /// Print the unstaged entries (or the staged ones with `--staged`) instead of starting.
+    #[arg(long, conflicts_with = "file")]
+    pub list: bool,
+
+    /// With `--list`, print a status letter and the path(s) per line, separated by tabs.
+    #[arg(long, requires = "list", conflicts_with = "json")]
+    pub porcelain: bool,
+
+    /// With `--list`, print the entries as a JSON array.
+    #[arg(long, requires = "list")]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Inspect the effective configuration.
+    Config {
+        /// Print the effective configuration and keymap as TOML.
+        #[arg(long, required = true)]
+        dump: bool,
+    },
+    /// Print a completion script for the given shell to stdout.
+    Completions { shell: Shell },
+    /// Print the man page (roff) to stdout.
+    Man,
+    /// Show the diff between two files, read-only: the two `git difftool --extcmd "git-istage
+    /// diff"` passes, or the seven arguments git passes the program in GIT_EXTERNAL_DIFF (the
+    /// path, then the file, id and mode of each side), which need no `diff` before them.
+    Diff {
+        #[arg(num_args = 2..=9, value_name = "ARG", required = true)]
+        args: Vec<OsString>,
+    },
+}
+
+// Parses the command line, taking git's external diff arguments as the `diff` subcommand's, as
+// GIT_EXTERNAL_DIFF can only name the program to run.
+pub fn parse() -> Cli {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    if is_external_diff(args.get(1..).unwrap_or_default()) {
+        args.insert(1, OsString::from("diff"));
+    }
+    Cli::parse_from(args)
+}
+
+// Seven arguments with the modes of the two sides third and sixth, or nine for a rename, which
+// adds the new path and a description of the rename.
+pub fn is_external_diff(args: &[OsString]) -> bool {
+    let mode = |arg: &OsString| {
+        arg == "."
+            || arg.len() == 6
+                && arg
+                    .as_encoded_bytes()
+                    .iter()
+                    .all(|b| (b'0'..=b'7').contains(b))
+    };
+    matches!(args.len(), 7 | 9) && mode(&args[3]) && mode(&args[6])
+}
+
+pub fn print_completions(shell: Shell) {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+}
+
+pub fn print_man_page() -> anyhow::Result<()> {
+    clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?;
+    Ok(())
+}
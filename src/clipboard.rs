@@ -0,0 +1,39 @@
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+};
+
+// Puts `text` on the clipboard of the terminal the app runs in with an OSC 52 escape sequence,
+// which reaches the local clipboard over SSH too. Inside tmux, the sequence is passed through to
+// the terminal tmux runs in.
+pub fn copy(text: &str) -> anyhow::Result<()> {
+    let mut stdout = io::stdout();
+    if !stdout.is_terminal() {
+        anyhow::bail!("there's no terminal to copy to the clipboard of");
+    }
+    let mut sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if env::var_os("TMUX").is_some() {
+        sequence = format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"));
+    }
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
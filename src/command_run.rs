@@ -0,0 +1,167 @@
+use std::{
+    io::{BufReader, Read},
+    process::{self, ExitStatus},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use tui::{
+    backend::Backend,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use git_istage_rs::console;
+
+use crate::popup;
+
+// A command (a hook, `git push`) running in the background from the repo root, with its output
+// collected line by line as it comes so that it can be shown while it runs.
+pub struct CommandRun {
+    pub title: String,
+    pub output: Vec<String>,
+    pub scroll: u16,
+    // Set once the command has exited.
+    pub status: Option<ExitStatus>,
+    // Whether the last line ended in a carriage return, as progress meters do to be redrawn, so
+    // that the next line replaces it.
+    overwrite_last: bool,
+    // The run's id in the console log.
+    logged: u64,
+    lines: Receiver<(String, bool)>,
+    exited: Receiver<std::io::Result<ExitStatus>>,
+}
+
+impl CommandRun {
+    pub fn start(title: String, mut command: process::Command) -> anyhow::Result<CommandRun> {
+        command
+            // The terminal is in raw mode, so nothing may wait for input from it.
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+        let logged = console::start(&command);
+        let mut child = command.spawn().map_err(|e| {
+            console::finish(logged, Err(&e));
+            anyhow::anyhow!("running {}: {}", title, e)
+        })?;
+
+        let (tx, lines) = mpsc::channel();
+        let readers = [
+            forward_lines(child.stdout.take(), tx.clone()),
+            forward_lines(child.stderr.take(), tx),
+        ];
+        let (done, exited) = mpsc::channel();
+        thread::spawn(move || {
+            // All the output has been sent once both pipes are closed.
+            for reader in readers {
+                let _ = reader.join();
+            }
+            let _ = done.send(child.wait());
+        });
+
+        Ok(CommandRun {
+            title,
+            output: Vec::new(),
+            scroll: 0,
+            status: None,
+            overwrite_last: false,
+            logged,
+            lines,
+            exited,
+        })
+    }
+
+    // Collects the output so far. Returns the exit status once the command has exited.
+    pub fn poll(&mut self) -> anyhow::Result<Option<ExitStatus>> {
+        if self.status.is_some() {
+            return Ok(self.status);
+        }
+        let exited = match self.exited.try_recv() {
+            Ok(status) => Some(status),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => anyhow::bail!("{} died", self.title),
+        };
+        for (line, overwrite) in self.lines.try_iter() {
+            if self.overwrite_last {
+                self.output.pop();
+            }
+            // Only the last state of a progress meter is worth keeping in the log.
+            if !overwrite {
+                console::append(self.logged, &line);
+            }
+            self.output.push(line);
+            self.overwrite_last = overwrite;
+        }
+        if let Some(status) = &exited {
+            console::finish(self.logged, status.as_ref().copied());
+        }
+        self.status = exited.transpose()?;
+        Ok(self.status)
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_add(1);
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+}
+
+// Sends each line of `pipe`, split at newlines and carriage returns, with whether it ended in a
+// carriage return.
+fn forward_lines<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    tx: Sender<(String, bool)>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let pipe = match pipe {
+            Some(pipe) => pipe,
+            None => return,
+        };
+        let mut line = Vec::new();
+        for byte in BufReader::new(pipe).bytes().map_while(Result::ok) {
+            if byte != b'\n' && byte != b'\r' {
+                line.push(byte);
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line).into_owned();
+            line.clear();
+            if tx.send((text, byte == b'\r')).is_err() {
+                return;
+            }
+        }
+        if !line.is_empty() {
+            let _ = tx.send((String::from_utf8_lossy(&line).into_owned(), false));
+        }
+    })
+}
+
+// Shows the command's output over the rest of the screen, with what can be done once it failed.
+pub fn render_command_run<B: Backend>(f: &mut Frame<B>, run: &CommandRun, failed_hint: &str) {
+    let area = popup::centered_rect(80, f.size().height.saturating_sub(4), f.size());
+    let (title, footer) = match run.status {
+        None => (format!(" {} (running...) ", run.title), None),
+        Some(status) => (
+            format!(" {} failed ({}) ", run.title, status),
+            Some(failed_hint),
+        ),
+    };
+
+    let mut lines: Vec<Spans> = run.output.iter().map(|l| Spans::from(l.clone())).collect();
+    if let Some(footer) = footer {
+        lines.push(Spans::default());
+        lines.push(Spans::from(Span::styled(
+            footer.to_string(),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .scroll((run.scroll, 0));
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
@@ -0,0 +1,778 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use toml::{Table, Value};
+
+use crate::{git::Location, paths::RepoPath};
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub auto_stage: AutoStageConfig,
+    // Globs for paths that may never be discarded and that need an extra confirmation to stage.
+    #[serde(deserialize_with = "one_or_many")]
+    pub protected: Vec<String>,
+    pub stage_warnings: StageWarningsConfig,
+    // Commands run on files matching their glob right before staging them, with the file's path
+    // appended, e.g. `"**/*.rs" = "rustfmt"`.
+    pub formatters: BTreeMap<String, String>,
+    pub discard_untracked: DiscardUntracked,
+    pub confirm: ConfirmPolicy,
+    // Per-action overrides of `confirm`, keyed by `ConfirmAction::name`: `true` always asks,
+    // `false` never does.
+    pub confirm_override: BTreeMap<String, bool>,
+    pub backend: BackendKind,
+    // Whether a HEAD that's already on a remote-tracking branch may be amended.
+    pub amend_pushed: bool,
+    pub commit: CommitConfig,
+    // How many seconds a failed action's error stays on screen. 0 keeps it until the next key.
+    pub error_timeout: u64,
+    pub layout: LayoutConfig,
+    pub scroll: ScrollConfig,
+    pub preview: PreviewConfig,
+    pub renames: RenamesConfig,
+    pub refresh: RefreshConfig,
+    pub list: ListConfig,
+    pub theme: ThemeConfig,
+    // Replaces the default keys of the named actions, by the names the TUI gives them.
+    #[serde(deserialize_with = "map_of_one_or_many")]
+    pub keys: BTreeMap<String, Vec<String>>,
+    // Keys that run a `:` command line, e.g. `ctrl-t = "stage *.rs"`, or an action by its name.
+    pub commands: BTreeMap<String, String>,
+    // The pathspecs given on the command line, relative to the root of the working tree, which
+    // limit the lists to the paths matching them. They're never read from a file.
+    #[serde(skip)]
+    pub pathspecs: Vec<String>,
+    // The collapsed untracked directories (see `ShowUntracked::Normal`) that were expanded, whose
+    // files are listed one by one.
+    #[serde(skip)]
+    pub expanded_dirs: Vec<RepoPath>,
+    // Set by `--emit-patch`: what's staged goes to a copy of the index, to be printed as a patch
+    // rather than committed.
+    #[serde(skip)]
+    pub emit_patch: bool,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfirmPolicy {
+    None,
+    #[default]
+    Destructive,
+    All,
+}
+
+#[derive(Clone, Copy)]
+pub enum ConfirmAction {
+    Stage,
+    Unstage,
+    Discard,
+    RestoreDiscard,
+    RestoreCheckpoint,
+    CherryPick,
+    Revert,
+    DropStash,
+    // Resolving a conflict with one side's version, which overwrites the file in the worktree.
+    ResolveConflict,
+    // Any operation on several entries at once (directory and glob commands), which is previewed
+    // before running.
+    Batch,
+}
+
+impl ConfirmAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfirmAction::Stage => "stage",
+            ConfirmAction::Unstage => "unstage",
+            ConfirmAction::Discard => "discard",
+            ConfirmAction::RestoreDiscard => "restore-discard",
+            ConfirmAction::RestoreCheckpoint => "restore-checkpoint",
+            ConfirmAction::CherryPick => "cherry-pick",
+            ConfirmAction::Revert => "revert",
+            ConfirmAction::DropStash => "drop-stash",
+            ConfirmAction::ResolveConflict => "resolve-conflict",
+            ConfirmAction::Batch => "batch",
+        }
+    }
+
+    pub fn is_destructive(self) -> bool {
+        match self {
+            ConfirmAction::Discard
+            | ConfirmAction::RestoreCheckpoint
+            | ConfirmAction::DropStash
+            | ConfirmAction::ResolveConflict
+            | ConfirmAction::Batch => true,
+            ConfirmAction::Stage
+            | ConfirmAction::Unstage
+            | ConfirmAction::RestoreDiscard
+            | ConfirmAction::CherryPick
+            | ConfirmAction::Revert => false,
+        }
+    }
+}
+
+// Which `backend::GitBackend` to drive git through. `gix` needs the `gix` feature.
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    #[default]
+    Git2,
+    Gix,
+}
+
+// What discarding an untracked file does with it. Moving it to the system trash means a mis-keyed
+// discard can still be recovered.
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscardUntracked {
+    #[default]
+    Trash,
+    Delete,
+}
+
+// How the main screen is laid out: the panes, side by side or stacked in the given order, each
+// taking `size` percent of the screen or a share of what the sized ones leave, below an optional
+// status bar with the branch and the counts of changes, and above an optional footer with key
+// hints.
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LayoutConfig {
+    pub direction: LayoutDirection,
+    pub panes: Vec<PaneConfig>,
+    pub status_bar: bool,
+    pub footer: bool,
+    // Whether the app takes the mouse, which leaves the terminal unable to select text itself.
+    pub mouse: bool,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            direction: LayoutDirection::default(),
+            panes: vec![
+                PaneConfig {
+                    pane: Pane::List,
+                    size: Some(40),
+                },
+                PaneConfig {
+                    pane: Pane::Diff,
+                    size: None,
+                },
+            ],
+            status_bar: true,
+            footer: true,
+            mouse: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutDirection {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct PaneConfig {
+    pub pane: Pane,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u16>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pane {
+    // The file list, or the log view while it's open.
+    List,
+    // The diff of the selected file.
+    Diff,
+    // The most recent commits.
+    Log,
+    // The commands run in the background and what they printed.
+    Console,
+}
+
+// How long a commit message's subject line may get before the editor warns about it, 0 never
+// warning, and whether the staged changes are reviewed before the message is written. With
+// `confirm-partial`, committing files that also have unstaged changes needs confirming first,
+// rather than being warned about once committed.
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CommitConfig {
+    pub subject_length: usize,
+    pub review: bool,
+    pub confirm_partial: bool,
+}
+
+impl Default for CommitConfig {
+    fn default() -> Self {
+        CommitConfig {
+            subject_length: 50,
+            review: false,
+            confirm_partial: false,
+        }
+    }
+}
+
+// With `watch`, the lists are refreshed whenever files in the worktree or git's index change,
+// e.g. when editing or staging from another terminal. They are also refreshed every `interval`
+// seconds if it's not 0, for file systems where watching doesn't work.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct RefreshConfig {
+    pub watch: bool,
+    pub interval: u64,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        RefreshConfig {
+            watch: true,
+            interval: 0,
+        }
+    }
+}
+
+// How the file lists are ordered, which of them is shown first, whether they're shown together in
+// one list with a section each, like `git status` does, rather than one at a time, and whether the
+// ignored files are listed with the untracked ones. They only set how the app starts.
+//
+// `untracked` is how untracked files are listed, which defaults to git's own
+// `status.showUntrackedFiles`, and `max-untracked` caps how many of them are looked for, 0 being no
+// cap: past it, the untracked directories not looked into yet stay collapsed.
+//
+// With `restore`, the lists start the way the last session in the repository left them instead:
+// the list shown, the selected files, the filter, the tree and the place in the diff.
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ListConfig {
+    pub sort: SortOrder,
+    pub view: ListView,
+    pub combined: bool,
+    pub show_ignored: bool,
+    pub untracked: ShowUntracked,
+    pub max_untracked: usize,
+    pub restore: bool,
+}
+
+impl Default for ListConfig {
+    fn default() -> Self {
+        ListConfig {
+            sort: SortOrder::default(),
+            view: ListView::default(),
+            combined: false,
+            show_ignored: false,
+            untracked: ShowUntracked::default(),
+            max_untracked: 0,
+            restore: true,
+        }
+    }
+}
+
+// Like git's `status.showUntrackedFiles`.
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShowUntracked {
+    // Not listed at all.
+    No,
+    // A directory of nothing but untracked files is listed as one entry, "dir/", whose files are
+    // only looked for once it's expanded, which saves walking them in a big repository.
+    Normal,
+    // Every untracked file, each on its own.
+    #[default]
+    All,
+}
+
+impl ShowUntracked {
+    // Reads git's values for it, where `true` means "normal" and `false` "no".
+    fn from_git(value: &str) -> Option<ShowUntracked> {
+        match value.to_ascii_lowercase().as_str() {
+            "no" | "false" | "off" | "0" => Some(ShowUntracked::No),
+            "normal" | "true" | "yes" | "on" | "1" => Some(ShowUntracked::Normal),
+            "all" => Some(ShowUntracked::All),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ShowUntracked::No => "no",
+            ShowUntracked::Normal => "normal",
+            ShowUntracked::All => "all",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListView {
+    #[default]
+    Unstaged,
+    Staged,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    // As git lists them.
+    #[default]
+    Path,
+    Status,
+    // Most recently modified first.
+    Modified,
+    // Most lines changed first.
+    Size,
+}
+
+impl SortOrder {
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Path => "path",
+            SortOrder::Status => "status",
+            SortOrder::Modified => "modified",
+            SortOrder::Size => "size",
+        }
+    }
+
+    pub fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Path => SortOrder::Status,
+            SortOrder::Status => SortOrder::Modified,
+            SortOrder::Modified => SortOrder::Size,
+            SortOrder::Size => SortOrder::Path,
+        }
+    }
+}
+
+// The colors the app is drawn with: the named built-in theme, then with `git-colors` whatever
+// git's own `color.status.*` and `color.diff.*` settings say, and then with any of its colors
+// replaced by `colors`, keyed by element (`status-added`, `diff-removed`, `selection`, `border`,
+// ...). A color is a name like `light-green`, `#rrggbb`, or an index into the 256-color palette.
+// `syntax` colors the keywords, strings, comments and numbers in diffs of the languages it knows,
+// `whitespace-errors` starts with the whitespace errors in added lines highlighted, and
+// `line-endings` with the `\r` of CRLF line endings shown. `line-counts` shows how many lines each
+// entry, each list and each hunk adds and removes, as git's diffstat does.
+// `no-color` (or `--no-color`, or NO_COLOR in the environment) draws without any color, for
+// monochrome terminals and screen readers: statuses and diff lines are told apart by text, the
+// selection is in reverse video, and the terminal's cursor is left on the selected entry.
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ThemeConfig {
+    pub name: ThemeName,
+    pub git_colors: bool,
+    pub syntax: bool,
+    pub whitespace_errors: bool,
+    pub line_endings: bool,
+    pub line_counts: bool,
+    pub no_color: bool,
+    #[serde(deserialize_with = "map_of_colors")]
+    pub colors: BTreeMap<String, String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            name: ThemeName::default(),
+            git_colors: false,
+            syntax: true,
+            whitespace_errors: false,
+            line_endings: false,
+            line_counts: false,
+            no_color: false,
+            colors: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    // For terminals with a light background, which the dark theme's yellows and grays wash out on.
+    Light,
+}
+
+// Scrolling a diff moves `step` lines per key, animated over a few frames if `smooth` is set.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(default)]
+pub struct ScrollConfig {
+    pub step: u16,
+    pub smooth: bool,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        ScrollConfig {
+            step: 3,
+            smooth: false,
+        }
+    }
+}
+
+// Untracked files bigger than `max-size` bytes aren't read to be shown in the diff pane, as they
+// have nothing in the index to compare against. 0 shows them whatever their size. Diffs of more
+// than `max-lines` lines are only shown once asked for, 0 showing them all. Diffs have `context`
+// lines around each change, and leave out the whitespace changes `whitespace` says to; these two
+// only set how the app starts. With `diff-filter`, a command like `delta --color-only`, the hunks
+// are shown as it renders them; git's `interactive.diffFilter` is taken if it isn't set.
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PreviewConfig {
+    pub max_size: u64,
+    pub max_lines: usize,
+    pub context: u32,
+    pub whitespace: Whitespace,
+    pub diff_filter: String,
+}
+
+impl PreviewConfig {
+    // The command diffs are rendered with, if there's one.
+    pub fn diff_filter(&self) -> Option<&str> {
+        Some(self.diff_filter.trim()).filter(|c| !c.is_empty())
+    }
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            max_size: 1024 * 1024,
+            max_lines: 20_000,
+            context: 3,
+            whitespace: Whitespace::default(),
+            diff_filter: String::new(),
+        }
+    }
+}
+
+// How renamed files are found among the changes: with `enabled`, a deleted file and an added one
+// that are at least `threshold` percent alike are listed as a rename. With `copies` too, files staged
+// as copies of files in HEAD are also found, comparing them with every file there as `git diff -C
+// --find-copies-harder` does.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct RenamesConfig {
+    pub enabled: bool,
+    pub threshold: u16,
+    pub copies: bool,
+}
+
+impl Default for RenamesConfig {
+    fn default() -> Self {
+        RenamesConfig {
+            enabled: true,
+            threshold: 50,
+            copies: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Whitespace {
+    #[default]
+    Show,
+    // Like `git diff --ignore-space-change`.
+    IgnoreChange,
+    // Like `git diff --ignore-all-space`.
+    IgnoreAll,
+}
+
+impl Whitespace {
+    pub fn next(self) -> Whitespace {
+        match self {
+            Whitespace::Show => Whitespace::IgnoreChange,
+            Whitespace::IgnoreChange => Whitespace::IgnoreAll,
+            Whitespace::IgnoreAll => Whitespace::Show,
+        }
+    }
+}
+
+// Files matching `globs` are staged as soon as the watcher sees them change, while the mode is
+// enabled. `enabled` only sets the initial state; the mode can be toggled in the app.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct AutoStageConfig {
+    pub enabled: bool,
+    #[serde(deserialize_with = "one_or_many")]
+    pub globs: Vec<String>,
+}
+
+// Staging a file bigger than `max-size` bytes (0 disables the check), or one whose path matches
+// `secrets`, asks for an extra confirmation. So does an env file with secret-looking values.
+#[derive(Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct StageWarningsConfig {
+    pub max_size: u64,
+    #[serde(deserialize_with = "one_or_many")]
+    pub secrets: Vec<String>,
+}
+
+impl Default for StageWarningsConfig {
+    fn default() -> Self {
+        StageWarningsConfig {
+            max_size: 5 * 1024 * 1024,
+            secrets: [
+                "**/*.pem",
+                "**/*.key",
+                "**/*.p12",
+                "**/*.pfx",
+                "**/id_rsa",
+                "**/id_dsa",
+                "**/id_ecdsa",
+                "**/id_ed25519",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl Config {
+    pub fn needs_confirm(&self, action: ConfirmAction) -> bool {
+        if let Some(&confirm) = self.confirm_override.get(action.name()) {
+            return confirm;
+        }
+        match self.confirm {
+            ConfirmPolicy::None => false,
+            ConfirmPolicy::Destructive => action.is_destructive(),
+            ConfirmPolicy::All => true,
+        }
+    }
+
+    // Builds the effective configuration from, in increasing order of precedence: the defaults,
+    // the user's config file (`file` if given, or else `$XDG_CONFIG_HOME/git-istage/config.toml` or
+    // `~/.config/git-istage/config.toml`), the repository's `.git-istage.toml`, `istage.*` git
+    // config variables, and `key=value` overrides from the command line. The repository's settings
+    // are only read given its `location`; otherwise just the user's git config is.
+    pub fn load(
+        location: Option<&Location>,
+        file: Option<&Path>,
+        overrides: &[String],
+    ) -> anyhow::Result<Config> {
+        let mut table = match file.map(Path::to_path_buf).or_else(config_path) {
+            Some(path) if file.is_some() || path.exists() => read_table(&path)?,
+            _ => Table::new(),
+        };
+
+        let repo_config = location.map(|l| l.workdir().join(REPO_CONFIG_FILE));
+        if let Some(path) = repo_config.filter(|p| p.exists()) {
+            let repo = read_table(&path)?;
+            // The file may well come with the repository, from whoever else committed it.
+            if repo.contains_key("formatters") {
+                anyhow::bail!(
+                    "{} sets formatters, which run commands: set them in your own config instead",
+                    path.display()
+                );
+            }
+            merge_tables(&mut table, repo);
+        }
+
+        merge_git_config(location, &mut table)?;
+
+        // As https://no-color.org has it, a NO_COLOR that isn't empty turns colors off whatever
+        // the config says, though an override can turn them back on.
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            set_path(&mut table, "theme.no-color", Value::Boolean(true));
+        }
+
+        for o in overrides {
+            let (key, value) = o
+                .split_once('=')
+                .ok_or(anyhow::anyhow!("expected key=value, got {}", o))?;
+            set_path(&mut table, key.trim(), parse_value(value.trim()));
+        }
+
+        Value::Table(table)
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("invalid configuration: {}", e))
+    }
+
+    // Remembers a setting for the current repository as an `istage.*` variable in its git config,
+    // which `load` reads back.
+    pub fn save_to_git(location: &Location, key: &str, value: &str) -> anyhow::Result<()> {
+        let repo = location.open()?;
+        let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+        config.set_str(&format!("istage.{}", key), value)?;
+        Ok(())
+    }
+
+    // Renders the configuration as TOML, with `[keys]` listing `keys`, every action's effective
+    // keys, rather than just the overridden ones.
+    pub fn dump(&self, keys: BTreeMap<String, Vec<String>>) -> anyhow::Result<String> {
+        let mut table = Table::try_from(self)?;
+        table.insert("keys".to_string(), Value::try_from(keys)?);
+        Ok(toml::to_string(&table)?)
+    }
+}
+
+fn read_table(path: &Path) -> anyhow::Result<Table> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("reading config file {}: {}", path.display(), e))?;
+    text.parse::<Table>()
+        .map_err(|e| anyhow::anyhow!("invalid config file {}: {}", path.display(), e))
+}
+
+// The name of the config file at the root of a repository's working tree.
+const REPO_CONFIG_FILE: &str = ".git-istage.toml";
+
+// Sets what `from` sets in `into`, table by table, so that `[theme] name = "light"` leaves the
+// other theme settings in `into` as they were. Anything else, lists included, is replaced whole.
+fn merge_tables(into: &mut Table, from: Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(Value::Table(into)), Value::Table(from)) => merge_tables(into, from),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("git-istage").join("config.toml"))
+}
+
+// Maps `istage.<key>` git config variables onto the same keys as the config file, e.g.
+// `istage.confirm` or `istage.auto-stage.enabled`. List-valued settings can be given several
+// times; for everything else the most specific git config level wins.
+fn merge_git_config(location: Option<&Location>, table: &mut Table) -> anyhow::Result<()> {
+    let config = match location {
+        Some(location) => location.open()?.config()?,
+        None => git2::Config::open_default()?,
+    };
+    let defaults = Value::try_from(Config::default())?;
+
+    // Git's own setting goes under any of ours, from whichever file it's in.
+    let set = table
+        .get("list")
+        .is_some_and(|list| list.get("untracked").is_some());
+    if let (false, Ok(value)) = (set, config.get_string("status.showUntrackedFiles")) {
+        if let Some(untracked) = ShowUntracked::from_git(&value) {
+            set_path(table, "list.untracked", Value::from(untracked.name()));
+        }
+    }
+    let set = table
+        .get("preview")
+        .is_some_and(|preview| preview.get("diff-filter").is_some());
+    if let (false, Ok(value)) = (set, config.get_string("interactive.diffFilter")) {
+        set_path(table, "preview.diff-filter", Value::from(value));
+    }
+
+    let mut lists: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    let mut entries = config.entries(Some("istage\\..*"))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let (name, value) = match (entry.name(), entry.value()) {
+            (Some(n), Some(v)) => (n, v),
+            _ => continue,
+        };
+        let key = &name["istage.".len()..];
+        let value = parse_value(value);
+
+        // Keybindings accept several keys per action, just like list-valued settings do.
+        if key.starts_with("keys.") || lookup(&defaults, key).is_some_and(Value::is_array) {
+            lists.entry(key.to_string()).or_default().push(value);
+        } else {
+            set_path(table, key, value);
+        }
+    }
+
+    for (key, values) in lists {
+        set_path(table, &key, Value::Array(values));
+    }
+    Ok(())
+}
+
+// Values are read as TOML when possible (`true`, `10`, `["a", "b"]`), and as plain strings
+// otherwise, so that `confirm=none` doesn't need quoting.
+fn parse_value(raw: &str) -> Value {
+    format!("v = {}", raw)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn set_path(table: &mut Table, key: &str, value: Value) {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap_or_default();
+
+    let mut current = table;
+    for part in parts {
+        let entry = current
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        current = match entry {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        };
+    }
+    current.insert(last.to_string(), value);
+}
+
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(value, |v, part| v.get(part))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Vec<String> {
+    fn from(value: OneOrMany) -> Self {
+        match value {
+            OneOrMany::One(s) => vec![s],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+// Lets list settings be written as a single string too, which is what a single-valued git config
+// variable turns into.
+fn one_or_many<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    Ok(OneOrMany::deserialize(d)?.into())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+    Name(String),
+    Index(u8),
+}
+
+// Lets palette indices be written as plain numbers, which is also what reading a git config
+// variable like `istage.theme.colors.marked=130` makes of them.
+fn map_of_colors<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<String, String>, D::Error> {
+    let map = BTreeMap::<String, ColorValue>::deserialize(d)?;
+    Ok(map
+        .into_iter()
+        .map(|(k, v)| match v {
+            ColorValue::Name(name) => (k, name),
+            ColorValue::Index(i) => (k, i.to_string()),
+        })
+        .collect())
+}
+
+fn map_of_one_or_many<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<BTreeMap<String, Vec<String>>, D::Error> {
+    let map = BTreeMap::<String, OneOrMany>::deserialize(d)?;
+    Ok(map.into_iter().map(|(k, v)| (k, v.into())).collect())
+}
@@ -0,0 +1,289 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{IndexEntry, Repository};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use git_istage_rs::git::{self, Location};
+
+use crate::{scroll::Scroll, snapshot};
+
+#[derive(Clone, Copy)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Ours => "ours",
+            Side::Theirs => "theirs",
+        }
+    }
+}
+
+// A file the merge left conflicted, with its version at each of the index's stages. A stage is
+// missing where that side doesn't have the file, e.g. when it was deleted on one side and modified
+// on the other.
+pub struct Conflict {
+    pub path: String,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+// The conflicted files, in the order the index has them.
+pub fn list(location: &Location) -> anyhow::Result<Vec<Conflict>> {
+    let repo = location.open()?;
+    let index = repo.index()?;
+    let mut conflicts = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let path = match [&conflict.our, &conflict.their, &conflict.ancestor]
+            .into_iter()
+            .flatten()
+            .next()
+        {
+            Some(entry) => String::from_utf8_lossy(&entry.path).into_owned(),
+            None => continue,
+        };
+        conflicts.push(Conflict {
+            path,
+            base: content(&repo, conflict.ancestor.as_ref())?,
+            ours: content(&repo, conflict.our.as_ref())?,
+            theirs: content(&repo, conflict.their.as_ref())?,
+        });
+    }
+    Ok(conflicts)
+}
+
+fn content(repo: &Repository, entry: Option<&IndexEntry>) -> anyhow::Result<Option<String>> {
+    let entry = match entry {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let blob = repo.find_blob(entry.id)?;
+    Ok(Some(match blob.is_binary() {
+        true => String::from("(binary content)"),
+        false => String::from_utf8_lossy(blob.content()).into_owned(),
+    }))
+}
+
+// Resolves the conflict on `path` with one side's version of the file, like
+// `git checkout --ours/--theirs` followed by `git add`. If that side deleted the file, it's deleted.
+pub fn resolve(location: &Location, path: &str, side: Side) -> anyhow::Result<()> {
+    let repo = location.open()?;
+    let mut index = repo.index()?;
+    let conflict = index
+        .conflicts()?
+        .filter_map(Result::ok)
+        .find(|c| {
+            [&c.our, &c.their, &c.ancestor]
+                .into_iter()
+                .flatten()
+                .any(|e| e.path == path.as_bytes())
+        })
+        .ok_or_else(|| anyhow::anyhow!("{} is not conflicted", path))?;
+    let entry = match side {
+        Side::Ours => conflict.our,
+        Side::Theirs => conflict.their,
+    };
+
+    let file = location.workdir().join(path);
+    match entry {
+        Some(entry) => {
+            fs::write(&file, repo.find_blob(entry.id)?.content())?;
+            set_mode(&file, entry.mode)?;
+        }
+        None => {
+            if file.exists() {
+                fs::remove_file(&file)?;
+            }
+        }
+    }
+    mark_resolved_in(&repo, &mut index, path)
+}
+
+// Marks the conflict on `path` resolved with the file as it is in the worktree, like `git add`.
+pub fn mark_resolved(location: &Location, path: &str) -> anyhow::Result<()> {
+    let repo = location.open()?;
+    let mut index = repo.index()?;
+    mark_resolved_in(&repo, &mut index, path)
+}
+
+// Adding or removing the path replaces its conflict stages with a single entry.
+fn mark_resolved_in(repo: &Repository, index: &mut git2::Index, path: &str) -> anyhow::Result<()> {
+    if git::repo_workdir(repo)?.join(path).exists() {
+        index.add_path(Path::new(path))?;
+    } else {
+        index.remove_path(Path::new(path))?;
+    }
+    Ok(index.write()?)
+}
+
+#[cfg(unix)]
+fn set_mode(file: &Path, mode: u32) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let perms = if mode & 0o111 != 0 { 0o755 } else { 0o644 };
+    Ok(fs::set_permissions(
+        file,
+        fs::Permissions::from_mode(perms),
+    )?)
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &Path, _mode: u32) -> anyhow::Result<()> {
+    Ok(())
+}
+
+pub struct ConflictView {
+    pub conflicts: Vec<Conflict>,
+    pub selected: usize,
+    // How many conflict regions the selected file still has in the worktree.
+    pub markers: usize,
+    pub scroll: Scroll,
+    // The root of the working tree the files are in.
+    root: PathBuf,
+}
+
+impl ConflictView {
+    // None if nothing is conflicted.
+    pub fn open(location: &Location) -> anyhow::Result<Option<ConflictView>> {
+        let conflicts = list(location)?;
+        if conflicts.is_empty() {
+            return Ok(None);
+        }
+        let mut view = ConflictView {
+            conflicts,
+            selected: 0,
+            markers: 0,
+            scroll: Scroll::default(),
+            root: location.workdir().to_path_buf(),
+        };
+        view.count_markers()?;
+        Ok(Some(view))
+    }
+
+    // Reloads the conflicts after some were resolved or edited, keeping the selection in place.
+    // None once they're all resolved.
+    pub fn reload(self, location: &Location) -> anyhow::Result<Option<ConflictView>> {
+        let mut view = match ConflictView::open(location)? {
+            Some(view) => view,
+            None => return Ok(None),
+        };
+        view.selected = self.selected.min(view.conflicts.len() - 1);
+        if view.selected == self.selected {
+            view.scroll = self.scroll;
+        }
+        view.count_markers()?;
+        Ok(Some(view))
+    }
+
+    pub fn next(&mut self) -> anyhow::Result<()> {
+        self.select((self.selected + 1) % self.conflicts.len())
+    }
+
+    pub fn previous(&mut self) -> anyhow::Result<()> {
+        self.select((self.selected + self.conflicts.len() - 1) % self.conflicts.len())
+    }
+
+    fn select(&mut self, selected: usize) -> anyhow::Result<()> {
+        self.selected = selected;
+        self.scroll = Scroll::default();
+        self.count_markers()
+    }
+
+    pub fn current(&self) -> &Conflict {
+        &self.conflicts[self.selected]
+    }
+
+    // The number of lines of the longest version, for scrolling.
+    pub fn line_count(&self) -> usize {
+        let c = self.current();
+        [&c.base, &c.ours, &c.theirs]
+            .into_iter()
+            .flatten()
+            .map(|s| s.lines().count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn count_markers(&mut self) -> anyhow::Result<()> {
+        let file = self.root.join(&self.current().path);
+        self.markers = match fs::read(file) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes)
+                .lines()
+                .filter(|l| snapshot::is_conflict_start(l))
+                .count(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(())
+    }
+}
+
+// Shows ours, the merge base and theirs side by side, like merge tools do, with `hints` for the
+// keys that resolve the conflict.
+pub fn render_conflicts<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    view: &ConflictView,
+    hints: &str,
+) {
+    let conflict = view.current();
+    let mut title = format!(
+        " Conflicts: {} ({}/{}) ",
+        conflict.path,
+        view.selected + 1,
+        view.conflicts.len()
+    );
+    match view.markers {
+        0 => title.push_str("no conflict markers left in the worktree "),
+        1 => title.push_str("1 conflict region left in the worktree "),
+        n => title.push_str(&format!("{} conflict regions left in the worktree ", n)),
+    }
+    let block = Block::default().borders(Borders::ALL).title(vec![
+        Span::styled(title, Style::default().add_modifier(Modifier::BOLD)),
+        Span::styled(format!("{} ", hints), Style::default().fg(Color::Gray)),
+    ]);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Ratio(1, 3),
+            Constraint::Ratio(1, 3),
+            Constraint::Ratio(1, 3),
+        ])
+        .split(inner);
+    let versions = [
+        ("ours", &conflict.ours, "(deleted in ours)"),
+        ("base", &conflict.base, "(not in the merge base)"),
+        ("theirs", &conflict.theirs, "(deleted in theirs)"),
+    ];
+    for ((name, content, missing), column) in versions.into_iter().zip(columns) {
+        let text = match content {
+            Some(content) => content.clone(),
+            None => String::from(missing),
+        };
+        let paragraph = Paragraph::new(text)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", name)),
+            )
+            .scroll((view.scroll.offset, 0));
+        f.render_widget(paragraph, column);
+    }
+}
@@ -0,0 +1,166 @@
+//! A log of the commands run behind the scenes (`git` subprocesses, hooks, formatters, signers),
+//! with what they printed and how they exited, so that their output isn't lost once they're done.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How many runs are kept, dropping the oldest ones first.
+const MAX_RUNS: usize = 200;
+/// How many lines of a single run's output are kept, dropping the first ones.
+const MAX_LINES: usize = 1000;
+
+/// A command that was run, as logged.
+#[derive(Clone)]
+pub struct Run {
+    id: u64,
+    /// When it started, in seconds since the epoch.
+    pub started: u64,
+    /// The program and its arguments, as a shell would take them.
+    pub command: String,
+    /// What it printed: its standard output, then its standard error, line by line. Lines of a
+    /// command that's still running are added as they come, both streams mixed.
+    pub output: Vec<String>,
+    /// How it exited, e.g. "exit 0", once it has.
+    pub exit: Option<String>,
+}
+
+struct Log {
+    runs: VecDeque<Run>,
+    next_id: u64,
+    changes: u64,
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log {
+    runs: VecDeque::new(),
+    next_id: 0,
+    changes: 0,
+});
+
+fn with_log<T>(f: impl FnOnce(&mut Log) -> T) -> T {
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut log)
+}
+
+/// Calls `f` with the runs logged so far, oldest first.
+pub fn with_runs<T>(f: impl FnOnce(&VecDeque<Run>) -> T) -> T {
+    with_log(|log| f(&log.runs))
+}
+
+/// How many times the log has changed, to tell whether it has since it was last shown.
+pub fn changes() -> u64 {
+    with_log(|log| log.changes)
+}
+
+/// Logs that `command` started, returning the id its output and exit are logged under with
+/// [`append`] and [`finish`].
+pub fn start(command: &Command) -> u64 {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    with_log(|log| {
+        let id = log.next_id;
+        log.next_id += 1;
+        log.changes += 1;
+        if log.runs.len() == MAX_RUNS {
+            log.runs.pop_front();
+        }
+        log.runs.push_back(Run {
+            id,
+            started,
+            command: describe(command),
+            output: Vec::new(),
+            exit: None,
+        });
+        id
+    })
+}
+
+/// Adds a line to the output of the run `id`.
+pub fn append(id: u64, line: &str) {
+    with_log(|log| {
+        if let Some(run) = log.runs.iter_mut().find(|r| r.id == id) {
+            if run.output.len() == MAX_LINES {
+                run.output.remove(0);
+            }
+            run.output.push(line.to_string());
+            log.changes += 1;
+        }
+    })
+}
+
+/// Logs how the run `id` exited, or that it couldn't be waited for.
+pub fn finish(id: u64, status: Result<ExitStatus, &io::Error>) {
+    let exit = match status {
+        Ok(status) => match status.code() {
+            Some(code) => format!("exit {}", code),
+            None => format!("{}", status),
+        },
+        Err(e) => format!("failed: {}", e),
+    };
+    with_log(|log| {
+        if let Some(run) = log.runs.iter_mut().find(|r| r.id == id) {
+            run.exit = Some(exit);
+            log.changes += 1;
+        }
+    })
+}
+
+/// Runs `command` to completion like [`Command::output`] does, logging it and its output.
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    let id = start(command);
+    let output = command.output();
+    log_output(id, &output);
+    output
+}
+
+/// Like [`output`], with `input` written to the command's standard input.
+pub fn output_with_input(command: &mut Command, input: &[u8]) -> io::Result<Output> {
+    let id = start(command);
+    let output = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(input)?;
+            }
+            child.wait_with_output()
+        });
+    log_output(id, &output);
+    output
+}
+
+fn log_output(id: u64, output: &io::Result<Output>) {
+    if let Ok(output) = output {
+        for stream in [&output.stdout, &output.stderr] {
+            for line in String::from_utf8_lossy(stream).lines() {
+                append(id, line);
+            }
+        }
+    }
+    finish(id, output.as_ref().map(|o| o.status));
+}
+
+// E.g. `git commit --quiet --message "Fix the parser"`, with the arguments a shell would split
+// quoted.
+fn describe(command: &Command) -> String {
+    let mut words = vec![command.get_program().to_string_lossy().into_owned()];
+    for arg in command.get_args() {
+        let arg = arg.to_string_lossy();
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| !c.is_whitespace() && !matches!(c, '"' | '\'' | '\\' | '$'));
+        words.push(match plain {
+            true => arg.into_owned(),
+            false => format!("{:?}", arg),
+        });
+    }
+    words.join(" ")
+}
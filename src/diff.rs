@@ -0,0 +1,760 @@
+use std::{collections::HashSet, iter::Sum, ops::Range};
+
+use git2::{Diff, DiffFindOptions, DiffOptions, FileMode, Oid, Patch, Repository};
+
+use crate::{
+    config::{Config, RenamesConfig, ShowUntracked, Whitespace},
+    git::FileStatusKind,
+    paths::RepoPath,
+    quote,
+    status::{Status, StatusEntry},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+    // The "\ No newline at end of file" marker following the last line on one side.
+    NoNewlineAtEof,
+}
+
+#[derive(Clone, Debug)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    // The line's text, without its trailing newline but with the `\r` of a CRLF line ending, so
+    // that the patches staging it match the file.
+    pub content: String,
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hunk {
+    // The "@@ -a,b +c,d @@ ..." line.
+    pub header: String,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    pub fn line_counts(&self) -> LineCounts {
+        LineCounts::of(&self.lines)
+    }
+}
+
+/// How many lines a hunk, or a file's whole diff, adds and removes, as git's diffstat counts them.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct LineCounts {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl LineCounts {
+    pub fn of<'a>(lines: impl IntoIterator<Item = &'a DiffLine>) -> LineCounts {
+        let mut counts = LineCounts::default();
+        for line in lines {
+            match line.kind {
+                LineKind::Added => counts.added += 1,
+                LineKind::Removed => counts.removed += 1,
+                LineKind::Context | LineKind::NoNewlineAtEof => {}
+            }
+        }
+        counts
+    }
+
+    pub fn total(self) -> usize {
+        self.added + self.removed
+    }
+}
+
+impl Sum for LineCounts {
+    fn sum<I: Iterator<Item = LineCounts>>(iter: I) -> LineCounts {
+        iter.fold(LineCounts::default(), |a, b| LineCounts {
+            added: a.added + b.added,
+            removed: a.removed + b.removed,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileDiff {
+    pub old_path: RepoPath,
+    pub new_path: RepoPath,
+    pub binary: bool,
+    // The size of each side in bytes, 0 for a side the file doesn't exist on.
+    pub old_size: u64,
+    pub new_size: u64,
+    // For a submodule, the commits its gitlink changes from and to, zero on a side it doesn't
+    // exist on. Submodules have no hunks.
+    pub submodule: Option<(Oid, Oid)>,
+    // The file's mode on each side when the change makes it executable or no longer, e.g.
+    // 0o100644 and 0o100755, whether its content changes too or not.
+    pub modes: Option<(u32, u32)>,
+    // For a symlink, the path it points to on each side, None on a side it isn't a symlink on.
+    // What's a symlink has no hunks: a type change only has those of the side that's a file.
+    pub symlink: Option<(Option<String>, Option<String>)>,
+    pub hunks: Vec<Hunk>,
+    // How many lines the hunks make, headers included, when they were left out for making more
+    // than the diff was asked for with (see `Backend::diff_up_to`).
+    pub held_back: Option<usize>,
+}
+
+impl FileDiff {
+    /// The lines all the hunks add and remove, or None for a binary file or a submodule, which
+    /// have no lines to count.
+    pub fn line_counts(&self) -> Option<LineCounts> {
+        if self.binary || self.submodule.is_some() {
+            return None;
+        }
+        Some(self.hunks.iter().map(Hunk::line_counts).sum())
+    }
+
+    /// Leaves the hunks out, saying how many lines they make in `held_back`, if that's more than
+    /// `max_lines`, 0 being no limit.
+    pub fn hold_back(&mut self, max_lines: usize) {
+        let lines = hunk_lines(&self.hunks) + self.held_back.unwrap_or(0);
+        if max_lines > 0 && lines > max_lines {
+            self.hunks.clear();
+            self.held_back = Some(lines);
+        }
+    }
+}
+
+// How many lines hunks make, each with its header.
+fn hunk_lines(hunks: &[Hunk]) -> usize {
+    hunks.iter().map(|h| 1 + h.lines.len()).sum()
+}
+
+/// How diffs are computed: how many lines of context surround each change, which whitespace
+/// changes are left out, and how renamed and copied files are found. Hunks are counted in diffs
+/// computed with the same settings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiffSettings {
+    pub context: u32,
+    pub whitespace: Whitespace,
+    pub renames: RenamesConfig,
+    /// Whether the ignored files are listed along with the untracked ones.
+    pub ignored: bool,
+    /// How the untracked files are listed.
+    pub untracked: ShowUntracked,
+    /// How many untracked entries are looked for before the untracked directories left are
+    /// listed collapsed, 0 for no limit. Only the git2 backend applies it.
+    pub max_untracked: usize,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        DiffSettings {
+            context: 3,
+            whitespace: Whitespace::Show,
+            renames: RenamesConfig::default(),
+            ignored: false,
+            untracked: ShowUntracked::All,
+            max_untracked: 0,
+        }
+    }
+}
+
+impl DiffSettings {
+    /// Fails if hunks of diffs computed with these settings can't be staged or unstaged: leaving
+    /// out whitespace changes leaves hunks that don't apply to the file as it is.
+    pub fn check_applicable(self) -> anyhow::Result<()> {
+        if self.whitespace != Whitespace::Show {
+            anyhow::bail!("hunks can't be staged or unstaged while whitespace changes are hidden");
+        }
+        Ok(())
+    }
+}
+
+impl From<&Config> for DiffSettings {
+    fn from(config: &Config) -> Self {
+        DiffSettings {
+            context: config.preview.context,
+            whitespace: config.preview.whitespace,
+            renames: config.renames,
+            ignored: config.list.show_ignored,
+            untracked: config.list.untracked,
+            max_untracked: config.list.max_untracked,
+        }
+    }
+}
+
+/// The options for finding the renames, and with `copies` the copies, that `renames` asks for.
+/// Copies are looked for among all the files on the old side, so the diff must include the
+/// unmodified ones, which are left out again once the copies are found.
+pub fn find_options(renames: &RenamesConfig, copies: bool) -> DiffFindOptions {
+    let mut opts = DiffFindOptions::new();
+    opts.renames(renames.enabled)
+        .rename_threshold(renames.threshold)
+        .copies(copies)
+        .copies_from_unmodified(copies)
+        .copy_threshold(renames.threshold)
+        .remove_unmodified(copies);
+    opts
+}
+
+// The git2 diff that a status entry of the given kind was computed from, restricted to the
+// entry's paths. Staged diffs of an unborn branch are taken against the empty tree.
+pub fn entry_diff<'r>(
+    repo: &'r Repository,
+    entry: &StatusEntry,
+    kind: FileStatusKind,
+    reverse: bool,
+    settings: DiffSettings,
+) -> anyhow::Result<Diff<'r>> {
+    let mut opts = DiffOptions::new();
+    opts.disable_pathspec_match(true)
+        .show_binary(true)
+        .reverse(reverse)
+        .context_lines(settings.context)
+        .ignore_whitespace_change(settings.whitespace == Whitespace::IgnoreChange)
+        .ignore_whitespace(settings.whitespace == Whitespace::IgnoreAll)
+        .include_unmodified(matches!(entry.status, Status::Copied))
+        .pathspec(entry.old_path())
+        .pathspec(entry.new_path());
+
+    let mut diff = match kind {
+        // libgit2 leaves out the content of ignored files, so the file is diffed against nothing
+        // as a new one, and the patch that makes read back.
+        FileStatusKind::Unstaged if matches!(entry.status, Status::Ignored) => {
+            let content = std::fs::read(entry.abs_path_new())?;
+            let path = entry.new_path();
+            let mut patch = Patch::from_buffers(&[], None, &content, Some(&path), Some(&mut opts))?;
+            Diff::from_buffer(&patch.to_buf()?)?
+        }
+        FileStatusKind::Unstaged => {
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            repo.diff_index_to_workdir(None, Some(&mut opts))?
+        }
+        FileStatusKind::Staged => {
+            let head = match repo.head() {
+                Ok(head) => Some(head.peel_to_tree()?),
+                Err(_) => None,
+            };
+            repo.diff_tree_to_index(head.as_ref(), None, Some(&mut opts))?
+        }
+    };
+
+    match entry.status {
+        Status::Renamed | Status::Copied => {
+            let copied = matches!(entry.status, Status::Copied);
+            let mut find = find_options(&settings.renames, copied);
+            find.for_untracked(matches!(kind, FileStatusKind::Unstaged));
+            diff.find_similar(Some(&mut find))?;
+        }
+        _ => {}
+    }
+    Ok(diff)
+}
+
+// Collects every delta of `diff` into owned `FileDiff`s.
+pub fn file_diffs(diff: &Diff) -> anyhow::Result<Vec<FileDiff>> {
+    file_diffs_up_to(diff, 0)
+}
+
+// Like `file_diffs`, but the hunks of a delta that would make more than `max_lines` lines aren't
+// collected at all, only counted, 0 being no limit.
+pub fn file_diffs_up_to(diff: &Diff, max_lines: usize) -> anyhow::Result<Vec<FileDiff>> {
+    let mut files = Vec::new();
+    for idx in 0..diff.deltas().len() {
+        let delta = diff
+            .get_delta(idx)
+            .ok_or(anyhow::anyhow!("missing delta"))?;
+        let path = |f: git2::DiffFile| f.path_bytes().map(RepoPath::from).unwrap_or_default();
+        let mut file = FileDiff {
+            old_path: path(delta.old_file()),
+            new_path: path(delta.new_file()),
+            binary: delta.flags().is_binary(),
+            old_size: delta.old_file().size(),
+            new_size: delta.new_file().size(),
+            submodule: None,
+            modes: mode_change(
+                u32::from(delta.old_file().mode()),
+                u32::from(delta.new_file().mode()),
+            ),
+            symlink: None,
+            hunks: Vec::new(),
+            held_back: None,
+        };
+
+        let gitlink = |f: git2::DiffFile| f.mode() == FileMode::Commit;
+        if gitlink(delta.old_file()) || gitlink(delta.new_file()) {
+            file.submodule = Some((delta.old_file().id(), delta.new_file().id()));
+            files.push(file);
+            continue;
+        }
+
+        if let Some(patch) = Patch::from_diff(diff, idx)? {
+            // Untracked files are only found to be binary once their content is loaded for the
+            // patch.
+            file.binary |= patch.delta().flags().is_binary();
+            file.old_size = patch.delta().old_file().size();
+            file.new_size = patch.delta().new_file().size();
+            let mut lines = 0;
+            for h in 0..patch.num_hunks() {
+                lines += 1 + patch.num_lines_in_hunk(h)?;
+            }
+            if max_lines > 0 && lines > max_lines {
+                file.held_back = Some(lines);
+                files.push(file);
+                continue;
+            }
+            // libgit2 only looks for NUL bytes, which plenty of binary formats go without.
+            let mut content = Vec::new();
+            for h in 0..patch.num_hunks() {
+                let (hunk, _) = patch.hunk(h)?;
+                let mut lines = Vec::new();
+                for l in 0..patch.num_lines_in_hunk(h)? {
+                    let line = patch.line_in_hunk(h, l)?;
+                    content.extend_from_slice(line.content());
+                    let kind = match line.origin() {
+                        '+' => LineKind::Added,
+                        '-' => LineKind::Removed,
+                        '=' | '>' | '<' => LineKind::NoNewlineAtEof,
+                        _ => LineKind::Context,
+                    };
+                    lines.push(DiffLine {
+                        kind,
+                        content: String::from_utf8_lossy(line.content())
+                            .trim_end_matches('\n')
+                            .to_string(),
+                        old_lineno: line.old_lineno(),
+                        new_lineno: line.new_lineno(),
+                    });
+                }
+                file.hunks.push(Hunk {
+                    header: String::from_utf8_lossy(hunk.header())
+                        .trim_end()
+                        .to_string(),
+                    old_start: hunk.old_start(),
+                    old_lines: hunk.old_lines(),
+                    new_start: hunk.new_start(),
+                    new_lines: hunk.new_lines(),
+                    lines,
+                });
+            }
+            file.binary |= looks_binary(&content);
+        }
+        // A symlink's content is the path it points to, on one line without a newline.
+        let (old_link, new_link) = (
+            is_symlink(u32::from(delta.old_file().mode())),
+            is_symlink(u32::from(delta.new_file().mode())),
+        );
+        if old_link || new_link {
+            let target = |kind: LineKind| -> String {
+                let lines = file.hunks.iter().flat_map(|h| &h.lines);
+                lines
+                    .filter(|l| l.kind == kind)
+                    .map(|l| l.content.as_str())
+                    .collect()
+            };
+            file.symlink = Some((
+                old_link.then(|| target(LineKind::Removed)),
+                new_link.then(|| target(LineKind::Added)),
+            ));
+            file.hunks.clear();
+        }
+        if file.binary {
+            file.hunks.clear();
+        }
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// The diff of two versions of the file at `path` given as their contents rather than found in
+/// the repository, e.g. the files an external diff is run with. `modes` are those of each side if
+/// they're known, 0 for a side the file doesn't exist on; a symlink's content is its target.
+pub fn buffers_diff(
+    path: &RepoPath,
+    old: &[u8],
+    new: &[u8],
+    modes: Option<(u32, u32)>,
+    settings: DiffSettings,
+) -> anyhow::Result<FileDiff> {
+    let (old_mode, new_mode) = modes.unwrap_or_default();
+    let (old_link, new_link) = (is_symlink(old_mode), is_symlink(new_mode));
+    let target =
+        |link: bool, content: &[u8]| link.then(|| String::from_utf8_lossy(content).into_owned());
+    let symlink = (old_link || new_link).then(|| (target(old_link, old), target(new_link, new)));
+    let sizes = (old.len() as u64, new.len() as u64);
+    // What's a symlink has no hunks, so its side is diffed as empty.
+    let old = if old_link { &[][..] } else { old };
+    let new = if new_link { &[][..] } else { new };
+
+    let mut opts = DiffOptions::new();
+    opts.context_lines(settings.context)
+        .ignore_whitespace_change(settings.whitespace == Whitespace::IgnoreChange)
+        .ignore_whitespace(settings.whitespace == Whitespace::IgnoreAll);
+    let at = path.to_path();
+    let mut patch = Patch::from_buffers(old, Some(&at), new, Some(&at), Some(&mut opts))?;
+    let diff = Diff::from_buffer(&patch.to_buf()?)?;
+    // Identical contents make no delta at all.
+    let mut file = file_diffs(&diff)?.pop().unwrap_or_else(|| FileDiff {
+        old_path: path.clone(),
+        new_path: path.clone(),
+        binary: false,
+        old_size: 0,
+        new_size: 0,
+        submodule: None,
+        modes: None,
+        symlink: None,
+        hunks: Vec::new(),
+        held_back: None,
+    });
+    file.binary |= looks_binary(old) || looks_binary(new);
+    if file.binary {
+        file.hunks.clear();
+    }
+    (file.old_size, file.new_size) = sizes;
+    file.modes = mode_change(old_mode, new_mode);
+    file.symlink = symlink;
+    Ok(file)
+}
+
+// The indices of the hunks of `file` with a change that `ignoring`, the same file's diff with
+// whitespace changes left out, has too. The others only change whitespace.
+pub fn substantive_hunks(file: &FileDiff, ignoring: &FileDiff) -> Vec<usize> {
+    let changes: HashSet<(LineKind, Option<u32>)> = ignoring
+        .hunks
+        .iter()
+        .flat_map(|h| &h.lines)
+        .filter_map(change)
+        .collect();
+    (0..file.hunks.len())
+        .filter(|&i| {
+            let mut lines = file.hunks[i].lines.iter();
+            lines.any(|l| change(l).is_some_and(|c| changes.contains(&c)))
+        })
+        .collect()
+}
+
+// An added line by where it is in the new file, or a removed one by where it was in the old one.
+fn change(line: &DiffLine) -> Option<(LineKind, Option<u32>)> {
+    match line.kind {
+        LineKind::Added => Some((line.kind, line.new_lineno)),
+        LineKind::Removed => Some((line.kind, line.old_lineno)),
+        LineKind::Context | LineKind::NoNewlineAtEof => None,
+    }
+}
+
+// The modes a change goes between if it only sets or clears the executable bit of a regular file,
+// rather than adding, deleting or changing the type of the file.
+pub fn mode_change(old: u32, new: u32) -> Option<(u32, u32)> {
+    let regular = |mode: u32| mode & 0o170000 == 0o100000;
+    (regular(old) && regular(new) && old != new).then_some((old, new))
+}
+
+pub fn is_symlink(mode: u32) -> bool {
+    mode & 0o170000 == 0o120000
+}
+
+// libgit2 diffs a type change, such as a symlink replaced by a file, as the old file deleted and
+// the new one added. This joins the two into the one file's diff, the removed lines first.
+pub fn join_typechange(files: Vec<FileDiff>) -> Option<FileDiff> {
+    let mut files = files.into_iter();
+    let mut file = files.next()?;
+    if let Some(added) = files.next() {
+        file.new_path = added.new_path;
+        file.new_size = added.new_size;
+        file.binary |= added.binary;
+        file.symlink = match (file.symlink.take(), added.symlink) {
+            (None, None) => None,
+            (old, new) => Some((old.and_then(|(o, _)| o), new.and_then(|(_, n)| n))),
+        };
+        file.held_back = match (file.held_back, added.held_back) {
+            (None, None) => None,
+            (old, new) => Some(
+                old.unwrap_or(hunk_lines(&file.hunks)) + new.unwrap_or(hunk_lines(&added.hunks)),
+            ),
+        };
+        file.hunks.extend(added.hunks);
+        if file.binary || file.held_back.is_some() {
+            file.hunks.clear();
+        }
+    }
+    Some(file)
+}
+
+// Git's heuristic for binary content: a NUL byte, or more than one control character per 128
+// printable ones.
+pub(crate) fn looks_binary(data: &[u8]) -> bool {
+    let mut printable = 0;
+    let mut control = 0;
+    for &b in data {
+        match b {
+            0 => return true,
+            b'\t' | b'\n' | b'\r' | 0x0c => {}
+            0x01..=0x1f | 0x7f => control += 1,
+            _ => printable += 1,
+        }
+    }
+    control > printable / 128
+}
+
+// Renders the hunks of `file` with the given indices as a patch that `git apply` accepts. The
+// positions are left as they are: each side's are exact for the content the patch applies to,
+// forwards or with `--reverse`.
+pub fn render_patch(file: &FileDiff, status: Status, hunks: &[usize]) -> String {
+    let mut out = format!(
+        "diff --git {} {}\n",
+        patch_path("a/", &file.old_path),
+        patch_path("b/", &file.new_path)
+    );
+    let (old, new) = match status {
+        Status::Added | Status::Untracked | Status::Ignored => {
+            out.push_str("new file mode 100644\n");
+            (String::from("/dev/null"), patch_path("b/", &file.new_path))
+        }
+        Status::Deleted => {
+            out.push_str("deleted file mode 100644\n");
+            (patch_path("a/", &file.old_path), String::from("/dev/null"))
+        }
+        _ => {
+            if file.old_path != file.new_path {
+                let how = match status {
+                    Status::Copied => "copy",
+                    _ => "rename",
+                };
+                out.push_str(&format!(
+                    "{} from {}\n{} to {}\n",
+                    how,
+                    file.old_path.quoted(),
+                    how,
+                    file.new_path.quoted()
+                ));
+            }
+            (
+                patch_path("a/", &file.old_path),
+                patch_path("b/", &file.new_path),
+            )
+        }
+    };
+    out.push_str(&format!("--- {}\n+++ {}\n", old, new));
+
+    for (i, hunk) in file.hunks.iter().enumerate() {
+        if !hunks.contains(&i) {
+            continue;
+        }
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+        ));
+        for line in &hunk.lines {
+            match line.kind {
+                LineKind::Context => out.push(' '),
+                LineKind::Added => out.push('+'),
+                LineKind::Removed => out.push('-'),
+                LineKind::NoNewlineAtEof => {
+                    out.push_str("\\ No newline at end of file\n");
+                    continue;
+                }
+            }
+            out.push_str(&line.content);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// A path in a patch's headers, after `prefix` and quoted if anything in it is escaped, as git quotes
+// such paths in patches.
+fn patch_path(prefix: &str, path: &RepoPath) -> String {
+    quote::quote(&format!("{}{}", prefix, path)).into_owned()
+}
+
+// The content changes of a rename, as changes to the file at its new path, which is what's left to
+// stage once the rename itself is staged.
+pub fn renamed_content(file: &FileDiff) -> FileDiff {
+    FileDiff {
+        old_path: file.new_path.clone(),
+        ..file.clone()
+    }
+}
+
+// Patches that take the hunks of `file` with the given indices back out of the file at its new
+// path, one per hunk: out of the index for a staged rename or copy, leaving the rename or copy
+// itself staged (the whole patch reversed would move the file back, or delete the copy), or out of
+// the working directory to discard them. They go from the last hunk, so that each leaves the
+// positions of the ones before it as they are.
+pub fn render_reverse_patches(file: &FileDiff, hunks: &[usize]) -> Vec<String> {
+    let mut hunks = hunks.to_vec();
+    hunks.sort_unstable_by(|a, b| b.cmp(a));
+    hunks
+        .into_iter()
+        .filter(|&h| h < file.hunks.len())
+        .map(|h| {
+            let lines = 0..file.hunks[h].lines.len();
+            render_line_patch(file, Status::Modified, h, lines, true)
+        })
+        .collect()
+}
+
+// Renders a patch that applies just the `lines` (indices into its `lines`) of hunk `hunk` to the
+// side of `file` the hunk starts from. Unselected removals are kept as context and unselected
+// additions are left out, and the header is recomputed to match. With `reverse`, the patch takes
+// the selected lines back out of the other side instead, for unstaging.
+pub fn render_line_patch(
+    file: &FileDiff,
+    status: Status,
+    hunk: usize,
+    lines: Range<usize>,
+    reverse: bool,
+) -> String {
+    let hunk = &file.hunks[hunk];
+    let (old_path, new_path) = match (reverse, status) {
+        // The index holds the new path, and this patch doesn't move it back.
+        (true, _) => (&file.new_path, &file.new_path),
+        _ => (&file.old_path, &file.new_path),
+    };
+    let mut out = format!(
+        "diff --git {} {}\n",
+        patch_path("a/", old_path),
+        patch_path("b/", new_path)
+    );
+    let old = match (reverse, status) {
+        (false, Status::Added | Status::Untracked | Status::Ignored) => {
+            out.push_str("new file mode 100644\n");
+            String::from("/dev/null")
+        }
+        _ => {
+            if old_path != new_path {
+                out.push_str(&format!(
+                    "rename from {}\nrename to {}\n",
+                    old_path.quoted(),
+                    new_path.quoted()
+                ));
+            }
+            patch_path("a/", old_path)
+        }
+    };
+    out.push_str(&format!(
+        "--- {}\n+++ {}\n",
+        old,
+        patch_path("b/", new_path)
+    ));
+
+    let mut body = String::new();
+    let (mut old_lines, mut new_lines) = (0, 0);
+    // Whether the last line was left out, taking its "no newline" marker with it.
+    let mut dropped = false;
+    for (i, line) in hunk.lines.iter().enumerate() {
+        let kind = match (reverse, line.kind) {
+            (true, LineKind::Added) => LineKind::Removed,
+            (true, LineKind::Removed) => LineKind::Added,
+            (_, kind) => kind,
+        };
+        let selected = lines.contains(&i);
+        let prefix = match kind {
+            LineKind::NoNewlineAtEof => {
+                if !dropped {
+                    body.push_str("\\ No newline at end of file\n");
+                }
+                continue;
+            }
+            LineKind::Added if !selected => {
+                dropped = true;
+                continue;
+            }
+            LineKind::Context => ' ',
+            LineKind::Removed if !selected => ' ',
+            LineKind::Added => '+',
+            LineKind::Removed => '-',
+        };
+        dropped = false;
+        if prefix != '+' {
+            old_lines += 1;
+        }
+        if prefix != '-' {
+            new_lines += 1;
+        }
+        body.push(prefix);
+        body.push_str(&line.content);
+        body.push('\n');
+    }
+
+    // An empty side starts at the line before the hunk's position.
+    let old_start = if reverse {
+        hunk.new_start
+    } else {
+        hunk.old_start
+    };
+    let new_start = match (old_lines, new_lines) {
+        (0, _) => old_start + 1,
+        (_, 0) => old_start.saturating_sub(1),
+        _ => old_start,
+    };
+    out.push_str(&format!(
+        "@@ -{},{} +{},{} @@\n",
+        old_start, old_lines, new_start, new_lines
+    ));
+    out.push_str(&body);
+    out
+}
+
+// Splits a hunk the way `git add -p` does: into one piece for each run of changes, with the
+// context lines around it, which neighbouring pieces share. Each piece comes with the range of
+// `hunk.lines` it's made of, so that it can be staged as those lines of the hunk. A hunk with a
+// single run of changes can't be split, and comes back as it is.
+pub fn split_hunk(hunk: &Hunk) -> Vec<(Range<usize>, Hunk)> {
+    // The first and last changed line of each run.
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (i, line) in hunk.lines.iter().enumerate() {
+        match line.kind {
+            LineKind::Added | LineKind::Removed => match runs.last_mut() {
+                Some((_, last))
+                    if !hunk.lines[*last + 1..i]
+                        .iter()
+                        .any(|l| l.kind == LineKind::Context) =>
+                {
+                    *last = i
+                }
+                _ => runs.push((i, i)),
+            },
+            LineKind::Context | LineKind::NoNewlineAtEof => {}
+        }
+    }
+    if runs.len() < 2 {
+        return vec![(0..hunk.lines.len(), hunk.clone())];
+    }
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (k, &(_, last)) in runs.iter().enumerate() {
+        let end = runs.get(k + 1).map_or(hunk.lines.len(), |&(next, _)| next);
+        let before = &hunk.lines[..start];
+        let lines = hunk.lines[start..end].to_vec();
+        let count = |lines: &[DiffLine], left_out: LineKind| {
+            lines
+                .iter()
+                .filter(|l| l.kind != left_out && l.kind != LineKind::NoNewlineAtEof)
+                .count() as u32
+        };
+        let old_start = hunk.old_start + count(before, LineKind::Added);
+        let new_start = hunk.new_start + count(before, LineKind::Removed);
+        let old_lines = count(&lines, LineKind::Added);
+        let new_lines = count(&lines, LineKind::Removed);
+        pieces.push((
+            start..end,
+            Hunk {
+                header: format!(
+                    "@@ -{},{} +{},{} @@",
+                    old_start, old_lines, new_start, new_lines
+                ),
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+                lines,
+            },
+        ));
+        start = last + 1;
+    }
+    pieces
+}
@@ -0,0 +1,202 @@
+use std::{
+    io::{Read, Write},
+    process::{self, Stdio},
+    thread,
+};
+
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+use git_istage_rs::diff::{FileDiff, LineKind};
+
+// A file's diff as `preview.diff-filter` (or git's `interactive.diffFilter`) renders it, e.g.
+// `delta --color-only` or `diff-so-fancy --patch`: the diff goes to the command's input colored
+// as `git diff` colors it, and what it prints, colors and all, is shown in place of each line.
+// Like git, which does the same for `git add -p`, this takes the command to print one line for
+// each line of the diff, so that hunks and lines can still be staged from what it shows. Returns
+// the lines of each hunk, its header first.
+pub fn filter(command: &str, file: &FileDiff) -> anyhow::Result<Vec<Vec<Spans<'static>>>> {
+    let mut words = command.split_whitespace();
+    let program = words
+        .next()
+        .ok_or(anyhow::anyhow!("empty diff filter command"))?;
+    let input = colored_patch(file);
+    // Not logged in the console, as it runs for every diff shown.
+    let mut child = process::Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("running diff filter `{}`: {}", command, e))?;
+    // Written from a thread of its own, as the command may only read more of a big diff once
+    // what it's printed of it has been read.
+    let mut stdin = child.stdin.take();
+    let lines = input.lines().count();
+    let writer = thread::spawn(move || stdin.as_mut().map(|s| s.write_all(input.as_bytes())));
+    let mut output = Vec::new();
+    if let Some(stdout) = &mut child.stdout {
+        stdout.read_to_end(&mut output)?;
+    }
+    let result = child.wait_with_output()?;
+    let _ = writer.join();
+    if !result.status.success() {
+        anyhow::bail!(
+            "diff filter `{}` failed: {}",
+            command,
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+
+    let output = String::from_utf8_lossy(&output);
+    let mut printed: Vec<&str> = output.lines().collect();
+    if printed.len() != lines {
+        anyhow::bail!(
+            "diff filter `{}` printed {} lines for the {} of the diff, rather than one for each",
+            command,
+            printed.len(),
+            lines
+        );
+    }
+    // Past the file's header, each hunk's lines follow its own header.
+    let mut printed = printed.split_off(lines - hunk_lines(file)).into_iter();
+    Ok(file
+        .hunks
+        .iter()
+        .map(|hunk| {
+            let hunk_printed = printed.by_ref().take(1 + hunk.lines.len());
+            hunk_printed.map(parse_ansi).collect()
+        })
+        .collect())
+}
+
+// How many lines the hunks take, their headers included.
+fn hunk_lines(file: &FileDiff) -> usize {
+    file.hunks.iter().map(|h| 1 + h.lines.len()).sum()
+}
+
+// The file's diff as `git diff --color` prints it with the default colors.
+fn colored_patch(file: &FileDiff) -> String {
+    const BOLD: &str = "\x1b[1m";
+    const RESET: &str = "\x1b[m";
+    let mut patch = format!(
+        "{BOLD}diff --git a/{old} b/{new}{RESET}\n{BOLD}--- a/{old}{RESET}\n{BOLD}+++ b/{new}{RESET}\n",
+        old = file.old_path,
+        new = file.new_path,
+    );
+    for hunk in &file.hunks {
+        // Only the "@@ ... @@" is colored, not the function name after it.
+        let end = hunk.header.get(2..).and_then(|h| h.find("@@"));
+        let end = end.map_or(hunk.header.len(), |i| i + 4);
+        let (range, context) = hunk.header.split_at(end);
+        patch += &format!("\x1b[36m{}{}{}\n", range, RESET, context);
+        for line in &hunk.lines {
+            patch += &match line.kind {
+                LineKind::Context => format!(" {}\n", line.content),
+                LineKind::Added => format!("\x1b[32m+{}{}\n", line.content, RESET),
+                LineKind::Removed => format!("\x1b[31m-{}{}\n", line.content, RESET),
+                LineKind::NoNewlineAtEof => String::from("\\ No newline at end of file\n"),
+            };
+        }
+    }
+    patch
+}
+
+// A line of the command's output, with its SGR escape sequences (colors, bold, ...) turned into
+// styles, and any other escape sequence, such as a hyperlink, left out.
+fn parse_ansi(line: &str) -> Spans<'static> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut spans = Vec::new();
+    let mut style = Style::default();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates up to a final byte, `m` for SGR.
+            Some('[') => {
+                let mut params = String::new();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        if c == 'm' {
+                            if !text.is_empty() {
+                                spans.push(Span::styled(std::mem::take(&mut text), style));
+                            }
+                            style = sgr(style, &params);
+                        }
+                        break;
+                    }
+                    params.push(c);
+                }
+            }
+            // OSC, e.g. a hyperlink: up to BEL or ST.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !text.is_empty() {
+        spans.push(Span::styled(text, style));
+    }
+    Spans::from(spans)
+}
+
+// `style` with the SGR parameters `params` (e.g. "1;38;5;208") applied.
+fn sgr(mut style: Style, params: &str) -> Style {
+    let mut codes = params
+        .split([';', ':'])
+        .map(|p| p.parse::<u16>().unwrap_or(0));
+    // An empty sequence resets, like a 0.
+    if params.is_empty() {
+        return Style::default();
+    }
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed((code - 30) as u8)),
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(Color::Indexed((code - 40) as u8)),
+            49 => style.bg(Color::Reset),
+            90..=97 => style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg(Color::Indexed((code - 100 + 8) as u8)),
+            38 | 48 => match extended_color(&mut codes) {
+                Some(color) if code == 38 => style.fg(color),
+                Some(color) => style.bg(color),
+                None => style,
+            },
+            _ => style,
+        };
+    }
+    style
+}
+
+// The color of a `38;5;n` or `38;2;r;g;b` sequence (or `48;...`), after its 38.
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut next = || codes.next().and_then(|c| u8::try_from(c).ok());
+    match next()? {
+        5 => Some(Color::Indexed(next()?)),
+        2 => Some(Color::Rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,557 @@
+use std::ops::Range;
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use git_istage_rs::{
+    config::{ScrollConfig, Whitespace},
+    diff::{self, DiffSettings, FileDiff, LineKind},
+    paths::RepoPath,
+    status::Status,
+};
+
+use crate::{
+    blame::{self, FileBlame},
+    diff_filter, last_line,
+    preview::{self, HunkSpans},
+    scroll::Scroll,
+    theme::Theme,
+};
+
+// The diff of the selected entry as shown in the diff pane, with one of its hunks selected. It's
+// computed once per selected entry until the next refresh.
+pub struct DiffView {
+    pub staged: bool,
+    pub path: RepoPath,
+    // Binary files, submodules and symlinks have no hunks to stage on their own.
+    pub binary: bool,
+    pub submodule: bool,
+    pub symlink: bool,
+    // The diff as shown, with any hunks that were split replaced by their pieces. None for a
+    // message.
+    diff: Option<FileDiff>,
+    // For each hunk shown, the hunk of the computed diff it is or was split from, and for a piece
+    // the range of that hunk's lines it's made of.
+    pieces: Vec<(usize, Option<Range<usize>>)>,
+    // The lines shown in place of hunks, for a message, a binary file or a submodule.
+    summary: Vec<Spans<'static>>,
+    // The lines of each hunk, styled as they're scrolled to.
+    hunk_spans: Vec<HunkSpans>,
+    line_count: usize,
+    // The number of each line on the old side of the diff, for lines that are there (context and
+    // removed lines), to look up who last changed them.
+    old_linenos: Vec<Option<u32>>,
+    // The line each hunk's header is on.
+    starts: Vec<usize>,
+    // The first added or removed line of each hunk, as an index into its lines.
+    first_changes: Vec<usize>,
+    pub hunk: usize,
+    // Lines being selected within the selected hunk, as indices into its lines: where the
+    // selection started and where the cursor is.
+    selection: Option<(usize, usize)>,
+    pub scroll: Scroll,
+    // Set when the statuses were refreshed, so that the diff is recomputed before it's next shown.
+    pub stale: bool,
+    // A diff too large to show is kept but shown as a message until it's loaded anyway, which a
+    // refresh of the same file keeps to.
+    capped: bool,
+    forced: bool,
+    // The command the hunks are rendered with, if any, and why it couldn't render them the last
+    // time it was run, for the app to say so.
+    filter: Option<String>,
+    pub filter_error: Option<anyhow::Error>,
+}
+
+impl DiffView {
+    // With `filter` set, the hunks are rendered by that command (see `diff_filter`), unless
+    // nothing is drawn in color.
+    pub fn new(
+        staged: bool,
+        path: RepoPath,
+        diff: FileDiff,
+        filter: Option<&str>,
+        theme: &Theme,
+    ) -> DiffView {
+        let mut view = DiffView {
+            staged,
+            path,
+            binary: diff.binary,
+            submodule: diff.submodule.is_some(),
+            symlink: diff.symlink.is_some(),
+            pieces: (0..diff.hunks.len()).map(|i| (i, None)).collect(),
+            diff: Some(diff),
+            summary: Vec::new(),
+            hunk_spans: Vec::new(),
+            line_count: 0,
+            old_linenos: Vec::new(),
+            starts: Vec::new(),
+            first_changes: Vec::new(),
+            hunk: 0,
+            selection: None,
+            scroll: Scroll::default(),
+            stale: false,
+            capped: false,
+            forced: false,
+            filter: filter.map(String::from),
+            filter_error: None,
+        };
+        view.lay_out(theme);
+        view
+    }
+
+    // A diff whose hunks were held back for being too many lines to show right away, shown as
+    // the lines of `message` until it's loaded anyway.
+    pub fn too_large(
+        staged: bool,
+        path: RepoPath,
+        diff: FileDiff,
+        message: Vec<String>,
+    ) -> DiffView {
+        let mut view = DiffView::message(staged, path, String::new());
+        view.summary = message.into_iter().map(Spans::from).collect();
+        view.line_count = view.summary.len();
+        view.binary = diff.binary;
+        view.submodule = diff.submodule.is_some();
+        view.symlink = diff.symlink.is_some();
+        view.diff = Some(diff);
+        view.capped = true;
+        view
+    }
+
+    pub fn is_capped(&self) -> bool {
+        self.capped
+    }
+
+    // Whether the diff was loaded anyway, however large, which its refreshes are too.
+    pub fn is_forced(&self) -> bool {
+        self.forced
+    }
+
+    // Asks for a diff that was too large to show right away to be loaded in full, saying it's
+    // loading until it is.
+    pub fn load_anyway(&mut self) {
+        if self.capped {
+            self.capped = false;
+            self.forced = true;
+            self.stale = true;
+            self.summary = vec![Spans::from("loading...")];
+            self.line_count = 1;
+        }
+    }
+
+    // Works out how many lines the diff has as shown, and where each hunk starts in them. The
+    // lines themselves are only styled once they're shown.
+    fn lay_out(&mut self, theme: &Theme) {
+        let diff = match &self.diff {
+            Some(diff) if !self.capped => diff,
+            _ => return,
+        };
+        self.starts.clear();
+        self.first_changes.clear();
+        self.old_linenos.clear();
+        self.hunk_spans.clear();
+        self.summary = preview::summary_spans(diff, theme).unwrap_or_default();
+        // Drawn without colors, the lines are told apart by the markers only the app's own
+        // rendering has.
+        let filter = self.filter.as_deref().filter(|_| !theme.no_color);
+        let mut filtered = match filter.filter(|_| self.summary.is_empty()) {
+            Some(command) => match diff_filter::filter(command, diff) {
+                Ok(hunks) => Some(hunks.into_iter()),
+                Err(e) => {
+                    self.filter_error = Some(e);
+                    None
+                }
+            },
+            None => None,
+        };
+        if self.summary.is_empty() {
+            let language = preview::diff_language(diff, theme);
+            let mut line = 0;
+            for hunk in &diff.hunks {
+                self.starts.push(line);
+                line += 1 + hunk.lines.len();
+                self.old_linenos.push(None);
+                self.old_linenos
+                    .extend(hunk.lines.iter().map(|l| match l.kind {
+                        LineKind::Context | LineKind::Removed => l.old_lineno,
+                        LineKind::Added | LineKind::NoNewlineAtEof => None,
+                    }));
+                let first = hunk
+                    .lines
+                    .iter()
+                    .position(|l| matches!(l.kind, LineKind::Added | LineKind::Removed));
+                self.first_changes.push(first.unwrap_or(0));
+                self.hunk_spans
+                    .push(match filtered.as_mut().and_then(|f| f.next()) {
+                        Some(lines) => HunkSpans::styled(lines),
+                        None => HunkSpans::new(hunk, language),
+                    });
+            }
+        }
+        self.line_count = match self.summary.len() {
+            0 => self.old_linenos.len(),
+            n => n,
+        };
+    }
+
+    // A pane showing `message` in place of a diff that couldn't be computed.
+    pub fn message(staged: bool, path: RepoPath, message: String) -> DiffView {
+        DiffView {
+            staged,
+            path,
+            binary: false,
+            submodule: false,
+            symlink: false,
+            diff: None,
+            pieces: Vec::new(),
+            summary: vec![Spans::from(message)],
+            hunk_spans: Vec::new(),
+            line_count: 1,
+            old_linenos: Vec::new(),
+            starts: Vec::new(),
+            first_changes: Vec::new(),
+            hunk: 0,
+            selection: None,
+            scroll: Scroll::default(),
+            stale: false,
+            capped: false,
+            forced: false,
+            filter: None,
+            filter_error: None,
+        }
+    }
+
+    // Takes over the selected hunk and the scroll offset of an older diff of the same file. A piece
+    // of a split hunk is looked for among the pieces of the same hunk split again, so that after
+    // staging one piece the next is selected.
+    pub fn keep_position(&mut self, previous: DiffView, theme: &Theme) {
+        self.forced = previous.forced && !self.capped;
+        self.scroll = previous.scroll;
+        let split = match previous.pieces.get(previous.hunk) {
+            Some(&(original, Some(_))) => original,
+            _ => {
+                self.hunk = previous.hunk.min(self.starts.len().saturating_sub(1));
+                return;
+            }
+        };
+        let first = previous.pieces.iter().position(|&(o, _)| o == split);
+        let nth = previous.hunk - first.unwrap_or(previous.hunk);
+        let original = split.min(self.starts.len().saturating_sub(1));
+        self.hunk = original;
+        if self.split_hunk(theme) {
+            let count = self.pieces.iter().filter(|&&(o, _)| o == original).count();
+            self.hunk += nth.min(count - 1);
+        }
+    }
+
+    // Splits the selected hunk into a hunk for each run of changes in it, like `git add -p` does,
+    // and selects the first of them. Returns false if there's just one run to begin with.
+    pub fn split_hunk(&mut self, theme: &Theme) -> bool {
+        let diff = match &mut self.diff {
+            Some(diff) => diff,
+            None => return false,
+        };
+        let pieces = match diff.hunks.get(self.hunk) {
+            Some(hunk) => diff::split_hunk(hunk),
+            None => return false,
+        };
+        if pieces.len() < 2 {
+            return false;
+        }
+        let at = self.hunk;
+        let (original, range) = self.pieces[at].clone();
+        let offset = range.map_or(0, |r| r.start);
+        diff.hunks
+            .splice(at..at + 1, pieces.iter().map(|(_, hunk)| hunk.clone()));
+        self.pieces.splice(
+            at..at + 1,
+            pieces
+                .into_iter()
+                .map(|(r, _)| (original, Some(offset + r.start..offset + r.end))),
+        );
+        self.selection = None;
+        self.lay_out(theme);
+        true
+    }
+
+    // What staging the selected hunk (or the lines selected in it) applies, in terms of the
+    // computed diff: the index of a hunk, and the range of its lines unless it's the whole hunk.
+    pub fn target(&self) -> (usize, Option<Range<usize>>) {
+        let (hunk, piece) = self
+            .pieces
+            .get(self.hunk)
+            .cloned()
+            .unwrap_or((self.hunk, None));
+        let lines = match (self.selected_range(), piece) {
+            (Some(selected), Some(piece)) => {
+                Some(piece.start + selected.start..piece.start + selected.end)
+            }
+            (selected, piece) => selected.or(piece),
+        };
+        (hunk, lines)
+    }
+
+    // The selected hunk as a patch of its own, for the file that has the status `status`.
+    pub fn hunk_patch(&self, status: Status) -> Option<String> {
+        let diff = self.diff.as_ref().filter(|_| !self.capped)?;
+        match self.starts.is_empty() {
+            true => None,
+            false => Some(diff::render_patch(diff, status, &[self.hunk])),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_count
+    }
+
+    // Styles the lines in `range` that aren't yet.
+    fn style(&mut self, range: Range<usize>, theme: &Theme) {
+        let diff = match &self.diff {
+            Some(diff) if self.summary.is_empty() => diff,
+            _ => return,
+        };
+        let first = self.first_hunk_in(&range);
+        for (h, spans) in self.hunk_spans.iter_mut().enumerate().skip(first) {
+            let start = self.starts[h];
+            if start >= range.end {
+                break;
+            }
+            let lines = range.start.saturating_sub(start)..range.end - start;
+            spans.style(&diff.hunks[h], lines, theme);
+        }
+    }
+
+    // The hunk `range` starts in.
+    fn first_hunk_in(&self, range: &Range<usize>) -> usize {
+        let after = self.starts.partition_point(|&s| s <= range.start);
+        after.saturating_sub(1)
+    }
+
+    // The lines in `range` that there are and have been styled, with their indices.
+    fn styled_lines(&self, range: Range<usize>) -> Vec<(usize, &Spans<'static>)> {
+        if !self.summary.is_empty() {
+            let end = range.end.min(self.summary.len());
+            return (range.start.min(end)..end)
+                .map(|i| (i, &self.summary[i]))
+                .collect();
+        }
+        let first = self.first_hunk_in(&range);
+        let mut lines = Vec::new();
+        for (spans, &start) in self.hunk_spans.iter().zip(&self.starts).skip(first) {
+            if start >= range.end {
+                break;
+            }
+            let from = range.start.saturating_sub(start);
+            lines.extend(
+                (from..range.end - start).filter_map(|i| spans.line(i).map(|l| (start + i, l))),
+            );
+        }
+        lines
+    }
+
+    pub fn hunk_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    // Selects the hunk at `index`, or the last one if there are fewer.
+    pub fn select_hunk(&mut self, index: usize, config: &ScrollConfig) {
+        self.hunk = index.min(self.starts.len().saturating_sub(1));
+        self.scroll_to_hunk(config);
+    }
+
+    // The hunk that `line` is in, and whether it's the hunk's header.
+    pub fn hunk_at(&self, line: usize) -> Option<(usize, bool)> {
+        if line >= self.line_count {
+            return None;
+        }
+        let hunk = self.starts.iter().rposition(|&start| start <= line)?;
+        Some((hunk, self.starts[hunk] == line))
+    }
+
+    // Selects the hunk at `index` where it's shown, as when it's clicked, rather than scrolling to
+    // it.
+    pub fn pick_hunk(&mut self, index: usize) {
+        self.hunk = index.min(self.starts.len().saturating_sub(1));
+        self.selection = None;
+    }
+
+    pub fn next_hunk(&mut self, config: &ScrollConfig) {
+        if self.hunk + 1 < self.starts.len() {
+            self.hunk += 1;
+        }
+        self.scroll_to_hunk(config);
+    }
+
+    pub fn previous_hunk(&mut self, config: &ScrollConfig) {
+        self.hunk = self.hunk.saturating_sub(1);
+        self.scroll_to_hunk(config);
+    }
+
+    fn scroll_to_hunk(&mut self, config: &ScrollConfig) {
+        self.selection = None;
+        if let Some(&start) = self.starts.get(self.hunk) {
+            let max = last_line(self.line_count);
+            self.scroll.to(config, (start as u16).min(max));
+        }
+    }
+
+    // Starts selecting lines of the selected hunk at its first change, or stops.
+    pub fn toggle_selection(&mut self) {
+        self.selection = match (self.selection, self.first_changes.get(self.hunk)) {
+            (None, Some(&first)) => Some((first, first)),
+            _ => None,
+        };
+    }
+
+    pub fn is_selecting(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    // Moves the end of the selection by a line, within the selected hunk.
+    pub fn move_cursor(&mut self, down: bool) {
+        let len = self.hunk_lines().len().saturating_sub(1);
+        if let Some((_, cursor)) = &mut self.selection {
+            *cursor = if down {
+                (*cursor + 1).min(len.saturating_sub(1))
+            } else {
+                cursor.saturating_sub(1)
+            };
+        }
+    }
+
+    // The selected lines, as indices into the selected hunk's lines.
+    pub fn selected_range(&self) -> Option<Range<usize>> {
+        self.selection
+            .map(|(anchor, cursor)| anchor.min(cursor)..anchor.max(cursor) + 1)
+    }
+
+    // The lines of the selected hunk, header included.
+    fn hunk_lines(&self) -> std::ops::Range<usize> {
+        match self.starts.get(self.hunk) {
+            Some(&start) => {
+                let end = self.starts.get(self.hunk + 1).copied();
+                start..end.unwrap_or(self.line_count)
+            }
+            None => 0..0,
+        }
+    }
+}
+
+// Renders the diff with a bar in the gutter next to the selected hunk, the lines being selected
+// highlighted, and a highlighted border while it has the focus. With `blame`, the lines of the old
+// side are annotated with the commit that last changed them. The title says how the diff was
+// computed, where that's not the usual way.
+pub fn render_diff<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    view: &mut DiffView,
+    focused: bool,
+    settings: DiffSettings,
+    blame: Option<&FileBlame>,
+    theme: &Theme,
+) {
+    let selected = view.hunk_lines();
+    // The selected lines, counted from the top like `selected`.
+    let marked = view
+        .selected_range()
+        .map(|r| selected.start + 1 + r.start..selected.start + 1 + r.end)
+        .unwrap_or(0..0);
+    let gutter = !view.starts.is_empty();
+    // Only the lines on screen are rendered, and a screenful below them styled ahead of time.
+    let offset = view.scroll.offset as usize;
+    let height = area.height.saturating_sub(2) as usize;
+    view.style(offset..offset + 2 * height, theme);
+    let view = &*view;
+    let lines: Vec<Spans> = view
+        .styled_lines(offset..offset + height)
+        .into_iter()
+        .map(|(i, line)| {
+            if !gutter {
+                return line.clone();
+            }
+            let bar = if selected.contains(&i) {
+                Span::styled("▌", Style::default().fg(theme.focused_border))
+            } else {
+                Span::raw(" ")
+            };
+            let mut spans = vec![bar];
+            if let Some(blame) = blame {
+                let label = match view.old_linenos.get(i).copied().flatten() {
+                    Some(lineno) => match blame.line(lineno) {
+                        Some(line) => line.label(),
+                        None => format!("{:<1$}", "not committed yet", blame::WIDTH),
+                    },
+                    None => " ".repeat(blame::WIDTH),
+                };
+                spans.push(Span::styled(label, Style::default().fg(Color::DarkGray)));
+            }
+            if marked.contains(&i) {
+                let highlight = Style::default().bg(theme.selection);
+                spans.extend(
+                    line.0
+                        .iter()
+                        .map(|s| Span::styled(s.content.clone(), s.style.patch(highlight))),
+                );
+            } else {
+                spans.extend(line.0.iter().cloned());
+            }
+            Spans::from(spans)
+        })
+        .collect();
+
+    let mut notes = Vec::new();
+    // The mode change and what a symlink points to go in the title when there are hunks to show,
+    // and in their place when not.
+    if let (Some(diff), true) = (&view.diff, gutter) {
+        notes.extend(preview::mode_line(diff));
+        notes.extend(
+            preview::symlink_lines(diff)
+                .into_iter()
+                .map(|(kind, line)| match kind {
+                    LineKind::Removed => format!("-{}", line),
+                    _ => format!("+{}", line),
+                }),
+        );
+    }
+    if let n @ 1.. = view.starts.len() {
+        notes.push(format!("hunk {}/{}", view.hunk + 1, n));
+    }
+    if blame.is_some() && gutter {
+        notes.push(String::from("blame"));
+    }
+    if settings.context != DiffSettings::default().context {
+        notes.push(format!("context {}", settings.context));
+    }
+    match settings.whitespace {
+        Whitespace::Show => {}
+        Whitespace::IgnoreChange => notes.push(String::from("ignoring space changes")),
+        Whitespace::IgnoreAll => notes.push(String::from("ignoring all space")),
+    }
+    let title = match notes.is_empty() {
+        true => String::from(" Diff "),
+        false => format!(" Diff ({}) ", notes.join(", ")),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.border(focused))
+        .title(title);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+
+    // Without colors, the cursor is left at the selected hunk, or at the selected lines in it, for
+    // screen readers to read out.
+    let at = if marked.is_empty() {
+        selected.start
+    } else {
+        marked.start
+    };
+    let row = at.checked_sub(offset).filter(|&r| r < height);
+    if let (true, true, Some(row)) = (theme.no_color, focused && gutter, row) {
+        f.set_cursor(area.x + 1, area.y + 1 + row as u16);
+    }
+}
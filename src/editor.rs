@@ -0,0 +1,163 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::popup::centered_rect;
+
+pub enum EditorEvent {
+    Submit(String),
+    Cancel,
+    Pending,
+}
+
+// A multi-line text editor shown as a popup, for writing messages such as those of tags.
+pub struct Editor {
+    pub title: String,
+    lines: Vec<String>,
+    // The cursor, as a line index and a char (not byte) index into that line.
+    row: usize,
+    col: usize,
+    // How long the first line may get before the status line warns, for commit messages.
+    subject_length: Option<usize>,
+}
+
+impl Editor {
+    pub fn new(title: impl Into<String>, text: &str) -> Editor {
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Editor {
+            title: title.into(),
+            lines,
+            row: 0,
+            col: 0,
+            subject_length: None,
+        }
+    }
+
+    // Shows the length of the first line in the status line, highlighted once it's longer than
+    // `limit`. 0 shows nothing.
+    pub fn with_subject_length(mut self, limit: usize) -> Editor {
+        self.subject_length = Some(limit).filter(|&l| l > 0);
+        self
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> EditorEvent {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            if key.code == KeyCode::Char('s') {
+                return EditorEvent::Submit(self.text());
+            }
+            return EditorEvent::Pending;
+        }
+
+        match key.code {
+            KeyCode::Esc => return EditorEvent::Cancel,
+            KeyCode::Enter => {
+                let rest = self.split_off_at_cursor();
+                self.row += 1;
+                self.lines.insert(self.row, rest);
+                self.col = 0;
+            }
+            KeyCode::Backspace => {
+                if self.col > 0 {
+                    self.col -= 1;
+                    let at = self.byte_index(self.col);
+                    self.lines[self.row].remove(at);
+                } else if self.row > 0 {
+                    let line = self.lines.remove(self.row);
+                    self.row -= 1;
+                    self.col = self.line_len();
+                    self.lines[self.row].push_str(&line);
+                }
+            }
+            KeyCode::Char(c) => {
+                let at = self.byte_index(self.col);
+                self.lines[self.row].insert(at, c);
+                self.col += 1;
+            }
+            KeyCode::Left => self.col = self.col.saturating_sub(1),
+            KeyCode::Right => self.col = (self.col + 1).min(self.line_len()),
+            KeyCode::Up => {
+                self.row = self.row.saturating_sub(1);
+                self.col = self.col.min(self.line_len());
+            }
+            KeyCode::Down => {
+                self.row = (self.row + 1).min(self.lines.len() - 1);
+                self.col = self.col.min(self.line_len());
+            }
+            KeyCode::Home => self.col = 0,
+            KeyCode::End => self.col = self.line_len(),
+            _ => {}
+        }
+        EditorEvent::Pending
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    fn byte_index(&self, col: usize) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices()
+            .nth(col)
+            .map(|(i, _)| i)
+            .unwrap_or(line.len())
+    }
+
+    fn split_off_at_cursor(&mut self) -> String {
+        let at = self.byte_index(self.col);
+        self.lines[self.row].split_off(at)
+    }
+}
+
+pub fn render_editor<B: Backend>(f: &mut Frame<B>, editor: &Editor) {
+    let area = centered_rect(80, f.size().height.saturating_sub(4), f.size());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" {} ", editor.title));
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(inner);
+
+    // Keep the cursor line in view.
+    let scroll = (editor.row as u16).saturating_sub(chunks[0].height.saturating_sub(1));
+    f.render_widget(Paragraph::new(editor.text()).scroll((scroll, 0)), chunks[0]);
+    let mut status = vec![Span::styled(
+        "[ctrl-s] save   [esc] cancel",
+        Style::default().fg(Color::Gray),
+    )];
+    if let Some(limit) = editor.subject_length {
+        let length = editor.lines[0].chars().count();
+        let style = match length > limit {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default().fg(Color::Gray),
+        };
+        status.push(Span::styled(
+            format!("   subject {}/{}", length, limit),
+            style,
+        ));
+    }
+    f.render_widget(Paragraph::new(Spans::from(status)), chunks[1]);
+
+    let col = editor.col as u16;
+    f.set_cursor(
+        chunks[0].x + col.min(chunks[0].width.saturating_sub(1)),
+        chunks[0].y + editor.row as u16 - scroll,
+    );
+}
@@ -0,0 +1,113 @@
+use git2::DiffFile;
+
+use git_istage_rs::{
+    diff::{self, DiffSettings},
+    git::{FileStatusKind, Location},
+    status::{Status, StatusEntry},
+};
+
+use crate::stats::format_size;
+
+// What an entry's one-letter status leaves out, as lines of text: its paths, how its mode and size
+// change, whether it's binary, and the status flags libgit2 gives its path, which tell apart
+// changes that look alike in the list, such as a type change and one of the mode alone.
+pub fn describe(
+    location: &Location,
+    entry: &StatusEntry,
+    kind: FileStatusKind,
+    settings: DiffSettings,
+) -> anyhow::Result<Vec<String>> {
+    let repo = location.open()?;
+    let side = match kind {
+        FileStatusKind::Staged => "staged",
+        FileStatusKind::Unstaged => "unstaged",
+    };
+    let mut lines = vec![format!("Path      {}", entry.new_file.quoted())];
+    if entry.old_file != entry.new_file {
+        lines.push(format!("Old path  {}", entry.old_file.quoted()));
+    }
+    lines.push(format!("Status    {}, {}", char::from(entry.status), side));
+
+    let diff = diff::entry_diff(&repo, entry, kind, false, settings)?;
+    // A type change comes as two deltas, the old file deleted and the new one added.
+    if let (Some(first), Some(last)) = (diff.deltas().next(), diff.deltas().next_back()) {
+        let (old, new) = (first.old_file(), last.new_file());
+        let mut line = format!("Mode      {}", mode(&old));
+        if old.mode() != new.mode() {
+            line.push_str(&format!(" -> {}", mode(&new)));
+        }
+        if let Some(change) = executable_change(&old, &new) {
+            line.push_str(&format!(", executable bit {}", change));
+        }
+        lines.push(line);
+        if entry.status == Status::Typechange {
+            lines.push(String::from("          (a type change)"));
+        }
+    }
+    let files = diff::file_diffs(&diff)?;
+    let file = match entry.status {
+        Status::Typechange => diff::join_typechange(files),
+        _ => files.into_iter().next(),
+    };
+    if let Some(file) = file {
+        let mut line = format!(
+            "Size      {} -> {}",
+            format_size(file.old_size),
+            format_size(file.new_size)
+        );
+        let growth = file.new_size as i64 - file.old_size as i64;
+        if growth != 0 {
+            let sign = if growth < 0 { "-" } else { "+" };
+            line.push_str(&format!(
+                " ({}{})",
+                sign,
+                format_size(growth.unsigned_abs())
+            ));
+        }
+        lines.push(line);
+        lines.push(format!(
+            "Binary    {}",
+            if file.binary { "yes" } else { "no" }
+        ));
+    }
+
+    let flags = match repo.status_file(&entry.new_path()) {
+        Ok(status) if status.is_empty() => String::from("CURRENT"),
+        Ok(status) => status
+            .iter_names()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>()
+            .join(" | "),
+        Err(e) => format!("unknown ({})", e.message()),
+    };
+    lines.push(format!("libgit2   {}", flags));
+    Ok(lines)
+}
+
+// E.g. "100755", or "120000 (symlink)" for what isn't a regular file, or "none" for a side the file
+// isn't on.
+fn mode(file: &DiffFile) -> String {
+    let mode = u32::from(file.mode());
+    let kind = match mode & 0o170000 {
+        0 => return String::from("none"),
+        0o040000 => " (directory)",
+        0o120000 => " (symlink)",
+        0o160000 => " (submodule)",
+        _ => "",
+    };
+    format!("{:06o}{}", mode, kind)
+}
+
+// Whether a regular file on both sides became executable ("set") or stopped being ("cleared").
+fn executable_change(old: &DiffFile, new: &DiffFile) -> Option<&'static str> {
+    let (old, new) = (u32::from(old.mode()), u32::from(new.mode()));
+    let regular = |mode: u32| mode & 0o170000 == 0o100000;
+    if !regular(old) || !regular(new) {
+        return None;
+    }
+    match (old & 0o111 != 0, new & 0o111 != 0) {
+        (false, true) => Some("set"),
+        (true, false) => Some("cleared"),
+        _ => None,
+    }
+}
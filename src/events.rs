@@ -0,0 +1,100 @@
+use std::{collections::VecDeque, thread, time::Duration};
+
+use crossterm::event::{
+    self, Event, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+
+use crate::keymap::Key;
+
+// Where `run_app` reads its input from: the terminal, or a script of keys in the UI tests.
+pub trait EventSource {
+    // The next event, waiting up to `timeout` for one.
+    fn next(&mut self, timeout: Duration) -> anyhow::Result<Option<Event>>;
+
+    // Whether there are no events left, which quits the app.
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    // Whether to hold the next event back until what's loading in the background has loaded, so
+    // that it lands on the same frame from run to run.
+    fn waits_for_loading(&self) -> bool {
+        false
+    }
+}
+
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn next(&mut self, timeout: Duration) -> anyhow::Result<Option<Event>> {
+        match event::poll(timeout)? {
+            true => Ok(Some(event::read()?)),
+            false => Ok(None),
+        }
+    }
+}
+
+// No input at all, for `--exec` running unattended, which quits once its commands have run.
+pub struct Unattended;
+
+impl EventSource for Unattended {
+    fn next(&mut self, timeout: Duration) -> anyhow::Result<Option<Event>> {
+        thread::sleep(timeout);
+        Ok(None)
+    }
+}
+
+// Keys typed one per frame, without waiting, and mouse events in between.
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct ScriptedEvents {
+    events: VecDeque<Event>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl ScriptedEvents {
+    // Parses keys written as in the config and separated by spaces, e.g. "down s ctrl-s", and
+    // mouse events written as what's done and where, e.g. "click@3,1" or "scroll-down@50,4".
+    pub fn new(script: &str) -> anyhow::Result<ScriptedEvents> {
+        let events = script
+            .split_whitespace()
+            .map(|e| match e.split_once('@') {
+                Some((kind, at)) => mouse_event(kind, at),
+                None => Ok(Event::Key(KeyEvent::from(e.parse::<Key>()?))),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(ScriptedEvents { events })
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn mouse_event(kind: &str, at: &str) -> anyhow::Result<Event> {
+    let kind = match kind {
+        "click" => MouseEventKind::Down(MouseButton::Left),
+        "scroll-down" => MouseEventKind::ScrollDown,
+        "scroll-up" => MouseEventKind::ScrollUp,
+        _ => anyhow::bail!("unknown mouse event {:?}", kind),
+    };
+    let (column, row) = at
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("{:?} isn't a column and a row", at))?;
+    Ok(Event::Mouse(MouseEvent {
+        kind,
+        column: column.parse()?,
+        row: row.parse()?,
+        modifiers: KeyModifiers::NONE,
+    }))
+}
+
+impl EventSource for ScriptedEvents {
+    fn next(&mut self, _timeout: Duration) -> anyhow::Result<Option<Event>> {
+        Ok(self.events.pop_front())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    fn waits_for_loading(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+
+// What `--exec` does next.
+pub enum Step {
+    Run(String),
+    // Answers yes to the confirmation waiting for one.
+    Confirm,
+    Quit,
+}
+
+// What `App::waiting_for` says of a confirmation, which `--yes` can answer.
+pub const CONFIRMATION: &str = "a confirmation";
+
+// The commands given with `--exec`, run on starting one after the other as if typed after `:`,
+// each once what the one before started is done. When the last of them is `quit`, they run
+// unattended, without the app ever showing: then a command failing, or leaving something open
+// that waits for an answer, fails the whole run rather than being left on screen. Confirmations
+// of what isn't destructive are answered meanwhile, and with `yes` (`--yes`) all of them are.
+#[derive(Default)]
+pub struct Exec {
+    commands: VecDeque<String>,
+    pub unattended: bool,
+    pub yes: bool,
+    // The command run last, which a confirmation answered for it fails as.
+    last: String,
+    failed: Option<anyhow::Error>,
+}
+
+impl Exec {
+    // Commands separated by `;`, e.g. `stage-all; commit --message "wip"`. A `;` in quotes is
+    // part of the command.
+    pub fn parse(script: &str) -> anyhow::Result<Exec> {
+        let mut commands = VecDeque::new();
+        let mut command = String::new();
+        let mut quote = None;
+        let mut escaped = false;
+        for c in script.chars() {
+            match (c, quote) {
+                (';', None) => {
+                    commands.push_back(std::mem::take(&mut command));
+                    continue;
+                }
+                ('"' | '\'', None) => quote = Some(c),
+                (c, Some(q)) if c == q && !escaped => quote = None,
+                _ => {}
+            }
+            // As `words` reads them, a `\"` in double quotes doesn't end them.
+            escaped = c == '\\' && quote == Some('"') && !escaped;
+            command.push(c);
+        }
+        if quote.is_some() {
+            anyhow::bail!("unterminated quote in --exec {:?}", script);
+        }
+        commands.push_back(command);
+        let mut commands: VecDeque<String> = commands
+            .into_iter()
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+        let unattended = commands.back().is_some_and(|c| c == "quit");
+        // Quitting unattended is what running out of commands does.
+        if unattended {
+            commands.pop_back();
+        }
+        Ok(Exec {
+            commands,
+            unattended,
+            yes: false,
+            last: String::new(),
+            failed: None,
+        })
+    }
+
+    // Whether a confirmation, of something destructive or not, is answered rather than waited
+    // for.
+    pub fn answers(&self, destructive: bool) -> bool {
+        self.unattended && (self.yes || !destructive)
+    }
+
+    // What to do next, with nothing in progress and `waiting` open for an answer if anything is.
+    // Attended, the commands wait for it to be given.
+    pub fn step(&mut self, waiting: Option<&str>) -> Option<Step> {
+        if !self.unattended {
+            return match waiting {
+                Some(_) => None,
+                None => self.commands.pop_front().map(Step::Run),
+            };
+        }
+        if let (Some(what), None) = (waiting, &self.failed) {
+            self.commands.clear();
+            let hint = match what {
+                CONFIRMATION => " (--yes answers it)",
+                _ => "",
+            };
+            self.failed = Some(anyhow::anyhow!(
+                "--exec left {} waiting for an answer, which it can't get when ending in `quit`{}",
+                what,
+                hint
+            ));
+        }
+        Some(match self.commands.pop_front() {
+            Some(command) => {
+                self.last.clone_from(&command);
+                Step::Run(command)
+            }
+            None => Step::Quit,
+        })
+    }
+
+    // The command run last, e.g. the one a confirmation was answered for.
+    pub fn last(&self) -> &str {
+        &self.last
+    }
+
+    // Drops the commands left after `command` failed with `e`. Returns the error to show, or
+    // None when unattended, where it's what the run ends with.
+    pub fn abort(&mut self, command: &str, e: anyhow::Error) -> Option<anyhow::Error> {
+        self.commands.clear();
+        let e = anyhow::anyhow!("--exec `{}`: {}", command, e);
+        match self.unattended {
+            true => {
+                self.failed = Some(e);
+                None
+            }
+            false => Some(e),
+        }
+    }
+
+    // How the run went, once the app quits.
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        match self.failed.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+// The words of a command's arguments, split on whitespace outside of quotes, e.g. `--message
+// "Fix the parser"`. In double quotes, `\"` and `\\` stand for `"` and `\`.
+pub fn words(args: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            (c, None) if c.is_whitespace() => words.extend(word.take()),
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            ('\\', Some('"')) => match chars.next() {
+                Some(c @ ('"' | '\\')) => word.get_or_insert_with(String::new).push(c),
+                Some(c) => word.get_or_insert_with(String::new).extend(['\\', c]),
+                None => word.get_or_insert_with(String::new).push('\\'),
+            },
+            (c, _) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        anyhow::bail!("unterminated quote in {:?}", args);
+    }
+    words.extend(word);
+    Ok(words)
+}
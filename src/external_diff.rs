@@ -0,0 +1,204 @@
+use std::{env, ffi::OsString, fs, io, path::PathBuf, time::Duration};
+
+use crossterm::event::{Event, KeyCode};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Terminal,
+};
+
+use git_istage_rs::{
+    config::Config,
+    diff::{self, DiffSettings},
+    git::Location,
+    paths::RepoPath,
+};
+
+use crate::{
+    diff_view::{self, DiffView},
+    events::EventSource,
+    keymap::{Action, Keymap},
+    last_line,
+    theme::{Theme, Uncolored},
+    FRAME_TIME,
+};
+
+// One side of the diff, as git hands it over.
+struct Side {
+    file: PathBuf,
+    // The blob's id and the file's mode (0 when it doesn't exist on this side), which only git's
+    // external diff arguments give.
+    id: Option<String>,
+    mode: Option<u32>,
+}
+
+impl Side {
+    // The content, none if the file doesn't exist on this side, which git passes as /dev/null.
+    fn read(&self) -> anyhow::Result<Vec<u8>> {
+        match fs::read(&self.file) {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => anyhow::bail!("reading {}: {}", self.file.display(), e),
+        }
+    }
+}
+
+// The diff between two files git runs us with, e.g. as `git difftool --extcmd` or in
+// GIT_EXTERNAL_DIFF, shown on its own and read-only: they're temporary copies, with nothing in
+// them to stage.
+pub struct ExternalDiff {
+    path: String,
+    old: Side,
+    new: Side,
+}
+
+impl ExternalDiff {
+    pub fn parse(args: &[OsString]) -> anyhow::Result<ExternalDiff> {
+        let text = |arg: &OsString| arg.to_string_lossy().into_owned();
+        let file = |file: &OsString| Side {
+            file: PathBuf::from(file),
+            id: None,
+            mode: None,
+        };
+        match args {
+            [old, new] => Ok(ExternalDiff {
+                // difftool names the file the two are versions of in BASE.
+                path: env::var("BASE").unwrap_or_else(|_| text(new)),
+                old: file(old),
+                new: file(new),
+            }),
+            [path, old, old_id, old_mode, new, new_id, new_mode, renamed @ ..]
+                if renamed.len() != 1 =>
+            {
+                let side = |file: &OsString, id: &OsString, mode: &OsString| Side {
+                    file: PathBuf::from(file),
+                    id: Some(text(id)).filter(|id| id != "."),
+                    mode: u32::from_str_radix(&text(mode), 8).ok().or(Some(0)),
+                };
+                Ok(ExternalDiff {
+                    // A rename comes with the new path and a description of it.
+                    path: match renamed.first() {
+                        Some(to) => format!("{} → {}", text(path), text(to)),
+                        None => text(path),
+                    },
+                    old: side(old, old_id, old_mode),
+                    new: side(new, new_id, new_mode),
+                })
+            }
+            _ => anyhow::bail!(
+                "expected two files, or the seven arguments git passes to GIT_EXTERNAL_DIFF"
+            ),
+        }
+    }
+
+    pub fn view(
+        &self,
+        settings: DiffSettings,
+        filter: Option<&str>,
+        theme: &Theme,
+    ) -> anyhow::Result<DiffView> {
+        let modes = self.old.mode.zip(self.new.mode);
+        let (old, new) = (self.old.read()?, self.new.read()?);
+        let path = RepoPath::from(self.path.as_str());
+        let file = diff::buffers_diff(&path, &old, &new, modes, settings)?;
+        let mut view = DiffView::new(false, path, file, filter, theme);
+        match view.filter_error.take() {
+            Some(e) => Err(e),
+            None => Ok(view),
+        }
+    }
+
+    // The path, and the blobs the diff goes between if git said.
+    fn header(&self) -> Spans<'static> {
+        let mut spans = vec![Span::styled(
+            self.path.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        if let (Some(old), Some(new)) = (&self.old.id, &self.new.id) {
+            let short = |id: &str| id.chars().take(7).collect::<String>();
+            spans.push(Span::styled(
+                format!("  {}..{}", short(old), short(new)),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        Spans::from(spans)
+    }
+}
+
+// Shows the diff until it's quit, or `events` runs dry. `location` is the repository it's run
+// from, if any, whose git config may give colors.
+pub fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    location: Option<&Location>,
+    external: &ExternalDiff,
+    config: &Config,
+    events: &mut impl EventSource,
+) -> anyhow::Result<()> {
+    let keymap = Keymap::new(&config.keys, &config.commands)?;
+    let theme = Theme::new(location, &config.theme)?;
+    let settings = DiffSettings::from(config);
+    let mut view = external.view(settings, config.preview.diff_filter(), &theme)?;
+    let hints = hints(&keymap);
+    loop {
+        let animating = view.scroll.tick();
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(1),
+                    Constraint::Min(0),
+                    Constraint::Length(1),
+                ])
+                .split(f.size());
+            f.render_widget(Paragraph::new(external.header()), chunks[0]);
+            diff_view::render_diff(f, chunks[1], &mut view, true, settings, None, &theme);
+            let hints = Span::styled(hints.as_str(), Style::default().fg(Color::DarkGray));
+            f.render_widget(Paragraph::new(Spans::from(hints)), chunks[2]);
+            if theme.no_color {
+                f.render_widget(Uncolored, f.size());
+            }
+        })?;
+
+        if events.is_closed() {
+            return Ok(());
+        }
+        let timeout = match animating {
+            true => FRAME_TIME,
+            false => Duration::from_millis(250),
+        };
+        let key = match events.next(timeout)? {
+            Some(Event::Key(key)) => key,
+            _ => continue,
+        };
+        if key.code == KeyCode::Esc {
+            return Ok(());
+        }
+        let scroll = &config.scroll;
+        match keymap.action(key) {
+            Some(Action::Quit) => return Ok(()),
+            Some(Action::Down | Action::NextHunk) => view.next_hunk(scroll),
+            Some(Action::Up | Action::PreviousHunk) => view.previous_hunk(scroll),
+            Some(Action::ScrollDown) => view.scroll.down(scroll, last_line(view.line_count())),
+            Some(Action::ScrollUp) => view.scroll.up(scroll),
+            _ => {}
+        }
+    }
+}
+
+fn hints(keymap: &Keymap) -> String {
+    let mut hints = vec![String::from("read-only")];
+    hints.extend(
+        [
+            (Action::NextHunk, "next hunk"),
+            (Action::PreviousHunk, "previous hunk"),
+            (Action::ScrollDown, "scroll"),
+            (Action::Quit, "quit"),
+        ]
+        .iter()
+        .filter_map(|&(a, name)| keymap.keys(a).first().map(|k| format!("{} {}", k, name))),
+    );
+    hints.join("  ")
+}
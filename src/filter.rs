@@ -0,0 +1,32 @@
+// Matches paths against the filter typed after `/`: as a substring if it appears as one, and
+// otherwise fuzzily, with its characters appearing in order. Case is ignored unless the filter
+// has uppercase letters.
+//
+// Returns the byte offsets of the matched characters in `path`, for highlighting.
+pub fn matches(filter: &str, path: &str) -> Option<Vec<usize>> {
+    let ignore_case = !filter.chars().any(char::is_uppercase);
+    let same = |a: char, b: char| match ignore_case {
+        true => a.to_lowercase().eq(b.to_lowercase()),
+        false => a == b,
+    };
+    let filter: Vec<char> = filter.chars().collect();
+    let path: Vec<(usize, char)> = path.char_indices().collect();
+    if filter.len() > path.len() {
+        return None;
+    }
+
+    for start in 0..=path.len() - filter.len() {
+        let window = &path[start..start + filter.len()];
+        if window.iter().zip(&filter).all(|(&(_, p), &f)| same(p, f)) {
+            return Some(window.iter().map(|&(i, _)| i).collect());
+        }
+    }
+
+    let mut offsets = Vec::with_capacity(filter.len());
+    let mut rest = path.iter();
+    for &f in &filter {
+        let &(i, _) = rest.find(|&&(_, p)| same(p, f))?;
+        offsets.push(i);
+    }
+    Some(offsets)
+}
@@ -0,0 +1,91 @@
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    Frame,
+};
+
+use crate::{history::LogCommit, popup, theme::Theme};
+
+// Only this many of the commits are listed at once; the list scrolls to the selected one.
+const SHOWN: usize = 10;
+
+pub enum FixupEvent {
+    // Commit the staged changes to be folded into this commit, keeping their own message as well
+    // if `squash`.
+    Pick { target: LogCommit, squash: bool },
+    Cancel,
+    Pending,
+}
+
+// The popup that picks the commit the staged changes fix up, among the recent ones, and whether
+// they're a `fixup!` or a `squash!` of it.
+pub struct FixupPicker {
+    commits: Vec<LogCommit>,
+    selected: usize,
+    squash: bool,
+}
+
+impl FixupPicker {
+    pub fn new(commits: Vec<LogCommit>) -> FixupPicker {
+        FixupPicker {
+            commits,
+            selected: 0,
+            squash: false,
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> FixupEvent {
+        let count = self.commits.len().max(1);
+        match code {
+            KeyCode::Down => self.selected = (self.selected + 1) % count,
+            KeyCode::Up => self.selected = self.selected.checked_sub(1).unwrap_or(count - 1),
+            KeyCode::Tab | KeyCode::BackTab => self.squash = !self.squash,
+            KeyCode::Enter => {
+                return match self.commits.get(self.selected) {
+                    Some(target) => FixupEvent::Pick {
+                        target: target.clone(),
+                        squash: self.squash,
+                    },
+                    None => FixupEvent::Cancel,
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return FixupEvent::Cancel,
+            _ => {}
+        }
+        FixupEvent::Pending
+    }
+}
+
+pub fn render_fixup_picker<B: Backend>(f: &mut Frame<B>, picker: &FixupPicker, theme: &Theme) {
+    let (kind, other) = match picker.squash {
+        true => ("squash!", "fixup!"),
+        false => ("fixup!", "squash!"),
+    };
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            format!("Commit the staged changes as {} of", kind),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::default(),
+    ];
+    let first = picker.selected.saturating_sub(SHOWN - 1);
+    for (i, commit) in picker.commits.iter().enumerate().skip(first).take(SHOWN) {
+        lines.push(match i == picker.selected {
+            true => Spans::from(Span::styled(
+                format!("> {}", commit.short_string()),
+                theme.selected(),
+            )),
+            false => Spans::from(format!("  {}", commit.short_string())),
+        });
+    }
+    if picker.commits.len() > first + SHOWN {
+        lines.push(Spans::from(Span::styled(
+            format!("  ... and {} more", picker.commits.len() - first - SHOWN),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    let footer = format!("[enter] commit   [tab] {}   [esc] cancel", other);
+    popup::render_lines(f, " Fix up ", lines, &footer);
+}
@@ -0,0 +1,69 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use globset::GlobSet;
+
+use git_istage_rs::{console, git::Location, status::StatusEntry};
+
+use crate::{exec, glob};
+
+// Commands run on files right before they're staged, each on the files matching its glob.
+pub struct Formatters {
+    globs: GlobSet,
+    commands: Vec<String>,
+}
+
+impl Formatters {
+    pub fn new(config: &BTreeMap<String, String>) -> anyhow::Result<Formatters> {
+        let globs: Vec<&String> = config.keys().collect();
+        Ok(Formatters {
+            globs: glob::set(&globs)?,
+            commands: config.values().cloned().collect(),
+        })
+    }
+
+    // Formats `entries` in place, each with the command of the first glob it matches. Returns the
+    // entries that are ready to be staged, and a description of each that failed to format and
+    // should be left alone.
+    pub fn run(
+        &self,
+        location: &Location,
+        entries: &[StatusEntry],
+    ) -> (Vec<StatusEntry>, Vec<String>) {
+        let mut ready = Vec::new();
+        let mut failed = Vec::new();
+        for e in entries {
+            let command = self
+                .globs
+                .matches(e.new_path())
+                .first()
+                .map(|&i| &self.commands[i]);
+            // Deletions have nothing to format.
+            let command = match command {
+                Some(c) if fs::metadata(e.abs_path_new()).is_ok() => c,
+                _ => {
+                    ready.push(e.clone());
+                    continue;
+                }
+            };
+            match format(location, command, &e.new_path()) {
+                Ok(()) => ready.push(e.clone()),
+                Err(err) => failed.push(format!("{}: {}", e.new_file, err)),
+            }
+        }
+        (ready, failed)
+    }
+}
+
+// Runs `command` from the repo root with `path` appended as its last argument. Its words are split
+// like `--exec` arguments, so that one can be quoted to hold spaces.
+fn format(location: &Location, command: &str, path: &Path) -> anyhow::Result<()> {
+    let words = exec::words(command)?;
+    let (program, args) = words
+        .split_first()
+        .ok_or(anyhow::anyhow!("empty formatter command"))?;
+    let output = console::output(location.command(program).args(args).arg(path))?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
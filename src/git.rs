@@ -1,47 +1,230 @@
-use std::fs;
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    ffi::OsStr,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use git2::StatusOptions;
+use git2::{
+    Branch, ErrorCode, Index, Oid, Repository, RepositoryOpenFlags, StatusOptions, Worktree,
+};
 
-use crate::status::StatusEntry;
+use crate::{config::ShowUntracked, diff::DiffSettings, paths};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum FileStatusKind {
     Unstaged,
     Staged,
 }
 
-impl From<FileStatusKind> for StatusOptions {
-    fn from(value: FileStatusKind) -> Self {
-        match value {
-            FileStatusKind::Unstaged => {
-                let mut opts = StatusOptions::default();
-                opts.renames_index_to_workdir(true)
-                    .include_untracked(true)
-                    .recurse_untracked_dirs(true);
-                opts
-            }
-            FileStatusKind::Staged => {
-                let mut opts = StatusOptions::default();
-                opts.renames_head_to_index(true);
-                opts
-            }
+// The options for listing the changes of the given kind, finding renames as configured, and with
+// the untracked files among the unstaged ones as configured, and the ignored files if they're
+// being shown. Untracked directories are only walked for all their files when there's no limit on
+// how many are looked for; otherwise they're listed collapsed, as "dir/".
+pub fn status_options(
+    kind: FileStatusKind,
+    settings: DiffSettings,
+    pathspecs: &[String],
+) -> StatusOptions {
+    let renames = settings.renames;
+    let mut opts = StatusOptions::default();
+    opts.rename_threshold(renames.threshold);
+    for pathspec in pathspecs {
+        opts.pathspec(pathspec);
+    }
+    match kind {
+        FileStatusKind::Unstaged => {
+            opts.renames_index_to_workdir(renames.enabled)
+                .include_untracked(settings.untracked != ShowUntracked::No)
+                .recurse_untracked_dirs(
+                    settings.untracked == ShowUntracked::All && settings.max_untracked == 0,
+                )
+                .include_unreadable(true)
+                .include_ignored(settings.ignored)
+                .recurse_ignored_dirs(settings.ignored);
+        }
+        FileStatusKind::Staged => {
+            opts.renames_head_to_index(renames.enabled);
         }
     }
+    opts
 }
 
-pub fn get_file_statuses(kind: FileStatusKind) -> anyhow::Result<Vec<StatusEntry>> {
-    let repo = git2::Repository::discover(".")?;
-    let d = repo.statuses(Some(&mut kind.into()))?;
+// Opens the repository containing `path` as git itself would find it: the one GIT_DIR names if
+// it's set, whatever `path` is, with the working tree GIT_WORK_TREE names if that's set.
+pub fn discover(path: impl AsRef<Path>) -> anyhow::Result<Repository> {
+    let repo = match env::var_os("GIT_DIR") {
+        Some(_) => Repository::open_from_env()?,
+        None => Repository::open_ext(path, RepositoryOpenFlags::FROM_ENV, [] as [&OsStr; 0])?,
+    };
+    Ok(repo)
+}
 
-    let wd = repo.workdir().ok_or(anyhow::anyhow!("what"))?;
-    let abs_path = fs::canonicalize(wd)?.to_string_lossy().to_string();
+/// Where a repository is, found once: its git directory, its working tree and the index to stage
+/// into, which is the repository's own unless a scratch one was set with [`Location::with_index`].
+/// Cheap to clone onto other threads, each of which opens the repository from it as needed, seeing
+/// its refs and index as they are then. Nothing about it depends on the current directory.
+#[derive(Clone, Debug)]
+pub struct Location {
+    git_dir: PathBuf,
+    workdir: PathBuf,
+    index: Option<PathBuf>,
+}
 
-    Ok(d.iter()
-        .filter_map(|st| match kind {
-            FileStatusKind::Unstaged => st.index_to_workdir(),
-            FileStatusKind::Staged => st.head_to_index(),
+impl Location {
+    /// Finds the repository containing `path` as [`discover`] does, with the index GIT_INDEX_FILE
+    /// names if that's set.
+    pub fn discover(path: impl AsRef<Path>) -> anyhow::Result<Location> {
+        let repo = discover(path)?;
+        let index = env::var_os("GIT_INDEX_FILE")
+            .map(std::path::absolute)
+            .transpose()?;
+        Ok(Location {
+            git_dir: repo.path().to_path_buf(),
+            workdir: repo_workdir(&repo)?,
+            index,
         })
-        .map(|st| (abs_path.clone(), st))
-        .map(StatusEntry::from)
-        .collect())
+    }
+
+    /// The same repository, staged into the index at `index` instead.
+    pub fn with_index(&self, index: PathBuf) -> Location {
+        Location {
+            index: Some(index),
+            ..self.clone()
+        }
+    }
+
+    /// Opens the repository afresh, with its working tree and index as found.
+    pub fn open(&self) -> anyhow::Result<Repository> {
+        let repo = Repository::open(&self.git_dir)?;
+        repo.set_workdir(&self.workdir, false)?;
+        if let Some(index) = &self.index {
+            repo.set_index(&mut Index::open(index)?)?;
+        }
+        Ok(repo)
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// The root of the working tree, canonicalized.
+    pub fn workdir(&self) -> &Path {
+        &self.workdir
+    }
+
+    /// The index file staged into, when it isn't the repository's own.
+    pub fn index(&self) -> Option<&Path> {
+        self.index.as_deref()
+    }
+
+    /// `program` run from the root of the working tree, with GIT_INDEX_FILE set to the index
+    /// staged into when it isn't the repository's own, so that a `git` it runs sees that one too.
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command.current_dir(&self.workdir);
+        if let Some(index) = &self.index {
+            command.env("GIT_INDEX_FILE", index);
+        }
+        command
+    }
+
+    /// `git` run as [`Location::command`] runs programs.
+    pub fn git(&self) -> Command {
+        self.command("git")
+    }
+}
+
+pub fn repo_workdir(repo: &Repository) -> anyhow::Result<PathBuf> {
+    let wd = repo.workdir().ok_or_else(|| bare_repository(repo.path()))?;
+    Ok(paths::canonicalize(wd)?)
+}
+
+// What's staged in `repo`'s index: a hash of each entry's path, stage, mode, blob and flags, but
+// not of the file stats cached alongside, which `git status` rewrites with nothing staged. Two
+// stamps differ if something was staged or unstaged in between.
+pub fn index_stamp(repo: &Repository) -> anyhow::Result<u64> {
+    let index = repo.index()?;
+    let mut hasher = DefaultHasher::new();
+    for entry in index.iter() {
+        entry.path.hash(&mut hasher);
+        (entry.mode, entry.flags, entry.flags_extended).hash(&mut hasher);
+        entry.id.as_bytes().hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
+// The error for a repository without a working tree, which there's nothing to stage from.
+pub fn bare_repository(path: &Path) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} is a bare repository: there's no working tree to stage changes from",
+        path.display()
+    )
+}
+
+// The name of the linked worktree (as `git worktree add` made it) `repo` was opened from, or None
+// in the main one.
+pub fn worktree_name(repo: &Repository) -> Option<String> {
+    if !repo.is_worktree() {
+        return None;
+    }
+    let worktree = Worktree::open_from_repository(repo).ok()?;
+    worktree.name().map(String::from)
+}
+
+// Where HEAD points.
+pub enum Head {
+    Branch(String),
+    Detached(Oid),
+    // A branch without commits yet, as in a new repository.
+    Unborn(String),
+}
+
+pub fn head(repo: &Repository) -> anyhow::Result<Head> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            let target = repo.find_reference("HEAD")?;
+            let name = target.symbolic_target().unwrap_or("HEAD");
+            return Ok(Head::Unborn(
+                name.strip_prefix("refs/heads/").unwrap_or(name).to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !head.is_branch() {
+        return Ok(Head::Detached(head.peel_to_commit()?.id()));
+    }
+    Ok(Head::Branch(head.shorthand().unwrap_or("?").to_string()))
+}
+
+// The branch the current one tracks, and how many commits each has that the other doesn't.
+pub struct Upstream {
+    pub name: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+// None when HEAD isn't on a branch, or its branch tracks nothing.
+pub fn upstream(repo: &Repository) -> anyhow::Result<Option<Upstream>> {
+    let head = match repo.head() {
+        Ok(head) if head.is_branch() => head,
+        _ => return Ok(None),
+    };
+    let branch = Branch::wrap(head);
+    let upstream = match branch.upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(None),
+    };
+    let (ahead, behind) = match (branch.get().target(), upstream.get().target()) {
+        (Some(local), Some(remote)) => repo.graph_ahead_behind(local, remote)?,
+        _ => (0, 0),
+    };
+    Ok(Some(Upstream {
+        name: upstream.name()?.unwrap_or("?").to_string(),
+        ahead,
+        behind,
+    }))
 }
@@ -0,0 +1,977 @@
+//! An experimental [`GitBackend`] built on gitoxide, enabled with the `gix` feature.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    ffi::OsString,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+};
+
+use git2::Oid;
+use gix::{
+    bstr::{BString, ByteSlice},
+    diff::{
+        blob::{
+            unified_diff::{ConsumeHunk, ContextSize, DiffLineKind, HunkHeader},
+            Algorithm, InternedInput, UnifiedDiff,
+        },
+        rewrites::{Copies, CopySource},
+        Rewrites,
+    },
+    dir::walk::EmissionMode,
+    pathspec::Search,
+    status::{index_worktree::iter::Summary, tree_index::TrackRenames, UntrackedFiles},
+    ObjectId,
+};
+
+use crate::{
+    backend::{self, GitBackend},
+    config::{DiscardUntracked, RenamesConfig, ShowUntracked, Whitespace},
+    console,
+    diff::{self, DiffLine, DiffSettings, FileDiff, Hunk, LineKind},
+    git::{FileStatusKind, Location},
+    paths::RepoPath,
+    status::{Status, StatusEntry},
+};
+
+/// Reads statuses and diffs through gitoxide, which is much faster than libgit2 on large
+/// repositories. Changes are written through the `git` CLI, hunks included, since gitoxide can't
+/// write the index or commit yet.
+///
+/// Diffs are computed on the raw contents, without applying filters such as line-ending
+/// conversion. Staged copies are only looked for when more than one file was added or modified,
+/// so a copy that's the only change isn't found.
+pub struct GixBackend {
+    repo: gix::Repository,
+    location: Location,
+    root: PathBuf,
+    diff_settings: Cell<DiffSettings>,
+    pathspecs: RefCell<Vec<String>>,
+    expanded_dirs: RefCell<Vec<RepoPath>>,
+}
+
+impl GixBackend {
+    /// Opens the repository containing `path`.
+    pub fn open(path: impl AsRef<std::path::Path>) -> anyhow::Result<GixBackend> {
+        GixBackend::at(&Location::discover(path)?)
+    }
+
+    /// Opens the repository at `location`, staging into the index it names.
+    pub fn at(location: &Location) -> anyhow::Result<GixBackend> {
+        let mut options = gix::open::Options::default();
+        if let Some(index) = location.index() {
+            let index = format!("gitoxide.core.indexFile={}", index.display());
+            options = options.config_overrides([index]);
+        }
+        let mut repo = gix::open_opts(location.git_dir(), options)?;
+        repo.set_workdir(Some(location.workdir().to_path_buf()))?;
+        Ok(GixBackend {
+            repo,
+            location: location.clone(),
+            root: location.workdir().to_path_buf(),
+            diff_settings: Cell::new(DiffSettings::default()),
+            pathspecs: RefCell::new(Vec::new()),
+            expanded_dirs: RefCell::new(Vec::new()),
+        })
+    }
+
+    // The untracked files in the collapsed untracked directory `dir`.
+    fn untracked_in(&self, dir: &StatusEntry) -> anyhow::Result<Vec<StatusEntry>> {
+        let pattern = BString::from(dir.new_file.as_bytes());
+        let iter = self
+            .repo
+            .status(gix::progress::Discard)?
+            .untracked_files(UntrackedFiles::Files)
+            .into_index_worktree_iter(vec![pattern])?;
+        let mut files = Vec::new();
+        for item in iter {
+            let item = item?;
+            if let Some(Summary::Added) = item.summary() {
+                let path = RepoPath::from(item.rela_path().to_vec());
+                files.push(StatusEntry::new(
+                    self.root.clone(),
+                    path.clone(),
+                    path,
+                    Status::Untracked,
+                ));
+            }
+        }
+        files.sort_by(|a, b| a.new_file.cmp(&b.new_file));
+        Ok(files)
+    }
+
+    // The id of what's at `path` in HEAD or the index, if there's anything.
+    fn head_id(&self, path: &RepoPath) -> anyhow::Result<Option<ObjectId>> {
+        let tree = match self.repo.head_commit() {
+            Ok(commit) => commit.tree()?,
+            // An unborn branch.
+            Err(_) => return Ok(None),
+        };
+        Ok(tree
+            .lookup_entry_by_path(path.to_path())?
+            .map(|entry| entry.object_id()))
+    }
+
+    fn index_id(&self, path: &RepoPath) -> anyhow::Result<Option<ObjectId>> {
+        let index = self.repo.index_or_empty()?;
+        Ok(index
+            .entry_by_path(path.as_bytes().as_bstr())
+            .map(|entry| entry.id))
+    }
+
+    // The content at `path` in HEAD, the index or the worktree, if there's any.
+    fn head_blob(&self, path: &RepoPath) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.head_id(path)? {
+            Some(id) => Ok(Some(self.repo.find_blob(id)?.data.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    fn index_blob(&self, path: &RepoPath) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.index_id(path)? {
+            Some(id) => Ok(Some(self.repo.find_blob(id)?.data.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    // The mode of the file at `path` in HEAD, the index or the worktree, 0 where it isn't. The
+    // worktree's is the index's when `core.fileMode` says the executable bit can't be trusted.
+    fn head_mode(&self, path: &RepoPath) -> anyhow::Result<u32> {
+        let tree = match self.repo.head_commit() {
+            Ok(commit) => commit.tree()?,
+            Err(_) => return Ok(0),
+        };
+        Ok(tree
+            .lookup_entry_by_path(path.to_path())?
+            .map_or(0, |entry| u32::from(entry.mode().value())))
+    }
+
+    fn index_mode(&self, path: &RepoPath) -> anyhow::Result<u32> {
+        let index = self.repo.index_or_empty()?;
+        Ok(index
+            .entry_by_path(path.as_bytes().as_bstr())
+            .map_or(0, |entry| entry.mode.bits()))
+    }
+
+    fn workdir_mode(&self, path: &RepoPath) -> anyhow::Result<u32> {
+        let full = self.root.join(path.to_path());
+        if full.symlink_metadata().is_err() {
+            return Ok(0);
+        }
+        match self.repo.config_snapshot().boolean("core.fileMode") {
+            Some(false) => self.index_mode(path),
+            _ => Ok(backend::file_mode(&full)),
+        }
+    }
+
+    // The file's content, or for a symlink the path it points to, as git stores it.
+    fn workdir_file(&self, path: &RepoPath) -> anyhow::Result<Option<Vec<u8>>> {
+        let full = self.root.join(path.to_path());
+        if full
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            let target = fs::read_link(&full)?;
+            return Ok(Some(gix::path::into_bstr(target)?.to_vec()));
+        }
+        match fs::read(full) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn submodule_paths(&self) -> anyhow::Result<HashSet<RepoPath>> {
+        let mut paths = HashSet::new();
+        if let Some(submodules) = self.repo.submodules()? {
+            for submodule in submodules {
+                paths.insert(RepoPath::from(submodule.path()?.to_vec()));
+            }
+        }
+        Ok(paths)
+    }
+
+    // The commits a submodule's gitlink changes between, zero where there's none: the one checked
+    // out in the submodule stands in for the worktree's side.
+    fn submodule_commits(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+    ) -> anyhow::Result<(Oid, Oid)> {
+        let (old, new) = match kind {
+            FileStatusKind::Unstaged => {
+                // An empty directory in place of a submodule that isn't checked out has none.
+                let dir = self.root.join(entry.new_path());
+                let checked_out = match dir.join(".git").exists() {
+                    true => gix::open(&dir)?.head_id().ok().map(|id| id.detach()),
+                    false => None,
+                };
+                (self.index_id(&entry.old_file)?, checked_out)
+            }
+            FileStatusKind::Staged => (
+                self.head_id(&entry.old_file)?,
+                self.index_id(&entry.new_file)?,
+            ),
+        };
+        Ok((to_oid(old)?, to_oid(new)?))
+    }
+
+    // Stages a rename without any of its content changes, moving the file's index entry to the
+    // new path as it is through `git update-index`.
+    fn stage_rename(&self, entry: &StatusEntry) -> anyhow::Result<()> {
+        let index = self.repo.index_or_empty()?;
+        let moved = index
+            .entry_by_path(entry.old_file.as_bytes().as_bstr())
+            .ok_or(anyhow::anyhow!("{} isn't in the index", entry.old_file))?;
+        // A zero mode removes the path.
+        let info = format!(
+            "{:o} {}\t{}\n0 {}\t{}\n",
+            moved.mode.bits(),
+            moved.id,
+            entry.new_file.quoted(),
+            moved.id.kind().null(),
+            entry.old_file.quoted()
+        );
+
+        let output = console::output_with_input(
+            self.location.git().arg("update-index").arg("--index-info"),
+            info.as_bytes(),
+        )?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "staging the rename of {}: {}",
+                entry.old_file,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    // Pipes `patch` to `git apply --cached`.
+    fn apply_cached(&self, patch: &str, reverse: bool) -> anyhow::Result<()> {
+        let mut args = vec!["--cached"];
+        if reverse {
+            args.push("--reverse");
+        }
+        self.apply(patch, &args)
+    }
+
+    // Pipes `patch` to `git apply` with `args`, or to apply it to the working directory without.
+    // Hunks without context are only applied when git's told to expect them.
+    fn apply(&self, patch: &str, args: &[&str]) -> anyhow::Result<()> {
+        let mut cmd = self.location.git();
+        cmd.arg("apply").args(args);
+        if self.diff_settings.get().context == 0 {
+            cmd.arg("--unidiff-zero");
+        }
+        let output = console::output_with_input(cmd.arg("-"), patch.as_bytes())?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
+
+impl GitBackend for GixBackend {
+    fn statuses(&self, kind: FileStatusKind) -> anyhow::Result<Vec<StatusEntry>> {
+        let root = self.root.clone();
+        let settings = self.diff_settings.get();
+        let platform = self
+            .repo
+            .status(gix::progress::Discard)?
+            .untracked_files(match settings.untracked {
+                ShowUntracked::No => UntrackedFiles::None,
+                ShowUntracked::All if settings.max_untracked == 0 => UntrackedFiles::Files,
+                _ => UntrackedFiles::Collapsed,
+            })
+            .dirwalk_options(|opts| {
+                opts.emit_ignored(settings.ignored.then_some(EmissionMode::Matching))
+            });
+        let no_patterns = Vec::<BString>::new();
+        let renames = settings.renames;
+
+        let mut entries = Vec::new();
+        match kind {
+            FileStatusKind::Unstaged => {
+                let iter = platform
+                    .index_worktree_rewrites(rewrites(&renames, false))
+                    .into_index_worktree_iter(no_patterns)?;
+                for item in iter {
+                    let item = item?;
+                    if let gix::status::index_worktree::Item::DirectoryContents { entry, .. } =
+                        &item
+                    {
+                        if matches!(entry.status, gix::dir::entry::Status::Ignored(_)) {
+                            let path = RepoPath::from(entry.rela_path.to_vec());
+                            let mut files = Vec::new();
+                            match entry.disk_kind {
+                                Some(gix::dir::entry::Kind::Directory) => {
+                                    files_under(&self.root, &path, &mut files)?
+                                }
+                                _ => files.push(path),
+                            }
+                            for file in files {
+                                entries.push(StatusEntry::new(
+                                    root.clone(),
+                                    file.clone(),
+                                    file,
+                                    Status::Ignored,
+                                ));
+                            }
+                            continue;
+                        }
+                    }
+                    let status = match item.summary() {
+                        Some(Summary::Removed) => Status::Deleted,
+                        Some(Summary::Added) => Status::Untracked,
+                        Some(Summary::Modified) => Status::Modified,
+                        Some(Summary::TypeChange) => Status::Typechange,
+                        Some(Summary::Renamed) => Status::Renamed,
+                        Some(Summary::Copied) => Status::Copied,
+                        Some(Summary::IntentToAdd) => Status::Added,
+                        Some(Summary::Conflict) => Status::Conflicted,
+                        None => continue,
+                    };
+                    let mut new_file = item.rela_path().to_vec();
+                    // A collapsed untracked directory, listed as "dir/" like libgit2 does.
+                    if let gix::status::index_worktree::Item::DirectoryContents { entry, .. } =
+                        &item
+                    {
+                        if entry.disk_kind == Some(gix::dir::entry::Kind::Directory) {
+                            new_file.push(b'/');
+                        }
+                    }
+                    let new_file = RepoPath::from(new_file);
+                    let old_file = match &item {
+                        gix::status::index_worktree::Item::Rewrite { source, .. } => {
+                            RepoPath::from(source.rela_path().to_vec())
+                        }
+                        _ => new_file.clone(),
+                    };
+                    entries.push(StatusEntry::new(root.clone(), old_file, new_file, status));
+                }
+            }
+            FileStatusKind::Staged => {
+                let iter = platform
+                    .tree_index_track_renames(match rewrites(&renames, renames.copies) {
+                        Some(rewrites) => TrackRenames::Given(rewrites),
+                        None => TrackRenames::Disabled,
+                    })
+                    .into_iter(no_patterns)?;
+                for item in iter {
+                    let change = match item? {
+                        gix::status::Item::TreeIndex(change) => change,
+                        gix::status::Item::IndexWorktree(_) => continue,
+                    };
+                    use gix::diff::index::ChangeRef::*;
+                    let (old_file, new_file, status) = match &change {
+                        Addition { location, .. } => (location, location, Status::Added),
+                        Deletion { location, .. } => (location, location, Status::Deleted),
+                        Modification {
+                            location,
+                            previous_entry_mode,
+                            entry_mode,
+                            ..
+                        } => (
+                            location,
+                            location,
+                            // The type of file changed, e.g. from a file to a symlink.
+                            if previous_entry_mode.bits() & 0o170000 != entry_mode.bits() & 0o170000
+                            {
+                                Status::Typechange
+                            } else {
+                                Status::Modified
+                            },
+                        ),
+                        Rewrite {
+                            source_location,
+                            location,
+                            copy,
+                            ..
+                        } => (
+                            source_location,
+                            location,
+                            if *copy {
+                                Status::Copied
+                            } else {
+                                Status::Renamed
+                            },
+                        ),
+                    };
+                    entries.push(StatusEntry::new(
+                        root.clone(),
+                        RepoPath::from(old_file.to_vec()),
+                        RepoPath::from(new_file.to_vec()),
+                        status,
+                    ));
+                }
+            }
+        }
+
+        // Unlike libgit2's, gitoxide's results arrive in no particular order.
+        entries.sort_by(|a, b| a.new_file.cmp(&b.new_file));
+        let mut entries = backend::expand_untracked_dirs(
+            entries,
+            settings,
+            &self.expanded_dirs.borrow(),
+            |dir| self.untracked_in(dir),
+        )?;
+
+        // Either side of a rename matching keeps it, as with libgit2.
+        let pathspecs = self.pathspecs.borrow();
+        if !pathspecs.is_empty() {
+            let defaults = self.repo.pathspec_defaults()?;
+            let patterns = pathspecs
+                .iter()
+                .map(|p| gix::pathspec::parse(p.as_bytes(), defaults))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut search = Search::from_specs(patterns, None, &self.root)?;
+            let mut matches = |path: &RepoPath| {
+                search
+                    .pattern_matching_relative_path(
+                        path.as_bytes().as_bstr(),
+                        None,
+                        &mut |_, _, _, _| false,
+                    )
+                    .is_some_and(|m| !m.is_excluded())
+            };
+            entries.retain(|e| matches(&e.new_file) || matches(&e.old_file));
+        }
+
+        let submodules = self.submodule_paths()?;
+        for e in &mut entries {
+            e.submodule = submodules.contains(&e.new_file);
+        }
+        Ok(entries)
+    }
+
+    fn set_diff_settings(&self, settings: DiffSettings) {
+        self.diff_settings.set(settings);
+    }
+
+    fn set_pathspecs(&self, pathspecs: &[String]) {
+        *self.pathspecs.borrow_mut() = pathspecs.to_vec();
+    }
+
+    fn set_expanded_dirs(&self, dirs: &[RepoPath]) {
+        *self.expanded_dirs.borrow_mut() = dirs.to_vec();
+    }
+
+    fn diff(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<FileDiff> {
+        if entry.is_dir() {
+            return Err(backend::untracked_dir(entry));
+        }
+        let settings = self.diff_settings.get();
+        if entry.submodule {
+            return Ok(FileDiff {
+                old_path: entry.old_file.clone(),
+                new_path: entry.new_file.clone(),
+                binary: false,
+                old_size: 0,
+                new_size: 0,
+                submodule: Some(self.submodule_commits(entry, kind)?),
+                modes: None,
+                symlink: None,
+                hunks: Vec::new(),
+                held_back: None,
+            });
+        }
+
+        let (old, new) = match kind {
+            FileStatusKind::Unstaged => (
+                self.index_blob(&entry.old_file)?,
+                self.workdir_file(&entry.new_file)?,
+            ),
+            FileStatusKind::Staged => (
+                self.head_blob(&entry.old_file)?,
+                self.index_blob(&entry.new_file)?,
+            ),
+        };
+        let (old_mode, new_mode) = match kind {
+            FileStatusKind::Unstaged => (
+                self.index_mode(&entry.old_file)?,
+                self.workdir_mode(&entry.new_file)?,
+            ),
+            FileStatusKind::Staged => (
+                self.head_mode(&entry.old_file)?,
+                self.index_mode(&entry.new_file)?,
+            ),
+        };
+        let old = old.unwrap_or_default();
+        let new = new.unwrap_or_default();
+        // What a symlink points to is shown as such, and only the side that's a file is diffed.
+        let target = |mode: u32, data: &[u8]| {
+            diff::is_symlink(mode).then(|| String::from_utf8_lossy(data).into_owned())
+        };
+        let symlink = match (target(old_mode, &old), target(new_mode, &new)) {
+            (None, None) => None,
+            targets => Some(targets),
+        };
+        let file_side = |mode: u32, data: Vec<u8>| match diff::is_symlink(mode) {
+            true => Vec::new(),
+            false => data,
+        };
+        let (old, new) = (file_side(old_mode, old), file_side(new_mode, new));
+
+        let mut file = FileDiff {
+            old_path: entry.old_file.clone(),
+            new_path: entry.new_file.clone(),
+            binary: is_binary(&old) || is_binary(&new),
+            old_size: old.len() as u64,
+            new_size: new.len() as u64,
+            submodule: None,
+            modes: diff::mode_change(old_mode, new_mode),
+            symlink,
+            hunks: Vec::new(),
+            held_back: None,
+        };
+        if file.binary {
+            return Ok(file);
+        }
+
+        // The lines are diffed as they compare under the whitespace setting, and shown as they are.
+        let (old, new) = (lines(&old), lines(&new));
+        let compared = |lines: &[&[u8]]| -> Vec<Vec<u8>> {
+            lines
+                .iter()
+                .map(|line| compared_line(line, settings.whitespace))
+                .collect()
+        };
+        let (old_compared, new_compared) = (compared(&old), compared(&new));
+        let mut input = InternedInput::default();
+        input.update_before(old_compared.iter().map(Vec::as_slice));
+        input.update_after(new_compared.iter().map(Vec::as_slice));
+        let blob_diff = gix::diff::blob::diff_with_slider_heuristics(Algorithm::Myers, &input);
+        file.hunks = UnifiedDiff::new(
+            &blob_diff,
+            &input,
+            HunkCollector {
+                old,
+                new,
+                hunks: Vec::new(),
+            },
+            ContextSize::symmetrical(settings.context),
+        )
+        .consume()?;
+        Ok(file)
+    }
+
+    // Renders the selected hunks as a patch and pipes it to `git apply --cached`.
+    fn apply_hunks(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunks: &[usize],
+    ) -> anyhow::Result<()> {
+        backend::check_hunk_apply(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, kind)?;
+        let applying = |e| anyhow::anyhow!("applying hunks to {}: {}", entry.new_file, e);
+        match (entry.status, kind) {
+            // The rename goes in first, and then just the selected hunks on top of it.
+            (Status::Renamed, FileStatusKind::Unstaged) => {
+                let patch =
+                    diff::render_patch(&diff::renamed_content(&file), Status::Modified, hunks);
+                self.stage_rename(entry)?;
+                self.apply_cached(&patch, false).map_err(applying)
+            }
+            (Status::Renamed | Status::Copied, FileStatusKind::Staged) => {
+                for patch in diff::render_reverse_patches(&file, hunks) {
+                    self.apply_cached(&patch, false).map_err(applying)?;
+                }
+                Ok(())
+            }
+            _ => {
+                let patch = diff::render_patch(&file, entry.status, hunks);
+                // Unstaging applies the staged changes backwards.
+                let reverse = matches!(kind, FileStatusKind::Staged);
+                self.apply_cached(&patch, reverse).map_err(applying)
+            }
+        }
+    }
+
+    fn apply_lines(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()> {
+        backend::check_hunk_apply(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, kind)?;
+        let reverse = matches!(kind, FileStatusKind::Staged);
+        // The patch is already reversed for unstaging.
+        let patch = match (entry.status, kind) {
+            (Status::Renamed, FileStatusKind::Unstaged) => {
+                let file = diff::renamed_content(&file);
+                let patch = diff::render_line_patch(&file, Status::Modified, hunk, lines, false);
+                self.stage_rename(entry)?;
+                patch
+            }
+            _ => diff::render_line_patch(&file, entry.status, hunk, lines, reverse),
+        };
+        self.apply_cached(&patch, false)
+            .map_err(|e| anyhow::anyhow!("applying lines to {}: {}", entry.new_file, e))
+    }
+
+    // Records the file's index entry again with the other mode and the same blob, with
+    // `git update-index --cacheinfo`: `--chmod` would stage the worktree's content along with it.
+    fn apply_mode(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<()> {
+        let mode = backend::mode_to_apply(entry, kind, &self.diff(entry, kind)?)?;
+        let index = self.repo.index_or_empty()?;
+        let id = match index.entry_by_path(entry.new_file.as_bytes().as_bstr()) {
+            Some(indexed) => indexed.id,
+            None => anyhow::bail!("{} isn't in the index", entry.new_file),
+        };
+        let mut info = OsString::from(format!("{:o},{},", mode, id));
+        info.push(entry.new_path());
+        let mut cmd = self.location.git();
+        cmd.arg("update-index").arg("--cacheinfo").arg(info);
+        run(cmd, "changing the mode")
+    }
+
+    fn discard_hunks(&self, entry: &StatusEntry, hunks: &[usize]) -> anyhow::Result<()> {
+        backend::check_hunk_discard(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, FileStatusKind::Unstaged)?;
+        for patch in diff::render_reverse_patches(&file, hunks) {
+            self.apply(&patch, &[])
+                .map_err(|e| backend::discarding(entry, e))?;
+        }
+        Ok(())
+    }
+
+    fn discard_lines(
+        &self,
+        entry: &StatusEntry,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()> {
+        backend::check_hunk_discard(entry)?;
+        self.diff_settings.get().check_applicable()?;
+        let file = self.diff(entry, FileStatusKind::Unstaged)?;
+        let patch = diff::render_line_patch(&file, Status::Modified, hunk, lines, true);
+        self.apply(&patch, &[])
+            .map_err(|e| backend::discarding(entry, e))
+    }
+
+    fn stage(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        git_stage(&self.location, entries)
+    }
+
+    fn intent_to_add(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        git_intent_to_add(&self.location, entries)
+    }
+
+    fn unstage(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        git_unstage(&self.location, entries)
+    }
+
+    fn discard(&self, entries: &[StatusEntry], untracked: DiscardUntracked) -> anyhow::Result<()> {
+        backend::refuse_submodules(entries)?;
+        git_discard(&self.location, entries, untracked)
+    }
+
+    // Commits through `git commit`, which signs as configured. As with libgit2, no hooks are run and
+    // the message is kept as it is.
+    fn commit(&self, message: &str) -> anyhow::Result<Oid> {
+        let output = console::output_with_input(
+            self.location
+                .git()
+                .arg("commit")
+                .arg("--quiet")
+                .arg("--no-verify")
+                .arg("--cleanup=verbatim")
+                .arg("--file=-"),
+            message.as_bytes(),
+        )?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "committing: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        to_oid(Some(self.repo.head_id()?.detach()))
+    }
+}
+
+// How to find the renames, and with `copies` the copies from any file on the old side, that
+// `renames` asks for. None when renames aren't looked for. gitoxide only looks for copies when
+// more than one file was added or modified.
+fn rewrites(renames: &RenamesConfig, copies: bool) -> Option<Rewrites> {
+    let percentage = Some(f32::from(renames.threshold) / 100.0);
+    renames.enabled.then(|| Rewrites {
+        copies: copies.then_some(Copies {
+            source: CopySource::FromSetOfModifiedFilesAndAllSources,
+            percentage,
+        }),
+        percentage,
+        ..Rewrites::default()
+    })
+}
+
+// The files anywhere beneath `dir`, a path relative to `root`. gitoxide stops at an ignored
+// directory, where libgit2 lists every file in it.
+fn files_under(root: &Path, dir: &RepoPath, files: &mut Vec<RepoPath>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(dir.to_path()))? {
+        let entry = entry?;
+        let mut path = dir.as_bytes().to_vec();
+        path.push(b'/');
+        path.extend_from_slice(RepoPath::from(Path::new(&entry.file_name())).as_bytes());
+        let path = RepoPath::from(path);
+        match entry.file_type()?.is_dir() {
+            true => files_under(root, &path, files)?,
+            false => files.push(path),
+        }
+    }
+    Ok(())
+}
+
+// `id` as the `Oid` the `GitBackend` trait deals in, zero for none.
+fn to_oid(id: Option<ObjectId>) -> anyhow::Result<Oid> {
+    match id {
+        Some(id) => Ok(Oid::from_bytes(id.as_bytes())?),
+        None => Ok(Oid::zero()),
+    }
+}
+
+// Runs `cmd`, failing with its stderr if it exits unsuccessfully.
+fn run(mut cmd: process::Command, what: &str) -> anyhow::Result<()> {
+    let output = console::output(&mut cmd)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{}: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+// The whole-file operations go through the `git` CLI. Each spawns a single `git` process for the
+// whole set of entries rather than one per entry.
+fn git_stage(location: &Location, entries: &[StatusEntry]) -> anyhow::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    // Assumption: these StatusEntries were obtained by comparing the index to the working directory.
+    let mut cmd = location.git();
+    cmd.arg("add");
+    // Like libgit2's, staging an ignored file adds it all the same.
+    if entries.iter().any(|e| matches!(e.status, Status::Ignored)) {
+        cmd.arg("--force");
+    }
+    cmd.arg("--");
+    for e in entries {
+        if let Status::Renamed = e.status {
+            cmd.arg(e.abs_path_old());
+        }
+        cmd.arg(e.abs_path_new());
+    }
+    run(cmd, "staging")
+}
+
+fn git_intent_to_add(location: &Location, entries: &[StatusEntry]) -> anyhow::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut cmd = location.git();
+    cmd.arg("add")
+        .arg("--intent-to-add")
+        .arg("--")
+        .args(entries.iter().map(|e| e.abs_path_new()));
+    run(cmd, "adding with intent to add")
+}
+
+fn git_unstage(location: &Location, entries: &[StatusEntry]) -> anyhow::Result<()> {
+    // Assumption: these StatusEntries were obtained by comparing HEAD to the index.
+    let (deleted, rest): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .partition(|e| matches!(e.status, Status::Deleted));
+
+    if !deleted.is_empty() {
+        let mut cmd = location.git();
+        cmd.arg("restore")
+            .arg("--staged")
+            .arg("--")
+            .args(deleted.iter().map(|e| e.abs_path_new()));
+        run(cmd, "unstaging")?;
+    }
+    if !rest.is_empty() {
+        let mut cmd = location.git();
+        cmd.arg("reset").arg("--quiet").arg("--");
+        for e in rest {
+            // Both sides of a rename go back to how HEAD has them.
+            if let Status::Renamed = e.status {
+                cmd.arg(e.abs_path_old());
+            }
+            cmd.arg(e.abs_path_new());
+        }
+        run(cmd, "unstaging")?;
+    }
+
+    Ok(())
+}
+
+fn git_discard(
+    location: &Location,
+    entries: &[StatusEntry],
+    untracked: DiscardUntracked,
+) -> anyhow::Result<()> {
+    // Assumption: these StatusEntries were obtained by comparing the index to the working directory.
+    let mut checkout = Vec::new();
+    for e in entries {
+        match e.status {
+            Status::Untracked | Status::Ignored => {
+                backend::remove_untracked(&e.abs_path_new(), untracked)?
+            }
+            Status::Renamed => {
+                backend::remove_untracked(&e.abs_path_new(), untracked)?;
+                checkout.push(e.abs_path_old());
+            }
+            _ => checkout.push(e.abs_path_new()),
+        }
+    }
+
+    if !checkout.is_empty() {
+        let mut cmd = location.git();
+        cmd.arg("checkout").arg("--").args(checkout);
+        run(cmd, "checking out")?;
+    }
+
+    Ok(())
+}
+
+// The same heuristic git uses: a NUL byte within the first 8000 bytes.
+fn is_binary(data: &[u8]) -> bool {
+    diff::looks_binary(&data[..data.len().min(8000)])
+}
+
+// The lines of `data`, each with its newline.
+fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+// `line` as it's compared with `whitespace`: ignoring changes in it, any run of whitespace counts
+// as a single space and none at the end counts, and ignoring all of it, none counts at all.
+fn compared_line(line: &[u8], whitespace: Whitespace) -> Vec<u8> {
+    let (text, newline) = match line.strip_suffix(b"\n") {
+        Some(text) => (text, true),
+        None => (line, false),
+    };
+    let mut out = Vec::with_capacity(line.len());
+    match whitespace {
+        Whitespace::Show => out.extend_from_slice(text),
+        Whitespace::IgnoreAll => out.extend(text.iter().filter(|b| !b.is_ascii_whitespace())),
+        Whitespace::IgnoreChange => {
+            let mut space = false;
+            for &b in text {
+                if b.is_ascii_whitespace() {
+                    space = true;
+                    continue;
+                }
+                if space && !out.is_empty() {
+                    out.push(b' ');
+                }
+                space = false;
+                out.push(b);
+            }
+        }
+    }
+    if newline {
+        out.push(b'\n');
+    }
+    out
+}
+
+// Collects the hunks of a diff of `old` and `new`'s lines. The lines diffed may have been
+// compared with some of their whitespace left out, and are shown from `old` and `new` as they
+// are: lines that are the same on both sides from the new side, as git does.
+struct HunkCollector<'a> {
+    old: Vec<&'a [u8]>,
+    new: Vec<&'a [u8]>,
+    hunks: Vec<Hunk>,
+}
+
+impl ConsumeHunk for HunkCollector<'_> {
+    type Out = Vec<Hunk>;
+
+    fn consume_hunk(
+        &mut self,
+        header: HunkHeader,
+        lines: &[(DiffLineKind, &[u8])],
+    ) -> io::Result<()> {
+        let mut old_lineno = header.before_hunk_start;
+        let mut new_lineno = header.after_hunk_start;
+        let mut out = Vec::new();
+        for &(kind, _) in lines {
+            // Line numbers count from 1.
+            let (kind, old, new, content) = match kind {
+                DiffLineKind::Context => {
+                    let content = self.new[new_lineno as usize - 1];
+                    old_lineno += 1;
+                    new_lineno += 1;
+                    (
+                        LineKind::Context,
+                        Some(old_lineno - 1),
+                        Some(new_lineno - 1),
+                        content,
+                    )
+                }
+                DiffLineKind::Remove => {
+                    let content = self.old[old_lineno as usize - 1];
+                    old_lineno += 1;
+                    (LineKind::Removed, Some(old_lineno - 1), None, content)
+                }
+                DiffLineKind::Add => {
+                    let content = self.new[new_lineno as usize - 1];
+                    new_lineno += 1;
+                    (LineKind::Added, None, Some(new_lineno - 1), content)
+                }
+            };
+            out.push(DiffLine {
+                kind,
+                content: String::from_utf8_lossy(content)
+                    .trim_end_matches('\n')
+                    .to_string(),
+                old_lineno: old,
+                new_lineno: new,
+            });
+            if !content.ends_with(b"\n") {
+                out.push(DiffLine {
+                    kind: LineKind::NoNewlineAtEof,
+                    content: String::from("\\ No newline at end of file"),
+                    old_lineno: None,
+                    new_lineno: None,
+                });
+            }
+        }
+
+        self.hunks.push(Hunk {
+            header: format!(
+                "@@ -{},{} +{},{} @@",
+                header.before_hunk_start,
+                header.before_hunk_len,
+                header.after_hunk_start,
+                header.after_hunk_len
+            ),
+            old_start: header.before_hunk_start,
+            old_lines: header.before_hunk_len,
+            new_start: header.after_hunk_start,
+            new_lines: header.after_hunk_len,
+            lines: out,
+        });
+        Ok(())
+    }
+
+    fn finish(self) -> Self::Out {
+        self.hunks
+    }
+}
@@ -0,0 +1,22 @@
+use globset::{GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+
+// Globs are matched against repo-relative paths; `*` stays within one path component while `**`
+// crosses directories.
+pub fn matcher(pattern: &str) -> anyhow::Result<GlobMatcher> {
+    Ok(GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()?
+        .compile_matcher())
+}
+
+pub fn set<S: AsRef<str>>(patterns: &[S]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for p in patterns {
+        builder.add(
+            GlobBuilder::new(p.as_ref())
+                .literal_separator(true)
+                .build()?,
+        );
+    }
+    Ok(builder.build()?)
+}
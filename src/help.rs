@@ -0,0 +1,65 @@
+use tui::{
+    backend::Backend,
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::{
+    keymap::{Action, Category, Keymap},
+    popup,
+};
+
+// The keys bound to every action, as configured, shown over the rest of the screen with `?`.
+pub struct HelpView {
+    lines: Vec<Spans<'static>>,
+    pub scroll: u16,
+}
+
+impl HelpView {
+    pub fn new(keymap: &Keymap) -> HelpView {
+        let mut lines = Vec::new();
+        for &category in Category::ALL {
+            if !lines.is_empty() {
+                lines.push(Spans::default());
+            }
+            lines.push(Spans::from(Span::styled(
+                category.title(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for &action in Action::ALL.iter().filter(|a| a.category() == category) {
+                let keys: Vec<String> = keymap.keys(action).iter().map(|k| k.to_string()).collect();
+                let keys = match keys.is_empty() {
+                    true => String::from("-"),
+                    false => keys.join(", "),
+                };
+                lines.push(Spans::from(format!("  {:<20}{}", keys, action.name())));
+            }
+        }
+        HelpView { lines, scroll: 0 }
+    }
+
+    // Scrolls by `delta` lines, no further than to show the last one at the bottom of a screen
+    // `height` rows tall.
+    pub fn scroll_by(&mut self, delta: i16, height: u16) {
+        let max = u16::try_from(self.lines.len())
+            .unwrap_or(u16::MAX)
+            .saturating_sub(height.saturating_sub(2));
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+}
+
+pub fn render_help<B: Backend>(f: &mut Frame<B>, view: &HelpView) {
+    let area = popup::centered_rect(80, f.size().height, f.size());
+    let paragraph = Paragraph::new(view.lines.clone())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Keys (up/down scroll, any other key closes) "),
+        )
+        .scroll((view.scroll, 0));
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
@@ -0,0 +1,511 @@
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use git2::{Branch, DiffFormat, DiffOptions, Oid, Reference, Repository, Revwalk};
+
+use git_istage_rs::{
+    config::Whitespace,
+    console,
+    diff::{self, DiffSettings, FileDiff},
+    git::Location,
+    signing,
+};
+
+use crate::{command_run::CommandRun, scroll::Scroll};
+
+// Runs `git` in the repo root and fails with its stderr if it exits unsuccessfully.
+fn run_git(location: &Location, args: &[&str]) -> anyhow::Result<String> {
+    let output = console::output(
+        location
+            .git()
+            // The terminal is in raw mode, so git must not stop to ask for credentials.
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .args(args),
+    )?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// A commit as listed in the log view.
+#[derive(Clone)]
+pub struct LogCommit {
+    pub id: Oid,
+    pub summary: String,
+    pub author: String,
+    // When it was committed, in seconds since the epoch.
+    pub time: i64,
+}
+
+impl LogCommit {
+    // E.g. "1a2b3c4 Fix the parser", for questions and the journal.
+    pub fn short_string(&self) -> String {
+        let id = self.id.to_string();
+        format!("{} {}", &id[..7], self.summary)
+    }
+
+    // E.g. "1a2b3c4 2 days ago  Jane Doe  Fix the parser".
+    pub fn pretty_string(&self) -> String {
+        let id = self.id.to_string();
+        format!(
+            "{} {}  {}  {}",
+            &id[..7],
+            relative_time(self.time),
+            self.author,
+            self.summary
+        )
+    }
+}
+
+// How long ago `time` (seconds since the epoch) was, in its biggest whole unit.
+fn relative_time(time: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let seconds = (now - time).max(0);
+    let units = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (7 * 24 * 3600, "week"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+    for (size, unit) in units {
+        let n = seconds / size;
+        if n > 0 {
+            return format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+        }
+    }
+    String::from("just now")
+}
+
+// The last `limit` commits reachable from HEAD, newest first. Empty on an unborn branch.
+pub fn recent_commits(location: &Location, limit: usize) -> anyhow::Result<Vec<LogCommit>> {
+    let repo = location.open()?;
+    if repo.head().is_err() {
+        return Ok(Vec::new());
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    log_commits(&repo, walk, limit)
+}
+
+// The last `limit` commits of the current branch's upstream that HEAD doesn't have yet, newest
+// first, along with the upstream's name. None when HEAD isn't on a branch that tracks one.
+pub fn incoming_commits(
+    location: &Location,
+    limit: usize,
+) -> anyhow::Result<Option<(String, Vec<LogCommit>)>> {
+    let repo = location.open()?;
+    let head = match repo.head() {
+        Ok(head) if head.is_branch() => head,
+        _ => return Ok(None),
+    };
+    let upstream = match Branch::wrap(head).upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(None),
+    };
+    let name = upstream.name()?.unwrap_or("?").to_string();
+    let mut walk = repo.revwalk()?;
+    if let Some(tip) = upstream.get().target() {
+        walk.push(tip)?;
+        walk.hide_head()?;
+    }
+    Ok(Some((name, log_commits(&repo, walk, limit)?)))
+}
+
+fn log_commits(repo: &Repository, walk: Revwalk, limit: usize) -> anyhow::Result<Vec<LogCommit>> {
+    let mut commits = Vec::new();
+    for id in walk.take(limit) {
+        let commit = repo.find_commit(id?)?;
+        commits.push(LogCommit {
+            id: commit.id(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            author: commit.author().name().unwrap_or_default().to_string(),
+            time: commit.time().seconds(),
+        });
+    }
+    Ok(commits)
+}
+
+pub fn head_commit(location: &Location) -> anyhow::Result<Oid> {
+    let repo = location.open()?;
+    let head = repo.head()?.peel_to_commit()?;
+    Ok(head.id())
+}
+
+// The full message of the HEAD commit.
+pub fn head_message(location: &Location) -> anyhow::Result<String> {
+    let repo = location.open()?;
+    let head = repo.head()?.peel_to_commit()?;
+    Ok(head.message().unwrap_or_default().to_string())
+}
+
+// Whether HEAD is contained in any remote-tracking branch, i.e. has been pushed.
+pub fn head_is_pushed(location: &Location) -> anyhow::Result<bool> {
+    let repo = location.open()?;
+    let head = repo.head()?.peel_to_commit()?.id();
+    for reference in repo.references_glob("refs/remotes/*")? {
+        let tip = match reference?.peel_to_commit() {
+            Ok(commit) => commit.id(),
+            // e.g. a symbolic `origin/HEAD` pointing at a branch that's gone.
+            Err(_) => continue,
+        };
+        if tip == head || repo.graph_descendant_of(tip, head)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Rewrites HEAD with the contents of the index and `message`, keeping its author, and signed if
+// `commit.gpgsign` is set.
+pub fn amend_head(location: &Location, message: &str) -> anyhow::Result<Oid> {
+    let repo = location.open()?;
+    let head = repo.head()?.peel_to_commit()?;
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let committer = repo.signature()?;
+    let parents: Vec<_> = head.parents().collect();
+    let parents: Vec<_> = parents.iter().collect();
+    let author = head.author();
+    let id = signing::commit(
+        &repo,
+        &author,
+        &committer,
+        message,
+        &tree,
+        &parents,
+        "commit (amend)",
+    )?;
+    Ok(id)
+}
+
+// The line `git commit --verbose` puts above the diff, below which the message ends.
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+// The text a new commit's message starts from: `commit.template`, relative to the worktree root
+// like other paths in git config. Empty without one.
+pub fn commit_template(location: &Location) -> anyhow::Result<String> {
+    let repo = location.open()?;
+    let path = match repo.config()?.get_path("commit.template") {
+        Ok(path) if path.is_absolute() => path,
+        Ok(path) => location.workdir().join(path),
+        Err(_) => return Ok(String::new()),
+    };
+    fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("reading commit.template {}: {}", path.display(), e))
+}
+
+// With `commit.verbose` set, the changes about to be committed (or, when amending, what HEAD will
+// hold since its parent) below the scissors line, as a commented tail for the message's editor.
+// Empty otherwise.
+pub fn verbose_tail(location: &Location, amend: bool) -> anyhow::Result<String> {
+    let repo = location.open()?;
+    if !repo.config()?.get_bool("commit.verbose").unwrap_or(false) {
+        return Ok(String::new());
+    }
+    let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let base = match (&head, amend) {
+        (Some(head), true) => head.parent(0).ok(),
+        (head, _) => head.clone(),
+    };
+    let tree = base.map(|c| c.tree()).transpose()?;
+    let diff = repo.diff_tree_to_index(tree.as_ref(), None, None)?;
+
+    let mut tail = format!(
+        "\n{}\n# Do not modify or remove the line above.\n# Everything below it will be ignored.\n",
+        SCISSORS
+    );
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            tail.push(line.origin());
+        }
+        tail.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok(tail)
+}
+
+// The message as `git commit` would commit it from its editor: without what's below the scissors
+// line, without comment lines, and with trailing whitespace and blank lines trimmed.
+pub fn clean_up_message(message: &str) -> anyhow::Result<String> {
+    let kept = match message.lines().position(|l| l == SCISSORS) {
+        Some(i) => message.lines().take(i).collect::<Vec<_>>().join("\n"),
+        None => message.to_string(),
+    };
+    Ok(git2::message_prettify(kept, Some(b'#'))?)
+}
+
+// Checks a tag name before the message is asked for, so that a typo doesn't cost the message.
+pub fn validate_tag_name(location: &Location, name: &str) -> anyhow::Result<()> {
+    let refname = format!("refs/tags/{}", name);
+    if name.is_empty() || !Reference::is_valid_name(&refname) {
+        anyhow::bail!("invalid tag name: {}", name);
+    }
+    let repo = location.open()?;
+    if repo.find_reference(&refname).is_ok() {
+        anyhow::bail!("tag {} already exists", name);
+    }
+    Ok(())
+}
+
+// Creates an annotated tag on `target`, or a lightweight one if `message` is blank.
+pub fn create_tag(
+    location: &Location,
+    name: &str,
+    target: Oid,
+    message: &str,
+) -> anyhow::Result<()> {
+    let repo = location.open()?;
+    let target = repo.find_object(target, None)?;
+    if message.trim().is_empty() {
+        repo.tag_lightweight(name, &target, false)?;
+    } else {
+        repo.tag(name, &target, &repo.signature()?, message, false)?;
+    }
+    Ok(())
+}
+
+// The names of the tags on commit `id`, annotated or not, in order.
+pub fn tags_on(location: &Location, id: Oid) -> anyhow::Result<Vec<String>> {
+    let repo = location.open()?;
+    let mut tags = Vec::new();
+    for reference in repo.references_glob("refs/tags/*")? {
+        let reference = reference?;
+        let on = reference.peel_to_commit().is_ok_and(|c| c.id() == id);
+        if let (true, Some(name)) = (on, reference.shorthand()) {
+            tags.push(name.to_string());
+        }
+    }
+    tags.sort();
+    Ok(tags)
+}
+
+// The remote tags are pushed to: that of the current branch's upstream, else "origin". None if
+// the repository has no such remote.
+pub fn push_remote(location: &Location) -> anyhow::Result<Option<String>> {
+    let repo = location.open()?;
+    let upstream_remote = repo
+        .head()
+        .ok()
+        .and_then(|head| head.name().map(String::from))
+        .and_then(|name| repo.branch_upstream_remote(&name).ok())
+        .and_then(|buf| buf.as_str().map(String::from));
+
+    let remote = upstream_remote.unwrap_or_else(|| String::from("origin"));
+    let exists = repo.find_remote(&remote).is_ok();
+    Ok(exists.then_some(remote))
+}
+
+pub fn push_tag(location: &Location, remote: &str, name: &str) -> anyhow::Result<()> {
+    run_git(location, &["push", remote, &format!("refs/tags/{}", name)])?;
+    Ok(())
+}
+
+// Starts `git fetch` in the background, fetching from the current branch's remote (or `origin`)
+// as plain `git fetch` would, with its progress coming in as output. As with a push, credentials
+// must come from a credential helper or an ssh agent.
+pub fn start_fetch(location: &Location) -> anyhow::Result<CommandRun> {
+    let mut command = location.git();
+    command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .args(["fetch", "--progress"]);
+    CommandRun::start(String::from("git fetch"), command)
+}
+
+// A git command run in the background with its output shown: a push or pull of the current
+// branch, or a rebase folding `fixup!` and `squash!` commits into the commits they name.
+#[derive(Clone, Copy)]
+pub enum GitOp {
+    Push,
+    Pull,
+    // Rebases the commits after `onto`, or all of them if there's none, with `--autosquash`.
+    Autosquash { onto: Option<Oid> },
+}
+
+impl GitOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            GitOp::Push => "push",
+            GitOp::Pull => "pull",
+            GitOp::Autosquash { .. } => "rebase",
+        }
+    }
+
+    // Pushes or pulls the current branch as plain `git push` or `git pull` would, so that the
+    // user's own config (upstreams, `push.default`, `pull.rebase`) applies, with progress shown as
+    // it goes. Credentials must come from a credential helper or an ssh agent, as git can't ask.
+    //
+    // The rebase takes the todo list `--autosquash` makes as it is, and the messages it combines
+    // for `squash!` commits as git prepares them, since no editor can be opened from the app.
+    pub fn start(self, location: &Location) -> anyhow::Result<CommandRun> {
+        let mut command = location.git();
+        command.env("GIT_TERMINAL_PROMPT", "0");
+        match self {
+            GitOp::Push | GitOp::Pull => {
+                command.args([self.name(), "--progress"]);
+            }
+            GitOp::Autosquash { onto } => {
+                command
+                    .env("GIT_SEQUENCE_EDITOR", "true")
+                    .env("GIT_EDITOR", "true")
+                    .args(["rebase", "--interactive", "--autosquash"]);
+                match onto {
+                    Some(onto) => command.arg(onto.to_string()),
+                    None => command.arg("--root"),
+                };
+            }
+        }
+        let title = match self {
+            GitOp::Autosquash { .. } => String::from("git rebase --autosquash"),
+            _ => format!("git {}", self.name()),
+        };
+        CommandRun::start(title, command)
+    }
+}
+
+// The message of a commit that `git rebase --autosquash` folds into `target`: "fixup! " and its
+// subject, or "squash! " for one whose own message is kept along with the target's.
+pub fn fixup_message(target: &LogCommit, squash: bool) -> String {
+    let kind = if squash { "squash" } else { "fixup" };
+    format!("{}! {}", kind, target.summary)
+}
+
+// The first parent of commit `id`, which a rebase reaching back to it starts from. None for a
+// root commit.
+pub fn first_parent(location: &Location, id: Oid) -> anyhow::Result<Option<Oid>> {
+    let repo = location.open()?;
+    let commit = repo.find_commit(id)?;
+    Ok(commit.parent_ids().next())
+}
+
+pub enum PickOutcome {
+    Done,
+    // Git stopped with these paths conflicted, to be resolved and committed in the worktree.
+    Conflicted(Vec<String>),
+}
+
+pub fn cherry_pick(location: &Location, id: Oid) -> anyhow::Result<PickOutcome> {
+    run_sequencer(location, &["cherry-pick", &id.to_string()])
+}
+
+// Reverts `id` in a new commit, or with `no_commit` only stages the reverting changes.
+pub fn revert(location: &Location, id: Oid, no_commit: bool) -> anyhow::Result<PickOutcome> {
+    let id = id.to_string();
+    let mut args = vec!["revert", "--no-edit"];
+    if no_commit {
+        args.push("--no-commit");
+    }
+    args.push(&id);
+    run_sequencer(location, &args)
+}
+
+// Runs a cherry-pick or revert through the `git` CLI, so that the sequencer state is left behind
+// on conflicts just like on the command line.
+fn run_sequencer(location: &Location, args: &[&str]) -> anyhow::Result<PickOutcome> {
+    match run_git(location, args) {
+        Ok(_) => Ok(PickOutcome::Done),
+        Err(e) => match conflicted_paths(location)? {
+            paths if paths.is_empty() => Err(e),
+            paths => Ok(PickOutcome::Conflicted(paths)),
+        },
+    }
+}
+
+fn conflicted_paths(location: &Location) -> anyhow::Result<Vec<String>> {
+    let repo = location.open()?;
+    let mut paths = Vec::new();
+    for conflict in repo.index()?.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+        if let Some(entry) = entry {
+            paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    Ok(paths)
+}
+
+// The changes a commit made, shown in the diff pane while it's selected in the log, one file at a
+// time.
+pub struct CommitDiff {
+    pub id: Oid,
+    pub files: Vec<FileDiff>,
+    pub file: usize,
+    pub scroll: Scroll,
+}
+
+impl CommitDiff {
+    // What `id` changed since its first parent, or everything it holds if it's a root commit.
+    pub fn open(location: &Location, id: Oid) -> anyhow::Result<CommitDiff> {
+        let repo = location.open()?;
+        let commit = repo.find_commit(id)?;
+        let parent = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let mut diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+        diff.find_similar(None)?;
+        Ok(CommitDiff {
+            id,
+            files: diff::file_diffs(&diff)?,
+            file: 0,
+            scroll: Scroll::default(),
+        })
+    }
+
+    pub fn next_file(&mut self) {
+        if self.file + 1 < self.files.len() {
+            self.file += 1;
+            self.scroll = Scroll::default();
+        }
+    }
+
+    pub fn previous_file(&mut self) {
+        if self.file > 0 {
+            self.file -= 1;
+            self.scroll = Scroll::default();
+        }
+    }
+
+    pub fn current_file(&self) -> Option<&FileDiff> {
+        self.files.get(self.file)
+    }
+}
+
+// What changed from `revision` (anything `git rev-parse` takes that names a commit, such as
+// "HEAD~3", a branch or a tag) to the worktree, or with `staged` to the index, like `git diff
+// <revision>` (or `--cached`) restricted to `pathspecs`. Returns the commit it names with the
+// changes, one file each.
+pub fn diff_since(
+    location: &Location,
+    revision: &str,
+    staged: bool,
+    settings: DiffSettings,
+    pathspecs: &[String],
+) -> anyhow::Result<(Oid, Vec<FileDiff>)> {
+    let repo = location.open()?;
+    let commit = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| anyhow::anyhow!("{}: {}", revision, e.message()))?;
+    let tree = commit.tree()?;
+    let mut opts = DiffOptions::new();
+    opts.show_binary(true)
+        .context_lines(settings.context)
+        .ignore_whitespace_change(settings.whitespace == Whitespace::IgnoreChange)
+        .ignore_whitespace(settings.whitespace == Whitespace::IgnoreAll);
+    for pathspec in pathspecs {
+        opts.pathspec(pathspec);
+    }
+    let mut diff = match staged {
+        true => repo.diff_tree_to_index(Some(&tree), None, Some(&mut opts))?,
+        false => repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut opts))?,
+    };
+    diff.find_similar(Some(&mut diff::find_options(&settings.renames, false)))?;
+    Ok((commit.id(), diff::file_diffs(&diff)?))
+}
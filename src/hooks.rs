@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::Repository;
+
+use git_istage_rs::git::{self, Location};
+
+use crate::command_run::CommandRun;
+
+// The hooks git runs when committing. Committing through libgit2 doesn't run them, so they're run
+// here instead.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreCommit,
+    // Gets the path of a file holding the message, which it may rewrite.
+    CommitMsg,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreCommit => "pre-commit",
+            Hook::CommitMsg => "commit-msg",
+        }
+    }
+}
+
+// Where git looks for hooks: `core.hooksPath`, relative to the worktree root like git does, or
+// `.git/hooks`.
+fn hooks_dir(repo: &Repository) -> anyhow::Result<PathBuf> {
+    match repo.config()?.get_path("core.hooksPath") {
+        Ok(dir) if dir.is_absolute() => Ok(dir),
+        Ok(dir) => Ok(git::repo_workdir(repo)?.join(dir)),
+        Err(_) => Ok(repo.path().join("hooks")),
+    }
+}
+
+// The hook's script, if there's an executable one.
+pub fn find(location: &Location, hook: Hook) -> anyhow::Result<Option<PathBuf>> {
+    let repo = location.open()?;
+    let path = hooks_dir(&repo)?.join(hook.name());
+    Ok(Some(path).filter(|p| is_executable(p)))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// Writes the message where git keeps the one being committed, for the commit-msg hook.
+pub fn write_message(location: &Location, message: &str) -> anyhow::Result<PathBuf> {
+    let path = location.git_dir().join("COMMIT_EDITMSG");
+    // Hooks append trailers as lines of their own.
+    fs::write(&path, format!("{}\n", message.trim_end()))?;
+    Ok(path)
+}
+
+// The message as the commit-msg hook left it.
+pub fn read_message(location: &Location) -> anyhow::Result<String> {
+    let path = location.git_dir().join("COMMIT_EDITMSG");
+    Ok(fs::read_to_string(path)?)
+}
+
+// Starts the hook's script at `path` with `args` from the root of the working tree, as git runs
+// hooks, showing its output as it runs.
+pub fn start(
+    location: &Location,
+    hook: Hook,
+    path: &Path,
+    args: &[&Path],
+) -> anyhow::Result<CommandRun> {
+    let mut command = location.command(path);
+    command.args(args);
+    CommandRun::start(format!("{} hook", hook.name()), command)
+}
@@ -0,0 +1,154 @@
+use std::{fs, io};
+
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    Frame,
+};
+
+use git_istage_rs::git::Location;
+
+use crate::{popup, theme::Theme};
+
+// Where an ignore pattern is written: the repository's `.gitignore`, which is committed along with
+// everything else, or `.git/info/exclude`, which only this clone reads.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreFile {
+    GitIgnore,
+    Exclude,
+}
+
+impl IgnoreFile {
+    pub fn name(self) -> &'static str {
+        match self {
+            IgnoreFile::GitIgnore => ".gitignore",
+            IgnoreFile::Exclude => ".git/info/exclude",
+        }
+    }
+}
+
+pub enum IgnoreEvent {
+    Ignore(String, IgnoreFile),
+    Cancel,
+    Pending,
+}
+
+// The popup that picks how an untracked file is ignored: by its path, by its extension, or by its
+// directory, in either file.
+pub struct IgnoreMenu {
+    path: String,
+    patterns: Vec<String>,
+    selected: usize,
+    file: IgnoreFile,
+}
+
+impl IgnoreMenu {
+    pub fn new(path: &str) -> IgnoreMenu {
+        // Anchored to the root, so that nothing of the same name elsewhere is ignored along with it.
+        let mut patterns = vec![format!("/{}", escape(path))];
+        let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+        if let Some(extension) = name.rfind('.').filter(|&i| i > 0).map(|i| &name[i + 1..]) {
+            patterns.push(format!("*.{}", escape(extension)));
+        }
+        if let Some(dir) = path.rfind('/').map(|i| &path[..i]) {
+            patterns.push(format!("/{}/", escape(dir)));
+        }
+        IgnoreMenu {
+            path: path.to_string(),
+            patterns,
+            selected: 0,
+            file: IgnoreFile::GitIgnore,
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> IgnoreEvent {
+        match code {
+            KeyCode::Down => self.selected = (self.selected + 1) % self.patterns.len(),
+            KeyCode::Up => {
+                self.selected = self
+                    .selected
+                    .checked_sub(1)
+                    .unwrap_or(self.patterns.len() - 1)
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.file = match self.file {
+                    IgnoreFile::GitIgnore => IgnoreFile::Exclude,
+                    IgnoreFile::Exclude => IgnoreFile::GitIgnore,
+                }
+            }
+            KeyCode::Enter => {
+                return IgnoreEvent::Ignore(self.patterns[self.selected].clone(), self.file)
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return IgnoreEvent::Cancel,
+            _ => {}
+        }
+        IgnoreEvent::Pending
+    }
+}
+
+// Appends `pattern` to `file`, unless it's already there.
+pub fn append(location: &Location, pattern: &str, file: IgnoreFile) -> anyhow::Result<()> {
+    let repo = location.open()?;
+    let path = match file {
+        IgnoreFile::GitIgnore => location.workdir().join(".gitignore"),
+        IgnoreFile::Exclude => repo.path().join("info").join("exclude"),
+    };
+    let mut content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    if content.lines().any(|l| l == pattern) {
+        return Ok(());
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(pattern);
+    content.push('\n');
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, content)?;
+    Ok(())
+}
+
+// `path` with its glob characters, and the trailing spaces git would drop, escaped so that it
+// matches just itself.
+fn escape(path: &str) -> String {
+    let kept = path.trim_end_matches(' ').len();
+    let mut escaped = String::new();
+    for (i, c) in path.char_indices() {
+        if matches!(c, '*' | '?' | '[' | '\\') || i >= kept {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub fn render_ignore_menu<B: Backend>(f: &mut Frame<B>, menu: &IgnoreMenu, theme: &Theme) {
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            format!("Ignore {} by", menu.path),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::default(),
+    ];
+    for (i, pattern) in menu.patterns.iter().enumerate() {
+        lines.push(match i == menu.selected {
+            true => Spans::from(Span::styled(format!("> {}", pattern), theme.selected())),
+            false => Spans::from(format!("  {}", pattern)),
+        });
+    }
+    lines.push(Spans::default());
+    lines.push(Spans::from(format!("in {}", menu.file.name())));
+    popup::render_lines(
+        f,
+        " Ignore ",
+        lines,
+        "[enter] ignore   [tab] file   [esc] cancel",
+    );
+}
@@ -0,0 +1,106 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use git_istage_rs::{git::Location, status::StatusEntry};
+
+fn journal_path(location: &Location) -> PathBuf {
+    location.git_dir().join("istage").join("journal.log")
+}
+
+// Appends one line per entry to `.git/istage/journal.log`, e.g.
+// "2024-05-01 09:30:12 UTC  stage  M src/main.rs".
+pub fn record(location: &Location, action: &str, entries: &[StatusEntry]) -> anyhow::Result<()> {
+    let details: Vec<String> = entries.iter().map(|e| e.pretty_string()).collect();
+    record_details(location, action, details)
+}
+
+pub fn record_details(
+    location: &Location,
+    action: &str,
+    details: Vec<String>,
+) -> anyhow::Result<()> {
+    if details.is_empty() {
+        return Ok(());
+    }
+
+    let path = journal_path(location);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    let now = format_utc(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+    let mut lines = String::new();
+    for d in &details {
+        lines.push_str(&format!("{}  {}  {}\n", now, action, d));
+    }
+    // A single write so that lines of concurrent sessions don't interleave.
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+// The journal's lines, oldest first. Empty if nothing has been recorded yet.
+pub fn read(location: &Location) -> anyhow::Result<Vec<String>> {
+    let path = journal_path(location);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+pub fn format_utc(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+pub struct JournalView {
+    pub lines: Vec<String>,
+    pub scroll: u16,
+}
+
+impl JournalView {
+    // Opens scrolled to the end, where the most recent actions are.
+    pub fn open(location: &Location, height: u16) -> anyhow::Result<JournalView> {
+        let lines = read(location)?;
+        let scroll = u16::try_from(lines.len())
+            .unwrap_or(u16::MAX)
+            .saturating_sub(height.saturating_sub(2));
+        Ok(JournalView { lines, scroll })
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_add(1);
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+}
@@ -0,0 +1,728 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Up,
+    Down,
+    Unselect,
+    ToggleView,
+    OpenFile,
+    CycleSnapshot,
+    Stage,
+    Unstage,
+    StageAll,
+    UnstageAll,
+    IntentToAdd,
+    StageMode,
+    StageIgnoringWhitespace,
+    Ignore,
+    ToggleMark,
+    MarkAll,
+    Refresh,
+    Filter,
+    ClearFilter,
+    ShowHelp,
+    ShowTour,
+    Discard,
+    StageDirectory,
+    UnstageDirectory,
+    DiscardDirectory,
+    RestoreDiscard,
+    CommandPrompt,
+    CommandPalette,
+    ToggleAutoStage,
+    ShowJournal,
+    ShowLog,
+    CompareRevision,
+    CreateTag,
+    Commit,
+    Amend,
+    Fixup,
+    CherryPick,
+    Revert,
+    StageRevert,
+    ShowStashes,
+    ShowDiscarded,
+    Stash,
+    ApplyStash,
+    PopStash,
+    DropStash,
+    ExportPatch,
+    ApplyPatch,
+    ShowConflicts,
+    UseOurs,
+    UseTheirs,
+    EditFile,
+    NextFile,
+    PreviousFile,
+    ScrollDown,
+    ScrollUp,
+    NextHunk,
+    PreviousHunk,
+    NextChange,
+    PreviousChange,
+    FocusDiff,
+    SelectLines,
+    SplitHunk,
+    RotateLayout,
+    GrowPane,
+    ShrinkPane,
+    NextConflict,
+    PreviousConflict,
+    ShowStats,
+    RecordMacro,
+    ReplayMacro,
+    FetchMissing,
+    Fetch,
+    ShowIncoming,
+    Push,
+    Pull,
+    ToggleTree,
+    CycleSort,
+    ToggleCombined,
+    ToggleIgnored,
+    ToggleBlame,
+    ToggleConsole,
+    MoreContext,
+    LessContext,
+    CycleWhitespace,
+    ToggleWhitespaceErrors,
+    ToggleLineEndings,
+    Suspend,
+    Shell,
+    CopyPath,
+    CopyAbsolutePath,
+    CopyHunk,
+    ShowEntryInfo,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Quit,
+        Action::Up,
+        Action::Down,
+        Action::Unselect,
+        Action::ToggleView,
+        Action::OpenFile,
+        Action::CycleSnapshot,
+        Action::Stage,
+        Action::Unstage,
+        Action::StageAll,
+        Action::UnstageAll,
+        Action::IntentToAdd,
+        Action::StageMode,
+        Action::StageIgnoringWhitespace,
+        Action::Ignore,
+        Action::ToggleMark,
+        Action::MarkAll,
+        Action::Refresh,
+        Action::Filter,
+        Action::ClearFilter,
+        Action::ShowHelp,
+        Action::ShowTour,
+        Action::Discard,
+        Action::StageDirectory,
+        Action::UnstageDirectory,
+        Action::DiscardDirectory,
+        Action::RestoreDiscard,
+        Action::CommandPrompt,
+        Action::CommandPalette,
+        Action::ToggleAutoStage,
+        Action::ShowJournal,
+        Action::ShowLog,
+        Action::CompareRevision,
+        Action::CreateTag,
+        Action::Commit,
+        Action::Amend,
+        Action::Fixup,
+        Action::CherryPick,
+        Action::Revert,
+        Action::StageRevert,
+        Action::ShowStashes,
+        Action::ShowDiscarded,
+        Action::Stash,
+        Action::ApplyStash,
+        Action::PopStash,
+        Action::DropStash,
+        Action::ExportPatch,
+        Action::ApplyPatch,
+        Action::ShowConflicts,
+        Action::UseOurs,
+        Action::UseTheirs,
+        Action::EditFile,
+        Action::NextFile,
+        Action::PreviousFile,
+        Action::ScrollDown,
+        Action::ScrollUp,
+        Action::NextHunk,
+        Action::PreviousHunk,
+        Action::NextChange,
+        Action::PreviousChange,
+        Action::FocusDiff,
+        Action::SelectLines,
+        Action::SplitHunk,
+        Action::RotateLayout,
+        Action::GrowPane,
+        Action::ShrinkPane,
+        Action::NextConflict,
+        Action::PreviousConflict,
+        Action::ShowStats,
+        Action::RecordMacro,
+        Action::ReplayMacro,
+        Action::FetchMissing,
+        Action::Fetch,
+        Action::ShowIncoming,
+        Action::Push,
+        Action::Pull,
+        Action::ToggleTree,
+        Action::CycleSort,
+        Action::ToggleCombined,
+        Action::ToggleIgnored,
+        Action::ToggleBlame,
+        Action::ToggleConsole,
+        Action::MoreContext,
+        Action::LessContext,
+        Action::CycleWhitespace,
+        Action::ToggleWhitespaceErrors,
+        Action::ToggleLineEndings,
+        Action::Suspend,
+        Action::Shell,
+        Action::CopyPath,
+        Action::CopyAbsolutePath,
+        Action::CopyHunk,
+        Action::ShowEntryInfo,
+    ];
+
+    // The name used to rebind the action in the `[keys]` table of the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Unselect => "unselect",
+            Action::ToggleView => "toggle-view",
+            Action::OpenFile => "open-file",
+            Action::CycleSnapshot => "cycle-snapshot",
+            Action::Stage => "stage",
+            Action::Unstage => "unstage",
+            Action::StageAll => "stage-all",
+            Action::UnstageAll => "unstage-all",
+            Action::IntentToAdd => "intent-to-add",
+            Action::StageMode => "stage-mode",
+            Action::StageIgnoringWhitespace => "stage-ignoring-whitespace",
+            Action::Ignore => "ignore",
+            Action::ToggleMark => "toggle-mark",
+            Action::MarkAll => "mark-all",
+            Action::Refresh => "refresh",
+            Action::Filter => "filter",
+            Action::ClearFilter => "clear-filter",
+            Action::ShowHelp => "show-help",
+            Action::ShowTour => "show-tour",
+            Action::Discard => "discard",
+            Action::StageDirectory => "stage-directory",
+            Action::UnstageDirectory => "unstage-directory",
+            Action::DiscardDirectory => "discard-directory",
+            Action::RestoreDiscard => "restore-discard",
+            Action::CommandPrompt => "command-prompt",
+            Action::CommandPalette => "command-palette",
+            Action::ToggleAutoStage => "toggle-auto-stage",
+            Action::ShowJournal => "show-journal",
+            Action::ShowLog => "show-log",
+            Action::CompareRevision => "compare-revision",
+            Action::CreateTag => "create-tag",
+            Action::Commit => "commit",
+            Action::Amend => "amend",
+            Action::Fixup => "fixup",
+            Action::CherryPick => "cherry-pick",
+            Action::Revert => "revert",
+            Action::StageRevert => "stage-revert",
+            Action::ShowStashes => "show-stashes",
+            Action::ShowDiscarded => "show-discarded",
+            Action::Stash => "stash",
+            Action::ApplyStash => "apply-stash",
+            Action::PopStash => "pop-stash",
+            Action::DropStash => "drop-stash",
+            Action::ExportPatch => "export-patch",
+            Action::ApplyPatch => "apply-patch",
+            Action::ShowConflicts => "show-conflicts",
+            Action::UseOurs => "use-ours",
+            Action::UseTheirs => "use-theirs",
+            Action::EditFile => "edit-file",
+            Action::NextFile => "next-file",
+            Action::PreviousFile => "previous-file",
+            Action::ScrollDown => "scroll-down",
+            Action::ScrollUp => "scroll-up",
+            Action::NextHunk => "next-hunk",
+            Action::PreviousHunk => "previous-hunk",
+            Action::NextChange => "next-change",
+            Action::PreviousChange => "previous-change",
+            Action::FocusDiff => "focus-diff",
+            Action::SelectLines => "select-lines",
+            Action::SplitHunk => "split-hunk",
+            Action::RotateLayout => "rotate-layout",
+            Action::GrowPane => "grow-pane",
+            Action::ShrinkPane => "shrink-pane",
+            Action::NextConflict => "next-conflict",
+            Action::PreviousConflict => "previous-conflict",
+            Action::ShowStats => "show-stats",
+            Action::RecordMacro => "record-macro",
+            Action::ReplayMacro => "replay-macro",
+            Action::FetchMissing => "fetch-missing",
+            Action::Fetch => "fetch",
+            Action::ShowIncoming => "show-incoming",
+            Action::Push => "push",
+            Action::Pull => "pull",
+            Action::ToggleTree => "toggle-tree",
+            Action::CycleSort => "cycle-sort",
+            Action::ToggleCombined => "toggle-combined",
+            Action::ToggleIgnored => "toggle-ignored",
+            Action::ToggleBlame => "toggle-blame",
+            Action::ToggleConsole => "toggle-console",
+            Action::MoreContext => "more-context",
+            Action::LessContext => "less-context",
+            Action::CycleWhitespace => "cycle-whitespace",
+            Action::ToggleWhitespaceErrors => "toggle-whitespace-errors",
+            Action::ToggleLineEndings => "toggle-line-endings",
+            Action::Suspend => "suspend",
+            Action::Shell => "shell",
+            Action::CopyPath => "copy-path",
+            Action::CopyAbsolutePath => "copy-absolute-path",
+            Action::CopyHunk => "copy-hunk",
+            Action::ShowEntryInfo => "show-entry-info",
+        }
+    }
+
+    // Where the action is listed in the help screen.
+    pub fn category(self) -> Category {
+        match self {
+            Action::Up
+            | Action::Down
+            | Action::Unselect
+            | Action::NextFile
+            | Action::PreviousFile
+            | Action::ScrollDown
+            | Action::ScrollUp
+            | Action::NextHunk
+            | Action::PreviousHunk
+            | Action::NextChange
+            | Action::PreviousChange
+            | Action::FocusDiff
+            | Action::NextConflict
+            | Action::PreviousConflict
+            | Action::Filter
+            | Action::ClearFilter => Category::Navigation,
+            Action::Stage
+            | Action::Unstage
+            | Action::StageAll
+            | Action::UnstageAll
+            | Action::IntentToAdd
+            | Action::StageMode
+            | Action::StageIgnoringWhitespace
+            | Action::Ignore
+            | Action::ToggleMark
+            | Action::MarkAll
+            | Action::Discard
+            | Action::StageDirectory
+            | Action::UnstageDirectory
+            | Action::DiscardDirectory
+            | Action::RestoreDiscard
+            | Action::SelectLines
+            | Action::SplitHunk
+            | Action::ToggleAutoStage => Category::Staging,
+            Action::ToggleView
+            | Action::OpenFile
+            | Action::EditFile
+            | Action::CycleSnapshot
+            | Action::Refresh
+            | Action::ShowJournal
+            | Action::ShowLog
+            | Action::ShowIncoming
+            | Action::CompareRevision
+            | Action::ShowStashes
+            | Action::ShowDiscarded
+            | Action::ShowConflicts
+            | Action::ShowStats
+            | Action::ShowEntryInfo
+            | Action::ShowHelp
+            | Action::ShowTour
+            | Action::RotateLayout
+            | Action::GrowPane
+            | Action::ShrinkPane
+            | Action::ToggleTree
+            | Action::CycleSort
+            | Action::ToggleCombined
+            | Action::ToggleIgnored
+            | Action::ToggleBlame
+            | Action::ToggleConsole
+            | Action::MoreContext
+            | Action::LessContext
+            | Action::CycleWhitespace
+            | Action::ToggleWhitespaceErrors
+            | Action::ToggleLineEndings => Category::Views,
+            Action::Commit
+            | Action::Amend
+            | Action::Fixup
+            | Action::CreateTag
+            | Action::CherryPick
+            | Action::Revert
+            | Action::StageRevert
+            | Action::Stash
+            | Action::ApplyStash
+            | Action::PopStash
+            | Action::DropStash
+            | Action::ExportPatch
+            | Action::ApplyPatch
+            | Action::UseOurs
+            | Action::UseTheirs
+            | Action::FetchMissing
+            | Action::Fetch
+            | Action::Push
+            | Action::Pull => Category::Git,
+            Action::Quit
+            | Action::CommandPrompt
+            | Action::CommandPalette
+            | Action::RecordMacro
+            | Action::ReplayMacro
+            | Action::Suspend
+            | Action::Shell
+            | Action::CopyPath
+            | Action::CopyAbsolutePath
+            | Action::CopyHunk => Category::Other,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q"],
+            Action::Up => &["up"],
+            Action::Down => &["down"],
+            Action::Unselect => &["left"],
+            Action::ToggleView => &["t"],
+            Action::OpenFile => &["enter"],
+            Action::CycleSnapshot => &["v"],
+            Action::Stage => &["s"],
+            Action::Unstage => &["u"],
+            Action::StageAll => &["S"],
+            Action::UnstageAll => &["U"],
+            Action::IntentToAdd => &["i"],
+            Action::StageMode => &["m"],
+            Action::StageIgnoringWhitespace => &["alt-s"],
+            Action::Ignore => &["G"],
+            Action::ToggleMark => &["space"],
+            Action::MarkAll => &["*"],
+            Action::Refresh => &["f5"],
+            Action::Filter => &["/"],
+            Action::ClearFilter => &["esc"],
+            Action::ShowHelp => &["?"],
+            // Shown on the first launch, and from the palette after that.
+            Action::ShowTour => &[],
+            Action::Discard => &["r"],
+            Action::StageDirectory => &["ctrl-s"],
+            Action::UnstageDirectory => &["ctrl-u"],
+            Action::DiscardDirectory => &["ctrl-r"],
+            Action::RestoreDiscard => &["R"],
+            Action::CommandPrompt => &[":"],
+            Action::CommandPalette => &["ctrl-p"],
+            Action::ToggleAutoStage => &["A"],
+            Action::ShowJournal => &["J"],
+            Action::ShowLog => &["L"],
+            Action::CompareRevision => &["alt-d"],
+            Action::CreateTag => &["T"],
+            Action::Commit => &["c"],
+            Action::Amend => &["alt-c"],
+            Action::Fixup => &["ctrl-f"],
+            Action::CherryPick => &["C"],
+            Action::Revert => &["V"],
+            Action::StageRevert => &["ctrl-v"],
+            Action::ShowStashes => &["Z"],
+            Action::ShowDiscarded => &["D"],
+            Action::Stash => &["z"],
+            Action::ApplyStash => &["a"],
+            Action::PopStash => &["p"],
+            Action::DropStash => &["d"],
+            Action::ExportPatch => &["alt-e"],
+            Action::ApplyPatch => &["alt-i"],
+            Action::ShowConflicts => &["X"],
+            Action::UseOurs => &["h"],
+            Action::UseTheirs => &["l"],
+            Action::EditFile => &["e"],
+            Action::NextFile => &["tab"],
+            Action::PreviousFile => &["backtab"],
+            Action::ScrollDown => &["pagedown"],
+            Action::ScrollUp => &["pageup"],
+            Action::NextHunk => &["j"],
+            Action::PreviousHunk => &["k"],
+            Action::NextChange => &["]"],
+            Action::PreviousChange => &["["],
+            Action::FocusDiff => &["right"],
+            Action::SelectLines => &["x"],
+            Action::SplitHunk => &["|"],
+            Action::RotateLayout => &["o"],
+            Action::GrowPane => &[">"],
+            Action::ShrinkPane => &["<"],
+            Action::NextConflict => &["n"],
+            Action::PreviousConflict => &["N"],
+            Action::ShowStats => &["I"],
+            Action::RecordMacro => &["Q"],
+            Action::ReplayMacro => &["@"],
+            Action::FetchMissing => &["F"],
+            Action::Fetch => &["f"],
+            Action::ShowIncoming => &["alt-l"],
+            Action::Push => &["P"],
+            Action::Pull => &["alt-p"],
+            Action::ToggleTree => &["`"],
+            Action::CycleSort => &["O"],
+            Action::ToggleCombined => &["g"],
+            Action::ToggleIgnored => &["ctrl-g"],
+            Action::ToggleBlame => &["b"],
+            Action::ToggleConsole => &["~"],
+            Action::MoreContext => &["+"],
+            Action::LessContext => &["-"],
+            Action::CycleWhitespace => &["w"],
+            Action::ToggleWhitespaceErrors => &["W"],
+            Action::ToggleLineEndings => &["alt-w"],
+            Action::Suspend => &["ctrl-z"],
+            Action::Shell => &["!"],
+            Action::CopyPath => &["y"],
+            Action::CopyAbsolutePath => &["Y"],
+            Action::CopyHunk => &["alt-y"],
+            Action::ShowEntryInfo => &["E"],
+        }
+    }
+}
+
+// Groups of related actions, in the order the help screen lists them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Navigation,
+    Staging,
+    Views,
+    Git,
+    Other,
+}
+
+impl Category {
+    pub const ALL: &'static [Category] = &[
+        Category::Navigation,
+        Category::Staging,
+        Category::Views,
+        Category::Git,
+        Category::Other,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Category::Navigation => "Navigation",
+            Category::Staging => "Staging",
+            Category::Views => "Views",
+            Category::Git => "Git",
+            Category::Other => "Other",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(value: KeyEvent) -> Self {
+        let mut modifiers = value.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        // Shift is already reflected in the character itself ('S' vs 's'), but terminals disagree
+        // on whether they report it for non-character keys, so only keep it for those.
+        if !matches!(value.code, KeyCode::Char(_)) {
+            modifiers |= value.modifiers & KeyModifiers::SHIFT;
+        }
+        Key {
+            code: value.code,
+            modifiers,
+        }
+    }
+}
+
+impl From<Key> for KeyEvent {
+    fn from(value: Key) -> Self {
+        KeyEvent::new(value.code, value.modifiers)
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    // Parses keys written like "s", "S", "ctrl-s", "alt-enter", "f5" or "space".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            if let Some(r) = rest.strip_prefix("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("shift-") {
+                modifiers |= KeyModifiers::SHIFT;
+                rest = r;
+            } else {
+                break;
+            }
+        }
+
+        let code = match rest {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => match rest.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(n) => KeyCode::F(n),
+                        None => anyhow::bail!("invalid key: {}", s),
+                    },
+                }
+            }
+        };
+
+        if matches!(code, KeyCode::Char(_)) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Ok(Key { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::BackTab => write!(f, "backtab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            _ => write!(f, "?"),
+        }
+    }
+}
+
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+    // Keys that run a `:` command, such as "stage *.rs", rather than an action.
+    commands: Vec<(Key, String)>,
+}
+
+impl Keymap {
+    // Builds the keymap from the defaults, with the keys of every action named in `overrides`
+    // replaced by the ones given there, and with the keys in `commands` bound to the command
+    // lines given for them.
+    pub fn new(
+        overrides: &BTreeMap<String, Vec<String>>,
+        commands: &BTreeMap<String, String>,
+    ) -> anyhow::Result<Keymap> {
+        for name in overrides.keys() {
+            if Action::from_name(name).is_none() {
+                anyhow::bail!("unknown action in [keys]: {}", name);
+            }
+        }
+
+        let mut bindings = Vec::new();
+        for &action in Action::ALL {
+            match overrides.get(action.name()) {
+                Some(keys) => {
+                    for k in keys {
+                        bindings.push((k.parse()?, action));
+                    }
+                }
+                None => {
+                    for k in action.default_keys() {
+                        bindings.push((k.parse()?, action));
+                    }
+                }
+            }
+        }
+
+        let commands = commands
+            .iter()
+            .map(|(key, command)| Ok((key.parse()?, command.trim().to_string())))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Keymap { bindings, commands })
+    }
+
+    // The command line bound to `key`, which takes precedence over any action bound to it.
+    pub fn command(&self, key: KeyEvent) -> Option<&str> {
+        let key = Key::from(key);
+        self.commands
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, command)| command.as_str())
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        let key = Key::from(key);
+        self.bindings
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|&(_, action)| action)
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|&&(_, a)| a == action)
+            .map(|&(k, _)| k)
+            .collect()
+    }
+
+    // Every action's keys by the action's name, as `[keys]` in the config names them.
+    pub fn bindings(&self) -> BTreeMap<String, Vec<String>> {
+        Action::ALL
+            .iter()
+            .map(|&a| {
+                let keys = self.keys(a).iter().map(|k| k.to_string()).collect();
+                (a.name().to_string(), keys)
+            })
+            .collect()
+    }
+}
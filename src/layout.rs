@@ -0,0 +1,65 @@
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+use git_istage_rs::config::{LayoutConfig, LayoutDirection, Pane};
+
+// Splits `area` into the configured panes, in order. Panes without a size share what's left of
+// the screen evenly. Without any configured pane, the file list takes up everything.
+pub fn split(area: Rect, config: &LayoutConfig) -> Vec<(Pane, Rect)> {
+    if config.panes.is_empty() {
+        return vec![(Pane::List, area)];
+    }
+
+    let sized: u16 = config.panes.iter().filter_map(|p| p.size).sum();
+    let flexible = config.panes.iter().filter(|p| p.size.is_none()).count() as u16;
+    let share = 100u16
+        .saturating_sub(sized)
+        .checked_div(flexible)
+        .unwrap_or(0);
+    let constraints: Vec<Constraint> = config
+        .panes
+        .iter()
+        .map(|p| Constraint::Percentage(p.size.unwrap_or(share)))
+        .collect();
+
+    let direction = match config.direction {
+        LayoutDirection::Horizontal => Direction::Horizontal,
+        LayoutDirection::Vertical => Direction::Vertical,
+    };
+    let areas = Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .split(area);
+    config.panes.iter().map(|p| p.pane).zip(areas).collect()
+}
+
+// Stacks side-by-side panes and vice versa.
+pub fn rotate(config: &mut LayoutConfig) {
+    config.direction = match config.direction {
+        LayoutDirection::Horizontal => LayoutDirection::Vertical,
+        LayoutDirection::Vertical => LayoutDirection::Horizontal,
+    };
+}
+
+// Grows (or, with a negative `delta`, shrinks) the first pane by `delta` percent of the screen,
+// keeping at least `MIN_SIZE` percent for it and for each of the panes sharing the rest.
+pub fn resize(config: &mut LayoutConfig, delta: i16) {
+    let (first, others) = match config.panes.split_first_mut() {
+        Some((first, others)) if !others.is_empty() => (first, others),
+        _ => return,
+    };
+    let others_sized: u16 = others.iter().filter_map(|p| p.size).sum();
+    let others_flexible = others.iter().filter(|p| p.size.is_none()).count() as u16;
+
+    let current = match first.size {
+        Some(size) => size,
+        None => 100u16.saturating_sub(others_sized) / (others_flexible + 1),
+    };
+    let max = 100u16
+        .saturating_sub(others_sized)
+        .saturating_sub(MIN_SIZE * others_flexible)
+        .max(MIN_SIZE);
+    let size = current.saturating_add_signed(delta).clamp(MIN_SIZE, max);
+    first.size = Some(size);
+}
+
+const MIN_SIZE: u16 = 10;
@@ -0,0 +1,24 @@
+//! The staging engine behind `git-istage`, usable without the TUI.
+//!
+//! [`Repo`] is the entry point: it lists status entries, computes their diffs, stages
+//! or unstages selected hunks, and commits the result. The types it deals in are re-exported here.
+//! [`console`] logs the commands the backends run, for the TUI or an embedding tool to show.
+
+pub mod backend;
+pub mod config;
+pub mod console;
+pub mod diff;
+pub mod git;
+#[cfg(feature = "gix")]
+pub mod gix_backend;
+pub mod paths;
+pub(crate) mod quote;
+pub mod repo;
+pub mod signing;
+pub mod status;
+
+pub use backend::GitBackend;
+pub use diff::{DiffLine, FileDiff, Hunk, LineKind};
+pub use git::FileStatusKind;
+pub use repo::Repo;
+pub use status::{Status, StatusEntry};
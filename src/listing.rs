@@ -0,0 +1,87 @@
+//! Plain-text renderings of status entries, as `git-istage --list` prints them.
+
+use std::fmt::Write;
+
+use git_istage_rs::status::{Status, StatusEntry};
+
+/// How `git-istage --list` prints the entries.
+#[derive(Clone, Copy)]
+pub enum Format {
+    /// One entry per line, as the TUI lists them.
+    Human,
+    /// One entry per line: the status letter, a tab and the path. Renames and copies have the old
+    /// path and the new one, separated by another tab. Paths that aren't printable UTF-8 are
+    /// escaped and quoted as git quotes them.
+    Porcelain,
+    /// An array with an object per entry, with its `status`, `path`, `old_path` and whether it's a
+    /// `submodule`. Paths are escaped as in `Porcelain`, without the quotes.
+    Json,
+}
+
+pub fn render(entries: &[StatusEntry], format: Format) -> String {
+    match format {
+        Format::Human => entries
+            .iter()
+            .map(|e| format!("{}\n", e.pretty_string()))
+            .collect(),
+        Format::Porcelain => entries.iter().map(porcelain_line).collect(),
+        Format::Json => json(entries),
+    }
+}
+
+fn porcelain_line(entry: &StatusEntry) -> String {
+    match entry.status {
+        Status::Renamed | Status::Copied => format!(
+            "{}\t{}\t{}\n",
+            char::from(entry.status),
+            entry.old_file.quoted(),
+            entry.new_file.quoted()
+        ),
+        _ => format!(
+            "{}\t{}\n",
+            char::from(entry.status),
+            entry.new_file.quoted()
+        ),
+    }
+}
+
+fn json(entries: &[StatusEntry]) -> String {
+    let mut out = String::from("[");
+    for (i, e) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "\n  {{\"status\": {}, \"path\": {}, \"old_path\": {}, \"submodule\": {}}}",
+            json_string(e.status.name()),
+            json_string(&e.new_file.escaped()),
+            json_string(&e.old_file.escaped()),
+            e.submodule
+        );
+    }
+    if !entries.is_empty() {
+        out.push('\n');
+    }
+    out.push_str("]\n");
+    out
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
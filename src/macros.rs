@@ -0,0 +1,89 @@
+use std::collections::{HashMap, VecDeque};
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+// Replays stop queueing keys beyond this many, so that a macro replaying itself can't run forever.
+const MAX_QUEUED: usize = 10_000;
+
+enum Awaiting {
+    Record,
+    Replay,
+}
+
+// Vim-style keyboard macros: keys are recorded into a register named by a character, and replayed
+// by feeding them back through the input loop as if they had been typed.
+#[derive(Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    recording: Option<(char, Vec<KeyEvent>)>,
+    awaiting: Option<Awaiting>,
+    last_replayed: Option<char>,
+    queue: VecDeque<KeyEvent>,
+}
+
+impl Macros {
+    // Starts recording, or stops the recording in progress. Starting waits for the register key.
+    pub fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            Some((register, mut keys)) => {
+                // The key that stopped the recording was recorded too.
+                keys.pop();
+                self.registers.insert(register, keys);
+            }
+            None => self.awaiting = Some(Awaiting::Record),
+        }
+    }
+
+    // Waits for the key of the register to replay; `@` replays the last one again.
+    pub fn start_replay(&mut self) {
+        self.awaiting = Some(Awaiting::Replay);
+    }
+
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|&(register, _)| register)
+    }
+
+    // Consumes `key` if it names the register a recording or replay was waiting for. Any other
+    // key cancels the wait, and is consumed too.
+    pub fn take_register(&mut self, key: KeyEvent) -> bool {
+        let awaiting = match self.awaiting.take() {
+            Some(a) => a,
+            None => return false,
+        };
+        let register = match key.code {
+            KeyCode::Char(c) => c,
+            _ => return true,
+        };
+        match awaiting {
+            Awaiting::Record => self.recording = Some((register, Vec::new())),
+            Awaiting::Replay => {
+                let register = match register {
+                    '@' => match self.last_replayed {
+                        Some(r) => r,
+                        None => return true,
+                    },
+                    r => r,
+                };
+                if let Some(keys) = self.registers.get(&register) {
+                    if self.queue.len() + keys.len() <= MAX_QUEUED {
+                        self.queue.extend(keys.iter().copied());
+                    }
+                    self.last_replayed = Some(register);
+                }
+            }
+        }
+        true
+    }
+
+    // Records a key typed while a recording is in progress.
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    // The next key of a macro being replayed.
+    pub fn replayed(&mut self) -> Option<KeyEvent> {
+        self.queue.pop_front()
+    }
+}
@@ -1,89 +1,450 @@
-mod git;
-mod status;
+mod autostage;
+mod blame;
+mod checkpoint;
+mod cli;
+mod clipboard;
+mod command_run;
+mod conflicts;
+mod diff_filter;
+mod diff_view;
+mod editor;
+mod entry_info;
+mod events;
+mod exec;
+mod external_diff;
+mod filter;
+mod fixup;
+mod formatter;
+mod glob;
+mod help;
+mod history;
+mod hooks;
+mod ignore;
+mod journal;
+mod keymap;
+mod layout;
+mod listing;
+mod macros;
+mod message;
+mod mouse;
+mod notifications;
+mod opening;
+mod palette;
+mod partial;
+mod patch;
+mod popup;
+mod preview;
+mod promisor;
+mod prompt;
+mod review;
+mod scroll;
+mod snapshot;
+mod sort;
+mod stash;
+mod stats;
+mod status_bar;
+mod suspicious;
+mod syntax;
+mod terminal;
+mod theme;
+mod tour;
+mod tree;
+mod ui_state;
+#[cfg(test)]
+mod ui_tests;
+mod watcher;
+mod word_diff;
+mod worker;
 
 use std::time::Instant;
-use std::{io, time::Duration};
-
-use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, IsTerminal, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
 };
+
+use crossterm::event::{Event, KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use tui::{
-    backend::{Backend, CrosstermBackend},
+    backend::{Backend, CrosstermBackend, TestBackend},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{List, ListItem, ListState},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
 
-use git::get_file_statuses;
-use status::StatusEntry;
+use autostage::AutoStage;
+use blame::{BlameCache, FileBlame};
+use command_run::CommandRun;
+use conflicts::{ConflictView, Side};
+use diff_view::DiffView;
+use editor::{Editor, EditorEvent};
+use events::{EventSource, TerminalEvents, Unattended};
+use exec::Exec;
+use fixup::{FixupEvent, FixupPicker};
+use formatter::Formatters;
+use git2::Oid;
+use git_istage_rs::{
+    backend::{self, GitBackend},
+    config::{
+        Config, ConfirmAction, DiscardUntracked, ListView, Pane, PaneConfig, ShowUntracked,
+        SortOrder,
+    },
+    console,
+    diff::{DiffSettings, FileDiff, LineCounts, LineKind},
+    git::{self, FileStatusKind, Location},
+    paths::{self, RepoPath},
+    status::{Status, StatusEntry},
+};
+use globset::GlobSet;
+use help::HelpView;
+use history::{CommitDiff, GitOp, LogCommit, PickOutcome};
+use hooks::Hook;
+use ignore::{IgnoreEvent, IgnoreMenu};
+use journal::JournalView;
+use keymap::{Action, Keymap};
+use macros::Macros;
+use message::{Command, Message};
+use notifications::Notifications;
+use opening::{Opened, Opening};
+use palette::{Choice, Palette, PaletteEvent};
+use partial::{PartialEvent, PartialWarning};
+use popup::Dialog;
+use promisor::Fetch;
+use prompt::{Prompt, PromptEvent};
+use review::Review;
+use scroll::Scroll;
+use snapshot::{FileView, Snapshot};
+use stash::{StashEntry, StashView};
+use status_bar::Summary;
+use terminal::TerminalGuard;
+use theme::{Theme, Uncolored};
+use tour::{Tour, TourEvent};
+use tree::Tree;
+use ui_state::{ListPosition, UiState};
+use watcher::Watcher;
+use worker::Worker;
 
 fn main() -> anyhow::Result<()> {
+    let cli = cli::parse();
     let tick_rate = Duration::from_millis(250);
-    let app = App::new(
-        get_file_statuses(git::FileStatusKind::Unstaged)?,
-        get_file_statuses(git::FileStatusKind::Staged)?,
-    );
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    match cli.command {
+        Some(cli::Command::Completions { shell }) => {
+            cli::print_completions(shell);
+            return Ok(());
+        }
+        Some(cli::Command::Man) => return cli::print_man_page(),
+        Some(cli::Command::Config { .. } | cli::Command::Diff { .. }) | None => {}
+    }
+
+    // The files are given relative to where we were started, before moving into the repository.
+    let file = cli.file.as_deref().map(std::path::absolute).transpose()?;
+    let apply_patch = cli.apply_patch.as_deref().map(std::path::absolute);
+    let apply_patch = apply_patch.transpose()?;
+    let config_file = cli.config_file.as_deref().map(std::path::absolute);
+    let config_file = config_file.transpose()?;
+    // The files to diff need no repository, and may well be outside of any.
+    if let Some(cli::Command::Diff { args }) = &cli.command {
+        let external = external_diff::ExternalDiff::parse(args)?;
+        let location = Location::discover(".").ok();
+        let config = load_config(&cli, location.as_ref(), config_file.as_deref())?;
+        let mouse = config.layout.mouse;
+        return in_terminal(mouse, |terminal| {
+            let location = location.as_ref();
+            external_diff::run(terminal, location, &external, &config, &mut TerminalEvents)
+        });
+    }
+    // And so are pathspecs, unless they're given from the root with `:/src` or `:(top)src`.
+    let pathspecs = cli
+        .pathspecs
+        .iter()
+        .map(|p| match p.starts_with(':') {
+            true => Ok((p.clone(), None)),
+            false => std::path::absolute(p).map(|abs| (p.clone(), Some(abs))),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    // So are the repository and working tree git was told about, which every `git` run from the
+    // working tree inherits too.
+    for var in ["GIT_DIR", "GIT_WORK_TREE"] {
+        if let Some(path) = std::env::var_os(var) {
+            std::env::set_var(var, std::path::absolute(path)?);
+        }
+    }
+    // The repository is looked for from the path given, without moving into it: everything run in
+    // it is run from the root of its working tree.
+    let dir = match &cli.path {
+        Some(path) => {
+            std::fs::read_dir(path)
+                .map_err(|e| anyhow::anyhow!("opening {}: {}", path.display(), e))?;
+            std::path::absolute(path)?
+        }
+        None => std::env::current_dir()?,
+    };
+
+    let opening = Opening {
+        dir,
+        config_file,
+        overrides: config_overrides(&cli),
+        pathspecs,
+        file,
+    };
+    if let Some(cli::Command::Config { dump: true }) = cli.command {
+        let location = Location::discover(&opening.dir).ok();
+        let config = Config::load(
+            location.as_ref(),
+            opening.config_file.as_deref(),
+            &opening.overrides,
+        )?;
+        print!(
+            "{}",
+            config.dump(Keymap::new(&config.keys, &config.commands)?.bindings())?
+        );
+        return Ok(());
+    }
+    let mut exec = cli.exec.as_deref().map(Exec::parse).transpose()?;
+    if let Some(exec) = &mut exec {
+        exec.yes = cli.yes;
+    }
+
+    // Printing the lists, or running unattended, nothing is shown while the repository opens.
+    if cli.list || exec.as_ref().is_some_and(|e| e.unattended) {
+        ensure_repository(&opening.dir, cli.init)?;
+        let opened = opening.open()?;
+        if cli.list {
+            return print_list(&cli, &opened.location, &opened.config);
+        }
+        let (mut app, emitting) = start_app(&cli, opened, apply_patch)?;
+        app.exec = exec.unwrap_or_default();
+        // Drawn where nobody sees it, without taking over the terminal.
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        run_app(&mut terminal, app, tick_rate, &mut Unattended)?;
+        return emit_patch(emitting);
+    }
+
+    let emitting = in_terminal(false, |terminal| {
+        let opened = loop {
+            match opening
+                .clone()
+                .open_showing(terminal, &mut TerminalEvents)?
+            {
+                None => return Ok(None),
+                // Asked, if it is, with the terminal as the shell has it.
+                Some(Err(e)) if repository_missing(&e) => {
+                    terminal::give_back()?;
+                    create_repository(&opening.dir, cli.init)?;
+                    terminal::take()?;
+                    terminal.clear()?;
+                }
+                Some(result) => break result?,
+            }
+        };
+        terminal::capture_mouse(opened.config.layout.mouse)?;
+        let (mut app, emitting) = start_app(&cli, opened, apply_patch)?;
+        app.exec = exec.unwrap_or_default();
+        // Only ever shown by itself once, even if it isn't gone through to the end, and shown
+        // again on the next launch if that can't be remembered.
+        if !tour::seen() {
+            app.tour = Some(Tour::new());
+            tour::mark_seen().ok();
+        }
+        run_app(terminal, app, tick_rate, &mut TerminalEvents)?;
+        Ok(emitting)
+    })?;
+    emit_patch(emitting)
+}
+
+// `--list`: the entries of the unstaged list, or the staged one with `--staged`, printed.
+fn print_list(cli: &cli::Cli, location: &Location, config: &Config) -> anyhow::Result<()> {
+    let kind = match cli.staged {
+        true => FileStatusKind::Staged,
+        false => FileStatusKind::Unstaged,
+    };
+    let format = match (cli.porcelain, cli.json) {
+        (true, _) => listing::Format::Porcelain,
+        (_, true) => listing::Format::Json,
+        _ => listing::Format::Human,
+    };
+    let backend = backend::open(config.backend, location)?;
+    backend.set_diff_settings(DiffSettings::from(config));
+    backend.set_pathspecs(&config.pathspecs);
+    let entries = sort::sorted_statuses(backend.as_ref(), kind, config.list.sort, false)?;
+    print!("{}", listing::render(&entries, format));
+    Ok(())
+}
+
+// The app on the opened repository, started as the command line has it, along with the patch
+// being emitted with `--emit-patch` and the settings to render it with.
+fn start_app(
+    cli: &cli::Cli,
+    opened: Opened,
+    apply_patch: Option<PathBuf>,
+) -> anyhow::Result<(App, Option<(patch::Emitting, DiffSettings)>)> {
+    let Opened {
+        location,
+        mut config,
+        file,
+    } = opened;
+    let emitting = cli.emit_patch.then(|| patch::Emitting::start(&location));
+    let emitting = emitting.transpose()?;
+    config.emit_patch = emitting.is_some();
+    let emitting = emitting.map(|e| (e, DiffSettings::from(&config)));
+    let location = match &emitting {
+        Some((emitting, _)) => emitting.location().clone(),
+        None => location,
+    };
+    let mut app = App::restored(location, config)?;
+    app.refresh_recent_commits()?;
+    if cli.staged {
+        app.view_state = AppViewState::StagedFiles;
+    }
+    if let Some(path) = apply_patch {
+        app.apply_patch(&path, cli.staged)?;
+    }
+    if let (Some(file), Some(startup)) = (file, &mut app.startup) {
+        startup.file = Some(RepoPath::from(file));
+    }
+    if app.config.auto_stage.enabled {
+        app.toggle_auto_stage()?;
+    }
+    Ok((app, emitting))
+}
 
-    let res = run_app(&mut terminal, app, tick_rate);
+// Prints the patch of what was staged with `--emit-patch`, once the terminal is given back.
+fn emit_patch(emitting: Option<(patch::Emitting, DiffSettings)>) -> anyhow::Result<()> {
+    if let Some((emitting, settings)) = emitting {
+        io::stdout().write_all(&emitting.finish(settings)?)?;
+    }
+    Ok(())
+}
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+// Makes sure there's a repository to work in at `dir` before anything else.
+fn ensure_repository(dir: &Path, init: bool) -> anyhow::Result<()> {
+    match git::discover(dir) {
+        Ok(_) => Ok(()),
+        Err(e) if !repository_missing(&e) => Err(e),
+        Err(_) => create_repository(dir, init),
+    }
+}
 
-    res?;
+// Whether `e`, from looking for the repository, is that there's none here. Where GIT_DIR points
+// somewhere else, that's where the repository was meant to be, and not finding it is an error.
+fn repository_missing(e: &anyhow::Error) -> bool {
+    let not_found = e
+        .downcast_ref::<git2::Error>()
+        .is_some_and(|e| e.code() == git2::ErrorCode::NotFound);
+    not_found && std::env::var_os("GIT_DIR").is_none()
+}
 
+// Outside of a repository, offers to create one in `dir`, which `init` does without asking, or
+// stops saying what to do. It's asked with the terminal as the shell has it.
+fn create_repository(dir: &Path, mut init: bool) -> anyhow::Result<()> {
+    if !init && io::stdin().is_terminal() && io::stderr().is_terminal() {
+        eprint!(
+            "{} isn't in a git repository. Create one? [y/N] ",
+            dir.display()
+        );
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        init = matches!(answer.trim(), "y" | "Y" | "yes");
+    }
+    if !init {
+        anyhow::bail!(
+            "{} isn't in a git repository: run `git init` first, or start with --init",
+            dir.display()
+        );
+    }
+    git2::Repository::init(dir)?;
+    eprintln!("Created an empty git repository in {}", dir.display());
     Ok(())
 }
 
+// The config, with the overrides given on the command line, and the settings of the repository at
+// `location` if there's one.
+fn load_config(
+    cli: &cli::Cli,
+    location: Option<&Location>,
+    file: Option<&Path>,
+) -> anyhow::Result<Config> {
+    Config::load(location, file, &config_overrides(cli))
+}
+
+// The `-c` overrides, with `--no-color` taken as the override it stands for.
+fn config_overrides(cli: &cli::Cli) -> Vec<String> {
+    let mut overrides = cli.config_overrides.clone();
+    if cli.no_color {
+        overrides.push(String::from("theme.no-color=true"));
+    }
+    overrides
+}
+
+// Takes over the terminal while `run` runs, in raw mode on the alternate screen, and gives it
+// back afterwards whether `run` succeeded, failed or panicked.
+fn in_terminal<T>(
+    mouse: bool,
+    run: impl FnOnce(&mut Terminal<CrosstermBackend<Box<dyn Write>>>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    terminal::give_back_on_exit()?;
+    let guard = TerminalGuard::take(mouse)?;
+    let backend = CrosstermBackend::new(terminal_output());
+    let mut terminal = Terminal::new(backend)?;
+
+    let res = run(&mut terminal);
+
+    guard.release()?;
+    res
+}
+
 struct StatefulList<T> {
     state: ListState,
+    // The item at the top, as the list was last drawn.
+    offset: usize,
+    // The items shown, i.e. those matching `filter`.
     items: Vec<T>,
+    // Every item, whether it matches `filter` or not.
+    all: Vec<T>,
+    filter: String,
+    // The `ListKey`s of the marked items, which batch operations act on instead of the current one.
+    marked: BTreeSet<RepoPath>,
 }
 
-impl<T> StatefulList<T> {
+impl<T: Clone> StatefulList<T> {
     fn with_items(items: Vec<T>) -> StatefulList<T> {
+        // Nothing is selected in an empty list.
         let mut state = ListState::default();
-        state.select(Some(0));
-        StatefulList { state, items }
-    }
-
-    fn set_items(&mut self, items: Vec<T>) {
-        self.items = items;
-
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i >= self.items.len() - 1 {
-                    self.items.len() - 1
-                } else {
-                    i
-                }
-            }
-            None => 0,
-        };
-        self.state.select(Some(i))
+        state.select((!items.is_empty()).then_some(0));
+        StatefulList {
+            state,
+            offset: 0,
+            all: items.clone(),
+            items,
+            filter: String::new(),
+            marked: BTreeSet::new(),
+        }
     }
+}
 
+impl<T> StatefulList<T> {
     fn current(&self) -> Option<&T> {
         match self.state.selected() {
-            Some(i) => Some(&self.items[i]),
+            Some(i) => self.items.get(i),
             None => None,
         }
     }
 
     fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -98,6 +459,9 @@ impl<T> StatefulList<T> {
     }
 
     fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -116,24 +480,637 @@ impl<T> StatefulList<T> {
     }
 }
 
+// Identifies a list item across refreshes, wherever it ends up in the list.
+trait ListKey {
+    fn list_key(&self) -> &RepoPath;
+}
+
+impl ListKey for StatusEntry {
+    fn list_key(&self) -> &RepoPath {
+        &self.new_file
+    }
+}
+
+impl<T: ListKey + Clone> StatefulList<T> {
+    // Replaces the items, keeping the cursor on the same item if it's still shown. Otherwise the
+    // cursor stays at the same position, or moves up to the new last item.
+    fn set_items(&mut self, all: Vec<T>) {
+        // The filter matches the paths as they're shown.
+        let items: Vec<T> = all
+            .iter()
+            .filter(|i| filter::matches(&self.filter, &i.list_key().escaped()).is_some())
+            .cloned()
+            .collect();
+        let key = self.current().map(|c| c.list_key().clone());
+        let moved = key.and_then(|k| items.iter().position(|i| *i.list_key() == k));
+        self.items = items;
+        self.all = all;
+        let all = &self.all;
+        self.marked
+            .retain(|k| all.iter().any(|i| i.list_key() == k));
+
+        let i = match (moved, self.state.selected()) {
+            _ if self.items.is_empty() => None,
+            (Some(i), _) => Some(i),
+            (None, Some(i)) => Some(i.min(self.items.len() - 1)),
+            (None, None) => Some(0),
+        };
+        self.state.select(i)
+    }
+
+    // Changes the items in place, rather than replacing them with freshly computed ones.
+    fn edit_items(&mut self, edit: impl FnOnce(&mut Vec<T>)) {
+        let mut all = std::mem::take(&mut self.all);
+        edit(&mut all);
+        self.set_items(all);
+    }
+
+    // Shows only the items matching `filter`, or all of them if it's empty.
+    fn set_filter(&mut self, filter: &str) {
+        self.filter = filter.to_string();
+        let all = std::mem::take(&mut self.all);
+        self.set_items(all);
+    }
+
+    // Moves the cursor to the item with `key`, if it's shown.
+    fn select_key(&mut self, key: &RepoPath) -> bool {
+        match self.items.iter().position(|i| i.list_key() == key) {
+            Some(i) => {
+                self.state.select(Some(i));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        let key = match self.current() {
+            Some(item) => item.list_key().clone(),
+            None => return,
+        };
+        if !self.marked.remove(&key) {
+            self.marked.insert(key);
+        }
+    }
+
+    // Marks every item shown, or unmarks them all if they already are.
+    fn toggle_mark_all(&mut self) {
+        let keys = self.items.iter().map(|i| i.list_key().clone());
+        if self.items.iter().all(|i| self.is_marked(i)) {
+            for key in keys {
+                self.marked.remove(&key);
+            }
+        } else {
+            self.marked.extend(keys);
+        }
+    }
+
+    fn is_marked(&self, item: &T) -> bool {
+        self.marked.contains(item.list_key())
+    }
+
+    fn marked_items(&self) -> Vec<&T> {
+        self.items.iter().filter(|i| self.is_marked(i)).collect()
+    }
+}
+
 enum AppViewState {
     UnstagedFiles,
     StagedFiles,
 }
 
+#[derive(Copy, Clone)]
+enum BatchOp {
+    Stage,
+    Unstage,
+    Discard,
+}
+
+impl BatchOp {
+    fn progress(self) -> &'static str {
+        match self {
+            BatchOp::Stage => "staging",
+            BatchOp::Unstage => "unstaging",
+            BatchOp::Discard => "discarding",
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            BatchOp::Stage => "Stage",
+            BatchOp::Unstage => "Unstage",
+            BatchOp::Discard => "Discard changes to",
+        }
+    }
+
+    fn action(self) -> ConfirmAction {
+        match self {
+            BatchOp::Stage => ConfirmAction::Stage,
+            BatchOp::Unstage => ConfirmAction::Unstage,
+            BatchOp::Discard => ConfirmAction::Discard,
+        }
+    }
+
+    fn run(
+        self,
+        location: &Location,
+        backend: &dyn GitBackend,
+        entries: &[StatusEntry],
+        config: &Config,
+    ) -> anyhow::Result<()> {
+        match self {
+            BatchOp::Stage => backend.stage(entries)?,
+            BatchOp::Unstage => backend.unstage(entries)?,
+            BatchOp::Discard => {
+                stash::save_discarded(location, entries)?;
+                backend.discard(entries, config.discard_untracked)?
+            }
+        }
+        journal::record(location, self.action().name(), entries)
+    }
+}
+
+// An operation on a commit selected in the log view.
+#[derive(Copy, Clone)]
+enum CommitOp {
+    CherryPick,
+    Revert,
+    // Reverts without committing, leaving the changes staged.
+    StageRevert,
+}
+
+impl CommitOp {
+    fn action(self) -> ConfirmAction {
+        match self {
+            CommitOp::CherryPick => ConfirmAction::CherryPick,
+            CommitOp::Revert | CommitOp::StageRevert => ConfirmAction::Revert,
+        }
+    }
+
+    fn command(self) -> &'static str {
+        match self {
+            CommitOp::CherryPick => "cherry-pick",
+            CommitOp::Revert | CommitOp::StageRevert => "revert",
+        }
+    }
+
+    fn question(self, commit: &str) -> String {
+        match self {
+            CommitOp::CherryPick => format!("Cherry-pick {} onto the current branch?", commit),
+            CommitOp::Revert => format!("Revert {} in a new commit?", commit),
+            CommitOp::StageRevert => format!("Stage the changes reverting {}?", commit),
+        }
+    }
+
+    fn verb(self, commit: &str) -> String {
+        match self {
+            CommitOp::CherryPick => format!("Cherry-picking {}", commit),
+            CommitOp::Revert | CommitOp::StageRevert => format!("Reverting {}", commit),
+        }
+    }
+
+    fn done(self) -> &'static str {
+        match self {
+            CommitOp::CherryPick => "Cherry-picked",
+            CommitOp::Revert => "Reverted",
+            CommitOp::StageRevert => "Staged the revert for review",
+        }
+    }
+
+    fn run(self, location: &Location, id: Oid) -> anyhow::Result<PickOutcome> {
+        match self {
+            CommitOp::CherryPick => history::cherry_pick(location, id),
+            CommitOp::Revert => history::revert(location, id, false),
+            CommitOp::StageRevert => history::revert(location, id, true),
+        }
+    }
+}
+
+// An operation on a stash selected in the stash view.
+#[derive(Copy, Clone)]
+enum StashOp {
+    Apply,
+    Pop,
+    Drop,
+}
+
+impl StashOp {
+    // The name of the operation in the journal.
+    fn name(self) -> &'static str {
+        match self {
+            StashOp::Apply => "apply-stash",
+            StashOp::Pop => "pop-stash",
+            StashOp::Drop => "drop-stash",
+        }
+    }
+
+    fn run(self, location: &Location, stash: &StashEntry) -> anyhow::Result<()> {
+        match self {
+            StashOp::Apply => stash::apply(location, stash),
+            StashOp::Pop => stash::pop(location, stash),
+            StashOp::Drop => stash::drop(location, stash.index),
+        }
+    }
+}
+
+enum PendingAction {
+    Batch {
+        op: BatchOp,
+        entries: Vec<StatusEntry>,
+        // Set when the batch contains protected or suspicious files that must be acknowledged in a
+        // second step.
+        needs_stage_ack: bool,
+    },
+    RestoreDiscard,
+    RestoreCheckpoint,
+    PushTag {
+        remote: String,
+        name: String,
+    },
+    Autosquash {
+        onto: Option<Oid>,
+    },
+    CommitOp(CommitOp, LogCommit),
+    DropStash(StashEntry),
+    ResolveConflict {
+        path: String,
+        side: Side,
+    },
+    Hunk {
+        op: BatchOp,
+        entry: StatusEntry,
+        hunk: usize,
+        // Just these lines of the hunk.
+        lines: Option<Range<usize>>,
+    },
+}
+
+struct Pending {
+    action: PendingAction,
+    confirm: Dialog,
+}
+
+// What the text typed into the prompt is for.
+#[derive(Copy, Clone)]
+enum PromptPurpose {
+    Command,
+    // Narrows the file lists down as it's typed.
+    Filter,
+    StashMessage,
+    TagName(Oid),
+    // The revision to list the changes since.
+    CompareRevision,
+    // Where to write the staged changes, or the selected file's unstaged ones, as a patch.
+    ExportPatch,
+    // The patch to apply to the worktree, or to the index from the staged view.
+    ApplyPatch,
+}
+
+// What a message being written in the editor is for.
+enum DraftPurpose {
+    // A tag whose name has already been chosen.
+    Tag { name: String, target: Oid },
+    Commit,
+    Amend,
+    // A `squash!` commit, whose target's parent a rebase folding it in starts from.
+    Fixup { onto: Option<Oid> },
+}
+
+struct Draft {
+    purpose: DraftPurpose,
+    editor: Editor,
+}
+
+// A commit waiting on its hooks, whose output is shown meanwhile.
+struct CommitHooks {
+    // What to go back to if a hook fails.
+    draft: Draft,
+    message: String,
+    hook: Hook,
+    run: CommandRun,
+}
+
+// What can be done about a hook that failed, for the pane showing its output.
+const HOOK_FAILED_HINT: &str =
+    "[n] commit anyway, without running hooks   [esc] back to the message";
+
+// What changed since a revision, listed in place of the file lists while comparing with it.
+struct Comparison {
+    // As it was typed, e.g. "HEAD~3".
+    revision: String,
+    // The commit it named.
+    id: Oid,
+    // Whether the changes go to the index rather than to the worktree.
+    staged: bool,
+    files: StatefulList<FileDiff>,
+    scroll: Scroll,
+}
+
+impl Comparison {
+    fn open(
+        location: &Location,
+        revision: &str,
+        staged: bool,
+        config: &Config,
+    ) -> anyhow::Result<Comparison> {
+        let settings = DiffSettings::from(config);
+        let (id, files) =
+            history::diff_since(location, revision, staged, settings, &config.pathspecs)?;
+        Ok(Comparison {
+            revision: revision.to_string(),
+            id,
+            staged,
+            files: StatefulList::with_items(files),
+            scroll: Scroll::default(),
+        })
+    }
+
+    // Compares again, with the index if `staged` and with the worktree otherwise, keeping the same
+    // file selected if it's still there.
+    fn reload(&mut self, location: &Location, staged: bool, config: &Config) -> anyhow::Result<()> {
+        let selected = self.files.current().map(|f| f.new_path.clone());
+        *self = Comparison::open(location, &self.revision, staged, config)?;
+        if let Some(i) = self
+            .files
+            .items
+            .iter()
+            .position(|f| Some(&f.new_path) == selected.as_ref())
+        {
+            self.files.state.select(Some(i));
+        }
+        Ok(())
+    }
+
+    fn select(&mut self, forward: bool) {
+        match forward {
+            true => self.files.next(),
+            false => self.files.previous(),
+        }
+        self.scroll = Scroll::default();
+    }
+}
+
+// A push, pull or rebase running in the background, shown until it exits.
+struct GitRun {
+    op: GitOp,
+    run: CommandRun,
+}
+
+// How many percent of the screen a pane grows or shrinks by per key.
+const RESIZE_STEP: i16 = 5;
+// How many percent of the screen the console takes when it's toggled on.
+const CONSOLE_SIZE: u16 = 30;
+
+// The lists are first loaded in the background like any refresh, so that the app shows right away
+// however long a big repository takes to read, saying it's loading meanwhile. What's selected
+// then, as the last session left it or given on the command line, waits until they're there.
+struct Startup {
+    // The lists not loaded yet.
+    loading: Vec<FileStatusKind>,
+    // The selected entries and scroll offsets of the unstaged and staged lists, and the hunk of
+    // the selected entry, as the last session left them.
+    restored: Option<(ListPosition, ListPosition, usize)>,
+    // The entry whose diff to open, wherever it's listed.
+    file: Option<RepoPath>,
+}
+
+// A batch big enough to take a moment, which is run on the next frame so that the footer can say
+// what's going on in the meantime.
+struct Busy {
+    op: BatchOp,
+    entries: Vec<StatusEntry>,
+}
+
+// The diff of an entry being computed in the background.
+struct DiffJob {
+    staged: bool,
+    path: RepoPath,
+    worker: Worker<FileDiff>,
+}
+
+// The blame of the selected entry's file, being loaded in the background for the diff pane.
+struct BlameJob {
+    staged: bool,
+    path: RepoPath,
+    worker: Worker<FileBlame>,
+}
+
+// How many entries make a batch `Busy`.
+const LARGE_BATCH: usize = 100;
+
+// How many commits the log view lists.
+const LOG_LIMIT: usize = 200;
+
+// The most times a count typed before a key repeats it.
+const MAX_COUNT: usize = 999;
+
+// The smallest terminal anything but a notice saying it's too small is drawn in.
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 8;
+
 struct App {
+    // The repository, found once on starting, that everything is done in.
+    location: Location,
+    backend: Box<dyn GitBackend>,
     view_state: AppViewState,
     unstaged_files: StatefulList<StatusEntry>,
     staged_files: StatefulList<StatusEntry>,
+    file_view: Option<FileView>,
+    journal: Option<JournalView>,
+    log: Option<StatefulList<LogCommit>>,
+    // The upstream the log lists the incoming commits of, those HEAD doesn't have yet, rather than
+    // the recent ones.
+    incoming: Option<String>,
+    // The changes of the commit selected in the log.
+    commit_diff: Option<CommitDiff>,
+    comparison: Option<Comparison>,
+    stashes: Option<StashView>,
+    conflicts: Option<ConflictView>,
+    // What to hand the terminal over to (an editor, a shell), which is left to `run_app` as it
+    // owns the terminal.
+    handoff: Option<Command>,
+    // What was last copied to the clipboard, shown in the footer until the next key.
+    copied: Option<String>,
+    // The commands given with `--exec` still to run.
+    exec: Exec,
+    // The count typed so far for the next key to repeat what it does, vim-style, e.g. the 5 of
+    // `5down`.
+    count: Option<usize>,
+    // Set when the process is sent SIGTSTP from outside, to stop it only once the terminal has
+    // been restored.
+    stop_requested: Arc<AtomicBool>,
+    pending: Option<Pending>,
+    notice: Option<Dialog>,
+    // A popup of information, such as the statistics, with its title.
+    info: Option<(String, Vec<String>)>,
+    help: Option<HelpView>,
+    // The walk through the workflow, shown on the first launch.
+    tour: Option<Tour>,
+    prompt: Option<(PromptPurpose, Prompt)>,
+    ignore: Option<IgnoreMenu>,
+    fixup: Option<FixupPicker>,
+    // The files a commit leaves some of the changes of out.
+    partial: Option<PartialWarning>,
+    palette: Option<Palette>,
+    // The staged changes, shown before the commit message is written if `commit.review` is set.
+    review: Option<Review>,
+    draft: Option<Draft>,
+    commit_hooks: Option<CommitHooks>,
+    git_run: Option<GitRun>,
+    config: Config,
+    protected: GlobSet,
+    secrets: GlobSet,
+    formatters: Formatters,
+    keymap: Keymap,
+    theme: Theme,
+    auto_stage: Option<AutoStage>,
+    macros: Macros,
+    diff_view: Option<DiffView>,
+    // Whether keys go to the diff pane rather than the file list.
+    diff_focused: bool,
+    // Set while the file list is shown as a tree of directories.
+    tree: Option<Tree>,
+    // Whether both lists are shown at once, with a section each. `view_state` is then the section
+    // the cursor is in.
+    combined: bool,
+    combined_state: ListState,
+    combined_offset: usize,
+    // Where the last frame drew what can be clicked.
+    regions: mouse::Regions,
+    // The commits shown in the log pane, if the layout has one.
+    recent_commits: Vec<LogCommit>,
+    fetch: Option<Fetch>,
+    // A `git fetch` going on in the background, its progress shown in the footer.
+    remote_fetch: Option<CommandRun>,
+    notifications: Notifications,
+    busy: Option<Busy>,
+    // The file lists being refreshed in the background, at most one of each kind.
+    refreshes: Vec<(FileStatusKind, Worker<Vec<StatusEntry>>)>,
+    diff_job: Option<DiffJob>,
+    // What waits on the lists' first load, until they've loaded.
+    startup: Option<Startup>,
+    // The entry whose diff opens at another hunk than its first once it's loaded, and which: its
+    // last when going back a change from the first hunk of the next entry, or the one the last
+    // session left it at.
+    opening_hunk: Option<(bool, RepoPath, usize)>,
+    // Whether the diff pane annotates lines with the commit that last changed them.
+    show_blame: bool,
+    // How many lines the console pane is scrolled back from its end.
+    console_scroll: usize,
+    blames: BlameCache,
+    blame_job: Option<BlameJob>,
+    summary: Option<Summary>,
+    summary_job: Option<Worker<Summary>>,
+    // Set if `refresh.watch` is, and watching the worktree could be set up.
+    watcher: Option<Watcher>,
+    last_refresh: Instant,
+    // The index as of the last refresh, or the last change made to it here, to tell whether
+    // another program has changed it since.
+    index_stamp: Option<u64>,
 }
 
 impl App {
-    fn new(unstaged_files: Vec<StatusEntry>, staged_files: Vec<StatusEntry>) -> App {
-        App {
-            view_state: AppViewState::UnstagedFiles,
-            unstaged_files: StatefulList::with_items(unstaged_files),
-            staged_files: StatefulList::with_items(staged_files),
+    fn new(location: Location, config: Config) -> anyhow::Result<App> {
+        let backend = backend::open(config.backend, &location)?;
+        backend.set_diff_settings(DiffSettings::from(&config));
+        backend.set_pathspecs(&config.pathspecs);
+        backend.set_expanded_dirs(&config.expanded_dirs);
+        let mut app = App {
+            view_state: match config.list.view {
+                ListView::Unstaged => AppViewState::UnstagedFiles,
+                ListView::Staged => AppViewState::StagedFiles,
+            },
+            // Both loaded in the background, at the same time, starting below.
+            unstaged_files: StatefulList::with_items(Vec::new()),
+            staged_files: StatefulList::with_items(Vec::new()),
+            startup: Some(Startup {
+                loading: vec![FileStatusKind::Unstaged, FileStatusKind::Staged],
+                restored: None,
+                file: None,
+            }),
+            backend,
+            file_view: None,
+            journal: None,
+            log: None,
+            incoming: None,
+            commit_diff: None,
+            comparison: None,
+            stashes: None,
+            conflicts: None,
+            handoff: None,
+            copied: None,
+            exec: Exec::default(),
+            count: None,
+            stop_requested: stop_signal()?,
+            pending: None,
+            notice: None,
+            info: None,
+            help: None,
+            tour: None,
+            prompt: None,
+            ignore: None,
+            fixup: None,
+            partial: None,
+            palette: None,
+            review: None,
+            draft: None,
+            commit_hooks: None,
+            git_run: None,
+            protected: glob::set(&config.protected)?,
+            secrets: glob::set(&config.stage_warnings.secrets)?,
+            formatters: Formatters::new(&config.formatters)?,
+            keymap: Keymap::new(&config.keys, &config.commands)?,
+            theme: Theme::new(Some(&location), &config.theme)?,
+            notifications: Notifications::new(config.error_timeout),
+            // Without a watcher, the lists are still refreshed after every action.
+            watcher: match config.refresh.watch {
+                true => Watcher::new(location.workdir()).ok(),
+                false => None,
+            },
+            combined: config.list.combined,
+            config,
+            auto_stage: None,
+            macros: Macros::default(),
+            diff_view: None,
+            diff_focused: false,
+            tree: None,
+            combined_state: ListState::default(),
+            combined_offset: 0,
+            regions: mouse::Regions::default(),
+            recent_commits: Vec::new(),
+            fetch: None,
+            remote_fetch: None,
+            busy: None,
+            refreshes: Vec::new(),
+            diff_job: None,
+            show_blame: false,
+            console_scroll: 0,
+            blames: BlameCache::default(),
+            opening_hunk: None,
+            blame_job: None,
+            summary: None,
+            summary_job: None,
+            last_refresh: Instant::now(),
+            index_stamp: current_index_stamp(&location),
+            location,
+        };
+        app.start_refresh(FileStatusKind::Unstaged);
+        app.start_refresh(FileStatusKind::Staged);
+        app.start_summary();
+        Ok(app)
+    }
+
+    // The app as the last session in the repository left it, if `list.restore` is set.
+    fn restored(location: Location, mut config: Config) -> anyhow::Result<App> {
+        let state = config.list.restore.then(|| ui_state::load(&location));
+        if let Some(state) = &state {
+            config.expanded_dirs = state.expanded_dirs.clone();
         }
+        let mut app = App::new(location, config)?;
+        if let Some(state) = state {
+            app.restore(state);
+        }
+        Ok(app)
     }
 
     fn curr_file_list(&mut self) -> &mut StatefulList<StatusEntry> {
@@ -143,119 +1120,4822 @@ impl App {
         }
     }
 
-    fn change_view_state<F>(&mut self, next: AppViewState, mut on_enter: F) -> anyhow::Result<()>
-    where
-        F: FnMut(&mut App) -> anyhow::Result<()>,
-    {
-        on_enter(self)?;
-        self.view_state = next;
+    // The tree and the list it shows, while the list is shown as a tree.
+    fn curr_tree(&mut self) -> Option<(&mut Tree, &mut StatefulList<StatusEntry>)> {
+        let list = match self.view_state {
+            AppViewState::UnstagedFiles => &mut self.unstaged_files,
+            AppViewState::StagedFiles => &mut self.staged_files,
+        };
+        self.tree.as_mut().map(|tree| (tree, list))
+    }
+
+    fn curr_kind(&self) -> FileStatusKind {
+        match self.view_state {
+            AppViewState::UnstagedFiles => FileStatusKind::Unstaged,
+            AppViewState::StagedFiles => FileStatusKind::Staged,
+        }
+    }
+
+    // Starts refreshing the current file list, and marks the diff to be recomputed.
+    fn refresh_current(&mut self) -> anyhow::Result<()> {
+        self.last_refresh = Instant::now();
+        self.index_stamp = current_index_stamp(&self.location);
+        self.invalidate_diff();
+        self.refresh_recent_commits()?;
+        self.start_summary();
+        // A refresh already going may have read the statuses from before whatever prompted this one,
+        // so it's started over too.
+        for kind in [FileStatusKind::Unstaged, FileStatusKind::Staged] {
+            let going = self.refreshes.iter().any(|(k, _)| *k == kind);
+            if self.combined || kind == self.curr_kind() || going {
+                self.start_refresh(kind);
+            }
+        }
         Ok(())
     }
-}
 
-fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    mut app: App,
-    tick_rate: Duration,
-) -> anyhow::Result<()> {
-    let mut last_tick = Instant::now();
-    loop {
-        terminal.draw(|f| ui(f, &mut app))?;
-
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
-
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = crossterm::event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char('s') => {
-                        if let AppViewState::StagedFiles = app.view_state {
-                            continue;
-                        }
-                        if let Some(item) = app.unstaged_files.current() {
-                            item.stage_to_index()?;
-                            app.unstaged_files
-                                .set_items(get_file_statuses(git::FileStatusKind::Unstaged)?);
-                        }
-                    }
-                    KeyCode::Char('r') => {
-                        if let AppViewState::StagedFiles = app.view_state {
-                            continue;
-                        }
-                        if let Some(item) = app.unstaged_files.current() {
-                            item.reset_from_workdir()?;
-                            app.unstaged_files
-                                .set_items(get_file_statuses(git::FileStatusKind::Unstaged)?);
-                        }
-                    }
-                    KeyCode::Char('u') => {
-                        if let AppViewState::UnstagedFiles = app.view_state {
-                            continue;
-                        }
-                        if let Some(item) = app.staged_files.current() {
-                            item.unstage_to_workdir()?;
-                            app.staged_files
-                                .set_items(get_file_statuses(git::FileStatusKind::Staged)?);
-                        }
-                    }
-                    KeyCode::Char('t') => match app.view_state {
-                        AppViewState::UnstagedFiles => {
-                            app.change_view_state(AppViewState::StagedFiles, |app| {
-                                app.staged_files
-                                    .set_items(get_file_statuses(git::FileStatusKind::Staged)?);
-                                Ok(())
-                            })?
-                        }
-                        AppViewState::StagedFiles => {
-                            app.change_view_state(AppViewState::UnstagedFiles, |app| {
-                                app.unstaged_files
-                                    .set_items(get_file_statuses(git::FileStatusKind::Unstaged)?);
-                                Ok(())
-                            })?
-                        }
-                    },
-                    KeyCode::Down => app.curr_file_list().next(),
-                    KeyCode::Up => app.curr_file_list().previous(),
-                    KeyCode::Left => app.curr_file_list().unselect(),
-                    _ => {}
-                }
+    // Before changing the index: if another program (`git add` in another terminal, an editor) has
+    // changed it since the lists were loaded, what they show isn't what would be changed, and a
+    // hunk may not even be the one selected. Rather than clobber that, nothing is changed, and
+    // everything is reloaded for the change to be made again from what's there now.
+    fn check_index(&mut self) -> anyhow::Result<()> {
+        if current_index_stamp(&self.location) == self.index_stamp {
+            return Ok(());
+        }
+        self.refresh_current()?;
+        // Whatever changed it may have changed what's in the list not shown, too.
+        for kind in [FileStatusKind::Unstaged, FileStatusKind::Staged] {
+            if !self.refreshes.iter().any(|(k, _)| *k == kind) {
+                self.start_refresh(kind);
             }
         }
+        anyhow::bail!("the index was changed by another program: reloaded, nothing was changed")
+    }
 
-        if last_tick.elapsed() >= tick_rate {
-            last_tick = Instant::now();
+    // Starts the refreshes already going over again, as what they read may be from before a
+    // change just made.
+    fn restart_refreshes(&mut self) {
+        let kinds: Vec<FileStatusKind> = self.refreshes.iter().map(|(k, _)| *k).collect();
+        for kind in kinds {
+            self.start_refresh(kind);
         }
     }
-}
 
-fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
-    match app.view_state {
-        AppViewState::UnstagedFiles => files_view(f, &mut app.unstaged_files),
-        AppViewState::StagedFiles => files_view(f, &mut app.staged_files),
+    // Moves the cursor down, or in the combined view from the end of a section to the start of
+    // the other one.
+    // Switches to the other list, or to the other section of the combined one.
+    fn toggle_view(&mut self) -> anyhow::Result<()> {
+        if self.combined {
+            self.switch_section(true);
+            return Ok(());
+        }
+        match self.view_state {
+            AppViewState::UnstagedFiles => {
+                self.change_view_state(AppViewState::StagedFiles, |app| {
+                    app.start_refresh(FileStatusKind::Staged);
+                    Ok(())
+                })
+            }
+            AppViewState::StagedFiles => {
+                self.change_view_state(AppViewState::UnstagedFiles, |app| {
+                    app.start_refresh(FileStatusKind::Unstaged);
+                    Ok(())
+                })
+            }
+        }
     }
-}
 
-fn files_view<B: Backend>(f: &mut Frame<B>, input: &mut StatefulList<StatusEntry>) {
-    let size = f.size();
-    let items: Vec<ListItem> = input
-        .items
-        .iter()
-        .map(|s| {
-            ListItem::new(Span::styled(
-                s.pretty_string(),
-                Style::default().fg(s.status.into()),
-            ))
-            .style(Style::default().fg(Color::Gray))
-        })
-        .collect();
+    fn next_entry(&mut self) {
+        let list = self.curr_file_list();
+        let at_end = list
+            .state
+            .selected()
+            .is_none_or(|i| i + 1 >= list.items.len());
+        if !(self.combined && at_end && self.switch_section(true)) {
+            self.curr_file_list().next();
+        }
+    }
 
-    let list = List::new(items).highlight_style(
-        Style::default()
-            .bg(Color::Rgb(75, 75, 75))
-            .add_modifier(Modifier::BOLD),
+    fn previous_entry(&mut self) {
+        let at_start = self
+            .curr_file_list()
+            .state
+            .selected()
+            .is_none_or(|i| i == 0);
+        if !(self.combined && at_start && self.switch_section(false)) {
+            self.curr_file_list().previous();
+        }
+    }
+
+    // Splits the selected hunk of the diff pane into a hunk for each run of changes in it.
+    fn split_hunk(&mut self) -> anyhow::Result<()> {
+        if let Some(view) = self.diff_view.as_mut().filter(|v| !v.stale) {
+            if view.hunk_count() > 0 && !view.split_hunk(&self.theme) {
+                anyhow::bail!("hunk {} can't be split any further", view.hunk + 1);
+            }
+        }
+        Ok(())
+    }
+
+    // Moves to the next (or previous) change: the next hunk of the selected entry's diff, or past
+    // its last one the first hunk of the next entry, wrapping around the list (and across both
+    // sections of the combined view). Nothing moves while the diff is loading.
+    fn jump_to_change(&mut self, forward: bool) {
+        let scroll = self.config.scroll;
+        if self
+            .config
+            .layout
+            .panes
+            .iter()
+            .any(|p| p.pane == Pane::Diff)
+        {
+            let view = match self.selected_diff() {
+                Some(view) if view.stale => return,
+                Some(view) => view,
+                None => return self.step_entry(forward),
+            };
+            match forward {
+                true if view.hunk + 1 < view.hunk_count() => return view.next_hunk(&scroll),
+                false if view.hunk > 0 => return view.previous_hunk(&scroll),
+                _ => {}
+            }
+        }
+
+        let before = self.curr_file_list().current().map(|e| e.new_file.clone());
+        self.step_entry(forward);
+        let staged = matches!(self.curr_kind(), FileStatusKind::Staged);
+        let after = match self.curr_file_list().current() {
+            Some(entry) => entry.new_file.clone(),
+            None => return,
+        };
+        let hunk = if forward { 0 } else { usize::MAX };
+        match &mut self.diff_view {
+            // The only entry: its diff wraps around to its other end.
+            Some(view) if before.as_ref() == Some(&after) && view.staged == staged => {
+                view.select_hunk(hunk, &scroll)
+            }
+            _ if !forward => self.opening_hunk = Some((staged, after, usize::MAX)),
+            _ => {}
+        }
+    }
+
+    // Selects the next (or previous) entry, passing over the directories of the tree.
+    fn step_entry(&mut self, forward: bool) {
+        if let Some((tree, list)) = self.curr_tree() {
+            for _ in 0..tree.rows(list).len() {
+                match forward {
+                    true => tree.next(list),
+                    false => tree.previous(list),
+                }
+                if list.current().is_some() {
+                    break;
+                }
+            }
+            return;
+        }
+        match forward {
+            true => self.next_entry(),
+            false => self.previous_entry(),
+        }
+    }
+
+    // Moves the cursor to the first (or last) entry of the other list, if it has any.
+    fn switch_section(&mut self, first: bool) -> bool {
+        let (other, list) = match self.view_state {
+            AppViewState::UnstagedFiles => (AppViewState::StagedFiles, &mut self.staged_files),
+            AppViewState::StagedFiles => (AppViewState::UnstagedFiles, &mut self.unstaged_files),
+        };
+        if list.items.is_empty() {
+            return false;
+        }
+        let i = match first {
+            true => 0,
+            false => list.items.len() - 1,
+        };
+        list.state.select(Some(i));
+        self.view_state = other;
+        true
+    }
+
+    // Changes how the diff pane shows diffs: with more or less context, leaving out whitespace
+    // changes or not, with whitespace errors highlighted or not, and with CRLF line endings
+    // shown or not.
+    fn adjust_diff(&mut self, action: Action) {
+        let preview = &mut self.config.preview;
+        match action {
+            Action::MoreContext => preview.context = preview.context.saturating_add(1),
+            Action::LessContext => preview.context = preview.context.saturating_sub(1),
+            Action::CycleWhitespace => preview.whitespace = preview.whitespace.next(),
+            Action::ToggleWhitespaceErrors => {
+                self.theme.whitespace_errors = !self.theme.whitespace_errors
+            }
+            Action::ToggleLineEndings => self.theme.line_endings = !self.theme.line_endings,
+            _ => return,
+        }
+        self.backend
+            .set_diff_settings(DiffSettings::from(&self.config));
+        self.invalidate_diff();
+    }
+
+    fn invalidate_diff(&mut self) {
+        if let Some(view) = &mut self.diff_view {
+            view.stale = true;
+        }
+        // A diff being computed may be from before whatever changed.
+        self.diff_job = None;
+        self.blames.invalidate();
+        self.blame_job = None;
+    }
+
+    // Moves the entries just staged, unstaged or discarded between the file lists, instead of
+    // recomputing every status. Returns false, having changed nothing, if where they end up isn't
+    // obvious (e.g. renames, or unstaging a file that has unstaged changes too) and the lists need
+    // a refresh.
+    fn update_lists(&mut self, op: BatchOp, entries: &[StatusEntry]) -> bool {
+        let simple = entries.iter().all(|e| {
+            !e.submodule
+                && matches!(
+                    e.status,
+                    Status::Added | Status::Deleted | Status::Modified | Status::Untracked
+                )
+        });
+        if !simple {
+            return false;
+        }
+        let find = |list: &StatefulList<StatusEntry>, e: &StatusEntry| {
+            list.all
+                .iter()
+                .find(|o| o.new_file == e.new_file)
+                .map(|o| o.status)
+        };
+        let (from, to) = match op {
+            BatchOp::Stage => (&mut self.unstaged_files, &mut self.staged_files),
+            BatchOp::Unstage => (&mut self.staged_files, &mut self.unstaged_files),
+            BatchOp::Discard => (&mut self.unstaged_files, &mut self.staged_files),
+        };
+
+        let mut moved = Vec::new();
+        if !matches!(op, BatchOp::Discard) {
+            for entry in entries {
+                let status = match (op, entry.status, find(to, entry)) {
+                    (BatchOp::Stage, Status::Untracked, None) => Status::Added,
+                    (BatchOp::Unstage, Status::Added, None) => Status::Untracked,
+                    (_, status, None) => status,
+                    // Staging the rest of a partly staged file leaves its staged entry as it was,
+                    // but for the lines it changes.
+                    (BatchOp::Stage, Status::Modified, Some(Status::Modified | Status::Added))
+                        if !self.theme.line_counts =>
+                    {
+                        continue
+                    }
+                    _ => return false,
+                };
+                let mut entry = entry.clone();
+                entry.status = status;
+                moved.push(entry);
+            }
+        }
+
+        let mut all = to.all.clone();
+        for entry in moved {
+            match sort::position(&all, &entry, self.config.list.sort) {
+                Some(at) => all.insert(at, entry),
+                None => return false,
+            }
+        }
+        to.set_items(all);
+        from.edit_items(|all| all.retain(|e| !entries.iter().any(|o| o.new_file == e.new_file)));
+        self.invalidate_diff();
+        self.restart_refreshes();
+        self.start_summary();
+        // The watcher would otherwise refresh the lists when it sees the index written.
+        if let Some(watcher) = &mut self.watcher {
+            watcher.ignore_index_writes();
+        }
+        true
+    }
+
+    // Starts computing the statuses of `kind` in the background, instead of any refresh of them
+    // already going.
+    fn start_refresh(&mut self, kind: FileStatusKind) {
+        self.refreshes.retain(|(k, _)| *k != kind);
+        let (order, counted) = (self.config.list.sort, self.theme.line_counts);
+        let worker = Worker::start(&self.location, &self.config, move |b| {
+            sort::sorted_statuses(b, kind, order, counted)
+        });
+        self.refreshes.push((kind, worker));
+    }
+
+    // Starts gathering what the status bar shows, over again if it's already going.
+    fn start_summary(&mut self) {
+        if self.config.layout.status_bar {
+            let pathspecs = self.config.pathspecs.clone();
+            let settings = DiffSettings::from(&self.config);
+            let location = self.location.clone();
+            let worker = Worker::start(&self.location, &self.config, move |_| {
+                Summary::load(&location, settings, pathspecs)
+            });
+            self.summary_job = Some(worker);
+        }
+    }
+
+    fn statuses_loaded(&mut self, kind: FileStatusKind, entries: Vec<StatusEntry>) {
+        match kind {
+            FileStatusKind::Unstaged => self.unstaged_files.set_items(entries),
+            FileStatusKind::Staged => self.staged_files.set_items(entries),
+        }
+        if let Some(startup) = &mut self.startup {
+            startup.loading.retain(|k| *k != kind);
+            if startup.loading.is_empty() {
+                if let Some(startup) = self.startup.take() {
+                    self.started(startup);
+                }
+            }
+        }
+    }
+
+    // Selects what was waiting for the lists' first load.
+    fn started(&mut self, startup: Startup) {
+        if let Some((unstaged, staged, hunk)) = startup.restored {
+            for (list, position) in [
+                (&mut self.unstaged_files, unstaged),
+                (&mut self.staged_files, staged),
+            ] {
+                if let Some(selected) = position.selected {
+                    list.select_key(&selected);
+                }
+                list.offset = position.offset;
+            }
+            let staged = self.curr_kind() == FileStatusKind::Staged;
+            if let Some(entry) = self.curr_file_list().current() {
+                self.opening_hunk = Some((staged, entry.new_file.clone(), hunk));
+            }
+        }
+        if let Some(file) = startup.file {
+            self.jump_to_file(&file);
+        }
+    }
+
+    // Whether git work is still going on in the background for the lists, the diff or the status
+    // bar.
+    fn loading(&self) -> bool {
+        !self.refreshes.is_empty()
+            || self.diff_job.is_some()
+            || self.blame_job.is_some()
+            || self.summary_job.is_some()
+    }
+
+    // Whether keys go to something other than the lists and the diff: a popup, another view, or
+    // a program the terminal is handed off to.
+    fn overlaid(&self) -> bool {
+        self.notice.is_some()
+            || self.info.is_some()
+            || self.help.is_some()
+            || self.tour.is_some()
+            || self.pending.is_some()
+            || self.commit_hooks.is_some()
+            || self.git_run.is_some()
+            || self.ignore.is_some()
+            || self.fixup.is_some()
+            || self.partial.is_some()
+            || self.draft.is_some()
+            || self.prompt.is_some()
+            || self.palette.is_some()
+            || self.file_view.is_some()
+            || self.journal.is_some()
+            || self.stashes.is_some()
+            || self.conflicts.is_some()
+            || self.review.is_some()
+            || self.comparison.is_some()
+            || self.log.is_some()
+            || self.handoff.is_some()
+            || self.busy.is_some()
+    }
+
+    // What's open waiting for an answer before what it's part of can go on, if anything is.
+    fn waiting_for(&self) -> Option<&'static str> {
+        if self.pending.is_some() {
+            Some(exec::CONFIRMATION)
+        } else if self.draft.is_some() {
+            Some("the message editor")
+        } else if self.commit_hooks.is_some() {
+            Some("a commit hook's output")
+        } else if self
+            .partial
+            .as_ref()
+            .is_some_and(|p| p.awaits_confirmation())
+        {
+            Some("the partly staged files")
+        } else if self.prompt.is_some() || self.palette.is_some() {
+            Some("a prompt")
+        } else if self.ignore.is_some() || self.fixup.is_some() {
+            Some("a menu")
+        } else {
+            None
+        }
+    }
+
+    // What `--exec` does next, once nothing's in progress.
+    fn exec_step(&mut self) -> Option<exec::Step> {
+        if self.in_progress() || self.busy.is_some() {
+            return None;
+        }
+        if let Some(pending) = &self.pending {
+            if self.exec.answers(self.is_destructive(&pending.action)) {
+                return Some(exec::Step::Confirm);
+            }
+        }
+        let waiting = self.waiting_for();
+        self.exec.step(waiting)
+    }
+
+    // Whether going on with `action` loses work, rewrites history or reaches outside the
+    // repository, or stages files that always have to be acknowledged explicitly.
+    fn is_destructive(&self, action: &PendingAction) -> bool {
+        match action {
+            PendingAction::Batch {
+                op: BatchOp::Stage,
+                entries,
+                needs_stage_ack,
+            } => *needs_stage_ack || !self.stage_warnings(entries).is_empty(),
+            PendingAction::Batch { op, .. } | PendingAction::Hunk { op, .. } => {
+                op.action().is_destructive()
+            }
+            PendingAction::RestoreDiscard => ConfirmAction::RestoreDiscard.is_destructive(),
+            PendingAction::CommitOp(op, _) => op.action().is_destructive(),
+            PendingAction::RestoreCheckpoint
+            | PendingAction::PushTag { .. }
+            | PendingAction::Autosquash { .. }
+            | PendingAction::DropStash(_)
+            | PendingAction::ResolveConflict { .. } => true,
+        }
+    }
+
+    // Whether something on screen changes by itself while it goes on: a spinner while loading,
+    // or the output of a command that's running.
+    fn in_progress(&self) -> bool {
+        self.loading()
+            || self.fetch.is_some()
+            || self.remote_fetch.is_some()
+            || self
+                .git_run
+                .as_ref()
+                .is_some_and(|r| r.run.status.is_none())
+            || self
+                .commit_hooks
+                .as_ref()
+                .is_some_and(|c| c.run.status.is_none())
+    }
+
+    // Filters both file lists, so that the filter still applies after switching between them.
+    fn set_filter(&mut self, filter: &str) {
+        self.unstaged_files.set_filter(filter);
+        self.staged_files.set_filter(filter);
+    }
+
+    fn refresh_recent_commits(&mut self) -> anyhow::Result<()> {
+        if self.config.layout.panes.iter().any(|p| p.pane == Pane::Log) {
+            self.recent_commits = history::recent_commits(&self.location, LOG_LIMIT)?;
+        }
+        Ok(())
+    }
+
+    // The diff of the selected entry, for the diff pane. It's computed in the background: until
+    // it's there, the pane keeps showing the entry's previous diff, or says it's loading.
+    fn selected_diff(&mut self) -> Option<&mut DiffView> {
+        let kind = self.curr_kind();
+        let staged = matches!(kind, FileStatusKind::Staged);
+        let entry = self.curr_file_list().current()?.clone();
+
+        let same = |v: &DiffView| v.staged == staged && v.path == entry.new_file;
+        let current = self.diff_view.as_ref().is_some_and(|v| !v.stale && same(v));
+        let loading = self
+            .diff_job
+            .as_ref()
+            .is_some_and(|j| j.staged == staged && j.path == entry.new_file);
+        if current || loading {
+            return self.diff_view.as_mut();
+        }
+
+        let path = entry.new_file.clone();
+        let max_size = self.config.preview.max_size;
+        let size = match entry.status {
+            Status::Untracked | Status::Ignored => {
+                fs::metadata(entry.abs_path_new()).map_or(0, |m| m.len())
+            }
+            _ => 0,
+        };
+        if entry.is_dir() {
+            self.diff_job = None;
+            let hint = collapsed_dir_hint(&self.keymap);
+            self.diff_view = Some(DiffView::message(staged, path, hint));
+            return self.diff_view.as_mut();
+        }
+        // An untracked or ignored file's diff is its whole content, so a big one isn't read at all.
+        if max_size > 0 && size > max_size {
+            let message = format!(
+                "new file of {}, too big to preview (preview.max-size is {})",
+                stats::format_size(size),
+                stats::format_size(max_size)
+            );
+            self.diff_job = None;
+            self.diff_view = Some(DiffView::message(staged, path, message));
+            return self.diff_view.as_mut();
+        }
+
+        if !self.diff_view.as_ref().is_some_and(same) {
+            let mut placeholder = DiffView::message(staged, path.clone(), "loading...".into());
+            placeholder.stale = true;
+            self.diff_view = Some(placeholder);
+        }
+        // A diff loaded anyway is loaded in full again when it's refreshed.
+        let forced = self
+            .diff_view
+            .as_ref()
+            .is_some_and(|v| same(v) && v.is_forced());
+        let max_lines = match forced {
+            true => 0,
+            false => self.config.preview.max_lines,
+        };
+        let worker = Worker::start(&self.location, &self.config, move |b| {
+            b.diff_up_to(&entry, kind, max_lines)
+        });
+        self.diff_job = Some(DiffJob {
+            staged,
+            path,
+            worker,
+        });
+        self.diff_view.as_mut()
+    }
+
+    fn diff_loaded(&mut self, job: DiffJob, diff: anyhow::Result<FileDiff>) {
+        let DiffJob { staged, path, .. } = job;
+        let max_lines = self.config.preview.max_lines;
+        let filter = self.config.preview.diff_filter();
+        let mut view = match diff {
+            Ok(diff) => match diff.held_back {
+                Some(lines) => {
+                    let hint = too_large_hint(lines, max_lines, &self.keymap);
+                    DiffView::too_large(staged, path, diff, hint)
+                }
+                None => DiffView::new(staged, path, diff, filter, &self.theme),
+            },
+            Err(e) if promisor::is_missing_object(&e) => {
+                DiffView::message(staged, path, not_fetched_hint(&self.keymap))
+            }
+            Err(e) => DiffView::message(staged, path, e.to_string()),
+        };
+        if let Some(e) = view.filter_error.take() {
+            self.notifications.push(e);
+        }
+        // Refreshing the same file's diff keeps the place in it.
+        let same = |v: &DiffView| v.staged == view.staged && v.path == view.path;
+        if let Some(previous) = self.diff_view.take().filter(same) {
+            view.keep_position(previous, &self.theme);
+        }
+        match self.opening_hunk.take() {
+            Some((staged, path, hunk)) if staged == view.staged && path == view.path => {
+                view.select_hunk(hunk, &self.config.scroll)
+            }
+            _ => {}
+        }
+        self.diff_view = Some(view);
+    }
+
+    // Loads the selected entry's diff in full if it was too large to show right away. Returns
+    // whether it was.
+    fn load_large_diff(&mut self) -> bool {
+        match self.diff_view.as_mut().filter(|v| v.is_capped()) {
+            Some(view) => {
+                view.load_anyway();
+                // Any diff of it still loading is held back too.
+                self.diff_job = None;
+                self.selected_diff();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // The blame of the selected entry's file, while the diff pane annotates lines with it. It's
+    // loaded in the background the first time it's needed, and again after a refresh.
+    fn selected_blame(&mut self) -> Option<&FileBlame> {
+        if !self.show_blame {
+            return None;
+        }
+        let staged = matches!(self.curr_kind(), FileStatusKind::Staged);
+        let entry = self.curr_file_list().current()?.clone();
+        let loading = self
+            .blame_job
+            .as_ref()
+            .is_some_and(|j| j.staged == staged && j.path == entry.new_file);
+        let stale = self
+            .blames
+            .get(staged, &entry.new_file)
+            .is_none_or(|(_, stale)| stale);
+        if stale && !loading {
+            // The old side of a rename is the file as it was before.
+            let old_file = entry.old_file.clone();
+            let location = self.location.clone();
+            let worker = Worker::start(&self.location, &self.config, move |_| {
+                FileBlame::load(&location, &old_file, staged)
+            });
+            self.blame_job = Some(BlameJob {
+                staged,
+                path: entry.new_file.clone(),
+                worker,
+            });
+        }
+        self.blames
+            .get(staged, &entry.new_file)
+            .map(|(blame, _)| blame)
+    }
+
+    // Starts fetching the objects of the selected entry that are missing from a partial clone.
+    fn fetch_missing(&mut self) -> anyhow::Result<()> {
+        if self.fetch.is_some() {
+            return Ok(());
+        }
+        let ids = match self.curr_file_list().current().cloned() {
+            Some(item) => promisor::missing_objects(&self.location, &item)?,
+            None => return Ok(()),
+        };
+        if ids.is_empty() {
+            self.notice = Some(Dialog::new("Nothing to fetch", Vec::new()));
+            return Ok(());
+        }
+        self.fetch = Some(Fetch::start(&self.location, ids)?);
+        Ok(())
+    }
+
+    // Starts fetching from the remote in the background, unless a fetch is going already. The
+    // lists stay usable meanwhile.
+    fn fetch_remote(&mut self) -> anyhow::Result<()> {
+        if self.remote_fetch.is_none() {
+            self.remote_fetch = Some(history::start_fetch(&self.location)?);
+        }
+        Ok(())
+    }
+
+    // Once the fetch is done, the status bar counts the commits it brought in, as does the log of
+    // the incoming ones if it's open.
+    fn remote_fetched(&mut self, status: anyhow::Result<ExitStatus>) -> anyhow::Result<()> {
+        let run = match self.remote_fetch.take() {
+            Some(run) => run,
+            None => return Ok(()),
+        };
+        if !status?.success() {
+            let last = run
+                .output
+                .iter()
+                .rev()
+                .map(|l| l.trim())
+                .find(|l| !l.is_empty() && !l.starts_with("hint:"))
+                .unwrap_or_default();
+            // As git can't ask for them with the terminal taken.
+            if run
+                .output
+                .iter()
+                .any(|l| l.contains("terminal prompts disabled"))
+            {
+                anyhow::bail!(
+                    "git fetch failed: {} (credentials must come from a credential helper or an ssh \
+                     agent)",
+                    last
+                );
+            }
+            anyhow::bail!("git fetch failed: {}", last);
+        }
+        self.start_summary();
+        if self.log.is_some() && self.incoming.is_some() {
+            self.open_incoming()?;
+        }
+        Ok(())
+    }
+
+    // The messages from the background work (fetches, commit hooks, pushes and pulls, outside
+    // changes, auto-staging) that has finished since the last call.
+    fn background_messages(&mut self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        let mut refreshes = Vec::new();
+        for (kind, worker) in self.refreshes.drain(..) {
+            match worker.poll() {
+                Some(result) => messages.push(Message::StatusesLoaded(kind, result)),
+                None => refreshes.push((kind, worker)),
+            }
+        }
+        self.refreshes = refreshes;
+        if let Some(result) = self.diff_job.as_ref().and_then(|j| j.worker.poll()) {
+            if let Some(job) = self.diff_job.take() {
+                messages.push(Message::DiffLoaded(job, result));
+            }
+        }
+        if let Some(result) = self.blame_job.as_ref().and_then(|j| j.worker.poll()) {
+            if let Some(job) = self.blame_job.take() {
+                messages.push(Message::BlameLoaded(job, result));
+            }
+        }
+        if let Some(result) = self.summary_job.as_ref().and_then(|w| w.poll()) {
+            self.summary_job = None;
+            messages.push(Message::SummaryLoaded(result));
+        }
+        if let Some(result) = self.fetch.as_ref().and_then(|f| f.poll()) {
+            self.fetch = None;
+            messages.push(Message::Fetched(result));
+        }
+        if let Some(c) = self
+            .commit_hooks
+            .as_mut()
+            .filter(|c| c.run.status.is_none())
+        {
+            match c.run.poll() {
+                Ok(Some(status)) => messages.push(Message::HookExited(Ok(status))),
+                Ok(None) => {}
+                Err(e) => messages.push(Message::HookExited(Err(e))),
+            }
+        }
+        if let Some(git_run) = &mut self.git_run {
+            match git_run.run.poll() {
+                Ok(Some(status)) => messages.push(Message::GitExited(Ok(status))),
+                Ok(None) => {}
+                Err(e) => messages.push(Message::GitExited(Err(e))),
+            }
+        }
+        if let Some(run) = &mut self.remote_fetch {
+            match run.poll() {
+                Ok(Some(status)) => messages.push(Message::RemoteFetched(Ok(status))),
+                Ok(None) => {}
+                Err(e) => messages.push(Message::RemoteFetched(Err(e))),
+            }
+        }
+
+        let watched = self.watcher.as_mut().is_some_and(|w| w.statuses_changed());
+        let interval = self.config.refresh.interval;
+        let due = interval > 0 && self.last_refresh.elapsed() >= Duration::from_secs(interval);
+        let staged = match &mut self.auto_stage {
+            Some(auto_stage) => auto_stage
+                .poll(&self.location, self.backend.as_ref())
+                .unwrap_or_else(|e| {
+                    messages.push(Message::Failed(e));
+                    false
+                }),
+            None => false,
+        };
+        if watched || due || staged {
+            messages.push(Message::StatusesChanged);
+        }
+        if self.stop_requested.swap(false, Ordering::Relaxed) {
+            messages.push(Message::Suspend);
+        }
+        messages
+    }
+
+    // Handles a message, returning what's left for `run_app` to do.
+    fn update(&mut self, message: Message, height: u16) -> anyhow::Result<Command> {
+        match message {
+            Message::Key { key, typed } => {
+                if self.macros.take_register(key) {
+                    return Ok(Command::None);
+                }
+                // Works anywhere, as ctrl-z does outside the app.
+                if let Some(Action::Suspend) = self.keymap.action(key) {
+                    return Ok(Command::Suspend);
+                }
+                if typed {
+                    self.macros.record(key);
+                }
+                self.notifications.dismiss();
+                self.copied = None;
+                if handle_key(self, key, height)? {
+                    // Quitting isn't held up by the state not being saved.
+                    if self.config.list.restore {
+                        ui_state::save(&self.location, &self.ui_state()).ok();
+                    }
+                    return Ok(Command::Quit);
+                }
+                if let Some(command) = self.handoff.take() {
+                    return Ok(command);
+                }
+            }
+            Message::Exec(command) => {
+                match run_command(self, &command, height) {
+                    Ok(true) => return Ok(Command::Quit),
+                    Ok(false) => {}
+                    Err(e) => {
+                        if let Some(e) = self.exec.abort(&command, e) {
+                            return Err(e);
+                        }
+                    }
+                }
+                if let Some(command) = self.handoff.take() {
+                    return Ok(command);
+                }
+            }
+            Message::ExecConfirm => {
+                if let Err(e) = self.confirm_pending() {
+                    let command = self.exec.last().to_string();
+                    if let Some(e) = self.exec.abort(&command, e) {
+                        return Err(e);
+                    }
+                }
+            }
+            Message::Mouse(event) => handle_mouse(self, event)?,
+            Message::Resized(height) => self.resized(height),
+            Message::StatusesChanged => self.refresh_current()?,
+            Message::StatusesLoaded(kind, entries) => self.statuses_loaded(kind, entries?),
+            Message::DiffLoaded(job, diff) => self.diff_loaded(job, diff),
+            Message::BlameLoaded(job, blame) => self.blames.insert(job.staged, job.path, blame?),
+            Message::SummaryLoaded(summary) => self.summary = Some(summary?),
+            Message::Fetched(result) => self.fetched(result)?,
+            Message::HookExited(status) => self.hook_exited(status)?,
+            Message::GitExited(status) => self.git_run_exited(status)?,
+            Message::RemoteFetched(status) => self.remote_fetched(status)?,
+            Message::RunBatch(busy) => self.run_batch_now(busy.op, &busy.entries)?,
+            Message::Suspend => return Ok(Command::Suspend),
+            // Whatever had the terminal may have changed anything.
+            Message::Resumed(result) => result.and_then(|()| self.reload_conflicts())?,
+            Message::Failed(e) => return Err(e),
+            Message::Tick => self.notifications.expire(),
+        }
+        // Staging or unstaging the last entry of a section leaves the cursor in the other one.
+        if self.combined && self.curr_file_list().items.is_empty() {
+            self.switch_section(true);
+        }
+        Ok(Command::None)
+    }
+
+    // Reloads what was waiting on a background fetch of missing objects.
+    fn fetched(&mut self, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        if let Err(e) = result {
+            self.notice = Some(Dialog::new(
+                "Could not fetch the missing objects",
+                vec![e.to_string()],
+            ));
+            return Ok(());
+        }
+
+        self.diff_view = None;
+        let snapshot = self.file_view.as_ref().map(|v| v.snapshot);
+        let item = self.curr_file_list().current().cloned();
+        if let (Some(snapshot), Some(item)) = (snapshot, item) {
+            self.file_view = Some(FileView::open(&self.location, &item, snapshot)?);
+        }
+        Ok(())
+    }
+
+    // The lists fit themselves to their panes as they're drawn, but the help is scrolled by the
+    // screenful, and may now be scrolled past its end.
+    fn resized(&mut self, height: u16) {
+        if let Some(help) = &mut self.help {
+            help.scroll_by(0, height);
+        }
+    }
+
+    // Advances smooth scrolling by a frame. Returns whether anything moved.
+    fn tick_scrolls(&mut self) -> bool {
+        let mut moving = false;
+        if let Some(p) = &mut self.diff_view {
+            moving |= p.scroll.tick();
+        }
+        if let Some(view) = &mut self.stashes {
+            moving |= view.scroll.tick();
+        }
+        if let Some(comparison) = &mut self.comparison {
+            moving |= comparison.scroll.tick();
+        }
+        if let Some(review) = &mut self.review {
+            moving |= review.scroll.tick();
+        }
+        moving
+    }
+
+    // Requests `op` (staging, unstaging or discarding) for just the hunk selected in the diff pane,
+    // or the lines selected within it.
+    fn request_hunk_op(&mut self, op: BatchOp) -> anyhow::Result<()> {
+        let entry = match self.curr_file_list().current() {
+            Some(item) => item.clone(),
+            None => return Ok(()),
+        };
+        let whole = match op {
+            BatchOp::Discard => "discarded",
+            _ => "staged or unstaged",
+        };
+        let (hunk, lines) = match &self.diff_view {
+            Some(view) if view.path == entry.new_file && view.stale => {
+                anyhow::bail!("the diff of {} is still loading", entry.new_file)
+            }
+            Some(view) if view.path == entry.new_file && view.binary => {
+                anyhow::bail!(
+                    "{} is binary and can only be {} as a whole",
+                    entry.new_file,
+                    whole
+                )
+            }
+            Some(view) if view.path == entry.new_file && view.submodule => {
+                anyhow::bail!(
+                    "{} is a submodule and can only be {} as a whole",
+                    entry.new_file,
+                    whole
+                )
+            }
+            Some(view) if view.path == entry.new_file && entry.status == Status::Typechange => {
+                anyhow::bail!(
+                    "{} changed type and can only be {} as a whole",
+                    entry.new_file,
+                    whole
+                )
+            }
+            Some(view) if view.path == entry.new_file && view.symlink => {
+                anyhow::bail!(
+                    "{} is a symlink and can only be {} as a whole",
+                    entry.new_file,
+                    whole
+                )
+            }
+            Some(view) if view.path == entry.new_file && view.hunk_count() > 0 => view.target(),
+            _ => return Ok(()),
+        };
+
+        if let BatchOp::Discard = op {
+            if self.is_protected(&entry) {
+                self.refuse_discard(vec![entry.pretty_string()]);
+                return Ok(());
+            }
+            backend::check_hunk_discard(&entry)?;
+        }
+
+        let warnings = match op {
+            BatchOp::Stage => self.stage_warnings(std::slice::from_ref(&entry)),
+            _ => Vec::new(),
+        };
+        if warnings.is_empty() && !self.config.needs_confirm(op.action()) {
+            return self.run_hunk_op(op, &entry, hunk, lines);
+        }
+
+        let message = format!(
+            "{} {} of {}?",
+            op.verb(),
+            describe_hunk(hunk, lines.as_ref()),
+            entry.new_file
+        );
+        let mut confirm = Dialog::new(message, warnings);
+        if let BatchOp::Discard = op {
+            confirm = confirm.with_note(self.discard_note(std::slice::from_ref(&entry)));
+        }
+        self.pending = Some(Pending {
+            action: PendingAction::Hunk {
+                op,
+                entry,
+                hunk,
+                lines,
+            },
+            confirm,
+        });
+        Ok(())
+    }
+
+    fn run_hunk_op(
+        &mut self,
+        op: BatchOp,
+        entry: &StatusEntry,
+        hunk: usize,
+        lines: Option<Range<usize>>,
+    ) -> anyhow::Result<()> {
+        let kind = match op {
+            BatchOp::Unstage => FileStatusKind::Staged,
+            _ => FileStatusKind::Unstaged,
+        };
+        self.check_index()?;
+        let details = vec![format!(
+            "{} ({})",
+            entry.new_file,
+            describe_hunk(hunk, lines.as_ref())
+        )];
+        match (op, lines) {
+            (BatchOp::Discard, lines) => {
+                stash::save_discarded(&self.location, std::slice::from_ref(entry))?;
+                match lines {
+                    Some(lines) => self.backend.discard_lines(entry, hunk, lines)?,
+                    None => self.backend.discard_hunks(entry, &[hunk])?,
+                }
+            }
+            (_, Some(lines)) => self.backend.apply_lines(entry, kind, hunk, lines)?,
+            (_, None) => self.backend.apply_hunks(entry, kind, &[hunk])?,
+        }
+        let action = format!("{}-hunk", op.action().name());
+        journal::record_details(&self.location, &action, details)?;
+        self.refresh_current()?;
+
+        // The hunk that took the place of the one just applied is selected next.
+        let scroll = self.config.scroll;
+        if let Some(view) = self.selected_diff() {
+            if view.path == entry.new_file {
+                view.select_hunk(hunk, &scroll);
+            }
+        }
+        Ok(())
+    }
+
+    // Stages the selected entry's change of mode without its content changes, or from the staged
+    // list unstages it.
+    fn stage_mode(&mut self) -> anyhow::Result<()> {
+        let entry = match self.curr_file_list().current() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+        let kind = self.curr_kind();
+        self.check_index()?;
+        self.backend.apply_mode(&entry, kind)?;
+        let action = match kind {
+            FileStatusKind::Unstaged => "stage-mode",
+            FileStatusKind::Staged => "unstage-mode",
+        };
+        journal::record_details(&self.location, action, vec![entry.new_file.to_string()])?;
+        self.refresh_current()
+    }
+
+    // Stages the selected entry's hunks that change more than whitespace, leaving those that only
+    // reformat it unstaged.
+    fn stage_ignoring_whitespace(&mut self) -> anyhow::Result<()> {
+        let entry = match self.curr_file_list().current() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+        if self.curr_kind() != FileStatusKind::Unstaged {
+            anyhow::bail!("only unstaged changes can be staged");
+        }
+        self.check_index()?;
+        let settings = DiffSettings::from(&self.config);
+        let (staged, left) =
+            backend::stage_ignoring_whitespace(self.backend.as_ref(), &entry, settings)?;
+        let details = format!("{} ({} staged, {} left)", entry.new_file, staged, left);
+        journal::record_details(&self.location, "stage-ignoring-whitespace", vec![details])?;
+        self.refresh_current()?;
+        let hunks = |n: usize| match n {
+            1 => String::from("1 hunk"),
+            n => format!("{} hunks", n),
+        };
+        let title = format!(
+            "Staged {} of {}, left {} only changing whitespace",
+            hunks(staged),
+            entry.new_file,
+            hunks(left)
+        );
+        self.notice = Some(Dialog::new(title, Vec::new()));
+        Ok(())
+    }
+
+    // Requests `op` for every entry in the current list.
+    fn request_all(&mut self, op: BatchOp) -> anyhow::Result<()> {
+        let entries = self.curr_file_list().items.clone();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.request(op, entries, Some("in the list"))
+    }
+
+    // Requests `op` for every entry in the current list that lives under the selected entry's
+    // directory.
+    fn request_directory_op(&mut self, op: BatchOp) -> anyhow::Result<()> {
+        match self.curr_file_list().current() {
+            Some(item) => {
+                let dir = item.dir();
+                self.request_under(op, dir)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Requests `op` for every entry in the current list that lives under `dir`.
+    fn request_under(&mut self, op: BatchOp, dir: RepoPath) -> anyhow::Result<()> {
+        let entries: Vec<StatusEntry> = self
+            .curr_file_list()
+            .items
+            .iter()
+            .filter(|e| e.is_under(&dir))
+            .cloned()
+            .collect();
+
+        let dir_label = if dir.is_empty() {
+            String::from("the repository root")
+        } else {
+            format!("{}/", dir)
+        };
+        self.request(op, entries, Some(&format!("under {}", dir_label)))
+    }
+
+    fn request_on_current(&mut self, op: BatchOp) -> anyhow::Result<()> {
+        let marked: Vec<StatusEntry> = self
+            .curr_file_list()
+            .marked_items()
+            .into_iter()
+            .cloned()
+            .collect();
+        if !marked.is_empty() {
+            return self.request(op, marked, Some("that are marked"));
+        }
+        match self.curr_file_list().current() {
+            Some(item) => {
+                let entries = vec![item.clone()];
+                self.request(op, entries, None)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Adds the marked untracked entries, or the selected one, to the index without their content,
+    // so that their hunks can be staged like those of any modified file.
+    fn intent_to_add(&mut self) -> anyhow::Result<()> {
+        let list = self.curr_file_list();
+        let mut entries: Vec<StatusEntry> = match list.marked_items() {
+            marked if !marked.is_empty() => marked.into_iter().cloned().collect(),
+            _ => list.current().into_iter().cloned().collect(),
+        };
+        entries.retain(|e| matches!(e.status, Status::Untracked));
+        if entries.is_empty() {
+            anyhow::bail!("only untracked files can be added with intent to add");
+        }
+        self.check_index()?;
+        self.backend.intent_to_add(&entries)?;
+        journal::record(&self.location, "intent-to-add", &entries)?;
+        self.refresh_current()
+    }
+
+    // Asks how to ignore the selected untracked file.
+    fn start_ignore(&mut self) -> anyhow::Result<()> {
+        match self.curr_file_list().current() {
+            Some(entry) if matches!(entry.status, Status::Untracked) => {
+                self.ignore = Some(IgnoreMenu::new(&entry.new_file.to_str_lossy()));
+                Ok(())
+            }
+            Some(_) => anyhow::bail!("only untracked files can be ignored"),
+            None => Ok(()),
+        }
+    }
+
+    // Adds `pattern` to `file`, which the refresh then leaves the ignored files out for.
+    fn ignore(&mut self, pattern: &str, file: ignore::IgnoreFile) -> anyhow::Result<()> {
+        ignore::append(&self.location, pattern, file)?;
+        journal::record_details(
+            &self.location,
+            "ignore",
+            vec![format!("{} in {}", pattern, file.name())],
+        )?;
+        self.refresh_current()
+    }
+
+    fn is_protected(&self, entry: &StatusEntry) -> bool {
+        self.protected.is_match(entry.new_path()) || self.protected.is_match(entry.old_path())
+    }
+
+    // The entries among `entries` that need an extra confirmation to stage, with the reason.
+    fn stage_warnings(&self, entries: &[StatusEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .filter_map(|e| {
+                let reason = if self.is_protected(e) {
+                    Some(String::from("protected"))
+                } else {
+                    suspicious::check(e, &self.config.stage_warnings, &self.secrets)
+                };
+                reason.map(|r| format!("{} ({})", e.pretty_string(), r))
+            })
+            .collect()
+    }
+
+    // Runs `op` on `entries`, or prepares a confirmation first if the confirmation policy asks for
+    // one. `scope` describes a batch ("under src/"); it's None for the entry under the cursor.
+    fn request(
+        &mut self,
+        op: BatchOp,
+        mut entries: Vec<StatusEntry>,
+        scope: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut skipped = 0;
+        if let BatchOp::Discard = op {
+            let (protected, rest): (Vec<_>, Vec<_>) =
+                entries.into_iter().partition(|e| self.is_protected(e));
+            if rest.is_empty() {
+                self.refuse_discard(protected.iter().map(|e| e.pretty_string()).collect());
+                return Ok(());
+            }
+            entries = rest;
+            skipped = protected.len();
+        }
+
+        let needs_stage_ack =
+            matches!(op, BatchOp::Stage) && !self.stage_warnings(&entries).is_empty();
+
+        let confirm = self.config.needs_confirm(op.action())
+            || (scope.is_some() && self.config.needs_confirm(ConfirmAction::Batch));
+        if !confirm {
+            if needs_stage_ack {
+                self.prepare_stage_ack(entries);
+                return Ok(());
+            }
+            return self.run_batch(op, &entries);
+        }
+
+        let mut message = match scope {
+            Some(scope) => format!("{} {} entries {}?", op.verb(), entries.len(), scope),
+            None => format!("{} {}?", op.verb(), entries[0].new_file),
+        };
+        if skipped > 0 {
+            message.push_str(&format!(" ({} protected skipped)", skipped));
+        }
+
+        let mut confirm = Dialog::new(message, entries.iter().map(|e| e.pretty_string()).collect());
+        let note = match op {
+            BatchOp::Discard => self.discard_note(&entries),
+            _ => String::new(),
+        };
+        if !note.is_empty() {
+            confirm = confirm.with_note(note);
+        }
+        self.pending = Some(Pending {
+            action: PendingAction::Batch {
+                op,
+                entries,
+                needs_stage_ack,
+            },
+            confirm,
+        });
+        Ok(())
+    }
+
+    fn run_batch(&mut self, op: BatchOp, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        if entries.len() >= LARGE_BATCH {
+            self.busy = Some(Busy {
+                op,
+                entries: entries.to_vec(),
+            });
+            return Ok(());
+        }
+        self.run_batch_now(op, entries)
+    }
+
+    fn run_batch_now(&mut self, op: BatchOp, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        self.check_index()?;
+        let formatted;
+        let entries = match op {
+            BatchOp::Stage => {
+                let failed;
+                (formatted, failed) = self.formatters.run(&self.location, entries);
+                if !failed.is_empty() {
+                    self.notice = Some(Dialog::new(
+                        "These files failed to format and were left unstaged",
+                        failed,
+                    ));
+                }
+                &formatted[..]
+            }
+            BatchOp::Unstage | BatchOp::Discard => entries,
+        };
+        if !entries.is_empty() {
+            op.run(&self.location, self.backend.as_ref(), entries, &self.config)?;
+        }
+        self.index_stamp = current_index_stamp(&self.location);
+        self.curr_file_list().marked.clear();
+        match self.update_lists(op, entries) {
+            true => Ok(()),
+            false => self.refresh_current(),
+        }
+    }
+
+    // Staging protected or suspicious files always has to be confirmed explicitly, regardless of
+    // the policy.
+    fn prepare_stage_ack(&mut self, entries: Vec<StatusEntry>) {
+        let warnings = self.stage_warnings(&entries);
+        let confirm = Dialog::new(
+            format!(
+                "{} of these entries are protected, large or may contain secrets. Stage them anyway?",
+                warnings.len()
+            ),
+            warnings,
+        );
+        self.pending = Some(Pending {
+            action: PendingAction::Batch {
+                op: BatchOp::Stage,
+                entries,
+                needs_stage_ack: false,
+            },
+            confirm,
+        });
+    }
+
+    fn refuse_discard(&mut self, protected: Vec<String>) {
+        self.notice = Some(Dialog::new(
+            "Refusing to discard protected paths",
+            protected,
+        ));
+    }
+
+    fn confirm_pending(&mut self) -> anyhow::Result<()> {
+        let pending = match self.pending.take() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        match pending.action {
+            PendingAction::Batch {
+                entries,
+                needs_stage_ack: true,
+                ..
+            } => {
+                self.prepare_stage_ack(entries);
+                Ok(())
+            }
+            PendingAction::Batch { op, entries, .. } => self.run_batch(op, &entries),
+            PendingAction::RestoreDiscard => self.restore_last_discard(),
+            PendingAction::RestoreCheckpoint => self.restore_checkpoint(),
+            PendingAction::PushTag { remote, name } => self.push_tag(&remote, &name),
+            PendingAction::Autosquash { onto } => self.start_git_op(GitOp::Autosquash { onto }),
+            PendingAction::CommitOp(op, commit) => self.run_commit_op(op, commit),
+            PendingAction::DropStash(entry) => self.run_stash_op(StashOp::Drop, entry),
+            PendingAction::ResolveConflict { path, side } => self.resolve_conflict(&path, side),
+            PendingAction::Hunk {
+                op,
+                entry,
+                hunk,
+                lines,
+            } => self.run_hunk_op(op, &entry, hunk, lines),
+        }
+    }
+
+    fn submit_prompt(&mut self, purpose: PromptPurpose, input: &str) -> anyhow::Result<()> {
+        match purpose {
+            PromptPurpose::Command => self.prepare_command(input),
+            PromptPurpose::Filter => {
+                self.set_filter(input);
+                Ok(())
+            }
+            PromptPurpose::StashMessage => self.stash(input.trim()),
+            PromptPurpose::CompareRevision => self.compare(input.trim()),
+            PromptPurpose::ExportPatch => self.export_patch(input.trim()),
+            PromptPurpose::ApplyPatch => {
+                let staged = matches!(self.curr_kind(), FileStatusKind::Staged);
+                self.apply_patch(Path::new(input.trim()), staged)
+            }
+            PromptPurpose::TagName(target) => {
+                let name = input.trim();
+                history::validate_tag_name(&self.location, name)?;
+                self.draft = Some(Draft {
+                    purpose: DraftPurpose::Tag {
+                        name: name.to_string(),
+                        target,
+                    },
+                    editor: Editor::new(
+                        format!("Tag {} (leave empty for a lightweight tag)", name),
+                        "",
+                    ),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    // Parses and runs a `:` command such as `stage **/*.test.ts`.
+    fn prepare_command(&mut self, input: &str) -> anyhow::Result<()> {
+        let (command, arg) = input
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((input.trim(), ""));
+        let arg = arg.trim();
+
+        let (op, kind) = match command {
+            "checkpoint" => return self.checkpoint(),
+            "commit" => return self.commit_command(arg),
+            "compare" => return self.compare(arg),
+            "restore-checkpoint" => return self.request_restore_checkpoint(),
+            "stage" => (BatchOp::Stage, FileStatusKind::Unstaged),
+            "unstage" => (BatchOp::Unstage, FileStatusKind::Staged),
+            "" => return Ok(()),
+            _ => anyhow::bail!("unknown command: {}", command),
+        };
+        if arg.is_empty() {
+            anyhow::bail!("usage: {} <glob>", command);
+        }
+
+        let matcher = glob::matcher(arg)?;
+        let entries: Vec<StatusEntry> = self
+            .backend
+            .statuses(kind)?
+            .into_iter()
+            .filter(|e| matcher.is_match(e.new_path()) || matcher.is_match(e.old_path()))
+            .collect();
+        if entries.is_empty() {
+            anyhow::bail!("no entries match {}", arg);
+        }
+
+        self.request(op, entries, Some(&format!("matching {}", arg)))
+    }
+
+    // `:commit` opens the editor as `c` does, and `:commit --message <message>` (or `-m`) commits
+    // the staged changes with that message right away, its hooks permitting.
+    fn commit_command(&mut self, arg: &str) -> anyhow::Result<()> {
+        let message = match exec::words(arg)?.as_slice() {
+            [] => return self.start_commit(),
+            [flag, message] if flag == "--message" || flag == "-m" => message.clone(),
+            _ => anyhow::bail!("usage: commit [--message <message>]"),
+        };
+        self.refuse_emitted_commit()?;
+        if self.backend.statuses(FileStatusKind::Staged)?.is_empty() {
+            anyhow::bail!("nothing staged to commit");
+        }
+        let message = history::clean_up_message(&message)?;
+        if message.is_empty() {
+            anyhow::bail!("not committing with an empty message");
+        }
+        let editor = Editor::new("Commit message", &message);
+        let draft = Draft {
+            purpose: DraftPurpose::Commit,
+            editor: editor.with_subject_length(self.config.commit.subject_length),
+        };
+        self.run_commit_hooks(draft, message, Hook::PreCommit)
+    }
+
+    // Spells out what discarding `entries` does to them, and how to undo it.
+    fn discard_note(&self, entries: &[StatusEntry]) -> String {
+        let untracked = entries
+            .iter()
+            .any(|e| matches!(e.status, Status::Untracked | Status::Renamed));
+
+        let mut note = Vec::new();
+        if untracked {
+            note.push(String::from(match self.config.discard_untracked {
+                DiscardUntracked::Trash => "Untracked files are moved to the trash.",
+                DiscardUntracked::Delete => "Untracked files are deleted.",
+            }));
+        }
+        let restore = self.keymap.keys(Action::RestoreDiscard).first().copied();
+        let show = self.keymap.keys(Action::ShowDiscarded).first().copied();
+        note.push(match (restore, show) {
+            (Some(restore), Some(show)) => format!(
+                "The changes are kept in a stash: {} restores them, and {} lists what was discarded.",
+                restore, show
+            ),
+            (Some(key), None) => format!("The changes are kept in a stash, and {} restores them.", key),
+            (None, Some(key)) => format!(
+                "The changes are kept in a stash, and {} lists what was discarded.",
+                key
+            ),
+            (None, None) => String::from("The changes are kept in a stash."),
+        });
+        note.join(" ")
+    }
+
+    fn request_restore_last_discard(&mut self) -> anyhow::Result<()> {
+        if !self.config.needs_confirm(ConfirmAction::RestoreDiscard) {
+            return self.restore_last_discard();
+        }
+        self.pending = Some(Pending {
+            action: PendingAction::RestoreDiscard,
+            confirm: Dialog::new("Re-apply the most recently discarded changes?", Vec::new()),
+        });
+        Ok(())
+    }
+
+    fn restore_last_discard(&mut self) -> anyhow::Result<()> {
+        // Failing to apply is expected if the files changed since, so report it rather than bail.
+        self.notice = Some(match stash::restore_last_discarded(&self.location) {
+            Ok(Some(entry)) => {
+                journal::record_details(
+                    &self.location,
+                    "restore-discard",
+                    vec![entry.pretty_string()],
+                )?;
+                Dialog::new("Restored discarded changes", vec![entry.message])
+            }
+            Ok(None) => Dialog::new("No discarded changes to restore", Vec::new()),
+            Err(e) => Dialog::new("Could not restore discarded changes", vec![e.to_string()]),
+        });
+        self.refresh_current()
+    }
+
+    // Adds the console after the other panes, scrolled to its end, or takes it away again.
+    fn toggle_console(&mut self) {
+        let panes = &mut self.config.layout.panes;
+        match panes.iter().position(|p| p.pane == Pane::Console) {
+            Some(i) => {
+                panes.remove(i);
+            }
+            None => {
+                panes.push(PaneConfig {
+                    pane: Pane::Console,
+                    size: Some(CONSOLE_SIZE),
+                });
+                self.console_scroll = 0;
+            }
+        }
+    }
+
+    fn open_log(&mut self) -> anyhow::Result<()> {
+        let commits = history::recent_commits(&self.location, LOG_LIMIT)?;
+        if commits.is_empty() {
+            self.notice = Some(Dialog::new("No commits yet", Vec::new()));
+            return Ok(());
+        }
+        self.log = Some(StatefulList::with_items(commits));
+        self.incoming = None;
+        self.select_commit()
+    }
+
+    // Opens the log on the commits fetched from the upstream that HEAD doesn't have, to decide
+    // whether to pull before committing on top of a stale base.
+    fn open_incoming(&mut self) -> anyhow::Result<()> {
+        let (upstream, commits) = match history::incoming_commits(&self.location, LOG_LIMIT)? {
+            Some(incoming) => incoming,
+            None => {
+                self.notice = Some(Dialog::new("The branch tracks no upstream", Vec::new()));
+                return Ok(());
+            }
+        };
+        if commits.is_empty() {
+            self.log = None;
+            self.notice = Some(Dialog::new(
+                format!("Nothing incoming from {}", upstream),
+                Vec::new(),
+            ));
+            return Ok(());
+        }
+        self.log = Some(StatefulList::with_items(commits));
+        self.incoming = Some(upstream);
+        self.select_commit()
+    }
+
+    // Lists what changed since `revision` in place of the file lists: up to the index from the
+    // staged view, and up to the worktree otherwise.
+    fn compare(&mut self, revision: &str) -> anyhow::Result<()> {
+        if revision.is_empty() {
+            anyhow::bail!("usage: compare <revision>");
+        }
+        let staged = matches!(self.curr_kind(), FileStatusKind::Staged);
+        let comparison = Comparison::open(&self.location, revision, staged, &self.config)?;
+        self.comparison = Some(comparison);
+        Ok(())
+    }
+
+    // Shows the changes of the commit selected in the log, unless they're shown already.
+    fn select_commit(&mut self) -> anyhow::Result<()> {
+        let id = match self.log.as_ref().and_then(|log| log.current()) {
+            Some(commit) => commit.id,
+            None => return Ok(()),
+        };
+        if self.commit_diff.as_ref().map(|d| d.id) != Some(id) {
+            self.commit_diff = Some(CommitDiff::open(&self.location, id)?);
+        }
+        Ok(())
+    }
+
+    // Lists the stashes, or with `discarded` just the ones holding discarded changes.
+    fn open_stashes(&mut self, discarded: bool) -> anyhow::Result<()> {
+        self.stashes = StashView::open(&self.location, discarded)?;
+        if self.stashes.is_none() {
+            let title = match discarded {
+                true => "Nothing has been discarded",
+                false => "No stashes",
+            };
+            self.notice = Some(Dialog::new(title, Vec::new()));
+        }
+        Ok(())
+    }
+
+    fn stash(&mut self, message: &str) -> anyhow::Result<()> {
+        let id = stash::save(&self.location, message)?;
+        journal::record_details(&self.location, "stash", vec![format!("{} {}", id, message)])?;
+        self.refresh_current()
+    }
+
+    // Runs `op` on the stash selected in the stash view, asking first to drop it.
+    fn request_stash_op(&mut self, op: StashOp) -> anyhow::Result<()> {
+        let entry = match &self.stashes {
+            Some(view) => view.stashes[view.selected].clone(),
+            None => return Ok(()),
+        };
+        if let StashOp::Drop = op {
+            if self.config.needs_confirm(ConfirmAction::DropStash) {
+                self.pending = Some(Pending {
+                    confirm: Dialog::new(format!("Drop {}?", entry.pretty_string()), Vec::new()),
+                    action: PendingAction::DropStash(entry),
+                });
+                return Ok(());
+            }
+        }
+        self.run_stash_op(op, entry)
+    }
+
+    fn run_stash_op(&mut self, op: StashOp, entry: StashEntry) -> anyhow::Result<()> {
+        op.run(&self.location, &entry)?;
+        journal::record_details(&self.location, op.name(), vec![entry.pretty_string()])?;
+        self.refresh_current()?;
+        match op {
+            // Leave the stashes for the files the stash was applied to.
+            StashOp::Apply | StashOp::Pop => self.stashes = None,
+            StashOp::Drop => {
+                let (selected, discarded) = self
+                    .stashes
+                    .as_ref()
+                    .map_or((0, false), |v| (v.selected, v.discarded));
+                self.stashes = StashView::open(&self.location, discarded)?;
+                if let Some(view) = &mut self.stashes {
+                    view.select(&self.location, selected.min(view.stashes.len() - 1))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Asks for the name of a tag on the commit selected in the log view, or on HEAD outside of it.
+    fn open_conflicts(&mut self) -> anyhow::Result<()> {
+        self.conflicts = ConflictView::open(&self.location)?;
+        if self.conflicts.is_none() {
+            self.notice = Some(Dialog::new("No conflicts", Vec::new()));
+        }
+        Ok(())
+    }
+
+    // Resolves the selected conflict with `side`'s version, asking first as it overwrites the file.
+    fn request_resolve_conflict(&mut self, side: Side) -> anyhow::Result<()> {
+        let path = match &self.conflicts {
+            Some(view) => view.current().path.clone(),
+            None => return Ok(()),
+        };
+        if self.config.needs_confirm(ConfirmAction::ResolveConflict) {
+            self.pending = Some(Pending {
+                confirm: Dialog::new(format!("Use {} for {}?", side.name(), path), Vec::new())
+                    .with_note("The file in the worktree is overwritten."),
+                action: PendingAction::ResolveConflict { path, side },
+            });
+            return Ok(());
+        }
+        self.resolve_conflict(&path, side)
+    }
+
+    fn resolve_conflict(&mut self, path: &str, side: Side) -> anyhow::Result<()> {
+        conflicts::resolve(&self.location, path, side)?;
+        let action = format!("use {}", side.name());
+        journal::record_details(&self.location, &action, vec![path.to_string()])?;
+        self.reload_conflicts()
+    }
+
+    // Marks the selected conflict resolved with the file as it is in the worktree.
+    fn mark_resolved(&mut self) -> anyhow::Result<()> {
+        let path = match &self.conflicts {
+            Some(view) => view.current().path.clone(),
+            None => return Ok(()),
+        };
+        conflicts::mark_resolved(&self.location, &path)?;
+        journal::record_details(&self.location, "mark resolved", vec![path])?;
+        self.reload_conflicts()
+    }
+
+    // Copies the selected entry's path, from the root of the repository or absolute.
+    fn copy_path(&mut self, absolute: bool) {
+        let entry = match self.curr_file_list().current() {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        let path = match absolute {
+            true => entry.abs_path_new(),
+            false => entry.new_path(),
+        };
+        let path = path.to_string_lossy().into_owned();
+        self.copied = Some(path.clone());
+        self.handoff = Some(Command::Copy(path));
+    }
+
+    // Copies the hunk selected in the diff pane, as a patch.
+    fn copy_hunk(&mut self) -> anyhow::Result<()> {
+        let status = match self.curr_file_list().current() {
+            Some(entry) => entry.status,
+            None => return Ok(()),
+        };
+        let view = self.selected_diff().filter(|v| !v.stale);
+        let (hunk, patch) = match view.and_then(|v| Some((v.hunk, v.hunk_patch(status)?))) {
+            Some(found) => found,
+            None => anyhow::bail!("there's no hunk to copy"),
+        };
+        self.copied = Some(format!("hunk {}", hunk + 1));
+        self.handoff = Some(Command::Copy(patch));
+        Ok(())
+    }
+
+    // Hands the selected entry to the editor, at the first line its diff changes. Only the
+    // unstaged diff's lines are the worktree's, but the staged one's are usually close.
+    fn edit_current(&mut self) -> anyhow::Result<()> {
+        let kind = self.curr_kind();
+        let entry = match self.curr_file_list().current() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+        let path = entry.abs_path_new();
+        if !path.is_file() {
+            anyhow::bail!("{} is not a file in the worktree", entry.new_file);
+        }
+        let line = self
+            .backend
+            .diff(&entry, kind)
+            .ok()
+            .and_then(|d| first_changed_line(&d));
+        self.handoff = Some(Command::Edit(path, line));
+        Ok(())
+    }
+
+    fn reload_conflicts(&mut self) -> anyhow::Result<()> {
+        self.refresh_current()?;
+        if let Some(view) = self.conflicts.take() {
+            self.conflicts = view.reload(&self.location)?;
+            if self.conflicts.is_none() {
+                self.notice = Some(Dialog::new("All conflicts resolved", Vec::new()));
+            }
+        }
+        Ok(())
+    }
+
+    fn start_tag(&mut self) {
+        let target = match &self.log {
+            Some(log) => log.current().map(|c| c.id),
+            None => history::head_commit(&self.location).ok(),
+        };
+        match target {
+            Some(target) => {
+                self.prompt = Some((PromptPurpose::TagName(target), Prompt::new("tag name: ")))
+            }
+            None => self.notice = Some(Dialog::new("No commit to tag", Vec::new())),
+        }
+    }
+
+    // Writes the staged changes, in the staged view, or the selected file's unstaged ones to the
+    // patch file `path`, relative to the root of the working tree.
+    fn export_patch(&mut self, path: &str) -> anyhow::Result<()> {
+        if path.is_empty() {
+            anyhow::bail!("no file given to export to");
+        }
+        let file = self.location.workdir().join(path);
+        let entry = match self.curr_kind() {
+            FileStatusKind::Staged => None,
+            FileStatusKind::Unstaged => match self.curr_file_list().current() {
+                Some(entry) => Some(entry.clone()),
+                None => anyhow::bail!("no file selected to export the changes of"),
+            },
+        };
+        let what = match &entry {
+            Some(entry) => format!("the unstaged changes of {}", entry.new_file),
+            None => String::from("the staged changes"),
+        };
+        let settings = DiffSettings::from(&self.config);
+        let files = patch::export(&self.location, &file, entry.as_ref(), settings)?;
+        journal::record_details(
+            &self.location,
+            "export-patch",
+            vec![file.display().to_string()],
+        )?;
+        self.notice = Some(Dialog::new(
+            format!("Exported {} to {}", what, path),
+            vec![count_files(files)],
+        ));
+        Ok(())
+    }
+
+    // Applies the patch file `path`, relative to the root of the working tree, to the index if
+    // `staged` and to the worktree otherwise.
+    fn apply_patch(&mut self, path: &Path, staged: bool) -> anyhow::Result<()> {
+        if path.as_os_str().is_empty() {
+            anyhow::bail!("no patch given to apply");
+        }
+        let file = self.location.workdir().join(path);
+        let files = patch::apply(&self.location, &file, staged)?;
+        journal::record_details(
+            &self.location,
+            "apply-patch",
+            vec![file.display().to_string()],
+        )?;
+        self.refresh_current()?;
+        self.notice = Some(Dialog::new(
+            format!(
+                "Applied {} to the {}",
+                path.display(),
+                if staged { "index" } else { "worktree" }
+            ),
+            vec![count_files(files)],
+        ));
+        Ok(())
+    }
+
+    // Creates the drafted tag with `message`, then offers to push it if there is a remote, or
+    // commits with `message` cleaned up as git would.
+    fn finish_draft(&mut self, message: &str) -> anyhow::Result<()> {
+        let draft = match self.draft.take() {
+            Some(d) => d,
+            None => return Ok(()),
+        };
+        if let DraftPurpose::Tag { name, target } = draft.purpose {
+            return self.finish_tag(name, target, message);
+        }
+        let message = history::clean_up_message(message)?;
+        if message.is_empty() {
+            self.notice = Some(Dialog::new(
+                "Not committing with an empty message",
+                Vec::new(),
+            ));
+            return Ok(());
+        }
+        // Like git, a template that wasn't filled in isn't taken for a message.
+        if let DraftPurpose::Commit = draft.purpose {
+            if message == history::clean_up_message(&history::commit_template(&self.location)?)? {
+                self.notice = Some(Dialog::new(
+                    "Not committing with the template's message unchanged",
+                    Vec::new(),
+                ));
+                return Ok(());
+            }
+        }
+        self.run_commit_hooks(draft, message, Hook::PreCommit)
+    }
+
+    // Starts `hook`, or the next one there is, before committing `message`. Commits right away if
+    // there are no hooks left to run.
+    fn run_commit_hooks(
+        &mut self,
+        draft: Draft,
+        message: String,
+        hook: Hook,
+    ) -> anyhow::Result<()> {
+        let mut next = Some(hook);
+        while let Some(hook) = next {
+            if let Some(path) = hooks::find(&self.location, hook)? {
+                let run = match hook {
+                    Hook::PreCommit => hooks::start(&self.location, hook, &path, &[])?,
+                    Hook::CommitMsg => hooks::start(
+                        &self.location,
+                        hook,
+                        &path,
+                        &[&hooks::write_message(&self.location, &message)?],
+                    )?,
+                };
+                self.commit_hooks = Some(CommitHooks {
+                    draft,
+                    message,
+                    hook,
+                    run,
+                });
+                return Ok(());
+            }
+            next = match hook {
+                Hook::PreCommit => Some(Hook::CommitMsg),
+                Hook::CommitMsg => None,
+            };
+        }
+        self.commit(&draft.purpose, &message)
+    }
+
+    // Moves on from a commit hook that exited: to the next hook, or to committing if it succeeded.
+    // A failed hook's output stays on screen until dismissed.
+    fn hook_exited(&mut self, status: anyhow::Result<ExitStatus>) -> anyhow::Result<()> {
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                self.draft = self.commit_hooks.take().map(|c| c.draft);
+                return Err(e);
+            }
+        };
+        if !status.success() {
+            return Ok(());
+        }
+        let CommitHooks {
+            draft,
+            message,
+            hook,
+            ..
+        } = match self.commit_hooks.take() {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        match hook {
+            Hook::PreCommit => self.run_commit_hooks(draft, message, Hook::CommitMsg),
+            Hook::CommitMsg => self.commit(&draft.purpose, &hooks::read_message(&self.location)?),
+        }
+    }
+
+    fn start_git_op(&mut self, op: GitOp) -> anyhow::Result<()> {
+        if self.git_run.is_none() {
+            let run = op.start(&self.location)?;
+            self.git_run = Some(GitRun { op, run });
+        }
+        Ok(())
+    }
+
+    // Closes the output of a push, pull or rebase that exited, with what git said last to say how
+    // it went. A pull or rebase may have changed anything, so the lists are refreshed.
+    fn git_run_exited(&mut self, status: anyhow::Result<ExitStatus>) -> anyhow::Result<()> {
+        let GitRun { op, run } = match self.git_run.take() {
+            Some(remote) => remote,
+            None => return Ok(()),
+        };
+        // Git's hints follow the error they're about.
+        let last = run
+            .output
+            .iter()
+            .rev()
+            .map(|l| l.trim())
+            .find(|l| !l.is_empty() && !l.starts_with("hint:"))
+            .unwrap_or_default()
+            .to_string();
+        if !status?.success() {
+            // A rebase stops with its conflicts to be resolved in the worktree.
+            if let GitOp::Autosquash { .. } = op {
+                self.refresh_current()?;
+            }
+            anyhow::bail!("git {} failed: {}", op.name(), last);
+        }
+        journal::record_details(&self.location, op.name(), vec![last.clone()])?;
+        self.refresh_current()?;
+        let done = match op {
+            GitOp::Push => "Pushed",
+            GitOp::Pull => "Pulled",
+            GitOp::Autosquash { .. } => "Rebased",
+        };
+        self.notice = Some(Dialog::new(done, vec![last]));
+        Ok(())
+    }
+
+    fn commit(&mut self, purpose: &DraftPurpose, message: &str) -> anyhow::Result<()> {
+        if let DraftPurpose::Amend = purpose {
+            let id = history::amend_head(&self.location, message)?;
+            return self.finish_commit("amend", "Amended HEAD as", id, message);
+        }
+        // Without `commit.confirm-partial`, it's only once committed that partly staged files
+        // are warned about.
+        let partial = match (purpose, self.config.commit.confirm_partial) {
+            (DraftPurpose::Commit, false) => partial::partial_files(self.backend.as_ref())?,
+            _ => Vec::new(),
+        };
+        let id = self.backend.commit(message)?;
+        self.finish_commit("commit", "Committed", id, message)?;
+        if !partial.is_empty() {
+            let done = self.notice.take().map(|n| n.message).unwrap_or_default();
+            self.partial = Some(PartialWarning::after_commit(&done, partial));
+        }
+        // Rather than say it's committed, offer to fold it into its target right away.
+        if let DraftPurpose::Fixup { onto } = *purpose {
+            let title = self.notice.take().map(|n| n.message).unwrap_or_default();
+            self.pending = Some(Pending {
+                confirm: Dialog::new(
+                    format!("{}. Fold it in now with `git rebase --autosquash`?", title),
+                    Vec::new(),
+                ),
+                action: PendingAction::Autosquash { onto },
+            });
+        }
+        Ok(())
+    }
+
+    // Opens the picker of the recent commit the staged changes fix up.
+    fn start_fixup(&mut self) -> anyhow::Result<()> {
+        self.refuse_emitted_commit()?;
+        if self.backend.statuses(FileStatusKind::Staged)?.is_empty() {
+            self.notice = Some(Dialog::new("Nothing staged to commit", Vec::new()));
+            return Ok(());
+        }
+        let commits = history::recent_commits(&self.location, LOG_LIMIT)?;
+        if commits.is_empty() {
+            self.notice = Some(Dialog::new("No commit to fix up", Vec::new()));
+            return Ok(());
+        }
+        self.fixup = Some(FixupPicker::new(commits));
+        Ok(())
+    }
+
+    // Commits the staged changes as a `fixup!` of `target` right away, its hooks permitting, or
+    // opens the editor on a `squash!` of it, for the message to add to the target's.
+    fn pick_fixup(&mut self, target: LogCommit, squash: bool) -> anyhow::Result<()> {
+        let message = history::clean_up_message(&history::fixup_message(&target, squash))?;
+        let purpose = DraftPurpose::Fixup {
+            onto: history::first_parent(&self.location, target.id)?,
+        };
+        if squash {
+            let editor = Editor::new("Squash message", &format!("{}\n", message));
+            self.draft = Some(Draft {
+                purpose,
+                editor: editor.with_subject_length(self.config.commit.subject_length),
+            });
+            return Ok(());
+        }
+        let editor = Editor::new("Fixup message", &message);
+        self.run_commit_hooks(Draft { purpose, editor }, message, Hook::PreCommit)
+    }
+
+    // Opens the editor for a new commit of the staged changes, or with `commit.review` the review
+    // of them first. With `commit.confirm-partial`, files with unstaged changes as well as staged
+    // ones are warned about before either.
+    fn start_commit(&mut self) -> anyhow::Result<()> {
+        self.refuse_emitted_commit()?;
+        if self.backend.statuses(FileStatusKind::Staged)?.is_empty() {
+            self.notice = Some(Dialog::new("Nothing staged to commit", Vec::new()));
+            return Ok(());
+        }
+        if self.config.commit.confirm_partial {
+            let files = partial::partial_files(self.backend.as_ref())?;
+            if !files.is_empty() {
+                self.partial = Some(PartialWarning::before_commit(files));
+                return Ok(());
+            }
+        }
+        self.continue_commit()
+    }
+
+    fn continue_commit(&mut self) -> anyhow::Result<()> {
+        if self.config.commit.review {
+            self.review = Review::load(self.backend.as_ref())?;
+            return Ok(());
+        }
+        self.write_commit_message()
+    }
+
+    // What's staged with `--emit-patch` is only staged in a copy of the index, so a commit of it
+    // wouldn't be what the index ends up with once the patch is applied.
+    fn refuse_emitted_commit(&self) -> anyhow::Result<()> {
+        if self.config.emit_patch {
+            anyhow::bail!(
+                "nothing is committed with --emit-patch: what's staged is printed on quitting"
+            );
+        }
+        Ok(())
+    }
+
+    fn write_commit_message(&mut self) -> anyhow::Result<()> {
+        let text = history::commit_template(&self.location)?
+            + &history::verbose_tail(&self.location, false)?;
+        let editor = Editor::new("Commit message", &text);
+        self.draft = Some(Draft {
+            purpose: DraftPurpose::Commit,
+            editor: editor.with_subject_length(self.config.commit.subject_length),
+        });
+        Ok(())
+    }
+
+    // Unstages the file selected in the review, which goes on with what's left staged, if anything.
+    fn unstage_reviewed(&mut self) -> anyhow::Result<()> {
+        let (entry, selected) = match &self.review {
+            Some(review) => match review.current() {
+                Some(entry) => (entry.clone(), review.selected),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        self.run_batch_now(BatchOp::Unstage, &[entry])?;
+        self.review = Review::load(self.backend.as_ref())?;
+        match &mut self.review {
+            Some(review) => review.selected = selected.min(review.files.len() - 1),
+            None => self.notice = Some(Dialog::new("Nothing staged to commit", Vec::new())),
+        }
+        Ok(())
+    }
+
+    // Leaves the review for the staged list, with the file selected in the review selected there
+    // too, to unstage some of it.
+    fn leave_review(&mut self) -> anyhow::Result<()> {
+        let path = match self.review.take() {
+            Some(review) => review.current().map(|e| e.list_key().clone()),
+            None => return Ok(()),
+        };
+        if !self.combined && matches!(self.view_state, AppViewState::UnstagedFiles) {
+            self.toggle_view()?;
+        }
+        self.view_state = AppViewState::StagedFiles;
+        if let Some(path) = path {
+            self.staged_files.select_key(&path);
+        }
+        Ok(())
+    }
+
+    // Leaves the warning about partly staged files for the unstaged list, with `path` selected to
+    // stage the rest of it.
+    fn jump_to_unstaged(&mut self, path: &RepoPath) -> anyhow::Result<()> {
+        self.partial = None;
+        if !self.combined && matches!(self.view_state, AppViewState::StagedFiles) {
+            self.toggle_view()?;
+        }
+        self.view_state = AppViewState::UnstagedFiles;
+        self.unstaged_files.select_key(path);
+        Ok(())
+    }
+
+    // Opens the editor for rewriting HEAD with the staged changes, starting from its message.
+    // Refuses to rewrite a commit that has been pushed unless the config allows it.
+    fn start_amend(&mut self) -> anyhow::Result<()> {
+        self.refuse_emitted_commit()?;
+        let message = match history::head_message(&self.location) {
+            Ok(message) => message,
+            Err(_) => {
+                self.notice = Some(Dialog::new("No commit to amend", Vec::new()));
+                return Ok(());
+            }
+        };
+        if !self.config.amend_pushed && history::head_is_pushed(&self.location)? {
+            self.notice = Some(Dialog::new(
+                "Not amending HEAD, it has already been pushed",
+                vec![String::from("Set `amend-pushed = true` to allow it.")],
+            ));
+            return Ok(());
+        }
+        let text = message.trim_end().to_string() + &history::verbose_tail(&self.location, true)?;
+        let editor = Editor::new("Amend HEAD", &text);
+        self.draft = Some(Draft {
+            purpose: DraftPurpose::Amend,
+            editor: editor.with_subject_length(self.config.commit.subject_length),
+        });
+        Ok(())
+    }
+
+    fn finish_commit(
+        &mut self,
+        action: &str,
+        done: &str,
+        id: Oid,
+        message: &str,
+    ) -> anyhow::Result<()> {
+        let commit = format!(
+            "{} {}",
+            &id.to_string()[..7],
+            message.lines().next().unwrap_or_default()
+        );
+        journal::record_details(&self.location, action, vec![commit.clone()])?;
+        self.refresh_current()?;
+        self.notice = Some(Dialog::new(format!("{} {}", done, commit), Vec::new()));
+        Ok(())
+    }
+
+    fn finish_tag(&mut self, name: String, target: Oid, message: &str) -> anyhow::Result<()> {
+        history::create_tag(&self.location, &name, target, message)?;
+        journal::record_details(&self.location, "tag", vec![format!("{} {}", name, target)])?;
+        // The new one among any the commit already had, e.g. a release and its release candidate.
+        let tags = vec![format!(
+            "Tags on {}: {}",
+            &target.to_string()[..7],
+            history::tags_on(&self.location, target)?.join(", ")
+        )];
+
+        match history::push_remote(&self.location)? {
+            Some(remote) => {
+                self.pending = Some(Pending {
+                    confirm: Dialog::new(
+                        format!("Created tag {}. Push it to {}?", name, remote),
+                        tags,
+                    ),
+                    action: PendingAction::PushTag { remote, name },
+                });
+            }
+            None => self.notice = Some(Dialog::new(format!("Created tag {}", name), tags)),
+        }
+        Ok(())
+    }
+
+    fn push_tag(&mut self, remote: &str, name: &str) -> anyhow::Result<()> {
+        // Pushing can fail for reasons outside of our control, so report it rather than bail.
+        self.notice = Some(match history::push_tag(&self.location, remote, name) {
+            Ok(()) => {
+                journal::record_details(
+                    &self.location,
+                    "push-tag",
+                    vec![format!("{} to {}", name, remote)],
+                )?;
+                Dialog::new(format!("Pushed tag {} to {}", name, remote), Vec::new())
+            }
+            Err(e) => Dialog::new(format!("Could not push tag {}", name), vec![e.to_string()]),
+        });
+        Ok(())
+    }
+
+    // Requests `op` on the commit selected in the log view.
+    fn request_commit_op(&mut self, op: CommitOp) -> anyhow::Result<()> {
+        let commit = match self.log.as_ref().and_then(|log| log.current()) {
+            Some(c) => c.clone(),
+            None => return Ok(()),
+        };
+        if !self.config.needs_confirm(op.action()) {
+            return self.run_commit_op(op, commit);
+        }
+        self.pending = Some(Pending {
+            confirm: Dialog::new(op.question(&commit.short_string()), Vec::new()),
+            action: PendingAction::CommitOp(op, commit),
+        });
+        Ok(())
+    }
+
+    fn run_commit_op(&mut self, op: CommitOp, commit: LogCommit) -> anyhow::Result<()> {
+        let label = commit.short_string();
+        let name = op.action().name();
+        // A dirty worktree or a merge commit makes git refuse, so report it rather than bail.
+        self.notice = Some(match op.run(&self.location, commit.id) {
+            Ok(PickOutcome::Done) => {
+                journal::record_details(&self.location, name, vec![label.clone()])?;
+                if let CommitOp::StageRevert = op {
+                    // Leave the log for the staged changes, which are up for review.
+                    self.log = None;
+                    self.view_state = AppViewState::StagedFiles;
+                } else {
+                    self.open_log()?;
+                }
+                Dialog::new(op.done(), vec![label])
+            }
+            Ok(PickOutcome::Conflicted(paths)) => {
+                journal::record_details(
+                    &self.location,
+                    name,
+                    vec![format!("{} (conflicted)", label)],
+                )?;
+                // Leave the log for the unstaged files, where the conflicted entries are listed.
+                self.log = None;
+                self.view_state = AppViewState::UnstagedFiles;
+                Dialog::new(
+                    format!(
+                        "{} stopped with conflicts. Resolve and commit them, or run `git {} --abort`.",
+                        op.verb(&label),
+                        op.command()
+                    ),
+                    paths,
+                )
+            }
+            Err(e) => Dialog::new(format!("Could not {} {}", name, label), vec![e.to_string()]),
+        });
+        self.refresh_current()
+    }
+
+    fn checkpoint(&mut self) -> anyhow::Result<()> {
+        let id = checkpoint::create(&self.location)?;
+        journal::record_details(&self.location, "checkpoint", vec![id.to_string()])?;
+        self.notice = Some(Dialog::new(
+            "Saved a checkpoint of HEAD, the index and the worktree",
+            vec![String::from("Run :restore-checkpoint to return to it")],
+        ));
+        Ok(())
+    }
+
+    fn request_restore_checkpoint(&mut self) -> anyhow::Result<()> {
+        if !self.config.needs_confirm(ConfirmAction::RestoreCheckpoint) {
+            return self.restore_checkpoint();
+        }
+        self.pending = Some(Pending {
+            action: PendingAction::RestoreCheckpoint,
+            confirm: Dialog::new(
+                "Return HEAD, the index and the worktree to the last checkpoint?",
+                vec![String::from(
+                    "Changes and files made since are replaced, but saved to be restored too",
+                )],
+            ),
+        });
+        Ok(())
+    }
+
+    fn restore_checkpoint(&mut self) -> anyhow::Result<()> {
+        // A restore fails halfway if e.g. a file is locked, so report it rather than bail.
+        self.notice = Some(match checkpoint::restore(&self.location) {
+            Ok(Some(id)) => {
+                journal::record_details(
+                    &self.location,
+                    "restore-checkpoint",
+                    vec![id.to_string()],
+                )?;
+                Dialog::new(
+                    "Restored the last checkpoint",
+                    vec![format!(
+                        "The state before was saved as {}",
+                        checkpoint::BEFORE_RESTORE_REF
+                    )],
+                )
+            }
+            Ok(None) => Dialog::new("No checkpoint to restore", Vec::new()),
+            Err(e) => Dialog::new("Could not restore the checkpoint", vec![e.to_string()]),
+        });
+        self.refresh_current()
+    }
+
+    // Moves on to the next order of the lists, which is remembered for the repository.
+    fn cycle_sort(&mut self) -> anyhow::Result<()> {
+        self.config.list.sort = self.config.list.sort.next();
+        // The other list is sorted again when it's switched to, as it's refreshed then.
+        self.refresh_current()?;
+        Config::save_to_git(&self.location, "list.sort", self.config.list.sort.name())
+    }
+
+    // Lists the ignored files along with the untracked ones, or stops.
+    fn toggle_ignored(&mut self) -> anyhow::Result<()> {
+        self.config.list.show_ignored = !self.config.list.show_ignored;
+        self.backend
+            .set_diff_settings(DiffSettings::from(&self.config));
+        self.refresh_current()
+    }
+
+    // Lists the files of a collapsed untracked directory one by one, for the rest of the session
+    // and, with `list.restore`, the next ones.
+    fn expand_untracked_dir(&mut self, dir: RepoPath) -> anyhow::Result<()> {
+        self.config.expanded_dirs.push(dir);
+        self.backend.set_expanded_dirs(&self.config.expanded_dirs);
+        self.refresh_current()
+    }
+
+    fn toggle_auto_stage(&mut self) -> anyhow::Result<()> {
+        if self.auto_stage.take().is_none() && !self.config.auto_stage.globs.is_empty() {
+            self.auto_stage = Some(AutoStage::start(
+                &self.location,
+                &self.config.auto_stage.globs,
+            )?);
+        }
+        Ok(())
+    }
+
+    // Selects the entry for `path` and focuses its diff, switching to the other view if only that
+    // one has changes to it.
+    fn jump_to_file(&mut self, path: &RepoPath) {
+        if !self.curr_file_list().select_key(path) {
+            let (other, list) = match self.view_state {
+                AppViewState::UnstagedFiles => (AppViewState::StagedFiles, &mut self.staged_files),
+                AppViewState::StagedFiles => {
+                    (AppViewState::UnstagedFiles, &mut self.unstaged_files)
+                }
+            };
+            if !list.select_key(path) {
+                self.notice = Some(Dialog::new(format!("{} has no changes", path), Vec::new()));
+                return;
+            }
+            self.view_state = other;
+        }
+        self.diff_focused = self
+            .config
+            .layout
+            .panes
+            .iter()
+            .any(|p| p.pane == Pane::Diff);
+    }
+
+    // How the lists are left, for the next session to start from.
+    fn ui_state(&self) -> UiState {
+        let position = |list: &StatefulList<StatusEntry>| ListPosition {
+            selected: list.current().map(|e| e.new_file.clone()),
+            offset: list.offset,
+        };
+        UiState {
+            view: match self.view_state {
+                AppViewState::UnstagedFiles => ListView::Unstaged,
+                AppViewState::StagedFiles => ListView::Staged,
+            },
+            combined: self.combined,
+            unstaged: position(&self.unstaged_files),
+            staged: position(&self.staged_files),
+            filter: self.unstaged_files.filter.clone(),
+            tree: self.tree.as_ref().map(Tree::collapsed),
+            expanded_dirs: self.config.expanded_dirs.clone(),
+            diff_focused: self.diff_focused,
+            hunk: self.diff_view.as_ref().map_or(0, |view| view.hunk),
+        }
+    }
+
+    // Puts the lists back the way the last session left them.
+    fn restore(&mut self, state: UiState) {
+        self.view_state = match state.view {
+            ListView::Unstaged => AppViewState::UnstagedFiles,
+            ListView::Staged => AppViewState::StagedFiles,
+        };
+        self.combined = state.combined && state.tree.is_none();
+        self.tree = state.tree.map(Tree::with_collapsed);
+        self.set_filter(&state.filter);
+        if let Some(startup) = &mut self.startup {
+            startup.restored = Some((state.unstaged, state.staged, state.hunk));
+        }
+        let panes = &self.config.layout.panes;
+        self.diff_focused = state.diff_focused && panes.iter().any(|p| p.pane == Pane::Diff);
+    }
+
+    fn change_view_state<F>(&mut self, next: AppViewState, mut on_enter: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&mut App) -> anyhow::Result<()>,
+    {
+        on_enter(self)?;
+        self.view_state = next;
+        Ok(())
+    }
+}
+
+// How often the screen is redrawn while something is scrolling smoothly.
+const FRAME_TIME: Duration = Duration::from_millis(16);
+
+// The offset that scrolls the last of `lines` lines to the top.
+fn last_line(lines: usize) -> u16 {
+    u16::try_from(lines.saturating_sub(1)).unwrap_or(u16::MAX)
+}
+
+// None if the index can't be read, in which case the change about to be made to it says why.
+fn current_index_stamp(location: &Location) -> Option<u64> {
+    location
+        .open()
+        .and_then(|repo| git::index_stamp(&repo))
+        .ok()
+}
+
+// Runs until the app quits, or `events` runs dry and the lists and diff have loaded.
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+    tick_rate: Duration,
+    events: &mut impl EventSource,
+) -> anyhow::Result<()> {
+    let mut last_tick = Instant::now();
+    // A frame is only drawn when something has changed since the last one, or while something on
+    // screen moves by itself. Otherwise the loop only wakes up to look for background work.
+    let mut changed = true;
+    let mut console_changes = console::changes();
+    loop {
+        let animating = app.tick_scrolls();
+        if changed || animating || app.in_progress() {
+            terminal.draw(|f| {
+                ui(f, &mut app);
+                if app.theme.no_color {
+                    f.render_widget(Uncolored, f.size());
+                }
+            })?;
+            changed = false;
+        }
+        let height = terminal.size()?.height;
+        if let Some(busy) = app.busy.take() {
+            if dispatch(terminal, &mut app, Message::RunBatch(busy), height) {
+                return app.exec.finish();
+            }
+            changed = true;
+            continue;
+        }
+
+        let timeout = if animating || app.loading() {
+            FRAME_TIME
+        } else {
+            tick_rate
+                .checked_sub(last_tick.elapsed())
+                .unwrap_or_else(|| Duration::from_secs(0))
+        };
+
+        // The `--exec` commands run before any key is read. Unattended, the app quits once they
+        // have all run.
+        match app.exec_step() {
+            Some(exec::Step::Run(command)) => {
+                changed = true;
+                if dispatch(terminal, &mut app, Message::Exec(command), height) {
+                    return app.exec.finish();
+                }
+                continue;
+            }
+            Some(exec::Step::Confirm) => {
+                changed = true;
+                if dispatch(terminal, &mut app, Message::ExecConfirm, height) {
+                    return app.exec.finish();
+                }
+                continue;
+            }
+            Some(exec::Step::Quit) => return app.exec.finish(),
+            None => {}
+        }
+
+        // Keys of a macro being replayed come first, as if they had just been typed.
+        let key = match app.macros.replayed() {
+            Some(key) => Some(Message::Key { key, typed: false }),
+            None if events.is_closed() && !app.loading() => return app.exec.finish(),
+            None if events.waits_for_loading() && app.loading() => {
+                thread::sleep(timeout);
+                None
+            }
+            None => match events.next(timeout)? {
+                Some(Event::Key(key)) => Some(Message::Key { key, typed: true }),
+                Some(Event::Mouse(event)) => Some(Message::Mouse(event)),
+                Some(Event::Resize(_, height)) => Some(Message::Resized(height)),
+                _ => None,
+            },
+        };
+
+        if let Some(key) = key {
+            changed = true;
+            if dispatch(terminal, &mut app, key, height) {
+                return app.exec.finish();
+            }
+        }
+        // Collected after the key is handled, so that results read before a change it made are
+        // dropped with their workers rather than applied over it.
+        let background = app.background_messages();
+        changed |= !background.is_empty();
+        let notifications = app.notifications.height();
+        for message in background.into_iter().chain([Message::Tick]) {
+            if dispatch(terminal, &mut app, message, height) {
+                return app.exec.finish();
+            }
+        }
+        changed |= app.notifications.height() != notifications;
+        let changes = console::changes();
+        changed |= changes != console_changes;
+        console_changes = changes;
+
+        if last_tick.elapsed() >= tick_rate {
+            last_tick = Instant::now();
+        }
+    }
+}
+
+// Hands `message` to the app, and carries out the commands that come back. Errors are shown as
+// notifications. Returns whether to quit.
+fn dispatch<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    message: Message,
+    height: u16,
+) -> bool {
+    let mut next = Some(message);
+    while let Some(message) = next.take() {
+        match app.update(message, height) {
+            Ok(Command::None) => {}
+            Ok(Command::Quit) => return true,
+            Ok(Command::Edit(path, line)) => {
+                let edited = open_in_editor(terminal, &app.location, &path, line);
+                next = Some(Message::Resumed(edited));
+            }
+            Ok(Command::Shell) => {
+                next = Some(Message::Resumed(open_shell(terminal, &app.location)));
+            }
+            Ok(Command::Copy(text)) => {
+                if let Err(e) = clipboard::copy(&text) {
+                    app.copied = None;
+                    app.notifications.push(e);
+                }
+            }
+            Ok(Command::Suspend) => {
+                next = Some(Message::Resumed(suspend(terminal, stop).and_then(|r| r)));
+            }
+            Err(e) => app.notifications.push(e),
+        }
+    }
+    false
+}
+
+// An absolute path as a path from `root`, that of the repository, like the entries have them.
+fn repo_path(root: &Path, file: &Path) -> anyhow::Result<String> {
+    // The worktree root is canonical, and a deleted file can only be canonicalized by its parent.
+    let canonical = match (paths::canonicalize(file), file.parent(), file.file_name()) {
+        (Ok(path), _, _) => path,
+        (Err(_), Some(parent), Some(name)) => paths::canonicalize(parent)?.join(name),
+        (Err(e), _, _) => return Err(e.into()),
+    };
+    match paths::relative_to(&canonical, root) {
+        Some(path) => Ok(path),
+        None => anyhow::bail!("{} is outside the repository", file.display()),
+    }
+}
+
+// The pathspecs given on the command line, as libgit2 matches them from `root`, that of the working
+// tree: each is resolved against where we were started, unless it's given from the root. libgit2
+// knows no other pathspec magic. A pathspec covering the whole tree leaves nothing to limit.
+fn repo_pathspecs(
+    root: &Path,
+    pathspecs: &[(String, Option<PathBuf>)],
+) -> anyhow::Result<Vec<String>> {
+    let mut resolved = Vec::new();
+    for (pathspec, absolute) in pathspecs {
+        let Some(absolute) = absolute else {
+            let from_root = [":/", ":(top)"]
+                .iter()
+                .find_map(|magic| pathspec.strip_prefix(magic));
+            match from_root {
+                Some("") => return Ok(Vec::new()),
+                Some(path) => resolved.push(path.to_string()),
+                None => anyhow::bail!("unsupported pathspec magic in {}", pathspec),
+            }
+            continue;
+        };
+        // The worktree root is canonical. What's past the deepest directory that exists may not be a
+        // path at all, e.g. `*.rs`, so it's kept as it is.
+        let existing = absolute
+            .ancestors()
+            .find(|a| a.exists())
+            .unwrap_or(absolute);
+        let rest = absolute.strip_prefix(existing).unwrap_or(Path::new(""));
+        let path = paths::canonicalize(existing)?.join(rest);
+        match paths::relative_to(&path, root) {
+            Some(p) if p.is_empty() => return Ok(Vec::new()),
+            Some(p) => resolved.push(p),
+            None => anyhow::bail!("{} is outside the repository", pathspec),
+        }
+    }
+    Ok(resolved)
+}
+
+// Hands the terminal over to the user's editor until it exits, at `line` if the editor is one known
+// to take a `+line` argument.
+fn open_in_editor<B: Backend>(
+    terminal: &mut Terminal<B>,
+    location: &Location,
+    path: &Path,
+    line: Option<u32>,
+) -> anyhow::Result<()> {
+    let editor = editor_command(location);
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let mut command = location.command(program);
+    command.args(words);
+    let name = Path::new(program).file_name().and_then(|n| n.to_str());
+    if let (Some(line), Some(name)) = (line, name) {
+        if LINE_ARG_EDITORS.contains(&name) {
+            command.arg(format!("+{}", line));
+        }
+    }
+    command.arg(path);
+    if let Some(tty) = tty() {
+        command.stdout(tty);
+    }
+
+    let status = suspend(terminal, || command.status())?;
+    let status = status.map_err(|e| anyhow::anyhow!("running {}: {}", program, e))?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+// Editors that open a file at line N when given `+N` before it.
+const LINE_ARG_EDITORS: [&str; 8] = [
+    "vi",
+    "vim",
+    "nvim",
+    "nano",
+    "emacs",
+    "emacsclient",
+    "kak",
+    "micro",
+];
+
+// The user's editor, picked the way git picks it: $GIT_EDITOR, core.editor, $VISUAL, $EDITOR, or
+// vi. It may come with arguments (e.g. "code --wait").
+fn editor_command(location: &Location) -> String {
+    let var = |name: &str| std::env::var(name).ok().filter(|e| !e.trim().is_empty());
+    let core_editor = || {
+        let repo = location.open().ok()?;
+        let editor = repo.config().ok()?.get_string("core.editor").ok()?;
+        Some(editor).filter(|e| !e.trim().is_empty())
+    };
+    var("GIT_EDITOR")
+        .or_else(core_editor)
+        .or_else(|| var("VISUAL"))
+        .or_else(|| var("EDITOR"))
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+// An interactive shell in the root of the repository, until it exits: $SHELL, or sh.
+fn open_shell<B: Backend>(terminal: &mut Terminal<B>, location: &Location) -> anyhow::Result<()> {
+    let shell = std::env::var("SHELL")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| String::from("sh"));
+    let mut command = location.command(&shell);
+    if let Some(tty) = tty() {
+        command.stdout(tty);
+    }
+    let status = suspend(terminal, || {
+        writeln!(terminal_output(), "Type `exit` to return to git-istage.").ok();
+        command.status()
+    })?;
+    // How the shell exits is up to whatever was last run in it.
+    status.map_err(|e| anyhow::anyhow!("running {}: {}", shell, e))?;
+    Ok(())
+}
+
+// Registers for SIGTSTP, which would otherwise stop the process with the terminal still in raw
+// mode and on the alternate screen.
+#[cfg(unix)]
+fn stop_signal() -> anyhow::Result<Arc<AtomicBool>> {
+    let flag = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTSTP, Arc::clone(&flag))?;
+    Ok(flag)
+}
+
+#[cfg(not(unix))]
+fn stop_signal() -> anyhow::Result<Arc<AtomicBool>> {
+    Ok(Arc::new(AtomicBool::new(false)))
+}
+
+// Stops the process like ctrl-z does outside the app, until the shell continues it. SIGSTOP
+// rather than SIGTSTP, which the app now handles itself.
+#[cfg(unix)]
+fn stop() -> anyhow::Result<()> {
+    signal_hook::low_level::raise(signal_hook::consts::SIGSTOP)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn stop() -> anyhow::Result<()> {
+    anyhow::bail!("suspending is only supported on Unix")
+}
+
+#[cfg(unix)]
+const TTY: &str = "/dev/tty";
+#[cfg(not(unix))]
+const TTY: &str = "CONOUT$";
+
+// The terminal, when stdout isn't it, as when `--emit-patch` is piped into `git apply`: the app is
+// drawn there instead, and the editor and shell it hands the terminal to write there.
+fn tty() -> Option<fs::File> {
+    if io::stdout().is_terminal() {
+        return None;
+    }
+    fs::OpenOptions::new().write(true).open(TTY).ok()
+}
+
+fn terminal_output() -> Box<dyn Write> {
+    match tty() {
+        Some(tty) => Box::new(tty),
+        None => Box::new(io::stdout()),
+    }
+}
+
+// Gives the terminal back to the shell while `run` runs, e.g. an editor, and takes it over again
+// afterwards, redrawing everything.
+fn suspend<B: Backend, T>(
+    terminal: &mut Terminal<B>,
+    run: impl FnOnce() -> T,
+) -> anyhow::Result<T> {
+    terminal::hand_over()?;
+    let result = run();
+    terminal::take()?;
+    terminal.clear()?;
+    Ok(result)
+}
+
+// The line in the new version of the file where its diff first changes something: the first added
+// line, or where the first removed one was.
+fn first_changed_line(diff: &FileDiff) -> Option<u32> {
+    let hunk = diff.hunks.first()?;
+    let mut line = hunk.new_start.max(1);
+    for l in &hunk.lines {
+        match l.kind {
+            LineKind::Context => line = l.new_lineno.map_or(line, |n| n + 1),
+            LineKind::Added => return l.new_lineno.or(Some(line)),
+            LineKind::Removed => return Some(line),
+            LineKind::NoNewlineAtEof => {}
+        }
+    }
+    Some(line)
+}
+
+// Handles a key typed (or replayed) on the current screen. Returns whether to quit.
+fn handle_key(app: &mut App, key: KeyEvent, height: u16) -> anyhow::Result<bool> {
+    if app.notice.is_some() {
+        app.notice = None;
+        return Ok(false);
+    }
+    if app.info.is_some() {
+        app.info = None;
+        return Ok(false);
+    }
+    if let Some(tour) = &mut app.tour {
+        if let TourEvent::Close = tour.handle_key(key.code) {
+            app.tour = None;
+        }
+        return Ok(false);
+    }
+    if let Some(help) = &mut app.help {
+        match key.code {
+            KeyCode::Down => help.scroll_by(1, height),
+            KeyCode::Up => help.scroll_by(-1, height),
+            KeyCode::PageDown => help.scroll_by(height as i16 / 2, height),
+            KeyCode::PageUp => help.scroll_by(-(height as i16 / 2), height),
+            _ => app.help = None,
+        }
+        return Ok(false);
+    }
+    if app.pending.is_some() {
+        handle_pending_key(app, key.code)?;
+        return Ok(false);
+    }
+    if app.commit_hooks.is_some() {
+        handle_commit_hooks_key(app, key)?;
+        return Ok(false);
+    }
+    if let Some(git_run) = &mut app.git_run {
+        match key.code {
+            KeyCode::Down => git_run.run.scroll_down(),
+            KeyCode::Up => git_run.run.scroll_up(),
+            _ => {}
+        }
+        return Ok(false);
+    }
+    if let Some(menu) = &mut app.ignore {
+        match menu.handle_key(key.code) {
+            IgnoreEvent::Ignore(pattern, file) => {
+                app.ignore = None;
+                app.ignore(&pattern, file)?;
+            }
+            IgnoreEvent::Cancel => app.ignore = None,
+            IgnoreEvent::Pending => {}
+        }
+        return Ok(false);
+    }
+    if let Some(picker) = &mut app.fixup {
+        match picker.handle_key(key.code) {
+            FixupEvent::Pick { target, squash } => {
+                app.fixup = None;
+                app.pick_fixup(target, squash)?;
+            }
+            FixupEvent::Cancel => app.fixup = None,
+            FixupEvent::Pending => {}
+        }
+        return Ok(false);
+    }
+    if let Some(warning) = &mut app.partial {
+        match warning.handle_key(key.code) {
+            PartialEvent::Jump(path) => app.jump_to_unstaged(&path)?,
+            PartialEvent::Commit => {
+                app.partial = None;
+                app.continue_commit()?;
+            }
+            PartialEvent::Cancel => app.partial = None,
+            PartialEvent::Pending => {}
+        }
+        return Ok(false);
+    }
+    if let Some(draft) = &mut app.draft {
+        match draft.editor.handle_key(key) {
+            EditorEvent::Submit(message) => app.finish_draft(&message)?,
+            EditorEvent::Cancel => app.draft = None,
+            EditorEvent::Pending => {}
+        }
+        return Ok(false);
+    }
+    if let Some((purpose, prompt)) = &mut app.prompt {
+        let purpose = *purpose;
+        match prompt.handle_key(key.code) {
+            // Closed before it's submitted, as what it runs may open another.
+            PromptEvent::Submit(input) => {
+                let closed = app.prompt.take();
+                let submitted = match purpose {
+                    PromptPurpose::Command => run_command(app, &input, height),
+                    _ => app.submit_prompt(purpose, &input).map(|()| false),
+                };
+                match submitted {
+                    Ok(quit) => return Ok(quit),
+                    Err(e) => {
+                        app.prompt = closed.map(|(purpose, mut prompt)| {
+                            prompt.error = Some(e.to_string());
+                            (purpose, prompt)
+                        });
+                    }
+                }
+            }
+            PromptEvent::Cancel => {
+                app.prompt = None;
+                if let PromptPurpose::Filter = purpose {
+                    app.set_filter("");
+                }
+            }
+            PromptEvent::Pending => {
+                if let PromptPurpose::Filter = purpose {
+                    let input = prompt.input.clone();
+                    app.set_filter(&input);
+                }
+            }
+        }
+        return Ok(false);
+    }
+    if let Some(palette) = &mut app.palette {
+        match palette.handle_key(key.code) {
+            PaletteEvent::Run(choice) => {
+                app.palette = None;
+                return match choice {
+                    Choice::Action(action) => handle_action(app, action, height),
+                    Choice::Command(command) => run_command(app, &command, height),
+                };
+            }
+            PaletteEvent::Cancel => app.palette = None,
+            PaletteEvent::Pending => {}
+        }
+        return Ok(false);
+    }
+    if app.file_view.is_some() {
+        handle_file_view_key(app, key)?;
+        return Ok(false);
+    }
+    if app.journal.is_some() {
+        handle_journal_key(app, key);
+        return Ok(false);
+    }
+    if app.stashes.is_some() {
+        handle_stash_key(app, key)?;
+        return Ok(false);
+    }
+    if app.conflicts.is_some() {
+        handle_conflicts_key(app, key)?;
+        return Ok(false);
+    }
+    if app.review.is_some() {
+        handle_review_key(app, key)?;
+        return Ok(false);
+    }
+    if app.comparison.is_some() {
+        handle_comparison_key(app, key)?;
+        return Ok(false);
+    }
+    if app.log.is_some() {
+        handle_log_key(app, key)?;
+        return Ok(false);
+    }
+
+    // Digits that aren't bound to anything make up a count, and the key after them does what it
+    // does that many times, unless it opens something along the way. `esc` drops the count.
+    if let Some(digit) = count_digit(app, key) {
+        let count = app.count.unwrap_or(0).saturating_mul(10) + digit;
+        app.count = Some(count.min(MAX_COUNT));
+        return Ok(false);
+    }
+    let count = match app.count.take() {
+        Some(_) if key.code == KeyCode::Esc => return Ok(false),
+        Some(count) => count.max(1),
+        None => 1,
+    };
+    let focused = app.diff_focused;
+    for _ in 0..count {
+        if handle_list_or_diff_key(app, key, height)? {
+            return Ok(true);
+        }
+        if app.diff_focused != focused || app.overlaid() {
+            break;
+        }
+    }
+    Ok(false)
+}
+
+// The digit `key` adds to the count, if it's an unbound digit. A count doesn't start with a 0.
+fn count_digit(app: &App, key: KeyEvent) -> Option<usize> {
+    let digit = match key.code {
+        KeyCode::Char(c) if key.modifiers.is_empty() => c.to_digit(10)? as usize,
+        _ => return None,
+    };
+    let bound = app.keymap.action(key).is_some() || app.keymap.command(key).is_some();
+    (!bound && (digit != 0 || app.count.is_some())).then_some(digit)
+}
+
+// Handles a key in the lists, or in the diff once it's focused. Returns whether to quit.
+fn handle_list_or_diff_key(app: &mut App, key: KeyEvent, height: u16) -> anyhow::Result<bool> {
+    if app.diff_focused {
+        handle_diff_key(app, key)?;
+        return Ok(false);
+    }
+
+    if let Some(command) = app.keymap.command(key) {
+        let command = command.to_string();
+        return run_command(app, &command, height);
+    }
+    match app.keymap.action(key) {
+        Some(action) => handle_action(app, action, height),
+        None => Ok(false),
+    }
+}
+
+// Runs a `:` command line, or the action it names, e.g. "stage *.rs" or "stage-all". Returns
+// whether to quit.
+fn run_command(app: &mut App, input: &str, height: u16) -> anyhow::Result<bool> {
+    match Action::from_name(input.trim()) {
+        Some(action) => handle_action(app, action, height),
+        None => app.prepare_command(input).map(|()| false),
+    }
+}
+
+// Runs an action in the lists, from its key or from the palette. Returns whether to quit.
+fn handle_action(app: &mut App, action: Action, height: u16) -> anyhow::Result<bool> {
+    if handle_tree_action(app, action)? {
+        return Ok(false);
+    }
+    match action {
+        Action::Quit => return Ok(true),
+        Action::Shell => app.handoff = Some(Command::Shell),
+        Action::CopyPath => app.copy_path(false),
+        Action::CopyAbsolutePath => app.copy_path(true),
+        Action::CopyHunk => app.copy_hunk()?,
+        // Handled in `App::update`, whatever the screen.
+        Action::Suspend => {}
+        Action::CommandPrompt => app.prompt = Some((PromptPurpose::Command, Prompt::new(":"))),
+        Action::CommandPalette => app.palette = Some(Palette::new()),
+        Action::Filter => {
+            let mut prompt = Prompt::new("/");
+            prompt.input = app.curr_file_list().filter.clone();
+            app.prompt = Some((PromptPurpose::Filter, prompt));
+        }
+        Action::ClearFilter => app.set_filter(""),
+        Action::ShowHelp => app.help = Some(HelpView::new(&app.keymap)),
+        Action::ShowTour => app.tour = Some(Tour::new()),
+        Action::ToggleAutoStage => app.toggle_auto_stage()?,
+        Action::ShowJournal => app.journal = Some(JournalView::open(&app.location, height)?),
+        Action::ShowLog => app.open_log()?,
+        Action::CompareRevision => {
+            app.prompt = Some((
+                PromptPurpose::CompareRevision,
+                Prompt::new("compare with revision: "),
+            ))
+        }
+        Action::ShowStashes => app.open_stashes(false)?,
+        Action::ShowDiscarded => app.open_stashes(true)?,
+        Action::ShowConflicts => app.open_conflicts()?,
+        Action::Stash => {
+            app.prompt = Some((
+                PromptPurpose::StashMessage,
+                Prompt::new("stash message (optional): "),
+            ))
+        }
+        Action::ExportPatch => {
+            app.prompt = Some((PromptPurpose::ExportPatch, Prompt::new("export patch to: ")))
+        }
+        Action::ApplyPatch => {
+            app.prompt = Some((PromptPurpose::ApplyPatch, Prompt::new("apply patch: ")))
+        }
+        Action::ScrollDown => {
+            if let Some(v) = &mut app.diff_view {
+                v.scroll.down(&app.config.scroll, last_line(v.line_count()));
+            }
+        }
+        Action::ScrollUp => {
+            if let Some(v) = &mut app.diff_view {
+                v.scroll.up(&app.config.scroll);
+            }
+        }
+        Action::NextHunk => {
+            if let Some(v) = &mut app.diff_view {
+                v.next_hunk(&app.config.scroll);
+            }
+        }
+        Action::PreviousHunk => {
+            if let Some(v) = &mut app.diff_view {
+                v.previous_hunk(&app.config.scroll);
+            }
+        }
+        Action::NextChange => app.jump_to_change(true),
+        Action::PreviousChange => app.jump_to_change(false),
+        Action::SplitHunk => app.split_hunk()?,
+        Action::FetchMissing => app.fetch_missing()?,
+        Action::Fetch => app.fetch_remote()?,
+        Action::ShowIncoming => app.open_incoming()?,
+        Action::Push => app.start_git_op(GitOp::Push)?,
+        Action::Pull => app.start_git_op(GitOp::Pull)?,
+        Action::FocusDiff => {
+            app.diff_focused = app.config.layout.panes.iter().any(|p| p.pane == Pane::Diff)
+        }
+        Action::RecordMacro => app.macros.toggle_recording(),
+        Action::ReplayMacro => app.macros.start_replay(),
+        Action::ShowStats => {
+            let lines = stats::summarize(
+                &app.location,
+                &app.backend.statuses(FileStatusKind::Unstaged)?,
+                &app.backend.statuses(FileStatusKind::Staged)?,
+            )?;
+            app.info = Some((String::from(" Statistics "), lines));
+        }
+        Action::ShowEntryInfo => {
+            let kind = app.curr_kind();
+            let settings = DiffSettings::from(&app.config);
+            if let Some(entry) = app.curr_file_list().current().cloned() {
+                let lines = entry_info::describe(&app.location, &entry, kind, settings)?;
+                app.info = Some((String::from(" Entry "), lines));
+            }
+        }
+        Action::CreateTag => app.start_tag(),
+        Action::Commit => app.start_commit()?,
+        Action::ToggleTree => {
+            app.tree = match app.tree {
+                Some(_) => None,
+                None => Some(Tree::new()),
+            };
+            app.combined = false;
+        }
+        Action::ToggleCombined => {
+            app.combined = !app.combined;
+            app.tree = None;
+            app.refresh_current()?;
+        }
+        Action::CycleSort => app.cycle_sort()?,
+        Action::ToggleIgnored => app.toggle_ignored()?,
+        Action::ToggleBlame => app.show_blame = !app.show_blame,
+        Action::ToggleConsole => app.toggle_console(),
+        Action::MoreContext
+        | Action::LessContext
+        | Action::CycleWhitespace
+        | Action::ToggleWhitespaceErrors
+        | Action::ToggleLineEndings => app.adjust_diff(action),
+        Action::RotateLayout => layout::rotate(&mut app.config.layout),
+        Action::GrowPane => layout::resize(&mut app.config.layout, RESIZE_STEP),
+        Action::ShrinkPane => layout::resize(&mut app.config.layout, -RESIZE_STEP),
+        Action::Amend => app.start_amend()?,
+        Action::Fixup => app.start_fixup()?,
+        Action::CherryPick
+        | Action::Revert
+        | Action::StageRevert
+        | Action::ApplyStash
+        | Action::PopStash
+        | Action::DropStash
+        | Action::UseOurs
+        | Action::UseTheirs
+        | Action::NextFile
+        | Action::PreviousFile
+        | Action::NextConflict
+        | Action::PreviousConflict
+        | Action::SelectLines => {}
+        Action::RestoreDiscard => app.request_restore_last_discard()?,
+        Action::Stage => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_on_current(BatchOp::Stage)?;
+            }
+        }
+        Action::IntentToAdd => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.intent_to_add()?;
+            }
+        }
+        Action::StageMode => app.stage_mode()?,
+        Action::StageIgnoringWhitespace => app.stage_ignoring_whitespace()?,
+        Action::Ignore => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.start_ignore()?;
+            }
+        }
+        Action::StageAll => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_all(BatchOp::Stage)?;
+            }
+        }
+        Action::UnstageAll => {
+            if let AppViewState::StagedFiles = app.view_state {
+                app.request_all(BatchOp::Unstage)?;
+            }
+        }
+        Action::Discard => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_on_current(BatchOp::Discard)?;
+            }
+        }
+        Action::Unstage => {
+            if let AppViewState::StagedFiles = app.view_state {
+                app.request_on_current(BatchOp::Unstage)?;
+            }
+        }
+        Action::StageDirectory => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_directory_op(BatchOp::Stage)?;
+            }
+        }
+        Action::DiscardDirectory => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_directory_op(BatchOp::Discard)?;
+            }
+        }
+        Action::UnstageDirectory => {
+            if let AppViewState::StagedFiles = app.view_state {
+                app.request_directory_op(BatchOp::Unstage)?;
+            }
+        }
+        Action::ToggleView => app.toggle_view()?,
+        Action::OpenFile if app.load_large_diff() => {}
+        Action::OpenFile => match app.curr_file_list().current().cloned() {
+            Some(item) if item.is_dir() => {
+                let dir = item.new_file.clone();
+                app.expand_untracked_dir(dir)?;
+            }
+            Some(item) => {
+                app.file_view = Some(FileView::open(&app.location, &item, Snapshot::Worktree)?)
+            }
+            None => {}
+        },
+        Action::EditFile => app.edit_current()?,
+        Action::Refresh => app.refresh_current()?,
+        Action::ToggleMark => app.curr_file_list().toggle_mark(),
+        Action::MarkAll => app.curr_file_list().toggle_mark_all(),
+        Action::Down => app.next_entry(),
+        Action::Up => app.previous_entry(),
+        Action::Unselect => app.curr_file_list().unselect(),
+        Action::CycleSnapshot => {}
+    }
+    Ok(false)
+}
+
+// Handles the actions that act differently on the tree of directories: moving through its rows,
+// collapsing directories, and staging, unstaging or discarding a directory as a whole. Returns
+// whether the action was handled.
+fn handle_tree_action(app: &mut App, action: Action) -> anyhow::Result<bool> {
+    let staged = matches!(app.view_state, AppViewState::StagedFiles);
+    let (tree, list) = match app.curr_tree() {
+        Some(t) => t,
+        None => return Ok(false),
+    };
+    match action {
+        Action::Down => tree.next(list),
+        Action::Up => tree.previous(list),
+        Action::Unselect => tree.collapse(list),
+        _ => {
+            let dir = match tree.current_dir(list) {
+                Some(dir) => dir,
+                None => return Ok(false),
+            };
+            match (action, staged) {
+                (Action::OpenFile, _) => tree.toggle(list),
+                (Action::Stage, false) => app.request_under(BatchOp::Stage, dir)?,
+                (Action::Discard, false) => app.request_under(BatchOp::Discard, dir)?,
+                (Action::Unstage, true) => app.request_under(BatchOp::Unstage, dir)?,
+                _ => return Ok(false),
+            }
+        }
+    }
+    Ok(true)
+}
+
+// Clicking a row of the list selects it (and opens or closes a directory of the tree), and the
+// wheel moves the cursor through it. In the diff pane, clicking a hunk selects it, clicking its
+// header stages or unstages it, and the wheel scrolls. In the status bar, the counts show their
+// list and the branch opens the log. Popups take no clicks, other than to be closed by one when
+// any key would close them.
+fn handle_mouse(app: &mut App, event: MouseEvent) -> anyhow::Result<()> {
+    if app.notice.is_some() || app.info.is_some() {
+        if event.kind == MouseEventKind::Down(MouseButton::Left) {
+            app.notice = None;
+            app.info = None;
+        }
+        return Ok(());
+    }
+    let covered = app.help.is_some()
+        || app.tour.is_some()
+        || app.pending.is_some()
+        || app.commit_hooks.is_some()
+        || app.git_run.is_some()
+        || app.draft.is_some()
+        || app.ignore.is_some()
+        || app.fixup.is_some()
+        || app.partial.is_some()
+        || app.prompt.is_some();
+    let target = match app.regions.target(event) {
+        Some(target) if !covered => target,
+        _ => return Ok(()),
+    };
+    app.notifications.dismiss();
+    match target {
+        mouse::Target::ListRow(row) => {
+            app.diff_focused = false;
+            click_row(app, row)?;
+        }
+        mouse::Target::ScrollList { down } => {
+            if at_end_of_list(app, down) {
+                return Ok(());
+            }
+            if let Some(comparison) = &mut app.comparison {
+                comparison.select(down);
+                return Ok(());
+            }
+            if let Some(log) = &mut app.log {
+                match down {
+                    true => log.next(),
+                    false => log.previous(),
+                }
+                return app.select_commit();
+            }
+            let action = if down { Action::Down } else { Action::Up };
+            if !handle_tree_action(app, action)? {
+                match down {
+                    true => app.next_entry(),
+                    false => app.previous_entry(),
+                }
+            }
+        }
+        mouse::Target::DiffLine(line) => click_diff(app, line)?,
+        mouse::Target::ScrollDiff { down } => scroll_diff(app, down),
+        mouse::Target::ScrollConsole { down } => {
+            let step = app.config.scroll.step as usize;
+            app.console_scroll = match down {
+                true => app.console_scroll.saturating_sub(step),
+                false => app.console_scroll.saturating_add(step),
+            };
+        }
+        mouse::Target::StatusBar(status_bar::Target::Log) => {
+            if app.log.is_none() {
+                app.open_log()?;
+            }
+        }
+        mouse::Target::StatusBar(status_bar::Target::Incoming) => {
+            if app.log.is_none() || app.incoming.is_none() {
+                app.open_incoming()?;
+            }
+        }
+        mouse::Target::StatusBar(status_bar::Target::List(kind)) => {
+            app.log = None;
+            app.comparison = None;
+            if app.curr_kind() != kind {
+                app.toggle_view()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Whether the cursor is on the last (or first) row of the list pane, where the wheel stops rather
+// than wrapping around as the keys do.
+fn at_end_of_list(app: &mut App, down: bool) -> bool {
+    let (selected, len) = if let Some(comparison) = &app.comparison {
+        let files = &comparison.files;
+        (files.state.selected(), files.items.len())
+    } else if let Some(log) = &app.log {
+        (log.state.selected(), log.items.len())
+    } else if app.combined {
+        // As one list, with the staged entries first.
+        let staged = app.staged_files.items.len();
+        let selected = match app.view_state {
+            AppViewState::StagedFiles => app.staged_files.state.selected(),
+            AppViewState::UnstagedFiles => app.unstaged_files.state.selected().map(|i| staged + i),
+        };
+        (selected, staged + app.unstaged_files.items.len())
+    } else if let Some((tree, list)) = app.curr_tree() {
+        let len = tree.rows(list).len();
+        (tree.state.selected(), len)
+    } else {
+        let list = app.curr_file_list();
+        (list.state.selected(), list.items.len())
+    };
+    match (selected, down) {
+        (Some(i), true) => i + 1 >= len,
+        (Some(i), false) => i == 0,
+        (None, _) => false,
+    }
+}
+
+// Selects the clicked row of whichever list the list pane shows.
+fn click_row(app: &mut App, row: usize) -> anyhow::Result<()> {
+    if let Some(comparison) = &mut app.comparison {
+        if row < comparison.files.items.len() {
+            comparison.files.state.select(Some(row));
+            comparison.scroll = Scroll::default();
+        }
+        return Ok(());
+    }
+    if let Some(log) = &mut app.log {
+        if row < log.items.len() {
+            log.state.select(Some(row));
+        }
+        return app.select_commit();
+    }
+    if app.combined {
+        // Each section has a heading, and a blank row comes between them.
+        let staged = app.staged_files.items.len();
+        let (view_state, list, i) = match row.checked_sub(1) {
+            Some(i) if i < staged => (AppViewState::StagedFiles, &mut app.staged_files, i),
+            _ => match row.checked_sub(staged + 3) {
+                Some(i) => (AppViewState::UnstagedFiles, &mut app.unstaged_files, i),
+                None => return Ok(()),
+            },
+        };
+        if i < list.items.len() {
+            list.state.select(Some(i));
+            app.view_state = view_state;
+        }
+        return Ok(());
+    }
+    if let Some((tree, list)) = app.curr_tree() {
+        if tree.select_row(list, row) {
+            tree.toggle(list);
+        }
+        return Ok(());
+    }
+    let list = app.curr_file_list();
+    if row < list.items.len() {
+        list.state.select(Some(row));
+    }
+    Ok(())
+}
+
+// Selects the clicked hunk, and stages or unstages it if its header was clicked.
+fn click_diff(app: &mut App, line: usize) -> anyhow::Result<()> {
+    if app.log.is_some() || app.comparison.is_some() {
+        return Ok(());
+    }
+    let view = match app.selected_diff() {
+        Some(view) => view,
+        None => return Ok(()),
+    };
+    let (hunk, header) = match view.hunk_at(line) {
+        Some(at) => at,
+        None => return Ok(()),
+    };
+    view.pick_hunk(hunk);
+    app.diff_focused = true;
+    if header {
+        match app.view_state {
+            AppViewState::UnstagedFiles => app.request_hunk_op(BatchOp::Stage)?,
+            AppViewState::StagedFiles => app.request_hunk_op(BatchOp::Unstage)?,
+        }
+    }
+    Ok(())
+}
+
+fn scroll_diff(app: &mut App, down: bool) {
+    let scroll = app.config.scroll;
+    if let Some(comparison) = &mut app.comparison {
+        let lines = comparison
+            .files
+            .current()
+            .map_or(0, |f| preview::line_count(f, &app.theme));
+        match down {
+            true => comparison.scroll.down(&scroll, last_line(lines)),
+            false => comparison.scroll.up(&scroll),
+        }
+        return;
+    }
+    if app.log.is_some() {
+        if let Some(diff) = &mut app.commit_diff {
+            let lines = diff
+                .current_file()
+                .map_or(0, |f| preview::line_count(f, &app.theme));
+            match down {
+                true => diff.scroll.down(&scroll, last_line(lines)),
+                false => diff.scroll.up(&scroll),
+            }
+        }
+        return;
+    }
+    if let Some(view) = app.selected_diff() {
+        match down {
+            true => view.scroll.down(&scroll, last_line(view.line_count())),
+            false => view.scroll.up(&scroll),
+        }
+    }
+}
+
+// "hunk 2", or "lines 3-5 of hunk 2" when only some of its lines are selected.
+fn describe_hunk(hunk: usize, lines: Option<&Range<usize>>) -> String {
+    match lines {
+        Some(lines) => format!(
+            "lines {}-{} of hunk {}",
+            lines.start + 1,
+            lines.end,
+            hunk + 1
+        ),
+        None => format!("hunk {}", hunk + 1),
+    }
+}
+
+fn handle_diff_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    let selecting = app.diff_view.as_ref().is_some_and(|v| v.is_selecting());
+    if key.code == KeyCode::Esc {
+        match &mut app.diff_view {
+            Some(view) if selecting => view.toggle_selection(),
+            _ => app.diff_focused = false,
+        }
+        return Ok(());
+    }
+
+    let scroll = app.config.scroll;
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::Unselect | Action::FocusDiff) => app.diff_focused = false,
+        Some(Action::SelectLines) => {
+            if let Some(view) = app.selected_diff() {
+                view.toggle_selection();
+            }
+        }
+        Some(action @ (Action::Down | Action::Up)) if selecting => {
+            if let Some(view) = app.selected_diff() {
+                view.move_cursor(action == Action::Down);
+            }
+        }
+        Some(Action::Down | Action::NextHunk) => {
+            if let Some(view) = app.selected_diff() {
+                view.next_hunk(&scroll);
+            }
+        }
+        Some(Action::Up | Action::PreviousHunk) => {
+            if let Some(view) = app.selected_diff() {
+                view.previous_hunk(&scroll);
+            }
+        }
+        Some(Action::NextChange) => app.jump_to_change(true),
+        Some(Action::PreviousChange) => app.jump_to_change(false),
+        Some(Action::SplitHunk) => app.split_hunk()?,
+        Some(Action::ScrollDown) => {
+            if let Some(view) = app.selected_diff() {
+                view.scroll.down(&scroll, last_line(view.line_count()));
+            }
+        }
+        Some(Action::ScrollUp) => {
+            if let Some(view) = app.selected_diff() {
+                view.scroll.up(&scroll);
+            }
+        }
+        Some(Action::Stage) => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_hunk_op(BatchOp::Stage)?;
+            }
+        }
+        Some(Action::Unstage) => {
+            if let AppViewState::StagedFiles = app.view_state {
+                app.request_hunk_op(BatchOp::Unstage)?;
+            }
+        }
+        Some(Action::Discard) => {
+            if let AppViewState::UnstagedFiles = app.view_state {
+                app.request_hunk_op(BatchOp::Discard)?;
+            }
+        }
+        Some(Action::StageMode) => app.stage_mode()?,
+        Some(Action::StageIgnoringWhitespace) => app.stage_ignoring_whitespace()?,
+        Some(Action::FetchMissing) => app.fetch_missing()?,
+        Some(Action::CopyPath) => app.copy_path(false),
+        Some(Action::CopyAbsolutePath) => app.copy_path(true),
+        Some(Action::CopyHunk) => app.copy_hunk()?,
+        Some(Action::OpenFile) => {
+            app.load_large_diff();
+        }
+        Some(
+            action @ (Action::MoreContext
+            | Action::LessContext
+            | Action::CycleWhitespace
+            | Action::ToggleWhitespaceErrors
+            | Action::ToggleLineEndings),
+        ) => app.adjust_diff(action),
+        _ => {}
+    }
+    Ok(())
+}
+
+// Scrolls the output of a running commit hook. Once one has failed, the commit can go ahead
+// without the hooks, like `git commit --no-verify`, or the message be edited again.
+fn handle_commit_hooks_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    let commit = match &mut app.commit_hooks {
+        Some(commit) => commit,
+        None => return Ok(()),
+    };
+    let failed = commit.run.status.is_some();
+    match key.code {
+        KeyCode::Down => commit.run.scroll_down(),
+        KeyCode::Up => commit.run.scroll_up(),
+        KeyCode::Esc if failed => app.draft = app.commit_hooks.take().map(|c| c.draft),
+        KeyCode::Char('n') if failed => {
+            if let Some(CommitHooks { draft, message, .. }) = app.commit_hooks.take() {
+                app.commit(&draft.purpose, &message)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_pending_key(app: &mut App, code: KeyCode) -> anyhow::Result<()> {
+    match code {
+        KeyCode::Char('y') | KeyCode::Enter => app.confirm_pending()?,
+        KeyCode::Char('n') | KeyCode::Esc => app.pending = None,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_file_view_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    if key.code == KeyCode::Esc {
+        app.file_view = None;
+        return Ok(());
+    }
+
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::OpenFile) => app.file_view = None,
+        Some(Action::CycleSnapshot) => {
+            let file_list = match app.view_state {
+                AppViewState::UnstagedFiles => &app.unstaged_files,
+                AppViewState::StagedFiles => &app.staged_files,
+            };
+            if let (Some(item), Some(view)) = (file_list.current(), &mut app.file_view) {
+                view.cycle(&app.location, item)?;
+            }
+        }
+        Some(Action::FetchMissing) => app.fetch_missing()?,
+        Some(Action::NextConflict) => {
+            if let Some(view) = &mut app.file_view {
+                view.next_conflict();
+            }
+        }
+        Some(Action::PreviousConflict) => {
+            if let Some(view) = &mut app.file_view {
+                view.previous_conflict();
+            }
+        }
+        Some(Action::Down) => {
+            if let Some(view) = &mut app.file_view {
+                view.scroll_down();
+            }
+        }
+        Some(Action::Up) => {
+            if let Some(view) = &mut app.file_view {
+                view.scroll_up();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_journal_key(app: &mut App, key: KeyEvent) {
+    if key.code == KeyCode::Esc {
+        app.journal = None;
+        return;
+    }
+
+    let view = match &mut app.journal {
+        Some(view) => view,
+        None => return,
+    };
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::ShowJournal) => app.journal = None,
+        Some(Action::Down) => view.scroll_down(),
+        Some(Action::Up) => view.scroll_up(),
+        _ => {}
+    }
+}
+
+fn handle_review_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Esc => return app.leave_review(),
+        KeyCode::Enter => {
+            app.review = None;
+            return app.write_commit_message();
+        }
+        _ => {}
+    }
+
+    let review = match &mut app.review {
+        Some(review) => review,
+        None => return Ok(()),
+    };
+    match app.keymap.action(key) {
+        Some(Action::Quit) => app.leave_review()?,
+        Some(Action::Commit) => {
+            app.review = None;
+            app.write_commit_message()?;
+        }
+        Some(Action::Unstage) => app.unstage_reviewed()?,
+        Some(Action::Down | Action::NextFile) => {
+            review.select(true, &app.config.scroll, &app.theme)
+        }
+        Some(Action::Up | Action::PreviousFile) => {
+            review.select(false, &app.config.scroll, &app.theme)
+        }
+        Some(Action::ScrollDown) => {
+            let lines = review.line_count(&app.theme);
+            review.scroll.down(&app.config.scroll, last_line(lines));
+        }
+        Some(Action::ScrollUp) => review.scroll.up(&app.config.scroll),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_comparison_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    if key.code == KeyCode::Esc {
+        app.comparison = None;
+        return Ok(());
+    }
+
+    let comparison = match &mut app.comparison {
+        Some(comparison) => comparison,
+        None => return Ok(()),
+    };
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::CompareRevision) => app.comparison = None,
+        Some(Action::Down | Action::NextFile) => comparison.select(true),
+        Some(Action::Up | Action::PreviousFile) => comparison.select(false),
+        Some(Action::ScrollDown) => {
+            let lines = comparison
+                .files
+                .current()
+                .map_or(0, |f| preview::line_count(f, &app.theme));
+            comparison.scroll.down(&app.config.scroll, last_line(lines));
+        }
+        Some(Action::ScrollUp) => comparison.scroll.up(&app.config.scroll),
+        Some(Action::ToggleView) => {
+            comparison.reload(&app.location, !comparison.staged, &app.config)?
+        }
+        Some(Action::Refresh) => {
+            comparison.reload(&app.location, comparison.staged, &app.config)?
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_log_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    if key.code == KeyCode::Esc {
+        app.log = None;
+        return Ok(());
+    }
+
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::ShowLog | Action::ShowIncoming) => app.log = None,
+        Some(Action::Fetch) => app.fetch_remote()?,
+        Some(Action::CreateTag) => app.start_tag(),
+        Some(Action::CherryPick) => app.request_commit_op(CommitOp::CherryPick)?,
+        Some(Action::Revert) => app.request_commit_op(CommitOp::Revert)?,
+        Some(Action::StageRevert) => app.request_commit_op(CommitOp::StageRevert)?,
+        Some(Action::Down) => {
+            if let Some(log) = &mut app.log {
+                log.next();
+            }
+            app.select_commit()?;
+        }
+        Some(Action::Up) => {
+            if let Some(log) = &mut app.log {
+                log.previous();
+            }
+            app.select_commit()?;
+        }
+        Some(Action::NextFile) => {
+            if let Some(diff) = &mut app.commit_diff {
+                diff.next_file();
+            }
+        }
+        Some(Action::PreviousFile) => {
+            if let Some(diff) = &mut app.commit_diff {
+                diff.previous_file();
+            }
+        }
+        Some(Action::ScrollDown) => {
+            if let Some(diff) = &mut app.commit_diff {
+                let lines = diff
+                    .current_file()
+                    .map_or(0, |f| preview::line_count(f, &app.theme));
+                diff.scroll.down(&app.config.scroll, last_line(lines));
+            }
+        }
+        Some(Action::ScrollUp) => {
+            if let Some(diff) = &mut app.commit_diff {
+                diff.scroll.up(&app.config.scroll);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_stash_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    if key.code == KeyCode::Esc {
+        app.stashes = None;
+        return Ok(());
+    }
+
+    let view = match &mut app.stashes {
+        Some(view) => view,
+        None => return Ok(()),
+    };
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::ShowStashes | Action::ShowDiscarded) => app.stashes = None,
+        Some(Action::ApplyStash) => app.request_stash_op(StashOp::Apply)?,
+        Some(Action::PopStash) => app.request_stash_op(StashOp::Pop)?,
+        Some(Action::DropStash) => app.request_stash_op(StashOp::Drop)?,
+        Some(Action::Down) => view.next(&app.location)?,
+        Some(Action::Up) => view.previous(&app.location)?,
+        Some(Action::NextFile) => view.next_file(),
+        Some(Action::PreviousFile) => view.previous_file(),
+        Some(Action::ScrollDown) => {
+            let lines = view
+                .current_file()
+                .map_or(0, |f| preview::line_count(f, &app.theme));
+            view.scroll.down(&app.config.scroll, last_line(lines));
+        }
+        Some(Action::ScrollUp) => view.scroll.up(&app.config.scroll),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_conflicts_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    if key.code == KeyCode::Esc {
+        app.conflicts = None;
+        return Ok(());
+    }
+
+    let view = match &mut app.conflicts {
+        Some(view) => view,
+        None => return Ok(()),
+    };
+    match app.keymap.action(key) {
+        Some(Action::Quit | Action::ShowConflicts) => app.conflicts = None,
+        Some(Action::UseOurs) => app.request_resolve_conflict(Side::Ours)?,
+        Some(Action::UseTheirs) => app.request_resolve_conflict(Side::Theirs)?,
+        Some(Action::EditFile) => {
+            let path = app.location.workdir().join(&view.current().path);
+            app.handoff = Some(Command::Edit(path, None));
+        }
+        Some(Action::Stage) => app.mark_resolved()?,
+        Some(Action::Down) => view.next()?,
+        Some(Action::Up) => view.previous()?,
+        Some(Action::ScrollDown) => {
+            let lines = view.line_count();
+            view.scroll.down(&app.config.scroll, last_line(lines));
+        }
+        Some(Action::ScrollUp) => view.scroll.up(&app.config.scroll),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    app.regions = mouse::Regions::default();
+    if f.size().width < MIN_WIDTH || f.size().height < MIN_HEIGHT {
+        too_small(f);
+        return;
+    }
+    if let Some(view) = &app.file_view {
+        file_content_view(f, view, &app.keymap);
+        return;
+    }
+    if let Some(view) = &app.journal {
+        journal_view(f, view);
+        return;
+    }
+    if let Some(view) = &app.stashes {
+        let area = notifications_area(f, f.size(), &app.notifications);
+        stash_view(f, area, view, &app.keymap, &app.theme);
+        popups(f, app);
+        return;
+    }
+    if let Some(review) = &app.review {
+        let area = notifications_area(f, f.size(), &app.notifications);
+        review::render_review(f, area, review, &review_hints(&app.keymap), &app.theme);
+        popups(f, app);
+        return;
+    }
+    if let Some(view) = &app.conflicts {
+        let area = notifications_area(f, f.size(), &app.notifications);
+        conflicts::render_conflicts(f, area, view, &conflict_hints(&app.keymap));
+        popups(f, app);
+        return;
+    }
+
+    let mut footer = Vec::new();
+    let filter = &app.curr_file_list().filter;
+    if !filter.is_empty() {
+        footer.push(Span::styled(
+            format!("/{}  ", filter),
+            Style::default().fg(Color::LightCyan),
+        ));
+    }
+    if !matches!(app.config.list.sort, SortOrder::Path) {
+        footer.push(Span::styled(
+            format!("by {}  ", app.config.list.sort.name()),
+            Style::default().fg(Color::LightCyan),
+        ));
+    }
+    if app.config.list.show_ignored {
+        footer.push(Span::styled(
+            "with ignored  ",
+            Style::default().fg(Color::LightCyan),
+        ));
+    }
+    let untracked = match app.config.list.untracked {
+        ShowUntracked::No => Some("without untracked  "),
+        ShowUntracked::Normal => Some("untracked dirs collapsed  "),
+        ShowUntracked::All => None,
+    };
+    if let Some(untracked) = untracked {
+        footer.push(Span::styled(
+            untracked,
+            Style::default().fg(Color::LightCyan),
+        ));
+    }
+    let marked = app.curr_file_list().marked.len();
+    if marked > 0 {
+        footer.push(Span::styled(
+            format!("{} marked  ", marked),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if let Some(busy) = &app.busy {
+        footer.push(Span::styled(
+            format!("{} {} entries...  ", busy.op.progress(), busy.entries.len()),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let refreshing = app.refreshes.iter().find_map(|(_, w)| w.spinner());
+    let diff_loading = app.diff_job.as_ref().and_then(|j| j.worker.spinner());
+    let blame_loading = app.blame_job.as_ref().and_then(|j| j.worker.spinner());
+    if let Some(spinner) = refreshing.or(diff_loading).or(blame_loading) {
+        let what = match (refreshing, diff_loading) {
+            (Some(_), _) => "refreshing",
+            (None, Some(_)) => "loading the diff",
+            (None, None) => "loading the blame",
+        };
+        footer.push(Span::styled(
+            format!("{} {}...  ", spinner, what),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if let Some(copied) = &app.copied {
+        footer.push(Span::styled(
+            format!("copied {}  ", copied),
+            Style::default().fg(Color::LightGreen),
+        ));
+    }
+    if let Some(count) = app.count {
+        footer.push(Span::styled(
+            format!("{}  ", count),
+            Style::default().fg(Color::LightCyan),
+        ));
+    }
+    if let Some(register) = app.macros.recording() {
+        footer.push(Span::styled(
+            format!("recording @{}  ", register),
+            Style::default().fg(Color::LightRed),
+        ));
+    }
+    if let Some(fetch) = &app.fetch {
+        footer.push(Span::styled(
+            format!("fetching {} objects...  ", fetch.count),
+            Style::default().fg(Color::LightBlue),
+        ));
+    }
+    if let Some(run) = &app.remote_fetch {
+        // Git's progress, e.g. "Receiving objects:  45% (90/200)".
+        let progress = run
+            .output
+            .iter()
+            .rev()
+            .map(|l| l.trim())
+            .find(|l| !l.is_empty())
+            .unwrap_or("starting");
+        footer.push(Span::styled(
+            format!("git fetch: {}  ", progress),
+            Style::default().fg(Color::LightBlue),
+        ));
+    }
+    if let Some(auto_stage) = &app.auto_stage {
+        footer.push(Span::styled(
+            format!("auto-stage on: {}", auto_stage.globs.join(", ")),
+            Style::default().fg(Color::LightGreen),
+        ));
+    }
+
+    if app.config.layout.footer {
+        footer.push(Span::styled(
+            key_hints(&app.keymap),
+            Style::default().fg(app.theme.text),
+        ));
+    }
+
+    let mut main_area = f.size();
+    if !footer.is_empty() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .split(main_area);
+        main_area = chunks[0];
+        f.render_widget(Paragraph::new(Spans::from(footer)), chunks[1]);
+    }
+    if let (true, Some(summary)) = (app.config.layout.status_bar, &app.summary) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(main_area);
+        main_area = chunks[1];
+        app.regions.status_bar = status_bar::render_status_bar(f, chunks[0], summary, &app.theme);
+    }
+    let main_area = notifications_area(f, main_area, &app.notifications);
+
+    for (pane, area) in layout::split(main_area, &app.config.layout) {
+        match pane {
+            Pane::List if app.comparison.is_some() => {
+                if let Some(comparison) = &mut app.comparison {
+                    comparison_view(f, area, comparison, &app.theme);
+                    app.regions.list = Some((mouse::inside_border(area), comparison.files.offset));
+                }
+            }
+            Pane::Diff if app.comparison.is_some() => {
+                if let Some(comparison) = &app.comparison {
+                    comparison_diff_view(f, area, comparison, &app.theme);
+                    app.regions.diff = Some((
+                        mouse::inside_border(area),
+                        comparison.scroll.offset as usize,
+                    ));
+                }
+            }
+            Pane::List => {
+                // The combined view has a heading of its own over each list.
+                let area = match app.log.is_none() && !app.combined && app.theme.line_counts {
+                    true => {
+                        let list = match app.view_state {
+                            AppViewState::UnstagedFiles => &app.unstaged_files,
+                            AppViewState::StagedFiles => &app.staged_files,
+                        };
+                        list_totals_header(f, area, list)
+                    }
+                    false => area,
+                };
+                let first = match (&mut app.log, &app.view_state) {
+                    (Some(log), _) => {
+                        log_view(f, area, log, app.incoming.as_deref(), &app.theme);
+                        log.offset
+                    }
+                    (None, _) if app.combined => {
+                        combined_view(f, area, app);
+                        app.combined_offset
+                    }
+                    (None, view_state) if app.tree.is_some() => {
+                        let list = match view_state {
+                            AppViewState::UnstagedFiles => &mut app.unstaged_files,
+                            AppViewState::StagedFiles => &mut app.staged_files,
+                        };
+                        match &mut app.tree {
+                            Some(tree) => {
+                                tree::render_tree(f, area, list, tree, &app.theme);
+                                tree.offset
+                            }
+                            None => 0,
+                        }
+                    }
+                    (None, AppViewState::UnstagedFiles) => {
+                        files_view(f, area, &mut app.unstaged_files, &app.theme);
+                        app.unstaged_files.offset
+                    }
+                    (None, AppViewState::StagedFiles) => {
+                        files_view(f, area, &mut app.staged_files, &app.theme);
+                        app.staged_files.offset
+                    }
+                };
+                // Only the log has a border around its rows.
+                let rows = match app.log {
+                    Some(_) => mouse::inside_border(area),
+                    None => area,
+                };
+                app.regions.list = Some((rows, first));
+            }
+            Pane::Diff if app.log.is_some() => match &app.commit_diff {
+                Some(diff) => {
+                    commit_diff_view(f, area, diff, &app.theme);
+                    app.regions.diff =
+                        Some((mouse::inside_border(area), diff.scroll.offset as usize));
+                }
+                None => f.render_widget(Block::default().borders(Borders::ALL), area),
+            },
+            Pane::Diff => {
+                // Found (or started) first, and borrowed again to draw it along with the theme.
+                let found = app.selected_diff().is_some();
+                let blamed = app.selected_blame().is_some();
+                let view = app.diff_view.as_mut().filter(|_| found);
+                let blame = view
+                    .as_ref()
+                    .filter(|_| blamed)
+                    .and_then(|v| app.blames.get(v.staged, &v.path))
+                    .map(|(blame, _)| blame);
+                match view {
+                    Some(view) => {
+                        diff_view::render_diff(
+                            f,
+                            area,
+                            view,
+                            app.diff_focused,
+                            DiffSettings::from(&app.config),
+                            blame,
+                            &app.theme,
+                        );
+                        app.regions.diff =
+                            Some((mouse::inside_border(area), view.scroll.offset as usize));
+                    }
+                    None => placeholder_view(f, area, app),
+                }
+            }
+            Pane::Log => {
+                let items: Vec<ListItem> = app
+                    .recent_commits
+                    .iter()
+                    .map(|c| ListItem::new(c.pretty_string()))
+                    .collect();
+                let list = List::new(items)
+                    .style(Style::default().fg(app.theme.text))
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(app.theme.border(false))
+                            .title(" Recent commits "),
+                    );
+                f.render_widget(list, area);
+            }
+            Pane::Console => {
+                console_view(f, area, &mut app.console_scroll, &app.theme);
+                app.regions.console = Some(mouse::inside_border(area));
+            }
+        }
+    }
+
+    popups(f, app);
+}
+
+// Says how big the terminal needs to be, in place of everything else, until it's resized.
+fn too_small<B: Backend>(f: &mut Frame<B>) {
+    let size = f.size();
+    let text = format!(
+        "Terminal too small: {}x{}, needs at least {}x{}",
+        size.width, size.height, MIN_WIDTH, MIN_HEIGHT
+    );
+    // Roughly in the middle, however many lines it wraps onto.
+    let top = size.height.saturating_sub(1) / 2;
+    let area = Rect::new(0, top, size.width, size.height - top);
+    let paragraph = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(paragraph, area);
+}
+
+// Renders the notifications at the bottom of `area`, if there are any, and returns the rest of it.
+fn notifications_area<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    notifications: &Notifications,
+) -> Rect {
+    if notifications.height() == 0 {
+        return area;
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(notifications.height()),
+        ])
+        .split(area);
+    notifications::render_notifications(f, chunks[1], notifications);
+    chunks[0]
+}
+
+// The prompt, editor and dialogs, drawn over whatever view is open.
+fn popups<B: Backend>(f: &mut Frame<B>, app: &App) {
+    if let Some((_, prompt)) = &app.prompt {
+        prompt::render_prompt(f, prompt);
+    }
+    if let Some(draft) = &app.draft {
+        editor::render_editor(f, &draft.editor);
+    }
+    if let Some(commit) = &app.commit_hooks {
+        command_run::render_command_run(f, &commit.run, HOOK_FAILED_HINT);
+    }
+    if let Some(git_run) = &app.git_run {
+        // It's closed as soon as it exits, so there's never a failure to explain.
+        command_run::render_command_run(f, &git_run.run, "");
+    }
+    if let Some(menu) = &app.ignore {
+        ignore::render_ignore_menu(f, menu, &app.theme);
+    }
+    if let Some(picker) = &app.fixup {
+        fixup::render_fixup_picker(f, picker, &app.theme);
+    }
+    if let Some(warning) = &app.partial {
+        partial::render_partial_warning(f, warning, &app.theme);
+    }
+    if let Some(palette) = &app.palette {
+        palette::render_palette(f, palette, &app.keymap, &app.theme);
+    }
+    if let Some(pending) = &app.pending {
+        popup::render_confirm(f, &pending.confirm);
+    }
+    if let Some((title, lines)) = &app.info {
+        popup::render_info(f, title, lines);
+    }
+    if let Some(help) = &app.help {
+        help::render_help(f, help);
+    }
+    if let Some(tour) = &app.tour {
+        tour::render_tour(f, tour, &app.keymap);
+    }
+    if let Some(notice) = &app.notice {
+        popup::render_notice(f, notice);
+    }
+}
+
+// The keys of the operations on the selected stash, for the title of the stash view.
+fn stash_hints(keymap: &Keymap) -> String {
+    [Action::ApplyStash, Action::PopStash, Action::DropStash]
+        .iter()
+        .filter_map(|&a| {
+            keymap
+                .keys(a)
+                .first()
+                .map(|k| format!("{} {}", k, a.name()))
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+// The keys that commit or unstage from the review, for its title.
+fn review_hints(keymap: &Keymap) -> String {
+    let mut hints: Vec<String> = [(Action::Commit, "commit"), (Action::Unstage, "unstage")]
+        .iter()
+        .filter_map(|&(a, name)| keymap.keys(a).first().map(|k| format!("{} {}", k, name)))
+        .collect();
+    hints.push(String::from("esc back"));
+    hints.join("  ")
+}
+
+// The keys that resolve the selected conflict, for the title of the conflicts view.
+fn conflict_hints(keymap: &Keymap) -> String {
+    [
+        (Action::UseOurs, "use ours"),
+        (Action::UseTheirs, "use theirs"),
+        (Action::EditFile, "edit"),
+        (Action::Stage, "mark resolved"),
+    ]
+    .iter()
+    .filter_map(|&(a, name)| keymap.keys(a).first().map(|k| format!("{} {}", k, name)))
+    .collect::<Vec<_>>()
+    .join("  ")
+}
+
+fn too_large_hint(lines: usize, max_lines: usize, keymap: &Keymap) -> Vec<String> {
+    let mut hint = vec![
+        String::from("diff too large to show right away:"),
+        format!("{} lines, preview.max-lines is {}", lines, max_lines),
+    ];
+    if let Some(key) = keymap.keys(Action::OpenFile).first() {
+        hint.push(format!("press {} to load it anyway", key));
+    }
+    hint
+}
+
+// The diff pane while there's no diff to show. When the list is empty, it says why: the working
+// tree is clean, nothing is staged, or the filter hides every file.
+fn placeholder_view<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border(false))
+        .title(" Diff ");
+    let (list, other, other_kind) = match app.view_state {
+        AppViewState::UnstagedFiles => (&app.unstaged_files, &app.staged_files, "staged"),
+        AppViewState::StagedFiles => (&app.staged_files, &app.unstaged_files, "unstaged"),
+    };
+    if !list.items.is_empty() || app.combined {
+        f.render_widget(block, area);
+        return;
+    }
+
+    let heading = match app.view_state {
+        _ if app.startup.is_some() => "Loading the repository...",
+        _ if !list.all.is_empty() => "No files match the filter",
+        AppViewState::UnstagedFiles => "Working tree clean",
+        AppViewState::StagedFiles => "Nothing staged",
+    };
+    let unborn = app.summary.as_ref().is_some_and(|s| s.unborn());
+    let detail = match other.all.len() {
+        _ if app.startup.is_some() || !list.all.is_empty() => String::new(),
+        0 if unborn => String::from("no commits yet, and no files to make the first one of"),
+        0 => String::from("nothing to commit"),
+        n => {
+            let files = if n == 1 { "file" } else { "files" };
+            match app.keymap.keys(Action::ToggleView).first() {
+                Some(key) => format!("{} {} {}, {} shows them", n, files, other_kind, key),
+                None => format!("{} {} {}", n, files, other_kind),
+            }
+        }
+    };
+
+    let inner = block.inner(area);
+    let mut lines = vec![Spans::default(); (inner.height as usize).saturating_sub(2) / 2];
+    lines.push(Spans::from(Span::styled(
+        heading,
+        Style::default()
+            .fg(app.theme.text)
+            .add_modifier(Modifier::BOLD),
+    )));
+    lines.push(Spans::from(Span::styled(
+        detail,
+        Style::default().fg(Color::DarkGray),
+    )));
+    let text = Paragraph::new(lines)
+        .block(block)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(text, area);
+}
+
+// E.g. "1 file changed", "3 files changed".
+fn count_files(files: usize) -> String {
+    format!(
+        "{} file{} changed",
+        files,
+        if files == 1 { "" } else { "s" }
+    )
+}
+
+fn collapsed_dir_hint(keymap: &Keymap) -> String {
+    match keymap.keys(Action::OpenFile).first() {
+        Some(key) => format!("(untracked directory, {} lists its files)", key),
+        None => String::from("(untracked directory, its files aren't listed)"),
+    }
+}
+
+fn not_fetched_hint(keymap: &Keymap) -> String {
+    match keymap.keys(Action::FetchMissing).first() {
+        Some(key) => format!(
+            "(content not fetched from the promisor remote yet, press {} to fetch it)",
+            key
+        ),
+        None => String::from("(content not fetched from the promisor remote yet)"),
+    }
+}
+
+// The keys of the most common actions, for the footer.
+fn key_hints(keymap: &Keymap) -> String {
+    [
+        Action::Stage,
+        Action::Unstage,
+        Action::Discard,
+        Action::ToggleView,
+        Action::CommandPrompt,
+        Action::ShowHelp,
+        Action::Quit,
+    ]
+    .iter()
+    .filter_map(|&a| {
+        keymap
+            .keys(a)
+            .first()
+            .map(|k| format!("{} {}", k, a.name()))
+    })
+    .collect::<Vec<_>>()
+    .join("  ")
+}
+
+fn files_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    input: &mut StatefulList<StatusEntry>,
+    theme: &Theme,
+) {
+    let items: Vec<ListItem> = input
+        .items
+        .iter()
+        .map(|s| entry_item(input, s, area.width, theme))
+        .collect();
+    let height = area.height as usize;
+    mouse::fit(&mut input.state, &mut input.offset, items.len(), height);
+    let list = List::new(items).highlight_style(theme.selected());
+    f.render_stateful_widget(list, area, &mut input.state);
+    show_selection(f, area, input.state.selected(), input.offset, theme);
+}
+
+// Renders the list's totals in the top row of `area`, and returns the rest of it.
+fn list_totals_header<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    list: &StatefulList<StatusEntry>,
+) -> Rect {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(area);
+    let header = Span::styled(
+        list_totals(list),
+        Style::default()
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD),
+    );
+    f.render_widget(Paragraph::new(Spans::from(header)), chunks[0]);
+    chunks[1]
+}
+
+// Without colors, leaves the terminal's cursor at the start of the selected row of a list drawn in
+// `area` from row `offset`, where screen readers, which follow the cursor, read it out.
+fn show_selection<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    selected: Option<usize>,
+    offset: usize,
+    theme: &Theme,
+) {
+    let row = selected.and_then(|i| i.checked_sub(offset));
+    if let (true, Some(row)) = (theme.no_color, row) {
+        if let Some(row) = u16::try_from(row).ok().filter(|&r| r < area.height) {
+            f.set_cursor(area.x, area.y + row);
+        }
+    }
+}
+
+// Both lists one after the other, each under a heading, with the cursor in the one it's in.
+fn combined_view<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App) {
+    let counted = app.theme.line_counts;
+    let heading = |title: &str, list: &StatefulList<StatusEntry>| {
+        let count = match counted {
+            true => list_totals(list),
+            false => list.items.len().to_string(),
+        };
+        ListItem::new(Span::styled(
+            format!("{} ({})", title, count),
+            Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        ))
+    };
+    let (staged, unstaged, theme) = (&app.staged_files, &app.unstaged_files, &app.theme);
+    let mut items = vec![heading("Changes to be committed", staged)];
+    let width = area.width;
+    items.extend(
+        staged
+            .items
+            .iter()
+            .map(|s| entry_item(staged, s, width, theme)),
+    );
+    items.push(ListItem::new(" "));
+    items.push(heading("Changes not staged", unstaged));
+    items.extend(
+        unstaged
+            .items
+            .iter()
+            .map(|s| entry_item(unstaged, s, width, theme)),
+    );
+
+    let selected = match app.view_state {
+        AppViewState::StagedFiles => staged.state.selected().map(|i| 1 + i),
+        AppViewState::UnstagedFiles => unstaged
+            .state
+            .selected()
+            .map(|i| staged.items.len() + 3 + i),
+    };
+    app.combined_state.select(selected);
+    let (state, offset) = (&mut app.combined_state, &mut app.combined_offset);
+    mouse::fit(state, offset, items.len(), area.height as usize);
+    let list = List::new(items).highlight_style(app.theme.selected());
+    f.render_stateful_widget(list, area, &mut app.combined_state);
+    let (selected, offset) = (app.combined_state.selected(), app.combined_offset);
+    show_selection(f, area, selected, offset, &app.theme);
+}
+
+// A row `width` wide of a list of entries.
+fn entry_item(
+    list: &StatefulList<StatusEntry>,
+    s: &StatusEntry,
+    width: u16,
+    theme: &Theme,
+) -> ListItem<'static> {
+    let mut spans = Vec::new();
+    if !list.marked.is_empty() {
+        spans.push(match list.is_marked(s) {
+            true => Span::styled("* ", Style::default().fg(theme.marked)),
+            false => Span::raw("  "),
+        });
+    }
+    let text = s.pretty_string();
+    let text = format!("{}{}", theme.status_marker(s.status), &text[1..]);
+    // The path comes last, after the status and where a rename comes from, and before the closing
+    // quote if it's quoted.
+    let path = s.new_file.escaped();
+    let quoted = s.new_file.quoted().len() > path.len();
+    let path_start = text.len() - usize::from(quoted) - path.len();
+    let matched: Vec<usize> = filter::matches(&list.filter, &path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|i| path_start + i)
+        .collect();
+    spans.extend(highlighted(&text, &matched, theme.entry(s.status), theme));
+    if s.submodule {
+        spans.push(Span::styled(
+            " [submodule]",
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if s.status == Status::Unreadable {
+        spans.push(Span::styled(
+            " [can't be read]",
+            Style::default().fg(Color::Red),
+        ));
+    }
+    push_line_counts(&mut spans, s, width, theme);
+    ListItem::new(Spans::from(spans)).style(Style::default().fg(theme.text))
+}
+
+// Ends a row `width` wide that starts with `spans` with the lines `entry` adds and removes, if
+// they were counted and there's room for them after a space.
+fn push_line_counts(
+    spans: &mut Vec<Span<'static>>,
+    entry: &StatusEntry,
+    width: u16,
+    theme: &Theme,
+) {
+    let Some(lines) = entry.lines else {
+        return;
+    };
+    let used = Spans::from(spans.clone()).width();
+    let counts = preview::line_count_spans(lines, theme);
+    let room = usize::from(width).saturating_sub(used + Spans::from(counts.clone()).width());
+    if room > 0 {
+        spans.push(Span::raw(" ".repeat(room)));
+        spans.extend(counts);
+    }
+}
+
+// How many entries the list shows, and the lines they add and remove between them, e.g.
+// "3 files, +12 −4 lines".
+fn list_totals(list: &StatefulList<StatusEntry>) -> String {
+    let lines: LineCounts = list.items.iter().filter_map(|e| e.lines).sum();
+    let files = match list.items.len() {
+        1 => String::from("1 file"),
+        n => format!("{} files", n),
+    };
+    format!("{}, +{} −{} lines", files, lines.added, lines.removed)
+}
+
+// Splits `text` into spans, with the characters at the byte offsets in `matched` highlighted.
+fn highlighted(text: &str, matched: &[usize], style: Style, theme: &Theme) -> Vec<Span<'static>> {
+    let highlight = style.fg(theme.matched).add_modifier(Modifier::UNDERLINED);
+    let mut spans: Vec<Span> = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in text.char_indices() {
+        let is_matched = matched.contains(&i);
+        if is_matched != run_matched && !run.is_empty() {
+            let style = if run_matched { highlight } else { style };
+            spans.push(Span::styled(std::mem::take(&mut run), style));
+        }
+        run_matched = is_matched;
+        run.push(c);
+    }
+    let style = if run_matched { highlight } else { style };
+    spans.push(Span::styled(run, style));
+    spans
+}
+
+fn log_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    log: &mut StatefulList<LogCommit>,
+    incoming: Option<&str>,
+    theme: &Theme,
+) {
+    let items: Vec<ListItem> = log
+        .items
+        .iter()
+        .map(|c| ListItem::new(c.pretty_string()).style(Style::default().fg(theme.text)))
+        .collect();
+    let height = area.height.saturating_sub(2) as usize;
+    mouse::fit(&mut log.state, &mut log.offset, items.len(), height);
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(match incoming {
+                    Some(upstream) => format!(" Incoming from {} ", upstream),
+                    None => String::from(" Log "),
+                }),
+        )
+        .highlight_style(theme.selected());
+
+    f.render_stateful_widget(list, area, &mut log.state);
+}
+
+// The commands run so far, each with the time it started, what it printed and how it exited, with
+// the latest at the bottom unless it's been scrolled back by `scroll` lines.
+fn console_view<B: Backend>(f: &mut Frame<B>, area: Rect, scroll: &mut usize, theme: &Theme) {
+    let mut lines: Vec<Spans> = Vec::new();
+    console::with_runs(|runs| {
+        for run in runs {
+            let started = journal::format_utc(run.started);
+            lines.push(Spans::from(vec![
+                Span::styled(
+                    format!("{} ", &started[11..]),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(run.command.clone(), Style::default().fg(theme.text)),
+            ]));
+            for line in &run.output {
+                lines.push(Spans::from(Span::styled(
+                    format!("  {}", line),
+                    Style::default().fg(theme.text),
+                )));
+            }
+            let (exit, color) = match run.exit.as_deref() {
+                None => ("running...", Color::Yellow),
+                Some(exit @ "exit 0") => (exit, Color::Green),
+                Some(exit) => (exit, Color::Red),
+            };
+            lines.push(Spans::from(Span::styled(
+                format!("  {}", exit),
+                Style::default().fg(color),
+            )));
+        }
+    });
+
+    let height = area.height.saturating_sub(2) as usize;
+    let last = lines.len().saturating_sub(height);
+    *scroll = (*scroll).min(last);
+    let title = match *scroll {
+        0 => String::from(" Console "),
+        n => format!(" Console ({} lines back) ", n),
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(title),
+        )
+        .scroll(((last - *scroll) as u16, 0));
+    f.render_widget(paragraph, area);
+}
+
+// The changes of the commit selected in the log, one file at a time.
+fn commit_diff_view<B: Backend>(f: &mut Frame<B>, area: Rect, diff: &CommitDiff, theme: &Theme) {
+    let id = diff.id.to_string();
+    let (title, lines) = match diff.current_file() {
+        Some(file) => (
+            format!(
+                " {} {} ({}/{}) ",
+                &id[..7],
+                file.new_path,
+                diff.file + 1,
+                diff.files.len()
+            ),
+            preview::diff_spans(file, theme),
+        ),
+        None => (format!(" {} (no changes) ", &id[..7]), Vec::new()),
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(title),
+        )
+        .scroll((diff.scroll.offset, 0));
+    f.render_widget(paragraph, area);
+}
+
+// The files changed since the revision compared with, in place of the file list.
+fn comparison_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    comparison: &mut Comparison,
+    theme: &Theme,
+) {
+    let items: Vec<ListItem> = comparison
+        .files
+        .items
+        .iter()
+        .map(|file| {
+            let path = match file.old_path == file.new_path {
+                true => file.new_path.to_string(),
+                false => format!("{} -> {}", file.old_path, file.new_path),
+            };
+            ListItem::new(path).style(Style::default().fg(theme.text))
+        })
+        .collect();
+    let height = area.height.saturating_sub(2) as usize;
+    let files = &mut comparison.files;
+    mouse::fit(&mut files.state, &mut files.offset, items.len(), height);
+
+    let id = comparison.id.to_string();
+    let title = format!(
+        " {} ({})..{} ",
+        comparison.revision,
+        &id[..7],
+        if comparison.staged {
+            "index"
+        } else {
+            "worktree"
+        }
     );
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(title),
+        )
+        .highlight_style(theme.selected());
+    f.render_stateful_widget(list, area, &mut files.state);
+}
+
+// The changes of the file selected among those changed since the revision compared with.
+fn comparison_diff_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    comparison: &Comparison,
+    theme: &Theme,
+) {
+    let files = &comparison.files;
+    let (title, lines) = match (files.current(), files.state.selected()) {
+        (Some(file), Some(i)) => (
+            format!(" {} ({}/{}) ", file.new_path, i + 1, files.items.len()),
+            preview::diff_spans(file, theme),
+        ),
+        _ => (String::from(" (no changes) "), Vec::new()),
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(title),
+        )
+        .scroll((comparison.scroll.offset, 0));
+    f.render_widget(paragraph, area);
+}
+
+fn file_content_view<B: Backend>(f: &mut Frame<B>, view: &FileView, keymap: &Keymap) {
+    let mut title = format!(" {} [{}] ", view.path, view.snapshot.label());
+    match (view.conflict, view.conflicts.len()) {
+        (_, 0) => {}
+        (Some(i), n) => title.push_str(&format!(
+            "conflict {}/{} at line {} ",
+            i + 1,
+            n,
+            view.conflicts[i] + 1
+        )),
+        (None, n) => title.push_str(&format!("{} conflicts ", n)),
+    }
+    let text = match &view.content {
+        Some(content) if !view.conflicts.is_empty() => Text::from(preview::conflict_spans(content)),
+        Some(content) => Text::from(content.clone()),
+        None if view.not_fetched => Text::from(not_fetched_hint(keymap)),
+        None => Text::from(format!(
+            "(file does not exist in {})",
+            view.snapshot.label()
+        )),
+    };
+
+    let paragraph = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .scroll((view.scroll, 0));
+
+    f.render_widget(paragraph, f.size());
+}
+
+fn journal_view<B: Backend>(f: &mut Frame<B>, view: &JournalView) {
+    let text = if view.lines.is_empty() {
+        String::from("(no actions recorded yet)")
+    } else {
+        view.lines.join("\n")
+    };
+
+    let paragraph = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(" Journal "))
+        .scroll((view.scroll, 0));
+
+    f.render_widget(paragraph, f.size());
+}
+
+fn stash_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    view: &StashView,
+    keymap: &Keymap,
+    theme: &Theme,
+) {
+    // The list takes up to this many rows; the rest is for the diff of the selected stash.
+    const MAX_LIST_ROWS: u16 = 8;
+    let list_height = u16::try_from(view.stashes.len())
+        .unwrap_or(u16::MAX)
+        .min(MAX_LIST_ROWS)
+        + 2;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(list_height), Constraint::Min(0)])
+        .split(area);
+
+    let items: Vec<ListItem> = view
+        .stashes
+        .iter()
+        .map(|s| ListItem::new(s.pretty_string()).style(Style::default().fg(theme.text)))
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(format!(
+                    " {} ({}) ",
+                    match view.discarded {
+                        true => "Recently discarded",
+                        false => "Stashes",
+                    },
+                    stash_hints(keymap)
+                )),
+        )
+        .highlight_style(theme.selected());
+    let mut state = ListState::default();
+    state.select(Some(view.selected));
+    f.render_stateful_widget(list, chunks[0], &mut state);
 
-    f.render_stateful_widget(list, size, &mut input.state);
+    let (title, lines) = match view.current_file() {
+        Some(file) => (
+            format!(
+                " {} ({}/{}) ",
+                file.new_path,
+                view.file + 1,
+                view.files.len()
+            ),
+            preview::diff_spans(file, theme),
+        ),
+        None => (String::from(" (no changes) "), Vec::new()),
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(title),
+        )
+        .scroll((view.scroll.offset, 0));
+    f.render_widget(paragraph, chunks[1]);
 }
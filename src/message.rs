@@ -0,0 +1,67 @@
+use std::{path::PathBuf, process::ExitStatus};
+
+use crossterm::event::{KeyEvent, MouseEvent};
+
+use git_istage_rs::{diff::FileDiff, git::FileStatusKind, status::StatusEntry};
+
+use crate::{blame::FileBlame, status_bar::Summary, BlameJob, Busy, DiffJob};
+
+// Everything the app reacts to. `run_app` gathers these from the terminal and from the work going
+// on in the background, and `App::update` handles them one at a time.
+pub enum Message {
+    // A key typed at the terminal, or replayed from a macro.
+    Key { key: KeyEvent, typed: bool },
+    // A command given with `--exec`, run once what the one before it started is done.
+    Exec(String),
+    // Answers yes to the confirmation an `--exec` command left, which it can do without.
+    ExecConfirm,
+    // A click or a turn of the mouse wheel, if mouse capture is on.
+    Mouse(MouseEvent),
+    // The terminal was resized to this many rows.
+    Resized(u16),
+    // The worktree, index or refs may have changed: the watcher saw something, auto-staging staged
+    // something, or the refresh interval is up.
+    StatusesChanged,
+    // A file list was refreshed in the background.
+    StatusesLoaded(FileStatusKind, anyhow::Result<Vec<StatusEntry>>),
+    // The selected entry's diff was computed in the background.
+    DiffLoaded(DiffJob, anyhow::Result<FileDiff>),
+    // The blame of the selected entry's file was loaded in the background.
+    BlameLoaded(BlameJob, anyhow::Result<FileBlame>),
+    // What the status bar shows was gathered in the background.
+    SummaryLoaded(anyhow::Result<Summary>),
+    // A fetch of missing objects finished.
+    Fetched(anyhow::Result<()>),
+    // The running commit hook exited.
+    HookExited(anyhow::Result<ExitStatus>),
+    // The running push, pull or rebase exited.
+    GitExited(anyhow::Result<ExitStatus>),
+    // The `git fetch` running in the background exited.
+    RemoteFetched(anyhow::Result<ExitStatus>),
+    // A large batch, held back for a frame so that the footer could say it's running.
+    RunBatch(Busy),
+    // The process was sent SIGTSTP, to stop it as ctrl-z would.
+    Suspend,
+    // The terminal is back from the editor, a shell, or the process being stopped.
+    Resumed(anyhow::Result<()>),
+    // Something in the background went wrong.
+    Failed(anyhow::Error),
+    // Sent once per turn of the loop, after everything else.
+    Tick,
+}
+
+// What handling a message needs from `run_app`, which owns the terminal.
+pub enum Command {
+    None,
+    Quit,
+    // Hands the terminal to the user's editor for this file, at the given line if there is one,
+    // then sends `Message::Resumed`.
+    Edit(PathBuf, Option<u32>),
+    // Hands the terminal to an interactive shell, then sends `Message::Resumed`.
+    Shell,
+    // Restores the terminal and stops the process until it's continued, then sends
+    // `Message::Resumed`.
+    Suspend,
+    // Puts the text on the terminal's clipboard.
+    Copy(String),
+}
@@ -0,0 +1,113 @@
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use tui::{
+    layout::{Margin, Rect},
+    widgets::ListState,
+};
+
+use crate::status_bar::Target as StatusTarget;
+
+// Where the last frame drew what can be clicked on the main screen, to map mouse events back to
+// it. Left empty while another screen is shown.
+#[derive(Default)]
+pub struct Regions {
+    // The rows of the list pane (or of the log in its place), and the index of the row at its top.
+    pub list: Option<(Rect, usize)>,
+    // The lines of the diff pane, and the index of the line at its top.
+    pub diff: Option<(Rect, usize)>,
+    // The lines of the console pane.
+    pub console: Option<Rect>,
+    pub status_bar: Vec<(Rect, StatusTarget)>,
+}
+
+// What a mouse event was aimed at.
+pub enum Target {
+    // A row of the list, counted from its first.
+    ListRow(usize),
+    ScrollList { down: bool },
+    // A line of the diff, counted from its first.
+    DiffLine(usize),
+    ScrollDiff { down: bool },
+    ScrollConsole { down: bool },
+    StatusBar(StatusTarget),
+}
+
+impl Regions {
+    // What a left click or a turn of the wheel at the event's position is aimed at. Anything else,
+    // like dragging or releasing a button, is left alone.
+    pub fn target(&self, event: MouseEvent) -> Option<Target> {
+        let (x, y) = (event.column, event.row);
+        let inside = |area: &Rect| {
+            (area.left()..area.right()).contains(&x) && (area.top()..area.bottom()).contains(&y)
+        };
+        let row = |(area, first): &(Rect, usize)| first + (y - area.y) as usize;
+        let list = self.list.as_ref().filter(|(area, _)| inside(area));
+        let diff = self.diff.as_ref().filter(|(area, _)| inside(area));
+        let console = self.console.filter(inside);
+
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(list) = list {
+                    return Some(Target::ListRow(row(list)));
+                }
+                if let Some(diff) = diff {
+                    return Some(Target::DiffLine(row(diff)));
+                }
+                self.status_bar
+                    .iter()
+                    .find(|(area, _)| inside(area))
+                    .map(|(_, target)| Target::StatusBar(*target))
+            }
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+                let down = event.kind == MouseEventKind::ScrollDown;
+                match (list, diff, console) {
+                    (Some(_), _, _) => Some(Target::ScrollList { down }),
+                    (_, Some(_), _) => Some(Target::ScrollDiff { down }),
+                    (_, _, Some(_)) => Some(Target::ScrollConsole { down }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+// Works out into `offset` the row at the top of a list of `len` one-line items about to be drawn
+// `height` rows high. A list that would leave rows empty at its bottom while some of it is out of
+// sight above, as once the terminal has grown or entries went away, is scrolled back up: tui
+// keeps its offset to itself, but forgets it along with the selection.
+pub fn fit(state: &mut ListState, offset: &mut usize, len: usize, height: usize) {
+    let mut first = first_row(*offset, state.selected(), len, height);
+    if first > 0 && first + height > len {
+        let selected = state.selected();
+        state.select(None);
+        state.select(selected);
+        first = first_row(0, selected, len, height);
+    }
+    *offset = first;
+}
+
+// The index of the first row a `List` of one-line items shows once it's drawn `height` rows high,
+// worked out as tui does: where it was last time, unless that would leave the selected row out of
+// sight.
+fn first_row(previous: usize, selected: Option<usize>, len: usize, height: usize) -> usize {
+    if len == 0 {
+        return previous;
+    }
+    let first = previous.min(len - 1);
+    let selected = selected.unwrap_or(0).min(len - 1);
+    if selected < first {
+        selected
+    } else if selected >= first + height {
+        (selected + 1).saturating_sub(height)
+    } else {
+        first
+    }
+}
+
+// The rows of a pane within its border.
+pub fn inside_border(area: Rect) -> Rect {
+    area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    })
+}
@@ -0,0 +1,78 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Frame,
+};
+
+// How many failures are shown at once. Older ones make room for newer ones.
+const MAX_SHOWN: usize = 3;
+
+// Failed actions, shown in a bar above the footer rather than tearing down the app. They go away
+// after the configured timeout or, without one, with the next key.
+pub struct Notifications {
+    timeout: Option<Duration>,
+    errors: VecDeque<(String, Instant)>,
+}
+
+impl Notifications {
+    // `timeout_secs` of 0 keeps the errors until the next key.
+    pub fn new(timeout_secs: u64) -> Notifications {
+        Notifications {
+            timeout: (timeout_secs > 0).then(|| Duration::from_secs(timeout_secs)),
+            errors: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, error: anyhow::Error) {
+        if self.errors.len() == MAX_SHOWN {
+            self.errors.pop_front();
+        }
+        // With its causes, e.g. "staging a.txt: could not lock the index".
+        self.errors
+            .push_back((format!("{:#}", error), Instant::now()));
+    }
+
+    // Drops the errors that have been shown for long enough.
+    pub fn expire(&mut self) {
+        if let Some(timeout) = self.timeout {
+            self.errors.retain(|(_, shown)| shown.elapsed() < timeout);
+        }
+    }
+
+    // Drops the errors waiting for a key, as one has just been pressed.
+    pub fn dismiss(&mut self) {
+        if self.timeout.is_none() {
+            self.errors.clear();
+        }
+    }
+
+    pub fn height(&self) -> u16 {
+        self.errors.len() as u16
+    }
+}
+
+pub fn render_notifications<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    notifications: &Notifications,
+) {
+    let lines: Vec<Spans> = notifications
+        .errors
+        .iter()
+        .map(|(message, _)| {
+            Spans::from(Span::styled(
+                format!("error: {}", message),
+                Style::default().fg(Color::White).bg(Color::Red),
+            ))
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), area);
+}
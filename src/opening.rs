@@ -0,0 +1,117 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::Instant,
+};
+
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Frame, Terminal,
+};
+
+use git_istage_rs::{config::Config, git::Location};
+
+use crate::{events::EventSource, repo_path, repo_pathspecs, worker, FRAME_TIME};
+
+// What opening the repository takes from the command line.
+#[derive(Clone)]
+pub struct Opening {
+    // Where to look for the repository from: the path given on the command line, or where we were
+    // started.
+    pub dir: PathBuf,
+    pub config_file: Option<PathBuf>,
+    // The `-c` overrides, `--no-color` included.
+    pub overrides: Vec<String>,
+    // Each as given, and resolved against where we were started unless it's given from the root.
+    pub pathspecs: Vec<(String, Option<PathBuf>)>,
+    // The file to start on, resolved against where we were started.
+    pub file: Option<PathBuf>,
+}
+
+// The repository found, and its config layered over the user's, with the pathspecs in it and the
+// file to start on made relative to the root of the working tree.
+pub struct Opened {
+    pub location: Location,
+    pub config: Config,
+    pub file: Option<String>,
+}
+
+impl Opening {
+    // Finds the repository, reads the config and resolves the paths against the repository's
+    // root. On a cold network filesystem, walking up to the repository and canonicalizing the
+    // paths can take seconds of their own.
+    pub fn open(self) -> anyhow::Result<Opened> {
+        // First, so that failing to find one fails with git's own error rather than any later one.
+        let location = Location::discover(&self.dir)?;
+        let root = location.workdir();
+        let config = Config::load(
+            Some(&location),
+            self.config_file.as_deref(),
+            &self.overrides,
+        );
+        let mut config = config?;
+        config.pathspecs = repo_pathspecs(root, &self.pathspecs)?;
+        let file = self.file.as_deref().map(|f| repo_path(root, f));
+        let file = file.transpose()?;
+        Ok(Opened {
+            location,
+            config,
+            file,
+        })
+    }
+
+    // Opens the repository on a thread of its own, saying on `terminal` that it's being opened
+    // until it is: the app shows right away, however slowly the repository is found. None if the
+    // user quit meanwhile, with `q`, `esc` or `ctrl-c`.
+    pub fn open_showing<B: Backend>(
+        self,
+        terminal: &mut Terminal<B>,
+        events: &mut impl EventSource,
+    ) -> anyhow::Result<Option<anyhow::Result<Opened>>> {
+        let dir = self.dir.clone();
+        let started = Instant::now();
+        let (tx, done) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver going away just means the user quit.
+            let _ = tx.send(self.open());
+        });
+        loop {
+            terminal.draw(|f| render_opening(f, &dir, started))?;
+            match done.try_recv() {
+                Ok(opened) => return Ok(Some(opened)),
+                Err(TryRecvError::Disconnected) => anyhow::bail!("opening the repository died"),
+                Err(TryRecvError::Empty) => {}
+            }
+            if let Some(Event::Key(key)) = events.next(FRAME_TIME)? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if ctrl_c || matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+// E.g. "⠋ Opening /home/me/src/project...", in the middle of the screen.
+fn render_opening<B: Backend>(f: &mut Frame<B>, dir: &Path, started: Instant) {
+    let area = f.size();
+    let spinner = worker::spinner(started.elapsed()).map_or(String::new(), |c| format!("{} ", c));
+    let line = Spans::from(Span::styled(
+        format!("{}Opening {}...", spinner, dir.display()),
+        Style::default().add_modifier(Modifier::BOLD),
+    ));
+    let middle = Rect::new(
+        area.x,
+        area.y + area.height / 2,
+        area.width,
+        1.min(area.height),
+    );
+    f.render_widget(Paragraph::new(line).alignment(Alignment::Center), middle);
+}
@@ -0,0 +1,183 @@
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    Frame,
+};
+
+use crate::{
+    filter,
+    keymap::{Action, Keymap},
+    popup,
+    theme::Theme,
+};
+
+// Only this many of the matching actions are listed at once; the list scrolls to the selected one.
+const SHOWN: usize = 10;
+
+// Actions that only do something in another screen (the log, the stashes, the conflicts, a file or
+// the focused diff), which the palette, opened from the lists, leaves out.
+const ELSEWHERE: &[Action] = &[
+    Action::CommandPalette,
+    Action::CycleSnapshot,
+    Action::CherryPick,
+    Action::Revert,
+    Action::StageRevert,
+    Action::ApplyStash,
+    Action::PopStash,
+    Action::DropStash,
+    Action::UseOurs,
+    Action::UseTheirs,
+    Action::NextFile,
+    Action::PreviousFile,
+    Action::NextConflict,
+    Action::PreviousConflict,
+    Action::SelectLines,
+];
+
+pub enum Choice {
+    Action(Action),
+    // A `:` command with its arguments, e.g. "stage *.rs".
+    Command(String),
+}
+
+pub enum PaletteEvent {
+    Run(Choice),
+    Cancel,
+    Pending,
+}
+
+// The popup that finds an action by typing part of its name, fuzzily like the filter does, and
+// runs it. What's typed is run as a `:` command instead once it has arguments.
+pub struct Palette {
+    input: String,
+    // The matching actions, with the byte offsets of the matched characters in their names.
+    matches: Vec<(Action, Vec<usize>)>,
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new() -> Palette {
+        let mut palette = Palette {
+            input: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+        };
+        palette.update();
+        palette
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> PaletteEvent {
+        match code {
+            KeyCode::Esc => return PaletteEvent::Cancel,
+            KeyCode::Enter => {
+                return match (self.is_command(), self.matches.get(self.selected)) {
+                    (false, Some(&(action, _))) => PaletteEvent::Run(Choice::Action(action)),
+                    _ if self.input.trim().is_empty() => PaletteEvent::Cancel,
+                    _ => PaletteEvent::Run(Choice::Command(self.input.trim().to_string())),
+                }
+            }
+            KeyCode::Down if !self.matches.is_empty() => {
+                self.selected = (self.selected + 1) % self.matches.len()
+            }
+            KeyCode::Up if !self.matches.is_empty() => {
+                self.selected = self
+                    .selected
+                    .checked_sub(1)
+                    .unwrap_or(self.matches.len() - 1)
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                self.update();
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.update();
+            }
+            _ => {}
+        }
+        PaletteEvent::Pending
+    }
+
+    // Whether what's typed has arguments, and so is a command rather than an action's name.
+    fn is_command(&self) -> bool {
+        self.input.trim().contains(char::is_whitespace)
+    }
+
+    fn update(&mut self) {
+        let filter = self.input.trim();
+        self.matches = Action::ALL
+            .iter()
+            .filter(|a| !ELSEWHERE.contains(a))
+            .filter_map(|&a| filter::matches(filter, a.name()).map(|m| (a, m)))
+            .collect();
+        // Names with what's typed as a substring come first, then the fuzzier matches.
+        self.matches
+            .sort_by_key(|(_, m)| m.windows(2).any(|w| w[1] != w[0] + 1));
+        self.selected = 0;
+    }
+}
+
+pub fn render_palette<B: Backend>(
+    f: &mut Frame<B>,
+    palette: &Palette,
+    keymap: &Keymap,
+    theme: &Theme,
+) {
+    let mut lines = vec![
+        Spans::from(vec![
+            Span::styled("> ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(palette.input.clone()),
+            Span::styled("_", Style::default().fg(Color::Gray)),
+        ]),
+        Spans::default(),
+    ];
+    if palette.is_command() {
+        lines.push(Spans::from(format!(":{}", palette.input.trim())));
+    } else if palette.matches.is_empty() {
+        lines.push(Spans::from(Span::styled(
+            "no action matches",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let first = palette.selected.saturating_sub(SHOWN - 1);
+    let shown = palette.matches.iter().enumerate().skip(first).take(SHOWN);
+    for (i, (action, matched)) in shown.filter(|_| !palette.is_command()) {
+        let selected = i == palette.selected;
+        let style = match selected {
+            true => theme.selected(),
+            false => Style::default(),
+        };
+        let mut spans = vec![Span::styled(if selected { "> " } else { "  " }, style)];
+        for (offset, c) in action.name().char_indices() {
+            let style = match matched.contains(&offset) {
+                true => style.fg(theme.matched),
+                false => style,
+            };
+            spans.push(Span::styled(c.to_string(), style));
+        }
+        let keys: Vec<String> = keymap.keys(*action).iter().map(|k| k.to_string()).collect();
+        if !keys.is_empty() {
+            let padding = 24usize.saturating_sub(action.name().len());
+            spans.push(Span::styled(
+                format!("{:padding$}{}", "", keys.join(", ")),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        lines.push(Spans::from(spans));
+    }
+    if palette.matches.len() > first + SHOWN && !palette.is_command() {
+        lines.push(Spans::from(Span::styled(
+            format!("  ... and {} more", palette.matches.len() - first - SHOWN),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    popup::render_lines(
+        f,
+        " Actions ",
+        lines,
+        "[enter] run   [up/down] select   [esc] cancel",
+    );
+}
@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    Frame,
+};
+
+use git_istage_rs::{git::FileStatusKind, paths::RepoPath, GitBackend};
+
+use crate::{popup, theme::Theme};
+
+// Only this many of the files are listed at once; the list scrolls to the selected one.
+const SHOWN: usize = 10;
+
+pub enum PartialEvent {
+    // Leave for this file's unstaged changes in the unstaged list.
+    Jump(RepoPath),
+    // Go on with the commit regardless.
+    Commit,
+    Cancel,
+    Pending,
+}
+
+// The warning about the files with changes both staged and not, the commit of which is only part
+// of what's in the worktree, so that a commit of them may not even build. With
+// `commit.confirm-partial` it's shown before the commit, which only goes ahead once confirmed;
+// otherwise it's shown once it's been committed, to stage the rest and amend.
+pub struct PartialWarning {
+    message: String,
+    files: Vec<RepoPath>,
+    selected: usize,
+    // Whether the commit is still to be confirmed.
+    confirm: bool,
+}
+
+impl PartialWarning {
+    pub fn before_commit(files: Vec<RepoPath>) -> PartialWarning {
+        PartialWarning {
+            message: format!(
+                "{} with unstaged changes left out of the commit",
+                count_files(files.len())
+            ),
+            files,
+            selected: 0,
+            confirm: true,
+        }
+    }
+
+    // After `done`, e.g. "Committed 1a2b3c4 Fix the parser".
+    pub fn after_commit(done: &str, files: Vec<RepoPath>) -> PartialWarning {
+        PartialWarning {
+            message: format!(
+                "{}, leaving out the unstaged changes of {}",
+                done,
+                count_files(files.len())
+            ),
+            files,
+            selected: 0,
+            confirm: false,
+        }
+    }
+
+    pub fn awaits_confirmation(&self) -> bool {
+        self.confirm
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> PartialEvent {
+        let count = self.files.len().max(1);
+        match code {
+            KeyCode::Down => self.selected = (self.selected + 1) % count,
+            KeyCode::Up => self.selected = self.selected.checked_sub(1).unwrap_or(count - 1),
+            KeyCode::Enter => {
+                return match self.files.get(self.selected) {
+                    Some(path) => PartialEvent::Jump(path.clone()),
+                    None => PartialEvent::Cancel,
+                }
+            }
+            KeyCode::Char('y') if self.confirm => return PartialEvent::Commit,
+            KeyCode::Esc | KeyCode::Char('n' | 'q') => return PartialEvent::Cancel,
+            _ => {}
+        }
+        PartialEvent::Pending
+    }
+}
+
+// The paths of the files with both staged and unstaged changes, as the lists key them.
+pub fn partial_files(backend: &dyn GitBackend) -> anyhow::Result<Vec<RepoPath>> {
+    let staged: HashSet<RepoPath> = backend
+        .statuses(FileStatusKind::Staged)?
+        .into_iter()
+        .map(|e| e.new_file)
+        .collect();
+    Ok(backend
+        .statuses(FileStatusKind::Unstaged)?
+        .into_iter()
+        .map(|e| e.new_file)
+        .filter(|path| staged.contains(path))
+        .collect())
+}
+
+fn count_files(count: usize) -> String {
+    match count {
+        1 => String::from("1 file"),
+        n => format!("{} files", n),
+    }
+}
+
+pub fn render_partial_warning<B: Backend>(
+    f: &mut Frame<B>,
+    warning: &PartialWarning,
+    theme: &Theme,
+) {
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            warning.message.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::default(),
+    ];
+    let first = warning.selected.saturating_sub(SHOWN - 1);
+    for (i, path) in warning.files.iter().enumerate().skip(first).take(SHOWN) {
+        lines.push(match i == warning.selected {
+            true => Spans::from(Span::styled(format!("> {}", path), theme.selected())),
+            false => Spans::from(format!("  {}", path)),
+        });
+    }
+    if warning.files.len() > first + SHOWN {
+        lines.push(Spans::from(Span::styled(
+            format!("  ... and {} more", warning.files.len() - first - SHOWN),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    let footer = match warning.confirm {
+        true => "[y] commit anyway   [enter] jump to file   [esc] cancel",
+        false => "[enter] jump to file   [esc] dismiss",
+    };
+    popup::render_lines(f, " Partly staged ", lines, footer);
+}
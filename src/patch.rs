@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{ApplyLocation, ApplyOptions, Diff, DiffFormat, DiffOptions, Index, Repository};
+
+use git_istage_rs::{
+    diff::{self, DiffSettings},
+    git::{FileStatusKind, Location},
+    status::StatusEntry,
+};
+
+// Writes the staged changes, or with `entry` just that file's unstaged ones, to `path` as a patch
+// `git apply` (and `apply` below) takes, binary files included. Returns how many files it changes.
+pub fn export(
+    location: &Location,
+    path: &Path,
+    entry: Option<&StatusEntry>,
+    settings: DiffSettings,
+) -> anyhow::Result<usize> {
+    let repo = location.open()?;
+    let diff = match entry {
+        Some(entry) => diff::entry_diff(&repo, entry, FileStatusKind::Unstaged, false, settings)?,
+        None => staged_diff(&repo, settings)?,
+    };
+    let files = diff.deltas().len();
+    if files == 0 {
+        anyhow::bail!("there are no changes to export");
+    }
+    let patch = patch_text(&diff)?;
+    fs::write(path, patch).map_err(|e| anyhow::anyhow!("writing {}: {}", path.display(), e))?;
+    Ok(files)
+}
+
+fn patch_text(diff: &Diff) -> anyhow::Result<Vec<u8>> {
+    let mut patch = Vec::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin() as u8);
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+    Ok(patch)
+}
+
+// With `--emit-patch`, the index is left alone: everything is staged into a copy of it, which the
+// app's location opens the repository with and names as GIT_INDEX_FILE to every `git` it runs,
+// and what was staged is printed as a patch on quitting.
+pub struct Emitting {
+    index: PathBuf,
+    // The repository with the copy as its index.
+    location: Location,
+}
+
+impl Emitting {
+    pub fn start(location: &Location) -> anyhow::Result<Emitting> {
+        let repo = location.open()?;
+        let index = match location.index() {
+            Some(index) => index.to_path_buf(),
+            None => repo.path().join("index"),
+        };
+        let dir = repo.path().join("istage");
+        fs::create_dir_all(&dir)?;
+        let scratch = dir.join("emit-index");
+        if index.exists() {
+            fs::copy(&index, &scratch)?;
+        } else if scratch.exists() {
+            fs::remove_file(&scratch)?;
+        }
+        let location = location.with_index(scratch);
+        Ok(Emitting { index, location })
+    }
+
+    // Where everything is to be staged.
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    // The changes from the index to the copy, for `git apply --cached` to stage, and nothing if
+    // nothing was staged. The copy is removed.
+    pub fn finish(self, settings: DiffSettings) -> anyhow::Result<Vec<u8>> {
+        let repo = self.location.open()?;
+        let scratch = self.location.index().unwrap();
+        let mut opts = DiffOptions::new();
+        opts.show_binary(true).context_lines(settings.context);
+        let mut diff = repo.diff_index_to_index(
+            &Index::open(&self.index)?,
+            &Index::open(scratch)?,
+            Some(&mut opts),
+        )?;
+        diff.find_similar(Some(&mut diff::find_options(&settings.renames, false)))?;
+        let patch = patch_text(&diff)?;
+        if scratch.exists() {
+            fs::remove_file(scratch)?;
+        }
+        Ok(patch)
+    }
+}
+
+// HEAD (or the empty tree on an unborn branch) to the index, with renames found as configured.
+fn staged_diff(repo: &Repository, settings: DiffSettings) -> anyhow::Result<Diff<'_>> {
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None,
+    };
+    let mut opts = DiffOptions::new();
+    opts.show_binary(true).context_lines(settings.context);
+    let mut diff = repo.diff_tree_to_index(head.as_ref(), None, Some(&mut opts))?;
+    diff.find_similar(Some(&mut diff::find_options(&settings.renames, false)))?;
+    Ok(diff)
+}
+
+// Applies the patch in `path` to the worktree, or to the index if `staged`, all of it or none:
+// when some of its files don't apply, the error names them. Returns how many files it changed.
+pub fn apply(location: &Location, path: &Path, staged: bool) -> anyhow::Result<usize> {
+    let repo = location.open()?;
+    let content =
+        fs::read(path).map_err(|e| anyhow::anyhow!("reading {}: {}", path.display(), e))?;
+    let diff = Diff::from_buffer(&content)
+        .map_err(|e| anyhow::anyhow!("{} isn't a patch: {}", path.display(), e.message()))?;
+    let location = match staged {
+        true => ApplyLocation::Index,
+        false => ApplyLocation::WorkDir,
+    };
+    let files = diff.deltas().len();
+    if files == 0 {
+        anyhow::bail!("{} changes nothing", path.display());
+    }
+
+    if repo
+        .apply(&diff, location, Some(ApplyOptions::new().check(true)))
+        .is_err()
+    {
+        let conflicts = conflicting_files(&repo, &diff, location);
+        anyhow::bail!(
+            "doesn't apply to the {}, nothing changed: {}",
+            if staged { "index" } else { "worktree" },
+            conflicts.join(", ")
+        );
+    }
+    repo.apply(&diff, location, None)?;
+    Ok(files)
+}
+
+// The paths of the files in `diff` that don't apply on their own, as libgit2 only says that the
+// patch as a whole doesn't.
+fn conflicting_files(repo: &Repository, diff: &Diff, location: ApplyLocation) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (i, delta) in diff.deltas().enumerate() {
+        let mut seen = 0;
+        let mut opts = ApplyOptions::new();
+        opts.check(true).delta_callback(|_| {
+            seen += 1;
+            seen == i + 1
+        });
+        if let Err(e) = repo.apply(diff, location, Some(&mut opts)) {
+            let path = delta.new_file().path().or(delta.old_file().path());
+            let path = path.map_or(String::from("?"), |p| p.display().to_string());
+            conflicts.push(format!("{} ({})", path, e.message()));
+        }
+    }
+    conflicts
+}
@@ -0,0 +1,184 @@
+//! Paths as the OS hands them out and as git takes them.
+//!
+//! On Windows, `fs::canonicalize` gives verbatim paths like `\\?\C:\repo`, which `git` and most
+//! other programs don't take as arguments or working directories, and in which `/` doesn't
+//! separate anything, so that a path from the repository joined onto one doesn't resolve. Paths
+//! in the repository, on the other hand, are always written with `/`.
+
+use std::{
+    borrow::Cow,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::quote;
+
+/// Like [`fs::canonicalize`], without the verbatim prefix where the path doesn't need one.
+pub fn canonicalize(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    Ok(simplified(fs::canonicalize(path)?))
+}
+
+/// `path` without its verbatim prefix: `\\?\C:\repo` is `C:\repo`, and `\\?\UNC\server\share` is
+/// `\\server\share`. Other paths, verbatim or not, are as they were.
+pub fn simplified(path: PathBuf) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path;
+    };
+    if let Some(share) = text.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{}", share));
+    }
+    match text.strip_prefix(r"\\?\") {
+        Some(rest) if is_drive_path(rest) => PathBuf::from(rest),
+        _ => path,
+    }
+}
+
+// E.g. `C:\repo`, but not `Volume{...}\repo`, which has no other form.
+fn is_drive_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\'
+}
+
+/// A relative path as git writes it, with `/` between its components whatever the OS separates
+/// them with. A `\` in a name stays as it is where it isn't a separator.
+pub fn to_slashes(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `path` from `root`, as git writes it, or None if it isn't inside `root`. Both are taken as
+/// they are: canonicalize them first for symlinks and `..` to resolve.
+pub fn relative_to(path: &Path, root: &Path) -> Option<String> {
+    path.strip_prefix(root).ok().map(to_slashes)
+}
+
+/// A path in the repository as git stores it: its bytes, relative to the root and separated by
+/// `/`, which needn't be UTF-8. It's shown escaped the way git escapes C-style quoted paths, and
+/// only then; everything else takes the bytes themselves.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct RepoPath(Vec<u8>);
+
+impl RepoPath {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The path relative to the root, to join onto it or hand to git2.
+    pub fn to_path(&self) -> PathBuf {
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+            PathBuf::from(OsString::from_vec(self.0.clone()))
+        }
+        // With the OS's separator, as `/` isn't one in a verbatim path like `\\?\C:\repo` it
+        // may be joined onto.
+        #[cfg(not(unix))]
+        {
+            let path = String::from_utf8_lossy(&self.0);
+            path.split('/').collect()
+        }
+    }
+
+    /// The path whose [`escaped`](RepoPath::escaped) form is `escaped`.
+    pub fn from_escaped(escaped: &str) -> RepoPath {
+        RepoPath(quote::unescape(escaped))
+    }
+
+    /// The path as text, escaped where it isn't printable UTF-8, e.g. `caf\351.txt` for a name in
+    /// Latin-1.
+    pub fn escaped(&self) -> String {
+        quote::escape(&self.0)
+    }
+
+    /// The path as `git status` shows it: escaped, and in double quotes if anything was.
+    pub fn quoted(&self) -> String {
+        quote::quote(&self.escaped()).into_owned()
+    }
+
+    /// The path as text, with what isn't UTF-8 replaced, for matching globs and names against.
+    pub fn to_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+
+    pub fn ends_with(&self, suffix: &str) -> bool {
+        self.0.ends_with(suffix.as_bytes())
+    }
+}
+
+impl fmt::Display for RepoPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.escaped())
+    }
+}
+
+// Written out escaped, as it's shown, which reads back as the same bytes.
+impl Serialize for RepoPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.escaped())
+    }
+}
+
+impl<'de> Deserialize<'de> for RepoPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RepoPath, D::Error> {
+        let escaped = String::deserialize(deserializer)?;
+        Ok(RepoPath::from_escaped(&escaped))
+    }
+}
+
+impl From<Vec<u8>> for RepoPath {
+    fn from(bytes: Vec<u8>) -> RepoPath {
+        RepoPath(bytes)
+    }
+}
+
+impl From<&[u8]> for RepoPath {
+    fn from(bytes: &[u8]) -> RepoPath {
+        RepoPath(bytes.to_vec())
+    }
+}
+
+impl From<String> for RepoPath {
+    fn from(path: String) -> RepoPath {
+        RepoPath(path.into_bytes())
+    }
+}
+
+impl From<&str> for RepoPath {
+    fn from(path: &str) -> RepoPath {
+        RepoPath(path.as_bytes().to_vec())
+    }
+}
+
+impl From<&Path> for RepoPath {
+    fn from(path: &Path) -> RepoPath {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            RepoPath(path.as_os_str().as_bytes().to_vec())
+        }
+        #[cfg(not(unix))]
+        {
+            RepoPath::from(to_slashes(path))
+        }
+    }
+}
+
+impl PartialEq<str> for RepoPath {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for RepoPath {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
@@ -0,0 +1,138 @@
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+pub struct Dialog {
+    pub message: String,
+    pub details: Vec<String>,
+    // What confirming will do beyond the message, e.g. where discarded files end up.
+    pub note: Option<String>,
+}
+
+impl Dialog {
+    pub fn new(message: impl Into<String>, details: Vec<String>) -> Dialog {
+        Dialog {
+            message: message.into(),
+            details,
+            note: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Dialog {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+// Only this many detail lines are listed; the rest are summarized as "... and N more".
+const MAX_DETAILS: usize = 10;
+
+pub fn render_confirm<B: Backend>(f: &mut Frame<B>, dialog: &Dialog) {
+    render_dialog(f, " Confirm ", dialog, "[y] confirm   [n] cancel");
+}
+
+pub fn render_notice<B: Backend>(f: &mut Frame<B>, dialog: &Dialog) {
+    render_dialog(f, " Notice ", dialog, "[any key] dismiss");
+}
+
+fn render_dialog<B: Backend>(f: &mut Frame<B>, title: &str, dialog: &Dialog, footer: &str) {
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            dialog.message.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::default(),
+    ];
+
+    lines.extend(
+        dialog
+            .details
+            .iter()
+            .take(MAX_DETAILS)
+            .map(|d| Spans::from(format!("  {}", d))),
+    );
+    if dialog.details.len() > MAX_DETAILS {
+        lines.push(Spans::from(format!(
+            "  ... and {} more",
+            dialog.details.len() - MAX_DETAILS
+        )));
+    }
+    if let Some(note) = &dialog.note {
+        lines.push(Spans::default());
+        lines.push(Spans::from(Span::styled(
+            note.clone(),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+
+    render_lines(f, title, lines, footer);
+}
+
+// A popup with free-form lines of text, such as the repository statistics.
+pub fn render_info<B: Backend>(f: &mut Frame<B>, title: &str, text: &[String]) {
+    let lines = text.iter().map(|l| Spans::from(l.clone())).collect();
+    render_lines(f, title, lines, "[any key] dismiss");
+}
+
+// A popup with `lines` above `footer`, as tall as they are once wrapped.
+pub fn render_lines<B: Backend>(
+    f: &mut Frame<B>,
+    title: &str,
+    mut lines: Vec<Spans>,
+    footer: &str,
+) {
+    lines.push(Spans::default());
+    lines.push(Spans::from(Span::styled(
+        footer.to_string(),
+        Style::default().fg(Color::Gray),
+    )));
+
+    // Account for wrapping so the footer never gets cut off.
+    let inner_width = centered_rect(60, 0, f.size())
+        .width
+        .saturating_sub(2)
+        .max(1) as usize;
+    let height: usize = lines
+        .iter()
+        .map(|l| l.width().div_ceil(inner_width).max(1))
+        .sum();
+    let area = centered_rect(60, height as u16 + 2, f.size());
+
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title.to_string()),
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+// A rectangle `percent_x`% of the width of `r` and `height` rows tall, centered in `r`.
+pub fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
+    let height = height.min(r.height);
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length((r.height - height) / 2),
+            Constraint::Length(height),
+            Constraint::Min(0),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
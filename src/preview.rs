@@ -0,0 +1,414 @@
+use std::ops::Range;
+
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+use git2::Oid;
+
+use git_istage_rs::diff::{DiffLine, FileDiff, Hunk, LineCounts, LineKind};
+
+use crate::{
+    stats::format_size,
+    syntax::{self, Highlighter, Language, Token},
+    theme::Theme,
+    word_diff,
+};
+
+// The lines of a file's diff, with hunk headers and added and removed lines colored, and the code
+// in them syntax colored if its language is known.
+pub fn diff_spans(file: &FileDiff, theme: &Theme) -> Vec<Spans<'static>> {
+    if let Some(lines) = summary_spans(file, theme) {
+        return lines;
+    }
+    let language = diff_language(file, theme);
+    let mut lines: Vec<Spans> = mode_line(file).into_iter().map(Spans::from).collect();
+    lines.extend(symlink_spans(file, theme));
+    for hunk in &file.hunks {
+        let mut spans = HunkSpans::new(hunk, language);
+        let count = hunk.lines.len() + 1;
+        spans.style(hunk, 0..count, theme);
+        lines.extend((0..count).filter_map(|i| spans.line(i).cloned()));
+    }
+    lines
+}
+
+// How many lines `diff_spans` makes of a file's diff, without styling any of them.
+pub fn line_count(file: &FileDiff, theme: &Theme) -> usize {
+    match summary_spans(file, theme) {
+        Some(lines) => lines.len(),
+        None => {
+            let modes = usize::from(file.modes.is_some());
+            modes
+                + symlink_lines(file).len()
+                + file.hunks.iter().map(|h| 1 + h.lines.len()).sum::<usize>()
+        }
+    }
+}
+
+// The lines shown in place of the hunks of a submodule, a binary file, a symlink or a diff without
+// any.
+pub fn summary_spans(file: &FileDiff, theme: &Theme) -> Option<Vec<Spans<'static>>> {
+    if let Some((old, new)) = file.submodule {
+        return Some(submodule_spans(file, old, new, theme));
+    }
+    if file.binary {
+        let mut lines = vec![Spans::from(format!(
+            "binary file, {} -> {}",
+            format_size(file.old_size),
+            format_size(file.new_size)
+        ))];
+        lines.extend(mode_line(file).map(Spans::from));
+        return Some(lines);
+    }
+    if file.symlink.is_some() && file.hunks.is_empty() {
+        return Some(symlink_spans(file, theme));
+    }
+    if file.hunks.is_empty() {
+        let line = mode_line(file).unwrap_or_else(|| String::from("(no textual changes)"));
+        return Some(vec![Spans::from(line)]);
+    }
+    None
+}
+
+// E.g. "mode 100644 → 100755", for a file made executable, or no longer.
+pub fn mode_line(file: &FileDiff) -> Option<String> {
+    file.modes
+        .map(|(old, new)| format!("mode {:06o} → {:06o}", old, new))
+}
+
+// E.g. "-symlink → a.txt" and "+symlink → b.txt", for each side of the diff the file is a symlink
+// on, with the path it points to there.
+pub fn symlink_lines(file: &FileDiff) -> Vec<(LineKind, String)> {
+    let (old, new) = file.symlink.clone().unwrap_or_default();
+    let side = |kind, target: Option<String>| target.map(|t| (kind, format!("symlink → {}", t)));
+    side(LineKind::Removed, old)
+        .into_iter()
+        .chain(side(LineKind::Added, new))
+        .collect()
+}
+
+fn symlink_spans(file: &FileDiff, theme: &Theme) -> Vec<Spans<'static>> {
+    symlink_lines(file)
+        .into_iter()
+        .map(|(kind, line)| {
+            let color = match kind {
+                LineKind::Removed => theme.diff_removed,
+                _ => theme.diff_added,
+            };
+            let line = format!("{}{}", theme.line_marker(kind), line);
+            Span::styled(line, Style::default().fg(color))
+        })
+        .map(Spans::from)
+        .collect()
+}
+
+// The language a file's diff is syntax colored as, if it's known and the theme colors syntax.
+pub fn diff_language(file: &FileDiff, theme: &Theme) -> Option<&'static Language> {
+    syntax::language(&file.new_path.to_str_lossy()).filter(|_| theme.syntax)
+}
+
+// A hunk's lines, header first, styled as they're first asked for and kept: scrolling through a
+// huge hunk styles what's been on screen, not what's above it. How a line is highlighted depends on
+// the lines before it, so highlighting carries on from the last line styled when the next one is
+// asked for, and starts over where lines are skipped, as when jumping to the end of the hunk.
+pub struct HunkSpans {
+    language: Option<&'static Language>,
+    // Each side of the hunk is highlighted as a file of its own, so that a block comment opened on
+    // a removed line doesn't carry over to the added lines.
+    old_side: Option<Highlighter>,
+    new_side: Option<Highlighter>,
+    // The line the highlighters carry on from, just past the last one styled.
+    next: usize,
+    // The line each removed or added line replaces or is replaced by, to compare them word by
+    // word. Worked out the first time a line is styled.
+    partners: Option<Vec<Option<usize>>>,
+    lines: Vec<Option<Spans<'static>>>,
+}
+
+impl HunkSpans {
+    pub fn new(hunk: &Hunk, language: Option<&'static Language>) -> HunkSpans {
+        HunkSpans {
+            language,
+            old_side: language.map(Highlighter::new),
+            new_side: language.map(Highlighter::new),
+            next: 1,
+            partners: None,
+            lines: vec![None; hunk.lines.len() + 1],
+        }
+    }
+
+    // A hunk's lines already styled, header first, e.g. by a diff filter.
+    pub fn styled(lines: Vec<Spans<'static>>) -> HunkSpans {
+        HunkSpans {
+            language: None,
+            old_side: None,
+            new_side: None,
+            next: lines.len(),
+            partners: None,
+            lines: lines.into_iter().map(Some).collect(),
+        }
+    }
+
+    // Styles the lines of `hunk` in `range`, counting the header as line 0, that aren't yet.
+    pub fn style(&mut self, hunk: &Hunk, range: Range<usize>, theme: &Theme) {
+        let end = range.end.min(self.lines.len());
+        for i in range.start.min(end)..end {
+            if self.lines[i].is_some() {
+                continue;
+            }
+            if i == 0 {
+                self.lines[0] = Some(header_spans(hunk, theme));
+                continue;
+            }
+            if i != self.next {
+                self.old_side = self.language.map(Highlighter::new);
+                self.new_side = self.language.map(Highlighter::new);
+            }
+            self.lines[i] = Some(self.style_line(hunk, i - 1, theme));
+            self.next = i + 1;
+        }
+    }
+
+    // Line `i`, counting the header as line 0, if it's been styled.
+    pub fn line(&self, i: usize) -> Option<&Spans<'static>> {
+        self.lines.get(i)?.as_ref()
+    }
+
+    fn style_line(&mut self, hunk: &Hunk, i: usize, theme: &Theme) -> Spans<'static> {
+        let line = &hunk.lines[i];
+        let (color, word_color, side) = match line.kind {
+            LineKind::Context => (theme.text, theme.text, &mut self.new_side),
+            LineKind::Added => (theme.diff_added, theme.word_added, &mut self.new_side),
+            LineKind::Removed => (theme.diff_removed, theme.word_removed, &mut self.old_side),
+            LineKind::NoNewlineAtEof => {
+                return Spans::from(Span::styled(
+                    "\\ No newline at end of file",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+        };
+        let (text, cr) = line_ending(&line.content);
+        let tokens = match side {
+            Some(highlighter) => highlighter.line(text),
+            None => vec![(0..text.len(), Token::Plain)],
+        };
+        if line.kind == LineKind::Context {
+            self.old_side.clone_from(&self.new_side);
+        }
+
+        let partners = self.partners.get_or_insert_with(|| partners(&hunk.lines));
+        let partner = partners[i].map(|p| line_ending(&hunk.lines[p].content));
+        let words = match (line.kind, partner) {
+            (LineKind::Removed, Some((new, _))) => {
+                word_diff::changes(text, new).map(|(old_words, _)| old_words)
+            }
+            (LineKind::Added, Some((old, _))) => {
+                word_diff::changes(old, text).map(|(_, new_words)| new_words)
+            }
+            _ => None,
+        };
+        let mut marks: Vec<(Range<usize>, Color)> = words
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| (w, word_color))
+            .collect();
+        if line.kind == LineKind::Added && theme.whitespace_errors {
+            marks.extend(
+                whitespace_errors(text)
+                    .into_iter()
+                    .map(|r| (r, theme.whitespace_error)),
+            );
+        }
+        let prefix = theme.line_marker(line.kind);
+        let mut spans = vec![Span::styled(prefix, Style::default().fg(color))];
+        for (range, token, mark) in segments(tokens, &marks) {
+            let mut style = Style::default().fg(theme.token(token, color));
+            if let Some(mark) = mark {
+                style = style.bg(mark);
+            }
+            spans.push(Span::styled(text[range].to_string(), style));
+        }
+        // The `\r` of a CRLF line is shown if asked for, and always where it's all that changed.
+        let only_ending = partner.is_some_and(|(other, other_cr)| other == text && other_cr != cr);
+        if cr && (theme.line_endings || only_ending) {
+            spans.push(Span::styled("␍", Style::default().fg(Color::DarkGray)));
+        }
+        Spans::from(spans)
+    }
+}
+
+// A diff line's text without the `\r` of a CRLF line ending, and whether it had one.
+fn line_ending(content: &str) -> (&str, bool) {
+    match content.strip_suffix('\r') {
+        Some(text) => (text, true),
+        None => (content, false),
+    }
+}
+
+// Cuts a line's tokens where the marked stretches (changed words, whitespace errors) start and
+// end, giving the pieces within them the background of the last mark they're in.
+fn segments(
+    tokens: Vec<(Range<usize>, Token)>,
+    marks: &[(Range<usize>, Color)],
+) -> Vec<(Range<usize>, Token, Option<Color>)> {
+    let mut segments = Vec::new();
+    for (range, token) in tokens {
+        let mut cuts: Vec<usize> = marks
+            .iter()
+            .flat_map(|(m, _)| [m.start, m.end])
+            .filter(|&c| c > range.start && c < range.end)
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+        cuts.push(range.end);
+        let mut start = range.start;
+        for end in cuts {
+            let mark = marks.iter().rev().find(|(m, _)| m.contains(&start));
+            segments.push((start..end, token, mark.map(|(_, color)| *color)));
+            start = end;
+        }
+    }
+    segments
+}
+
+// The whitespace git's default `core.whitespace` complains about in an added line: at its end, and
+// spaces before a tab in its indent.
+fn whitespace_errors(line: &str) -> Vec<Range<usize>> {
+    let mut errors = Vec::new();
+    let indent = line.len() - line.trim_start().len();
+    if let Some(tab) = line[..indent].rfind('\t') {
+        if let Some(space) = line[..tab].find(' ') {
+            errors.push(space..tab);
+        }
+    }
+    let end = line.trim_end().len();
+    if end < line.len() {
+        errors.push(end..line.len());
+    }
+    errors
+}
+
+// The hunk's "@@ ... @@" line, followed by how many lines it adds and removes if the theme shows
+// that.
+fn header_spans(hunk: &Hunk, theme: &Theme) -> Spans<'static> {
+    let header = Span::styled(hunk.header.clone(), Style::default().fg(theme.hunk_header));
+    if !theme.line_counts {
+        return Spans::from(header);
+    }
+    let mut spans = vec![header, Span::raw("  ")];
+    spans.extend(line_count_spans(hunk.line_counts(), theme));
+    Spans::from(spans)
+}
+
+// E.g. `+3/−1`, each count in the color of its lines.
+pub fn line_count_spans(lines: LineCounts, theme: &Theme) -> Vec<Span<'static>> {
+    vec![
+        Span::styled(
+            format!("+{}", lines.added),
+            Style::default().fg(theme.diff_added),
+        ),
+        Span::raw("/"),
+        Span::styled(
+            format!("−{}", lines.removed),
+            Style::default().fg(theme.diff_removed),
+        ),
+    ]
+}
+
+// The line each of a hunk's lines is compared with word by word. A run of removed lines followed by
+// a run of added lines is taken to be the former replaced by the latter, line for line; other
+// lines have none.
+fn partners(hunk: &[DiffLine]) -> Vec<Option<usize>> {
+    let mut partners = vec![None; hunk.len()];
+    let run = |from: usize, kind: LineKind| -> Vec<usize> {
+        hunk[from..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, l)| l.kind == kind || l.kind == LineKind::NoNewlineAtEof)
+            .filter(|(_, l)| l.kind == kind)
+            .map(|(i, _)| from + i)
+            .collect()
+    };
+    let mut i = 0;
+    while i < hunk.len() {
+        if hunk[i].kind != LineKind::Removed {
+            i += 1;
+            continue;
+        }
+        let removed = run(i, LineKind::Removed);
+        let added_from = removed.last().map_or(i, |&r| r + 1);
+        let added_from = hunk[added_from..]
+            .iter()
+            .position(|l| l.kind != LineKind::NoNewlineAtEof)
+            .map_or(hunk.len(), |p| added_from + p);
+        let added = run(added_from, LineKind::Added);
+        for (&old, &new) in removed.iter().zip(&added) {
+            partners[old] = Some(new);
+            partners[new] = Some(old);
+        }
+        i = added.last().map_or(added_from, |&a| a + 1);
+    }
+    partners
+}
+
+// The commits a submodule's gitlink moves between, as short SHAs.
+fn submodule_spans(file: &FileDiff, old: Oid, new: Oid, theme: &Theme) -> Vec<Spans<'static>> {
+    let short = |id: Oid| match id.is_zero() {
+        true => String::from("(none)"),
+        false => id.to_string()[..7].to_string(),
+    };
+    let header = Spans::from(Span::styled(
+        format!("Submodule {}", file.new_path),
+        Style::default().fg(Color::Magenta),
+    ));
+    // The commit is the same when only the submodule's own worktree has changes.
+    if old == new {
+        return vec![
+            header,
+            Spans::from(format!(
+                "at {}, with uncommitted changes in the submodule",
+                short(old)
+            )),
+        ];
+    }
+    vec![
+        header,
+        Spans::from(vec![
+            Span::styled(short(old), Style::default().fg(theme.diff_removed)),
+            Span::raw(" -> "),
+            Span::styled(short(new), Style::default().fg(theme.diff_added)),
+        ]),
+    ]
+}
+
+// The lines of a file containing conflict markers, with the markers highlighted and our side, the
+// merge base (diff3 style) and their side in distinct colors.
+pub fn conflict_spans(text: &str) -> Vec<Spans<'static>> {
+    let marker = Style::default()
+        .fg(Color::White)
+        .add_modifier(Modifier::BOLD | Modifier::REVERSED);
+    let mut region = Style::default();
+
+    text.lines()
+        .map(|line| {
+            let style = if line.starts_with("<<<<<<<") {
+                region = Style::default().fg(Color::LightGreen);
+                marker
+            } else if line.starts_with("|||||||") {
+                region = Style::default().fg(Color::DarkGray);
+                marker
+            } else if line == "=======" {
+                region = Style::default().fg(Color::LightBlue);
+                marker
+            } else if line.starts_with(">>>>>>>") {
+                region = Style::default();
+                marker
+            } else {
+                region
+            };
+            Spans::from(Span::styled(line.to_string(), style))
+        })
+        .collect()
+}
@@ -0,0 +1,87 @@
+use std::{
+    process,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use git2::{ErrorClass, ErrorCode, Oid};
+
+use git_istage_rs::{console, git::Location, status::StatusEntry};
+
+// Whether `e` is libgit2 failing to find an object, which in a partial clone means it hasn't been
+// fetched from the promisor remote yet. libgit2 never fetches missing objects itself, so reading
+// them fails fast instead of waiting on the network.
+pub fn is_missing_object(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<git2::Error>()
+        .is_some_and(|e| e.code() == ErrorCode::NotFound && e.class() == ErrorClass::Odb)
+}
+
+// The blobs of `entry` in the index and in HEAD that aren't in the local object database.
+pub fn missing_objects(location: &Location, entry: &StatusEntry) -> anyhow::Result<Vec<Oid>> {
+    let repo = location.open()?;
+    let odb = repo.odb()?;
+
+    let mut ids = Vec::new();
+    let index = repo.index()?;
+    for path in [entry.new_path(), entry.old_path()] {
+        if let Some(e) = index.get_path(&path, 0) {
+            ids.push(e.id);
+        }
+    }
+    if let Ok(tree) = repo.head().and_then(|h| h.peel_to_tree()) {
+        if let Ok(te) = tree.get_path(&entry.old_path()) {
+            ids.push(te.id());
+        }
+    }
+
+    ids.sort();
+    ids.dedup();
+    ids.retain(|&id| !odb.exists(id));
+    Ok(ids)
+}
+
+// Objects being fetched in the background, so that the UI stays responsive however slow the
+// network is.
+pub struct Fetch {
+    pub count: usize,
+    done: Receiver<anyhow::Result<()>>,
+}
+
+impl Fetch {
+    pub fn start(location: &Location, ids: Vec<Oid>) -> anyhow::Result<Fetch> {
+        let location = location.clone();
+        let count = ids.len();
+        let (tx, done) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(fetch(&location, &ids));
+        });
+        Ok(Fetch { count, done })
+    }
+
+    // The outcome, once the fetch has finished.
+    pub fn poll(&self) -> Option<anyhow::Result<()>> {
+        match self.done.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow::anyhow!("fetch thread died"))),
+        }
+    }
+}
+
+// Reading an object with the `git` CLI fetches it from the promisor remote if it's missing.
+fn fetch(location: &Location, ids: &[Oid]) -> anyhow::Result<()> {
+    for id in ids {
+        let output = console::output(
+            location
+                .git()
+                // The terminal is in raw mode, so git must not stop to ask for credentials.
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .args(["cat-file", "blob", &id.to_string()])
+                .stdout(process::Stdio::null()),
+        )?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+    }
+    Ok(())
+}
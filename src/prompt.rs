@@ -0,0 +1,74 @@
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{Clear, Paragraph},
+    Frame,
+};
+
+pub enum PromptEvent {
+    Submit(String),
+    Cancel,
+    Pending,
+}
+
+// A single-line input shown in the bottom row of the screen, vim-style.
+pub struct Prompt {
+    prefix: &'static str,
+    pub input: String,
+    pub error: Option<String>,
+}
+
+impl Prompt {
+    pub fn new(prefix: &'static str) -> Prompt {
+        Prompt {
+            prefix,
+            input: String::new(),
+            error: None,
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> PromptEvent {
+        self.error = None;
+        match code {
+            KeyCode::Enter => PromptEvent::Submit(self.input.clone()),
+            KeyCode::Esc => PromptEvent::Cancel,
+            KeyCode::Backspace => {
+                if self.input.pop().is_none() {
+                    return PromptEvent::Cancel;
+                }
+                PromptEvent::Pending
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                PromptEvent::Pending
+            }
+            _ => PromptEvent::Pending,
+        }
+    }
+}
+
+pub fn render_prompt<B: Backend>(f: &mut Frame<B>, prompt: &Prompt) {
+    let size = f.size();
+    if size.height == 0 {
+        return;
+    }
+    let area = Rect::new(size.x, size.y + size.height - 1, size.width, 1);
+
+    let mut spans = vec![Span::raw(prompt.prefix), Span::raw(prompt.input.clone())];
+    if let Some(err) = &prompt.error {
+        spans.push(Span::styled(
+            format!("  {}", err),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+    f.set_cursor(
+        area.x + (prompt.prefix.len() + prompt.input.chars().count()) as u16,
+        area.y,
+    );
+}
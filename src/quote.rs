@@ -0,0 +1,76 @@
+//! Paths as they're shown: what isn't printable UTF-8 is escaped the way git escapes it in C-style
+//! quoted paths, so that every path can be shown safely, and read back into the very bytes it was
+//! made from.
+
+use std::borrow::Cow;
+
+/// The escaped form of the path `bytes`. Valid UTF-8 is kept as it is, except for `"`, `\` and
+/// control characters, which are escaped like the bytes that aren't valid UTF-8 are, e.g.
+/// `caf\351.txt` for a name in Latin-1.
+pub fn escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        out.push_str(&format!("\\{:03o}", b));
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\{:03o}", b));
+        }
+    }
+    out
+}
+
+/// The bytes of the path `escaped` was made from by [`escape`].
+pub fn unescape(escaped: &str) -> Vec<u8> {
+    if !escaped.contains('\\') {
+        return escaped.as_bytes().to_vec();
+    }
+    let bytes = escaped.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 == bytes.len() {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)));
+        match (bytes[i + 1], octal) {
+            (_, Some(digits)) => {
+                let value = digits.iter().fold(0u32, |v, d| v * 8 + (d - b'0') as u32);
+                out.push(value as u8);
+                i += 4;
+                continue;
+            }
+            (b't', _) => out.push(b'\t'),
+            (b'n', _) => out.push(b'\n'),
+            (b'r', _) => out.push(b'\r'),
+            (other, _) => out.push(other),
+        }
+        i += 2;
+    }
+    out
+}
+
+/// `escaped` in double quotes if anything in it is escaped, as `git status` shows such paths.
+pub fn quote(escaped: &str) -> Cow<'_, str> {
+    match escaped.contains('\\') {
+        true => Cow::Owned(format!("\"{}\"", escaped)),
+        false => Cow::Borrowed(escaped),
+    }
+}
@@ -0,0 +1,151 @@
+//! The library's facade over a single repository, for staging without the TUI.
+
+use std::{ops::Range, path::Path};
+
+use git2::Oid;
+
+use crate::{
+    backend::{self, Git2Backend, GitBackend},
+    config::{BackendKind, DiscardUntracked},
+    diff::{DiffSettings, FileDiff},
+    git::{FileStatusKind, Location},
+    paths::RepoPath,
+    status::StatusEntry,
+};
+
+/// A repository that statuses can be read from and changes staged into, through any
+/// [`GitBackend`]. It's what tools embedding the staging engine drive it through.
+///
+/// ```no_run
+/// use git_istage_rs::{FileStatusKind, Repo};
+///
+/// let repo = Repo::open(".")?;
+/// for entry in repo.statuses(FileStatusKind::Unstaged)? {
+///     // Stage only the first hunk of every changed file.
+///     repo.apply_hunks(&entry, FileStatusKind::Unstaged, &[0])?;
+/// }
+/// repo.commit("Partial commit")?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Repo {
+    backend: Box<dyn GitBackend>,
+}
+
+impl Repo {
+    /// Opens the repository containing `path` with the default [`Git2Backend`].
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Repo> {
+        Ok(Repo::with_backend(Git2Backend::open(path)?))
+    }
+
+    /// Opens the repository containing `path` with the given kind of backend.
+    pub fn open_with(kind: BackendKind, path: impl AsRef<Path>) -> anyhow::Result<Repo> {
+        Ok(Repo {
+            backend: backend::open(kind, &Location::discover(path)?)?,
+        })
+    }
+
+    pub fn with_backend(backend: impl GitBackend + 'static) -> Repo {
+        Repo {
+            backend: Box::new(backend),
+        }
+    }
+
+    pub fn backend(&self) -> &dyn GitBackend {
+        self.backend.as_ref()
+    }
+
+    /// See [`GitBackend::statuses`].
+    pub fn statuses(&self, kind: FileStatusKind) -> anyhow::Result<Vec<StatusEntry>> {
+        self.backend.statuses(kind)
+    }
+
+    /// See [`GitBackend::set_diff_settings`].
+    pub fn set_diff_settings(&self, settings: DiffSettings) {
+        self.backend.set_diff_settings(settings)
+    }
+
+    /// See [`GitBackend::set_pathspecs`].
+    pub fn set_pathspecs(&self, pathspecs: &[String]) {
+        self.backend.set_pathspecs(pathspecs)
+    }
+
+    /// See [`GitBackend::set_expanded_dirs`].
+    pub fn set_expanded_dirs(&self, dirs: &[RepoPath]) {
+        self.backend.set_expanded_dirs(dirs)
+    }
+
+    /// See [`GitBackend::diff`].
+    pub fn diff(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<FileDiff> {
+        self.backend.diff(entry, kind)
+    }
+
+    /// See [`GitBackend::apply_hunks`].
+    pub fn apply_hunks(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunks: &[usize],
+    ) -> anyhow::Result<()> {
+        self.backend.apply_hunks(entry, kind, hunks)
+    }
+
+    /// See [`GitBackend::apply_lines`].
+    pub fn apply_lines(
+        &self,
+        entry: &StatusEntry,
+        kind: FileStatusKind,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()> {
+        self.backend.apply_lines(entry, kind, hunk, lines)
+    }
+
+    /// See [`GitBackend::apply_mode`].
+    pub fn apply_mode(&self, entry: &StatusEntry, kind: FileStatusKind) -> anyhow::Result<()> {
+        self.backend.apply_mode(entry, kind)
+    }
+
+    /// Stages the entire changes of unstaged entries.
+    pub fn stage(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        self.backend.stage(entries)
+    }
+
+    /// See [`GitBackend::intent_to_add`].
+    pub fn intent_to_add(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        self.backend.intent_to_add(entries)
+    }
+
+    /// Unstages the entire changes of staged entries.
+    pub fn unstage(&self, entries: &[StatusEntry]) -> anyhow::Result<()> {
+        self.backend.unstage(entries)
+    }
+
+    /// Throws away the working directory changes of unstaged entries.
+    pub fn discard(
+        &self,
+        entries: &[StatusEntry],
+        untracked: DiscardUntracked,
+    ) -> anyhow::Result<()> {
+        self.backend.discard(entries, untracked)
+    }
+
+    /// See [`GitBackend::discard_hunks`].
+    pub fn discard_hunks(&self, entry: &StatusEntry, hunks: &[usize]) -> anyhow::Result<()> {
+        self.backend.discard_hunks(entry, hunks)
+    }
+
+    /// See [`GitBackend::discard_lines`].
+    pub fn discard_lines(
+        &self,
+        entry: &StatusEntry,
+        hunk: usize,
+        lines: Range<usize>,
+    ) -> anyhow::Result<()> {
+        self.backend.discard_lines(entry, hunk, lines)
+    }
+
+    /// Commits the index with the identity from the git config.
+    pub fn commit(&self, message: &str) -> anyhow::Result<Oid> {
+        self.backend.commit(message)
+    }
+}
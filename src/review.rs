@@ -0,0 +1,123 @@
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use git_istage_rs::{
+    config::ScrollConfig, diff::FileDiff, git::FileStatusKind, status::StatusEntry, GitBackend,
+};
+
+use crate::{preview, scroll::Scroll, theme::Theme};
+
+// How many columns the list of files takes, the rest going to the diffs.
+const SIDEBAR_WIDTH: u16 = 32;
+
+// Everything about to be committed, shown before the message is written: the staged diffs of all
+// the files one after the other, with the files listed beside them to jump between.
+pub struct Review {
+    pub files: Vec<(StatusEntry, FileDiff)>,
+    pub selected: usize,
+    pub scroll: Scroll,
+}
+
+impl Review {
+    // The staged changes, or None if nothing is staged.
+    pub fn load(backend: &dyn GitBackend) -> anyhow::Result<Option<Review>> {
+        let mut files = Vec::new();
+        for entry in backend.statuses(FileStatusKind::Staged)? {
+            let diff = backend.diff(&entry, FileStatusKind::Staged)?;
+            files.push((entry, diff));
+        }
+        Ok((!files.is_empty()).then_some(Review {
+            files,
+            selected: 0,
+            scroll: Scroll::default(),
+        }))
+    }
+
+    pub fn current(&self) -> Option<&StatusEntry> {
+        self.files.get(self.selected).map(|(entry, _)| entry)
+    }
+
+    // Selects the next (or previous) file, wrapping around, and scrolls to the start of its diff.
+    pub fn select(&mut self, forward: bool, config: &ScrollConfig, theme: &Theme) {
+        let count = self.files.len().max(1);
+        self.selected = match forward {
+            true => (self.selected + 1) % count,
+            false => self.selected.checked_sub(1).unwrap_or(count - 1),
+        };
+        let start = self.starts(theme)[self.selected];
+        self.scroll
+            .to(config, u16::try_from(start).unwrap_or(u16::MAX));
+    }
+
+    // The line each file's diff starts at, its path's line included, and the line past the last.
+    fn starts(&self, theme: &Theme) -> Vec<usize> {
+        let mut starts = vec![0];
+        for (_, diff) in &self.files {
+            // The path, the diff, and a blank line before the next file.
+            let last = starts.last().copied().unwrap_or_default();
+            starts.push(last + 1 + preview::line_count(diff, theme) + 1);
+        }
+        starts
+    }
+
+    pub fn line_count(&self, theme: &Theme) -> usize {
+        self.starts(theme).last().copied().unwrap_or_default()
+    }
+}
+
+pub fn render_review<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    review: &Review,
+    hints: &str,
+    theme: &Theme,
+) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(0)])
+        .split(area);
+
+    let items: Vec<ListItem> = review
+        .files
+        .iter()
+        .map(|(entry, _)| {
+            ListItem::new(entry.pretty_string()).style(Style::default().fg(theme.text))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(false))
+                .title(format!(" Staged ({}) ", review.files.len())),
+        )
+        .highlight_style(theme.selected());
+    let mut state = ListState::default();
+    state.select(Some(review.selected));
+    f.render_stateful_widget(list, chunks[0], &mut state);
+
+    let mut lines = Vec::new();
+    for (entry, diff) in &review.files {
+        lines.push(Spans::from(Span::styled(
+            entry.pretty_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        lines.extend(preview::diff_spans(diff, theme));
+        lines.push(Spans::default());
+    }
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border(true))
+                .title(format!(" Review ({}) ", hints)),
+        )
+        .scroll((review.scroll.offset, 0));
+    f.render_widget(paragraph, chunks[1]);
+}
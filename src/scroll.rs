@@ -0,0 +1,47 @@
+use git_istage_rs::config::ScrollConfig;
+
+// A vertical scroll offset that moves `step` lines per key, either at once or, in smooth mode,
+// easing towards its target over the next few frames. Held keys keep pushing the target further,
+// so the motion doesn't restart with every repeat.
+#[derive(Default)]
+pub struct Scroll {
+    pub offset: u16,
+    target: u16,
+}
+
+impl Scroll {
+    pub fn down(&mut self, config: &ScrollConfig, max: u16) {
+        self.target = self.target.saturating_add(config.step).min(max);
+        self.jump_unless_smooth(config);
+    }
+
+    pub fn up(&mut self, config: &ScrollConfig) {
+        self.target = self.target.saturating_sub(config.step);
+        self.jump_unless_smooth(config);
+    }
+
+    // Scrolls so that `line` is at the top.
+    pub fn to(&mut self, config: &ScrollConfig, line: u16) {
+        self.target = line;
+        self.jump_unless_smooth(config);
+    }
+
+    fn jump_unless_smooth(&mut self, config: &ScrollConfig) {
+        if !config.smooth {
+            self.offset = self.target;
+        }
+    }
+
+    // Moves half of the remaining distance (at least a line) towards the target. Returns whether
+    // it moved.
+    pub fn tick(&mut self) -> bool {
+        let distance = self.target.abs_diff(self.offset);
+        let step = distance.div_ceil(2);
+        if self.target > self.offset {
+            self.offset += step;
+        } else {
+            self.offset -= step;
+        }
+        step > 0
+    }
+}
@@ -0,0 +1,191 @@
+//! Signing commits made through libgit2, which doesn't do it on its own, the way `git commit`
+//! does when `commit.gpgsign` is set.
+
+use std::{env, fs, io, path::PathBuf, process};
+
+use git2::{Commit, Config, Oid, Repository, Signature, Tree};
+
+use crate::console;
+
+/// How commits are signed: by `gpg.format`, with the program git would run for it and the key
+/// in `user.signingkey`, if any.
+pub enum Signer {
+    OpenPgp {
+        program: String,
+        key: Option<String>,
+    },
+    X509 {
+        program: String,
+        key: Option<String>,
+    },
+    Ssh {
+        program: String,
+        key: String,
+    },
+}
+
+impl Signer {
+    /// The signer the config asks for, or None unless `commit.gpgsign` is set.
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Signer>> {
+        if !config.get_bool("commit.gpgsign").unwrap_or(false) {
+            return Ok(None);
+        }
+        let key = config.get_string("user.signingkey").ok();
+        let format = config
+            .get_string("gpg.format")
+            .unwrap_or_else(|_| String::from("openpgp"));
+        let program = |specific: &str, default: &str| {
+            config
+                .get_string(specific)
+                .or_else(|_| config.get_string("gpg.program").map_err(|_| ()))
+                .unwrap_or_else(|_| default.to_string())
+        };
+        let signer = match format.as_str() {
+            "openpgp" => Signer::OpenPgp {
+                program: program("gpg.openpgp.program", "gpg"),
+                key,
+            },
+            "x509" => Signer::X509 {
+                program: config
+                    .get_string("gpg.x509.program")
+                    .unwrap_or_else(|_| String::from("gpgsm")),
+                key,
+            },
+            "ssh" => Signer::Ssh {
+                program: config
+                    .get_string("gpg.ssh.program")
+                    .unwrap_or_else(|_| String::from("ssh-keygen")),
+                key: key.ok_or_else(|| {
+                    anyhow::anyhow!("commit.gpgsign is set, but user.signingkey isn't")
+                })?,
+            },
+            other => anyhow::bail!("unknown gpg.format: {}", other),
+        };
+        Ok(Some(signer))
+    }
+
+    /// Signs `buffer` as `committer`, whose identity picks the key for gpg when there's no
+    /// `user.signingkey`, and returns the armored signature.
+    pub fn sign(&self, buffer: &str, committer: &Signature) -> anyhow::Result<String> {
+        let identity = format!(
+            "{} <{}>",
+            committer.name().unwrap_or_default(),
+            committer.email().unwrap_or_default()
+        );
+        // A key given by its contents rather than its path is handed to ssh-keygen in a file.
+        let mut literal_key = None;
+        let (program, args) = match self {
+            Signer::OpenPgp { program, key } | Signer::X509 { program, key } => {
+                let key = key.clone().unwrap_or(identity);
+                (
+                    program,
+                    vec![String::from("--status-fd=2"), "-bsau".into(), key],
+                )
+            }
+            Signer::Ssh { program, key } => {
+                let literal = match key.strip_prefix("key::") {
+                    Some(literal) => Some(literal),
+                    None if key.starts_with("ssh-") => Some(key.as_str()),
+                    None => None,
+                };
+                let path = match literal {
+                    Some(literal) => {
+                        let file = LiteralKey::write(literal)?;
+                        let path = file.path.clone();
+                        literal_key = Some(file);
+                        path
+                    }
+                    None => expand_home(key),
+                };
+                let args = ["-Y", "sign", "-n", "git", "-f"].map(String::from);
+                let mut args = args.to_vec();
+                args.push(path.to_string_lossy().into_owned());
+                (program, args)
+            }
+        };
+
+        let output = console::output_with_input(
+            process::Command::new(program).args(&args),
+            buffer.as_bytes(),
+        )
+        .map_err(|e| anyhow::anyhow!("signing the commit: couldn't run {}: {}", program, e))?;
+        drop(literal_key);
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // gpg's status lines are for git to parse, not for people to read.
+            let message: Vec<&str> = stderr
+                .lines()
+                .filter(|l| !l.starts_with("[GNUPG:]") && !l.trim().is_empty())
+                .collect();
+            anyhow::bail!(
+                "signing the commit with {} failed: {}",
+                program,
+                message.join(" ")
+            );
+        }
+        let signature = String::from_utf8(output.stdout)?;
+        if signature.trim().is_empty() {
+            anyhow::bail!("signing the commit with {} gave no signature", program);
+        }
+        Ok(signature)
+    }
+}
+
+// A key from the config written to a file of its own, removed again once it's been used.
+struct LiteralKey {
+    path: PathBuf,
+}
+
+impl LiteralKey {
+    fn write(key: &str) -> io::Result<LiteralKey> {
+        let path = env::temp_dir().join(format!("git-istage-signing-key-{}", process::id()));
+        fs::write(&path, format!("{}\n", key.trim()))?;
+        Ok(LiteralKey { path })
+    }
+}
+
+impl Drop for LiteralKey {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Creates a commit like [Repository::commit] does, signed if the config asks for it, and moves
+/// HEAD (or the branch it points to) to it with `reflog` as the reason, e.g. "commit (amend)".
+pub fn commit(
+    repo: &Repository,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree: &Tree,
+    parents: &[&Commit],
+    reflog: &str,
+) -> anyhow::Result<Oid> {
+    let id = match Signer::from_config(&repo.config()?)? {
+        Some(signer) => {
+            let buffer = repo.commit_create_buffer(author, committer, message, tree, parents)?;
+            let buffer = std::str::from_utf8(&buffer)?;
+            let signature = signer.sign(buffer, committer)?;
+            repo.commit_signed(buffer, &signature, None)?
+        }
+        None => repo.commit(None, author, committer, message, tree, parents)?,
+    };
+
+    // Not through `commit` itself, which only moves HEAD on from its first parent, not for amends.
+    let head = repo.find_reference("HEAD")?;
+    let log = format!("{}: {}", reflog, message.lines().next().unwrap_or_default());
+    match head.symbolic_target() {
+        Some(branch) => {
+            repo.reference(branch, id, true, &log)?;
+        }
+        None => repo.set_head_detached(id)?,
+    }
+    Ok(id)
+}
@@ -0,0 +1,177 @@
+use std::fs;
+
+use git_istage_rs::{git::Location, paths::RepoPath, status::StatusEntry};
+
+use crate::promisor;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Snapshot {
+    Worktree,
+    Index,
+    Head,
+}
+
+impl Snapshot {
+    pub fn next(self) -> Snapshot {
+        match self {
+            Snapshot::Worktree => Snapshot::Index,
+            Snapshot::Index => Snapshot::Head,
+            Snapshot::Head => Snapshot::Worktree,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Snapshot::Worktree => "worktree",
+            Snapshot::Index => "index",
+            Snapshot::Head => "HEAD",
+        }
+    }
+}
+
+pub struct FileView {
+    pub path: RepoPath,
+    pub snapshot: Snapshot,
+    pub content: Option<String>,
+    // Set when the content is missing from a partial clone, rather than from the snapshot.
+    pub not_fetched: bool,
+    pub scroll: u16,
+    // The lines at which conflict regions start ("<<<<<<< ours"), and the one last jumped to.
+    pub conflicts: Vec<usize>,
+    pub conflict: Option<usize>,
+}
+
+impl FileView {
+    pub fn open(
+        location: &Location,
+        entry: &StatusEntry,
+        snapshot: Snapshot,
+    ) -> anyhow::Result<FileView> {
+        let mut view = FileView {
+            path: entry.new_file.clone(),
+            snapshot,
+            content: None,
+            not_fetched: false,
+            scroll: 0,
+            conflicts: Vec::new(),
+            conflict: None,
+        };
+        view.load(location, entry)?;
+        Ok(view)
+    }
+
+    pub fn cycle(&mut self, location: &Location, entry: &StatusEntry) -> anyhow::Result<()> {
+        self.snapshot = self.snapshot.next();
+        self.scroll = 0;
+        self.load(location, entry)
+    }
+
+    fn load(&mut self, location: &Location, entry: &StatusEntry) -> anyhow::Result<()> {
+        let (bytes, not_fetched) = match read_snapshot(location, entry, self.snapshot) {
+            Ok(bytes) => (bytes, false),
+            Err(e) if promisor::is_missing_object(&e) => (None, true),
+            Err(e) => return Err(e),
+        };
+        self.not_fetched = not_fetched;
+        self.content = bytes.map(|bytes| {
+            if bytes.contains(&0) {
+                String::from("(binary content)")
+            } else {
+                String::from_utf8_lossy(&bytes).into_owned()
+            }
+        });
+        self.conflicts = match &self.content {
+            Some(content) => content
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| is_conflict_start(l))
+                .map(|(i, _)| i)
+                .collect(),
+            None => Vec::new(),
+        };
+        self.conflict = None;
+        Ok(())
+    }
+
+    // Scrolls the next conflict region to the top, wrapping around to the first.
+    pub fn next_conflict(&mut self) {
+        if self.conflicts.is_empty() {
+            return;
+        }
+        let i = match self.conflict {
+            Some(i) => (i + 1) % self.conflicts.len(),
+            None => 0,
+        };
+        self.jump_to_conflict(i);
+    }
+
+    pub fn previous_conflict(&mut self) {
+        if self.conflicts.is_empty() {
+            return;
+        }
+        let i = match self.conflict {
+            Some(0) | None => self.conflicts.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.jump_to_conflict(i);
+    }
+
+    fn jump_to_conflict(&mut self, i: usize) {
+        self.conflict = Some(i);
+        self.scroll = u16::try_from(self.conflicts[i]).unwrap_or(u16::MAX);
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_add(1);
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+}
+
+// Returns None when the file does not exist in the requested snapshot (e.g. a new file has no HEAD
+// version, a deleted file has no worktree version).
+pub fn read_snapshot(
+    location: &Location,
+    entry: &StatusEntry,
+    snapshot: Snapshot,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let repo = location.open()?;
+
+    match snapshot {
+        Snapshot::Worktree => match fs::read(location.workdir().join(entry.new_path())) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        },
+        Snapshot::Index => {
+            let index = repo.index()?;
+            let found = [entry.new_path(), entry.old_path()]
+                .into_iter()
+                .find_map(|p| index.get_path(&p, 0));
+            match found {
+                Some(e) => Ok(Some(repo.find_blob(e.id)?.content().to_vec())),
+                None => Ok(None),
+            }
+        }
+        Snapshot::Head => {
+            let tree = match repo.head() {
+                Ok(head) => head.peel_to_tree()?,
+                // An unborn branch has no HEAD tree, so nothing exists there yet.
+                Err(e) if e.code() == git2::ErrorCode::UnbornBranch => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            // The pre-image path is the one that exists in HEAD for renames.
+            match tree.get_path(&entry.old_path()) {
+                Ok(te) => Ok(Some(repo.find_blob(te.id())?.content().to_vec())),
+                Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+}
+
+pub fn is_conflict_start(line: &str) -> bool {
+    line.starts_with("<<<<<<<")
+}
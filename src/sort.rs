@@ -0,0 +1,102 @@
+//! Orders of the file list other than the one git lists entries in.
+
+use std::{cmp::Reverse, fs, time::SystemTime};
+
+use git_istage_rs::{
+    backend::GitBackend,
+    config::SortOrder,
+    git::FileStatusKind,
+    status::{Status, StatusEntry},
+};
+
+/// The statuses of the given kind, sorted by `order`, and with the lines each adds and removes
+/// counted if `counted`.
+pub fn sorted_statuses(
+    backend: &dyn GitBackend,
+    kind: FileStatusKind,
+    order: SortOrder,
+    counted: bool,
+) -> anyhow::Result<Vec<StatusEntry>> {
+    let mut entries = backend.statuses(kind)?;
+    if counted {
+        count_lines(&mut entries, backend, kind);
+    }
+    sort(&mut entries, order, backend, kind);
+    Ok(entries)
+}
+
+/// Counts the lines each of `entries` of the given kind adds and removes, which computes each
+/// one's diff. Binary files and anything whose diff fails are left uncounted.
+pub fn count_lines(entries: &mut [StatusEntry], backend: &dyn GitBackend, kind: FileStatusKind) {
+    for entry in entries {
+        entry.lines = backend
+            .diff(entry, kind)
+            .ok()
+            .and_then(|diff| diff.line_counts());
+    }
+}
+
+/// Sorts `entries` of the given kind, keeping them in path order where the order has them equal.
+/// Sorting by size computes the diff of each entry whose lines haven't been counted.
+pub fn sort(
+    entries: &mut [StatusEntry],
+    order: SortOrder,
+    backend: &dyn GitBackend,
+    kind: FileStatusKind,
+) {
+    entries.sort_by(|a, b| a.new_file.cmp(&b.new_file));
+    match order {
+        SortOrder::Path => {}
+        SortOrder::Status => entries.sort_by_key(|e| status_rank(e.status)),
+        // Newest first, and deleted files, which have no time, last.
+        SortOrder::Modified => entries.sort_by_cached_key(|e| Reverse(modified(e))),
+        // Biggest first.
+        SortOrder::Size => entries.sort_by_cached_key(|e| Reverse(changed_lines(backend, e, kind))),
+    }
+}
+
+/// Where `entry` goes among `entries` already sorted by `order`, or `None` if finding out needs
+/// more than the entries themselves.
+pub fn position(entries: &[StatusEntry], entry: &StatusEntry, order: SortOrder) -> Option<usize> {
+    match order {
+        SortOrder::Path => Some(entries.partition_point(|e| e.new_file < entry.new_file)),
+        SortOrder::Status => {
+            let key = |e: &StatusEntry| (status_rank(e.status), e.new_file.clone());
+            Some(entries.partition_point(|e| key(e) < key(entry)))
+        }
+        SortOrder::Modified | SortOrder::Size => None,
+    }
+}
+
+// Conflicts first, as they need resolving before anything else, then changes to existing files
+// before new ones.
+fn status_rank(status: Status) -> u8 {
+    match status {
+        Status::Conflicted => 0,
+        Status::Modified | Status::Typechange => 1,
+        Status::Renamed | Status::Copied => 2,
+        Status::Deleted => 3,
+        Status::Added => 4,
+        Status::Untracked => 5,
+        Status::Unmodified | Status::Ignored | Status::Unreadable => 6,
+    }
+}
+
+fn modified(entry: &StatusEntry) -> Option<SystemTime> {
+    fs::metadata(entry.abs_path_new())
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+// How many lines the entry adds and removes. Binary files have no lines to count, and anything
+// whose diff fails counts as nothing.
+fn changed_lines(backend: &dyn GitBackend, entry: &StatusEntry, kind: FileStatusKind) -> usize {
+    if let Some(lines) = entry.lines {
+        return lines.total();
+    }
+    let diff = match backend.diff(entry, kind) {
+        Ok(diff) => diff,
+        Err(_) => return 0,
+    };
+    diff.hunks.iter().map(|h| h.line_counts().total()).sum()
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use git2::{ApplyLocation, IndexAddOption, Oid, Repository, Signature, StashFlags};
+
+use git_istage_rs::{
+    diff::{self, FileDiff},
+    git::Location,
+    status::StatusEntry,
+};
+
+use crate::{journal, scroll::Scroll};
+
+// How the stashes holding discarded changes start their message.
+const DISCARDED: &str = "git-istage: discarded";
+
+#[derive(Clone)]
+pub struct StashEntry {
+    pub index: usize,
+    pub id: Oid,
+    pub message: String,
+}
+
+impl StashEntry {
+    pub fn pretty_string(&self) -> String {
+        format!("stash@{{{}}}: {}", self.index, self.message)
+    }
+
+    // Whether the stash holds changes saved by discarding them.
+    pub fn is_discarded(&self) -> bool {
+        self.message.starts_with(DISCARDED)
+    }
+}
+
+// The stashes, most recent first.
+pub fn list(location: &Location) -> anyhow::Result<Vec<StashEntry>> {
+    let mut repo = location.open()?;
+    let mut stashes = Vec::new();
+    repo.stash_foreach(|index, message, &id| {
+        stashes.push(StashEntry {
+            index,
+            id,
+            message: message.to_string(),
+        });
+        true
+    })?;
+    Ok(stashes)
+}
+
+// Stashes the changes in the worktree and the index like `git stash push`, with git's default
+// "WIP on <branch>" message if `message` is empty.
+pub fn save(location: &Location, message: &str) -> anyhow::Result<Oid> {
+    let mut repo = location.open()?;
+    let signature = repo.signature()?;
+    let message = Some(message).filter(|m| !m.is_empty());
+    Ok(repo.stash_save2(&signature, message, Some(StashFlags::DEFAULT))?)
+}
+
+// Saves the changes about to be discarded from `entries` as a stash, e.g. "git-istage: discarded
+// src/main.rs 2024-05-01 09:30:12 UTC", without touching the worktree or the index. Like a stash
+// made by `git stash push --keep-index --include-untracked` for just those files, it holds the
+// index, with the files as they are in the worktree on top, and the untracked ones apart. Returns
+// None if there's nothing to save, or no commit to stash on yet.
+pub fn save_discarded(location: &Location, entries: &[StatusEntry]) -> anyhow::Result<Option<Oid>> {
+    let repo = location.open()?;
+    let head = match repo.head() {
+        Ok(head) => head.peel_to_commit()?,
+        Err(_) => return Ok(None),
+    };
+    // Submodules can't be discarded, and their checkouts aren't stashed.
+    let paths: BTreeSet<PathBuf> = entries
+        .iter()
+        .filter(|e| !e.submodule)
+        .flat_map(|e| [e.old_path(), e.new_path()])
+        .collect();
+    let first = match entries.first() {
+        Some(e) if !paths.is_empty() => &e.new_file,
+        _ => return Ok(None),
+    };
+
+    let workdir = location.workdir();
+    let sig = repo
+        .signature()
+        .or_else(|_| Signature::now("git-istage", "git-istage@localhost"))?;
+    let now = journal::format_utc(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+    let message = match entries.len() {
+        1 => format!("{} {} {}", DISCARDED, first, now),
+        n => format!("{} {} and {} more {}", DISCARDED, first, n - 1, now),
+    };
+    let on = format!(
+        "on {}: {}",
+        head.summary().unwrap_or_default(),
+        &head.id().to_string()[..7]
+    );
+
+    // The index is only changed in memory, to write the trees from.
+    let mut index = repo.index()?;
+    index.read(false)?;
+    let index_tree = repo.find_tree(index.write_tree()?)?;
+    let index_commit = repo.find_commit(repo.commit(
+        None,
+        &sig,
+        &sig,
+        &format!("index {}", on),
+        &index_tree,
+        &[&head],
+    )?)?;
+
+    let (tracked, untracked): (Vec<PathBuf>, Vec<PathBuf>) = paths
+        .into_iter()
+        .partition(|p| index.get_path(p, 0).is_some());
+    for path in tracked {
+        match workdir.join(&path).symlink_metadata() {
+            Ok(_) => index.add_path(&path)?,
+            Err(_) => index.remove_path(&path)?,
+        }
+    }
+    let worktree_tree = repo.find_tree(index.write_tree()?)?;
+
+    let mut parents = vec![head.clone(), index_commit];
+    let untracked: Vec<PathBuf> = untracked
+        .into_iter()
+        .filter(|p| workdir.join(p).symlink_metadata().is_ok())
+        .collect();
+    if !untracked.is_empty() {
+        index.clear()?;
+        for path in untracked {
+            match workdir.join(&path).symlink_metadata()?.is_dir() {
+                // A collapsed untracked directory, whose files are added like `git add` would.
+                true => index.add_all([&path], IndexAddOption::DEFAULT, None)?,
+                false => index.add_path(&path)?,
+            }
+        }
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let message = format!("untracked files {}", on);
+        parents.push(repo.find_commit(repo.commit(None, &sig, &sig, &message, &tree, &[])?)?);
+    }
+
+    let parents: Vec<_> = parents.iter().collect();
+    let id = repo.commit(None, &sig, &sig, &message, &worktree_tree, &parents)?;
+    repo.reference_ensure_log("refs/stash")?;
+    repo.reference("refs/stash", id, true, &message)?;
+    Ok(Some(id))
+}
+
+// Pops the most recent stash of discarded changes, if there is one.
+pub fn restore_last_discarded(location: &Location) -> anyhow::Result<Option<StashEntry>> {
+    let entry = match list(location)?.into_iter().find(|s| s.is_discarded()) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    pop(location, &entry)?;
+    Ok(Some(entry))
+}
+
+pub fn apply(location: &Location, stash: &StashEntry) -> anyhow::Result<()> {
+    let mut repo = location.open()?;
+    match stash.is_discarded() {
+        true => apply_discarded(&repo, stash.id),
+        false => Ok(repo.stash_apply(stash.index, None)?),
+    }
+}
+
+// Applies the stash and drops it, unless applying it failed.
+pub fn pop(location: &Location, stash: &StashEntry) -> anyhow::Result<()> {
+    apply(location, stash)?;
+    drop(location, stash.index)
+}
+
+// Puts discarded changes back into the worktree, leaving the index alone: libgit2 won't apply a
+// stash over staged changes, which are usually there when something's discarded, and a merge
+// like `git stash apply` does would conflict with them wherever they're close to the discarded
+// changes.
+fn apply_discarded(repo: &Repository, id: Oid) -> anyhow::Result<()> {
+    let stash = repo.find_commit(id)?;
+    let index = stash.parent(1)?.tree()?;
+    let mut diffs = vec![repo.diff_tree_to_tree(Some(&index), Some(&stash.tree()?), None)?];
+    if let Ok(untracked) = stash.parent(2) {
+        diffs.push(repo.diff_tree_to_tree(None, Some(&untracked.tree()?), None)?);
+    }
+    for diff in diffs {
+        repo.apply(&diff, ApplyLocation::WorkDir, None)
+            .map_err(|e| anyhow::anyhow!("restoring the discarded changes: {}", e.message()))?;
+    }
+    Ok(())
+}
+
+pub fn drop(location: &Location, index: usize) -> anyhow::Result<()> {
+    Ok(location.open()?.stash_drop(index)?)
+}
+
+// What applying the stash does to the worktree: its changes against the commit it was made on,
+// followed by the untracked files it holds, if it was made with `--include-untracked`. For
+// discarded changes, just what was discarded: the changes against the index they were made on.
+pub fn diffs(location: &Location, stash: &StashEntry) -> anyhow::Result<Vec<FileDiff>> {
+    let repo = location.open()?;
+    let base = match stash.is_discarded() {
+        true => 1,
+        false => 0,
+    };
+    let stash = repo.find_commit(stash.id)?;
+    let base = stash.parent(base)?.tree()?;
+
+    let mut diff = repo.diff_tree_to_tree(Some(&base), Some(&stash.tree()?), None)?;
+    diff.find_similar(None)?;
+    let mut files = diff::file_diffs(&diff)?;
+
+    // The untracked files are kept in a third, parentless commit.
+    if let Ok(untracked) = stash.parent(2) {
+        let diff = repo.diff_tree_to_tree(None, Some(&untracked.tree()?), None)?;
+        files.extend(diff::file_diffs(&diff)?);
+    }
+    Ok(files)
+}
+
+pub struct StashView {
+    // Whether only the stashes of discarded changes are shown.
+    pub discarded: bool,
+    pub stashes: Vec<StashEntry>,
+    pub selected: usize,
+    // The diff of the selected stash, one file at a time.
+    pub files: Vec<FileDiff>,
+    pub file: usize,
+    pub scroll: Scroll,
+}
+
+impl StashView {
+    // None if there are no stashes to show.
+    pub fn open(location: &Location, discarded: bool) -> anyhow::Result<Option<StashView>> {
+        let stashes: Vec<StashEntry> = list(location)?
+            .into_iter()
+            .filter(|s| !discarded || s.is_discarded())
+            .collect();
+        let files = match stashes.first() {
+            Some(s) => diffs(location, s)?,
+            None => return Ok(None),
+        };
+        Ok(Some(StashView {
+            discarded,
+            stashes,
+            selected: 0,
+            files,
+            file: 0,
+            scroll: Scroll::default(),
+        }))
+    }
+
+    pub fn next(&mut self, location: &Location) -> anyhow::Result<()> {
+        self.select(location, (self.selected + 1) % self.stashes.len())
+    }
+
+    pub fn previous(&mut self, location: &Location) -> anyhow::Result<()> {
+        let selected = (self.selected + self.stashes.len() - 1) % self.stashes.len();
+        self.select(location, selected)
+    }
+
+    pub fn select(&mut self, location: &Location, selected: usize) -> anyhow::Result<()> {
+        self.files = diffs(location, &self.stashes[selected])?;
+        self.selected = selected;
+        self.file = 0;
+        self.scroll = Scroll::default();
+        Ok(())
+    }
+
+    pub fn next_file(&mut self) {
+        if self.file + 1 < self.files.len() {
+            self.file += 1;
+            self.scroll = Scroll::default();
+        }
+    }
+
+    pub fn previous_file(&mut self) {
+        if self.file > 0 {
+            self.file -= 1;
+            self.scroll = Scroll::default();
+        }
+    }
+
+    pub fn current_file(&self) -> Option<&FileDiff> {
+        self.files.get(self.file)
+    }
+}
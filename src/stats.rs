@@ -0,0 +1,126 @@
+use std::{cmp::Reverse, collections::BTreeMap, fs};
+
+use git2::{DiffOptions, Repository};
+
+use git_istage_rs::{
+    git::{self, Head, Location},
+    paths::RepoPath,
+    status::StatusEntry,
+};
+
+use crate::history;
+
+// How many of the biggest changed files are listed.
+const LARGEST_FILES: usize = 5;
+
+// A summary of the repository's state, as lines of text.
+pub fn summarize(
+    location: &Location,
+    unstaged: &[StatusEntry],
+    staged: &[StatusEntry],
+) -> anyhow::Result<Vec<String>> {
+    let repo = location.open()?;
+    let mut lines = vec![branch_line(&repo)?];
+    if let Ok(head) = repo.head().and_then(|h| h.peel_to_commit()) {
+        let tags = history::tags_on(location, head.id())?;
+        if !tags.is_empty() {
+            lines.push(format!("Tags: {}", tags.join(", ")));
+        }
+    }
+    lines.push(String::new());
+
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None,
+    };
+    let mut opts = DiffOptions::new();
+    let staged_stats = repo
+        .diff_tree_to_index(head.as_ref(), None, Some(&mut opts))?
+        .stats()?;
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    let unstaged_stats = repo.diff_index_to_workdir(None, Some(&mut opts))?.stats()?;
+
+    lines.push(count_line(
+        "Unstaged",
+        unstaged,
+        unstaged_stats.insertions(),
+        unstaged_stats.deletions(),
+    ));
+    lines.push(count_line(
+        "Staged",
+        staged,
+        staged_stats.insertions(),
+        staged_stats.deletions(),
+    ));
+
+    // Sizes are those in the worktree; deleted files have none.
+    let mut sizes: BTreeMap<&RepoPath, u64> = BTreeMap::new();
+    for e in unstaged.iter().chain(staged) {
+        if let Ok(meta) = fs::metadata(e.abs_path_new()) {
+            sizes.insert(&e.new_file, meta.len());
+        }
+    }
+    let mut sizes: Vec<(&RepoPath, u64)> = sizes.into_iter().collect();
+    sizes.sort_by_key(|&(_, size)| Reverse(size));
+    if !sizes.is_empty() {
+        lines.push(String::new());
+        lines.push(String::from("Largest changed files:"));
+        for (path, size) in sizes.into_iter().take(LARGEST_FILES) {
+            lines.push(format!("  {:>10}  {}", format_size(size), path));
+        }
+    }
+    Ok(lines)
+}
+
+fn branch_line(repo: &Repository) -> anyhow::Result<String> {
+    let name = match git::head(repo)? {
+        Head::Unborn(_) => return Ok(String::from("Branch: none yet (no commits)")),
+        Head::Detached(id) => {
+            return Ok(format!("HEAD detached at {}", &id.to_string()[..7]));
+        }
+        Head::Branch(name) => name,
+    };
+    match git::upstream(repo)? {
+        Some(upstream) => Ok(format!(
+            "Branch: {} -> {} (ahead {}, behind {})",
+            name, upstream.name, upstream.ahead, upstream.behind
+        )),
+        None => Ok(format!("Branch: {} (no upstream)", name)),
+    }
+}
+
+// E.g. "Staged: 3 files (2 M, 1 A), +10 -4".
+fn count_line(label: &str, entries: &[StatusEntry], insertions: usize, deletions: usize) -> String {
+    let mut counts: BTreeMap<char, usize> = BTreeMap::new();
+    for e in entries {
+        *counts.entry(char::from(e.status)).or_default() += 1;
+    }
+    let counts: Vec<String> = counts
+        .iter()
+        .map(|(status, n)| format!("{} {}", n, status))
+        .collect();
+
+    let mut line = format!("{}: {} files", label, entries.len());
+    if !counts.is_empty() {
+        line.push_str(&format!(" ({})", counts.join(", ")));
+    }
+    line.push_str(&format!(", +{} -{}", insertions, deletions));
+    line
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -1,118 +1,120 @@
-use std::{
-    fs,
-    path::{self, PathBuf},
-    process,
-};
+use std::path::PathBuf;
 
 use git2::{Delta, DiffDelta};
-use tui::style::Color;
 
+use crate::{diff::LineCounts, paths::RepoPath};
+
+#[derive(Clone)]
 pub struct StatusEntry {
-    repo_root: String,
-    pub old_file: String,
-    pub new_file: String,
+    repo_root: PathBuf,
+    // The paths relative to the repo root, as git has them.
+    pub old_file: RepoPath,
+    pub new_file: RepoPath,
     pub status: Status,
+    // Set for the gitlink of a submodule, which is staged by the commit it points to rather than
+    // by content.
+    pub submodule: bool,
+    // The lines the change adds and removes, when the lists show them and they could be counted.
+    pub lines: Option<LineCounts>,
 }
 
-impl<'a> From<(String, DiffDelta<'a>)> for StatusEntry {
-    fn from(value: (String, DiffDelta<'a>)) -> Self {
+impl<'a> From<(PathBuf, DiffDelta<'a>)> for StatusEntry {
+    fn from(value: (PathBuf, DiffDelta<'a>)) -> Self {
         Self {
             repo_root: value.0,
             old_file: value
                 .1
                 .old_file()
-                .path()
-                .map(|p| p.to_string_lossy().into_owned())
+                .path_bytes()
+                .map(RepoPath::from)
                 .unwrap_or_default(),
             new_file: value
                 .1
                 .new_file()
-                .path()
-                .map(|p| p.to_string_lossy().into_owned())
+                .path_bytes()
+                .map(RepoPath::from)
                 .unwrap_or_default(),
             status: value.1.status().into(),
+            submodule: false,
+            lines: None,
         }
     }
 }
 
 impl StatusEntry {
+    // An entry for `old_file` -> `new_file` (the same path unless renamed), relative to
+    // `repo_root`.
+    pub fn new(
+        repo_root: impl Into<PathBuf>,
+        old_file: impl Into<RepoPath>,
+        new_file: impl Into<RepoPath>,
+        status: Status,
+    ) -> StatusEntry {
+        StatusEntry {
+            repo_root: repo_root.into(),
+            old_file: old_file.into(),
+            new_file: new_file.into(),
+            status,
+            submodule: false,
+            lines: None,
+        }
+    }
+
     pub fn pretty_string(&self) -> String {
         match self.status {
-            Status::Renamed => format!(
+            Status::Renamed | Status::Copied => format!(
                 "{} {} -> {}",
                 char::from(self.status),
-                self.old_file,
-                self.new_file
+                self.old_file.quoted(),
+                self.new_file.quoted()
             ),
-            _ => format!("{} {}", char::from(self.status), self.new_file),
+            _ => format!("{} {}", char::from(self.status), self.new_file.quoted()),
         }
     }
 
-    fn abs_path_old(&self) -> PathBuf {
-        path::Path::new(&self.repo_root).join(&self.old_file)
+    pub fn old_path(&self) -> PathBuf {
+        self.old_file.to_path()
     }
 
-    fn abs_path_new(&self) -> PathBuf {
-        path::Path::new(&self.repo_root).join(&self.new_file)
+    pub fn new_path(&self) -> PathBuf {
+        self.new_file.to_path()
     }
 
-    pub fn stage_to_index(&self) -> anyhow::Result<()> {
-        let mut cmd = process::Command::new("git");
-        cmd.arg("add");
-
-        // Assumption: this StatusEntry was obtained by compaing the index to the working directory.
-        match self.status {
-            Status::Renamed => cmd.args([self.abs_path_old(), self.abs_path_new()]),
-            _ => cmd.arg(&self.abs_path_new()),
-        };
+    // Whether this is an untracked directory listed as one entry, "dir/", rather than file by
+    // file.
+    pub fn is_dir(&self) -> bool {
+        self.new_file.ends_with("/")
+    }
 
-        cmd.output()?;
-        Ok(())
+    // The directory containing this entry, relative to the repo root ("" for top-level files).
+    pub fn dir(&self) -> RepoPath {
+        let path = self.new_file.as_bytes();
+        let path = path.strip_suffix(b"/").unwrap_or(path);
+        match path.iter().rposition(|&b| b == b'/') {
+            Some(i) => RepoPath::from(&path[..i]),
+            None => RepoPath::default(),
+        }
     }
 
-    pub fn reset_from_workdir(&self) -> anyhow::Result<()> {
-        // Assumption: this StatusEntry was obtained by compaing the index to the working directory.
-        match self.status {
-            Status::Untracked => {
-                fs::remove_file(self.abs_path_new())?;
-            }
-            Status::Renamed => {
-                fs::remove_file(self.abs_path_new())?;
-                process::Command::new("git")
-                    .arg("checkout")
-                    .arg(self.abs_path_old())
-                    .output()?;
-            }
-            _ => {
-                process::Command::new("git")
-                    .arg("checkout")
-                    .arg(self.abs_path_new())
-                    .output()?;
-            }
-        };
-
-        Ok(())
+    pub fn is_under(&self, dir: &RepoPath) -> bool {
+        dir.is_empty()
+            || [&self.old_file, &self.new_file].iter().any(|p| {
+                p.as_bytes()
+                    .strip_prefix(dir.as_bytes())
+                    .is_some_and(|rest| rest.starts_with(b"/"))
+            })
     }
 
-    pub fn unstage_to_workdir(&self) -> anyhow::Result<()> {
-        let mut cmd = process::Command::new("git");
+    pub fn abs_path_old(&self) -> PathBuf {
+        self.repo_root.join(self.old_path())
+    }
 
-        // Assumption: this StatusEntry was obtained by comparing HEAD to the index.
-        match self.status {
-            Status::Deleted => {
-                cmd.arg("restore").arg("--staged").arg(self.abs_path_new());
-            }
-            _ => {
-                cmd.arg("reset").arg(self.abs_path_new());
-            }
-        };
-
-        cmd.output()?;
-        Ok(())
+    pub fn abs_path_new(&self) -> PathBuf {
+        self.repo_root.join(self.new_path())
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Status {
     Unmodified,
     Added,
@@ -127,6 +129,25 @@ pub enum Status {
     Unreadable,
 }
 
+impl Status {
+    /// The name of the status, as `git-istage --list --json` prints it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Unmodified => "unmodified",
+            Status::Added => "added",
+            Status::Deleted => "deleted",
+            Status::Modified => "modified",
+            Status::Renamed => "renamed",
+            Status::Copied => "copied",
+            Status::Ignored => "ignored",
+            Status::Untracked => "untracked",
+            Status::Conflicted => "conflicted",
+            Status::Typechange => "typechange",
+            Status::Unreadable => "unreadable",
+        }
+    }
+}
+
 impl From<Delta> for Status {
     fn from(value: Delta) -> Self {
         match value {
@@ -157,26 +178,8 @@ impl From<Status> for char {
             Status::Ignored => '!',
             Status::Untracked => 'U',
             Status::Conflicted => 'X',
-            Status::Typechange => todo!(),
-            Status::Unreadable => todo!(),
-        }
-    }
-}
-
-impl From<Status> for Color {
-    fn from(value: Status) -> Self {
-        match value {
-            Status::Unmodified => Color::White,
-            Status::Added => Color::LightGreen,
-            Status::Deleted => Color::Red,
-            Status::Modified => Color::Yellow,
-            Status::Renamed => Color::Cyan,
-            Status::Copied => Color::LightBlue,
-            Status::Ignored => Color::Gray,
-            Status::Untracked => Color::Green,
-            Status::Conflicted => Color::LightRed,
-            Status::Typechange => todo!(),
-            Status::Unreadable => todo!(),
+            Status::Typechange => 'T',
+            Status::Unreadable => '?',
         }
     }
 }
@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use git2::{Status as GitStatus, StatusOptions};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Frame,
+};
+
+use git_istage_rs::{
+    config::ShowUntracked,
+    diff::DiffSettings,
+    git::{self, FileStatusKind, Head, Location, Upstream},
+    status::Status,
+};
+
+use crate::theme::Theme;
+
+// What the bar above the panes says about the repository: its name, where HEAD is, and how many
+// files have changes of each kind. It's gathered in the background along with the lists, which
+// only hold the kind of changes being shown.
+pub struct Summary {
+    name: String,
+    // The name of the linked worktree, when it's not the main one.
+    worktree: Option<String>,
+    head: Head,
+    upstream: Option<Upstream>,
+    staged: usize,
+    unstaged: usize,
+    untracked: usize,
+    // The pathspecs the counts, like the lists, are limited to.
+    pathspecs: Vec<String>,
+}
+
+impl Summary {
+    // Untracked files are counted as `settings` lists them, a collapsed directory as one.
+    pub fn load(
+        location: &Location,
+        settings: DiffSettings,
+        pathspecs: Vec<String>,
+    ) -> anyhow::Result<Summary> {
+        let repo = location.open()?;
+        let worktree = git::worktree_name(&repo);
+        // A linked worktree is named after the repository it belongs to, whose git directory
+        // holds those of its worktrees under worktrees/: that's after the main working tree, unless
+        // the repository is bare.
+        let top = match worktree {
+            Some(_) => repo.path().ancestors().nth(2).map(|common| {
+                match common.file_name().is_some_and(|n| n == ".git") {
+                    true => common.parent().unwrap_or(common).to_path_buf(),
+                    false => common.to_path_buf(),
+                }
+            }),
+            None => Some(git::repo_workdir(&repo)?),
+        };
+        let name = top
+            .as_deref()
+            .and_then(Path::file_name)
+            .map_or(String::from("?"), |n| n.to_string_lossy().into_owned());
+
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(settings.untracked != ShowUntracked::No)
+            .recurse_untracked_dirs(
+                settings.untracked == ShowUntracked::All && settings.max_untracked == 0,
+            );
+        for pathspec in &pathspecs {
+            opts.pathspec(pathspec);
+        }
+        let (mut staged, mut unstaged, mut untracked) = (0, 0, 0);
+        for entry in repo.statuses(Some(&mut opts))?.iter() {
+            let status = entry.status();
+            if status.intersects(
+                GitStatus::INDEX_NEW
+                    | GitStatus::INDEX_MODIFIED
+                    | GitStatus::INDEX_DELETED
+                    | GitStatus::INDEX_RENAMED
+                    | GitStatus::INDEX_TYPECHANGE,
+            ) {
+                staged += 1;
+            }
+            if status.contains(GitStatus::WT_NEW) {
+                untracked += 1;
+            } else if status.intersects(
+                GitStatus::WT_MODIFIED
+                    | GitStatus::WT_DELETED
+                    | GitStatus::WT_RENAMED
+                    | GitStatus::WT_TYPECHANGE
+                    | GitStatus::CONFLICTED,
+            ) {
+                unstaged += 1;
+            }
+        }
+
+        Ok(Summary {
+            name,
+            worktree,
+            head: git::head(&repo)?,
+            upstream: git::upstream(&repo)?,
+            staged,
+            unstaged,
+            untracked,
+            pathspecs,
+        })
+    }
+
+    // Whether HEAD is a branch with no commits yet.
+    pub fn unborn(&self) -> bool {
+        matches!(self.head, Head::Unborn(_))
+    }
+}
+
+// What clicking a part of the bar does: the branch opens the log, how far it's behind its upstream
+// the log of the incoming commits, and the counts show the list with
+// those changes.
+#[derive(Clone, Copy)]
+pub enum Target {
+    Log,
+    // The log of the commits the upstream has that HEAD doesn't.
+    Incoming,
+    List(FileStatusKind),
+}
+
+// E.g. "git-istage-rs  main -> origin/main ↑2 ↓1  1 commit behind origin/main  3 staged  1 unstaged  4 untracked". Returns where
+// its parts that can be clicked were drawn.
+pub fn render_status_bar<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    summary: &Summary,
+    theme: &Theme,
+) -> Vec<(Rect, Target)> {
+    let mut spans = vec![Span::styled(
+        format!("{}  ", summary.name),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+    if let Some(worktree) = &summary.worktree {
+        spans.push(Span::styled(
+            format!("worktree {}  ", worktree),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    let head = match &summary.head {
+        Head::Branch(name) => name.clone(),
+        Head::Detached(id) => format!("detached at {}", &id.to_string()[..7]),
+        Head::Unborn(name) => format!("{} (no commits yet)", name),
+    };
+    spans.push(Span::styled(head, Style::default().fg(Color::LightCyan)));
+    let mut targets = vec![(spans.len() - 1, Target::Log)];
+    if let Some(upstream) = &summary.upstream {
+        spans.push(Span::styled(
+            format!(" -> {}", upstream.name),
+            Style::default().fg(theme.text),
+        ));
+        spans.push(Span::styled(
+            format!(" ↑{} ↓{}", upstream.ahead, upstream.behind),
+            Style::default().fg(match (upstream.ahead, upstream.behind) {
+                (0, 0) => theme.text,
+                _ => Color::Yellow,
+            }),
+        ));
+        // Spelled out, as a reason to pull before committing on a stale base.
+        if upstream.behind > 0 {
+            spans.push(Span::styled(
+                format!(
+                    "  {} commit{} behind {}",
+                    upstream.behind,
+                    if upstream.behind == 1 { "" } else { "s" },
+                    upstream.name
+                ),
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ));
+            targets.push((spans.len() - 1, Target::Incoming));
+        }
+    }
+    let counts = [
+        (
+            summary.staged,
+            "staged",
+            theme.status(Status::Added),
+            FileStatusKind::Staged,
+        ),
+        (
+            summary.unstaged,
+            "unstaged",
+            theme.status(Status::Modified),
+            FileStatusKind::Unstaged,
+        ),
+        (
+            summary.untracked,
+            "untracked",
+            theme.status(Status::Untracked),
+            FileStatusKind::Unstaged,
+        ),
+    ];
+    for (count, label, color, kind) in counts {
+        let color = if count == 0 { theme.text } else { color };
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            format!("{} {}", count, label),
+            Style::default().fg(color),
+        ));
+        targets.push((spans.len() - 1, Target::List(kind)));
+    }
+
+    if !summary.pathspecs.is_empty() {
+        spans.push(Span::styled(
+            format!("  in {}", summary.pathspecs.join(" ")),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+
+    // Each span starts where the ones before it end.
+    let mut starts = Vec::new();
+    let mut x = area.x;
+    for span in &spans {
+        starts.push(x);
+        x = x.saturating_add(span.width() as u16);
+    }
+    let regions = targets
+        .into_iter()
+        .map(|(i, target)| {
+            let width = (spans[i].width() as u16).min(area.right().saturating_sub(starts[i]));
+            (Rect::new(starts[i], area.y, width, 1), target)
+        })
+        .collect();
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+    regions
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, fs};
+
+use globset::GlobSet;
+
+use git_istage_rs::{config::StageWarningsConfig, status::StatusEntry};
+
+// Values in env files at least this long and with at least this many bits of entropy per
+// character look like generated secrets (API keys, tokens) rather than settings.
+const MIN_SECRET_LEN: usize = 20;
+const MIN_SECRET_ENTROPY: f64 = 3.5;
+
+// Why staging `entry` deserves a second look, if it does: it's bigger than the configured limit,
+// its path matches one of the `secrets` patterns, or it's an env file holding secret-looking
+// values. Deleted files are never suspicious.
+pub fn check(
+    entry: &StatusEntry,
+    config: &StageWarningsConfig,
+    secrets: &GlobSet,
+) -> Option<String> {
+    let path = entry.abs_path_new();
+    let meta = fs::metadata(&path).ok().filter(|m| m.is_file())?;
+
+    if secrets.is_match(entry.new_path()) {
+        return Some(String::from("may contain secrets"));
+    }
+    if config.max_size > 0 && meta.len() > config.max_size {
+        return Some(format!("{:.1} MiB", meta.len() as f64 / (1024.0 * 1024.0)));
+    }
+    if is_env_file(&entry.new_file.to_str_lossy())
+        && has_secret_values(&fs::read_to_string(path).ok()?)
+    {
+        return Some(String::from("env file with secret-looking values"));
+    }
+    None
+}
+
+fn is_env_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.starts_with(".env") || name.ends_with(".env")
+}
+
+fn has_secret_values(text: &str) -> bool {
+    text.lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(_, v)| v.trim().trim_matches(['"', '\'']))
+        // URLs and sentences are long and varied too, but rarely secrets by themselves.
+        .filter(|v| !v.contains("://") && !v.contains(char::is_whitespace))
+        .any(|v| v.chars().count() >= MIN_SECRET_LEN && entropy(v) >= MIN_SECRET_ENTROPY)
+}
+
+// Shannon entropy in bits per character.
+fn entropy(s: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in s.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = s.chars().count() as f64;
+    counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,341 @@
+use std::ops::Range;
+
+// What a stretch of a line is, for coloring it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+// Enough of a language's syntax to pick out its keywords, strings, comments and numbers, a line at
+// a time. The tables are built in, so there's nothing to load or parse when a diff is shown.
+pub struct Language {
+    extensions: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    // Whether `'` quotes a single character, and is otherwise a lifetime or label, as in Rust.
+    char_literals: bool,
+}
+
+const C_LIKE_BLOCK: Option<(&str, &str)> = Some(("/*", "*/"));
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        extensions: &["rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        line_comments: &["//"],
+        block_comment: C_LIKE_BLOCK,
+        quotes: &['"'],
+        char_literals: true,
+    },
+    Language {
+        extensions: &["go"],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "fallthrough",
+            "false",
+            "for",
+            "func",
+            "go",
+            "goto",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        line_comments: &["//"],
+        block_comment: C_LIKE_BLOCK,
+        quotes: &['"', '\'', '`'],
+        char_literals: false,
+    },
+    Language {
+        extensions: &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "java", "cs"],
+        keywords: &[
+            "auto",
+            "bool",
+            "break",
+            "case",
+            "catch",
+            "char",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "double",
+            "else",
+            "enum",
+            "extends",
+            "extern",
+            "false",
+            "final",
+            "float",
+            "for",
+            "goto",
+            "if",
+            "implements",
+            "import",
+            "int",
+            "long",
+            "namespace",
+            "new",
+            "null",
+            "nullptr",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "short",
+            "signed",
+            "sizeof",
+            "static",
+            "struct",
+            "switch",
+            "template",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "typedef",
+            "union",
+            "unsigned",
+            "using",
+            "virtual",
+            "void",
+            "volatile",
+            "while",
+        ],
+        line_comments: &["//"],
+        block_comment: C_LIKE_BLOCK,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "of",
+            "return",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        line_comments: &["//"],
+        block_comment: C_LIKE_BLOCK,
+        quotes: &['"', '\'', '`'],
+        char_literals: false,
+    },
+    Language {
+        extensions: &["py"],
+        keywords: &[
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "self", "try", "while", "with", "yield",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        extensions: &["rb"],
+        keywords: &[
+            "begin", "break", "case", "class", "def", "do", "else", "elsif", "end", "ensure",
+            "false", "for", "if", "in", "module", "next", "nil", "return", "self", "then", "true",
+            "unless", "until", "when", "while", "yield",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        extensions: &["sh", "bash", "zsh"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "until", "while",
+        ],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        extensions: &["toml", "yaml", "yml"],
+        keywords: &["true", "false", "null"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+];
+
+// The language of a file, going by its extension.
+pub fn language(path: &str) -> Option<&'static Language> {
+    let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+    let extension = &name[name.rfind('.')? + 1..];
+    LANGUAGES.iter().find(|l| l.extensions.contains(&extension))
+}
+
+// Splits lines into tokens, carrying a block comment that's still open over to the next line.
+#[derive(Clone)]
+pub struct Highlighter {
+    language: &'static Language,
+    in_comment: bool,
+}
+
+impl Highlighter {
+    pub fn new(language: &'static Language) -> Highlighter {
+        Highlighter {
+            language,
+            in_comment: false,
+        }
+    }
+
+    // The tokens of `line`, covering all of it.
+    pub fn line(&mut self, line: &str) -> Vec<(Range<usize>, Token)> {
+        let language = self.language;
+        let mut tokens: Vec<(Range<usize>, Token)> = Vec::new();
+        let mut push = |range: Range<usize>, token: Token| match tokens.last_mut() {
+            Some((last, t)) if *t == token && last.end == range.start => last.end = range.end,
+            _ => tokens.push((range, token)),
+        };
+
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            if self.in_comment {
+                let close = language.block_comment.map_or("", |(_, close)| close);
+                let end = rest.find(close).map_or(line.len(), |e| {
+                    self.in_comment = false;
+                    i + e + close.len()
+                });
+                push(i..end, Token::Comment);
+                i = end;
+                continue;
+            }
+            if language.line_comments.iter().any(|c| rest.starts_with(c)) {
+                push(i..line.len(), Token::Comment);
+                break;
+            }
+            if let Some((open, _)) = language.block_comment.filter(|(o, _)| rest.starts_with(o)) {
+                self.in_comment = true;
+                push(i..i + open.len(), Token::Comment);
+                i += open.len();
+                continue;
+            }
+
+            let c = rest.chars().next().unwrap_or_default();
+            let (len, token) = if language.quotes.contains(&c) {
+                (string_len(rest, c), Token::String)
+            } else if c == '\'' && language.char_literals {
+                // `'a'` or `'\n'`, but not `'a` or `'static`.
+                match string_len(rest, c) {
+                    len @ 3..=4 if rest[..len].ends_with('\'') => (len, Token::String),
+                    _ => (1, Token::Plain),
+                }
+            } else if c.is_ascii_digit() {
+                // A dot only belongs to numbers, like `1.5`.
+                let len = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+                    .unwrap_or(rest.len());
+                (len, Token::Number)
+            } else if c.is_alphanumeric() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                match language.keywords.contains(&&rest[..len]) {
+                    true => (len, Token::Keyword),
+                    false => (len, Token::Plain),
+                }
+            } else {
+                (c.len_utf8(), Token::Plain)
+            };
+            push(i..i + len, token);
+            i += len;
+        }
+        tokens
+    }
+}
+
+// The length of the string starting at the start of `rest` with `quote`, up to its closing quote,
+// or the end of the line if it isn't closed on it.
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
@@ -0,0 +1,141 @@
+use std::{
+    panic,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
+use crossterm::{
+    cursor::Show,
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::terminal_output;
+
+// Whether the app has the terminal, in raw mode on the alternate screen, and whether it captures
+// the mouse there: what has to be undone before the process exits, however it exits.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+static MOUSE: AtomicBool = AtomicBool::new(false);
+// Whether a program run in the foreground, the editor or the `!` shell, has the terminal meanwhile,
+// and the SIGTERM or SIGHUP that came while it had, to end the app with once it's done.
+static HANDED_OVER: AtomicBool = AtomicBool::new(false);
+static PENDING: AtomicI32 = AtomicI32::new(0);
+
+// The terminal, taken over for as long as the guard lives. Dropping it, when returning early or
+// unwinding from a panic, gives the terminal back.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn take(mouse: bool) -> anyhow::Result<TerminalGuard> {
+        MOUSE.store(mouse, Ordering::SeqCst);
+        let guard = TerminalGuard;
+        take()?;
+        Ok(guard)
+    }
+
+    // Gives the terminal back, saying if that failed, which dropping the guard can't.
+    pub fn release(self) -> anyhow::Result<()> {
+        give_back()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = give_back();
+    }
+}
+
+// Takes the terminal (back) over: raw mode, the alternate screen, and the mouse if it's captured.
+// Coming back from a program it was handed over to, ends the app instead if it was told to meanwhile.
+pub fn take() -> anyhow::Result<()> {
+    HANDED_OVER.store(false, Ordering::SeqCst);
+    let signal = PENDING.swap(0, Ordering::SeqCst);
+    if signal != 0 {
+        std::process::exit(128 + signal);
+    }
+    TAKEN.store(true, Ordering::SeqCst);
+    enable_raw_mode()?;
+    let mut out = terminal_output();
+    execute!(out, EnterAlternateScreen)?;
+    if MOUSE.load(Ordering::SeqCst) {
+        execute!(out, EnableMouseCapture)?;
+    }
+    Ok(())
+}
+
+// Starts or stops capturing the mouse, e.g. once the config saying whether to has been read.
+pub fn capture_mouse(mouse: bool) -> anyhow::Result<()> {
+    if MOUSE.swap(mouse, Ordering::SeqCst) == mouse || !TAKEN.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let mut out = terminal_output();
+    match mouse {
+        true => execute!(out, EnableMouseCapture)?,
+        false => execute!(out, DisableMouseCapture)?,
+    }
+    Ok(())
+}
+
+// Gives the terminal back to the shell as it was, if the app has it, e.g. while the editor runs.
+pub fn give_back() -> anyhow::Result<()> {
+    if !TAKEN.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    disable_raw_mode()?;
+    let mut out = terminal_output();
+    execute!(out, LeaveAlternateScreen)?;
+    if MOUSE.load(Ordering::SeqCst) {
+        execute!(out, DisableMouseCapture)?;
+    }
+    execute!(out, Show)?;
+    Ok(())
+}
+
+// Gives the terminal back for a program to run in the foreground until it's taken again. Meanwhile
+// ctrl-c is the program's own: the app ignores the SIGINT, as git does while the editor runs.
+pub fn hand_over() -> anyhow::Result<()> {
+    HANDED_OVER.store(true, Ordering::SeqCst);
+    give_back()
+}
+
+// Makes sure the terminal is given back however the process ends: before a panic's message is
+// printed, so that it can be read, and on SIGINT, SIGTERM or SIGHUP, which then end it as they
+// would have, once a program the terminal is handed over to is done. Only ctrl-c typed at the app
+// is a key rather than a SIGINT.
+pub fn give_back_on_exit() -> anyhow::Result<()> {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = give_back();
+        previous(info);
+    }));
+    give_back_on_signals()
+}
+
+#[cfg(unix)]
+fn give_back_on_signals() -> anyhow::Result<()> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if HANDED_OVER.load(Ordering::SeqCst) {
+                if signal != SIGINT {
+                    PENDING.store(signal, Ordering::SeqCst);
+                }
+                continue;
+            }
+            let _ = give_back();
+            // As a shell reports a process killed by the signal.
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn give_back_on_signals() -> anyhow::Result<()> {
+    Ok(())
+}
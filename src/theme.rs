@@ -0,0 +1,398 @@
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::Widget,
+};
+
+use git_istage_rs::{
+    config::{ThemeConfig, ThemeName},
+    diff::LineKind,
+    git::Location,
+    status::Status,
+};
+
+use crate::syntax::Token;
+
+// The colors the file lists, diffs and panes are drawn with: one of the built-in themes, with the
+// colors named in the config's `[theme.colors]` replaced.
+pub struct Theme {
+    // The color of each entry in the file lists, by status.
+    statuses: Vec<(Status, Color)>,
+    // Unchanged lines in the file lists and diffs.
+    pub text: Color,
+    pub diff_added: Color,
+    pub diff_removed: Color,
+    // The background of the words that changed within a changed line.
+    pub word_added: Color,
+    pub word_removed: Color,
+    // Whether the whitespace errors in added lines are shown, and with what background.
+    pub whitespace_errors: bool,
+    pub whitespace_error: Color,
+    // Whether the `\r` of lines ending in CRLF is shown, which it otherwise only is where a line's
+    // ending is all that changed.
+    pub line_endings: bool,
+    // Whether the entries, the lists and the hunk headers say how many lines they add and remove.
+    pub line_counts: bool,
+    pub hunk_header: Color,
+    // The background of the selected entry, and of the lines being selected in a hunk.
+    pub selection: Color,
+    pub border: Color,
+    // The border of the focused pane, and the bar next to the selected hunk.
+    pub focused_border: Color,
+    // The characters a filter matched.
+    pub matched: Color,
+    // The marker of marked entries.
+    pub marked: Color,
+    // Whether diffs are syntax colored, and with what.
+    pub syntax: bool,
+    pub keyword: Color,
+    pub string: Color,
+    pub comment: Color,
+    pub number: Color,
+    // Whether nothing is colored, everything colors tell apart being marked some other way.
+    pub no_color: bool,
+}
+
+impl Theme {
+    // `location` is the repository whose git config gives colors, if there's one.
+    pub fn new(location: Option<&Location>, config: &ThemeConfig) -> anyhow::Result<Theme> {
+        let mut theme = match config.name {
+            ThemeName::Dark => Theme::dark(),
+            ThemeName::Light => Theme::light(),
+        };
+        theme.syntax = config.syntax && !config.no_color;
+        theme.no_color = config.no_color;
+        theme.whitespace_errors = config.whitespace_errors;
+        theme.line_endings = config.line_endings;
+        theme.line_counts = config.line_counts;
+        if config.git_colors {
+            theme.apply_git_colors(location)?;
+        }
+        for (element, color) in &config.colors {
+            let color = parse_color(color).ok_or(anyhow::anyhow!(
+                "invalid color for {}: {}",
+                element,
+                color
+            ))?;
+            *theme.element(element).ok_or(anyhow::anyhow!(
+                "unknown element in [theme.colors]: {}",
+                element
+            ))? = color;
+        }
+        Ok(theme)
+    }
+
+    // The palette the app always had, for dark backgrounds.
+    fn dark() -> Theme {
+        Theme {
+            statuses: vec![
+                (Status::Unmodified, Color::White),
+                (Status::Added, Color::LightGreen),
+                (Status::Deleted, Color::Red),
+                (Status::Modified, Color::Yellow),
+                (Status::Renamed, Color::Cyan),
+                (Status::Copied, Color::LightBlue),
+                (Status::Ignored, Color::Gray),
+                (Status::Untracked, Color::Green),
+                (Status::Conflicted, Color::LightRed),
+                (Status::Typechange, Color::Magenta),
+                (Status::Unreadable, Color::DarkGray),
+            ],
+            text: Color::Gray,
+            diff_added: Color::LightGreen,
+            diff_removed: Color::Red,
+            word_added: Color::Rgb(0, 85, 0),
+            word_removed: Color::Rgb(95, 0, 0),
+            whitespace_errors: false,
+            whitespace_error: Color::Red,
+            line_endings: false,
+            line_counts: false,
+            hunk_header: Color::Cyan,
+            selection: Color::Rgb(75, 75, 75),
+            border: Color::Reset,
+            focused_border: Color::Yellow,
+            matched: Color::Yellow,
+            marked: Color::Yellow,
+            syntax: true,
+            keyword: Color::LightMagenta,
+            string: Color::LightYellow,
+            comment: Color::DarkGray,
+            number: Color::LightCyan,
+            no_color: false,
+        }
+    }
+
+    // Darker colors that stay readable on light backgrounds, with the terminal's own text color.
+    fn light() -> Theme {
+        Theme {
+            statuses: vec![
+                (Status::Unmodified, Color::Reset),
+                (Status::Added, Color::Indexed(28)),
+                (Status::Deleted, Color::Indexed(124)),
+                (Status::Modified, Color::Indexed(130)),
+                (Status::Renamed, Color::Indexed(30)),
+                (Status::Copied, Color::Blue),
+                (Status::Ignored, Color::DarkGray),
+                (Status::Untracked, Color::Indexed(34)),
+                (Status::Conflicted, Color::Indexed(160)),
+                (Status::Typechange, Color::Magenta),
+                (Status::Unreadable, Color::DarkGray),
+            ],
+            text: Color::Reset,
+            diff_added: Color::Indexed(28),
+            diff_removed: Color::Indexed(124),
+            word_added: Color::Rgb(175, 235, 175),
+            word_removed: Color::Rgb(245, 185, 185),
+            whitespace_errors: false,
+            whitespace_error: Color::Indexed(160),
+            line_endings: false,
+            line_counts: false,
+            hunk_header: Color::Indexed(30),
+            selection: Color::Rgb(210, 210, 210),
+            border: Color::Reset,
+            focused_border: Color::Blue,
+            matched: Color::Blue,
+            marked: Color::Indexed(130),
+            syntax: true,
+            keyword: Color::Indexed(90),
+            string: Color::Indexed(94),
+            comment: Color::Indexed(244),
+            number: Color::Indexed(25),
+            no_color: false,
+        }
+    }
+
+    // Takes the foreground colors git's own config gives `git status` and `git diff`, where it
+    // sets any.
+    fn apply_git_colors(&mut self, location: Option<&Location>) -> anyhow::Result<()> {
+        let config = match location {
+            Some(location) => location.open()?.config()?,
+            None => git2::Config::open_default()?,
+        };
+        for (key, element) in GIT_COLORS {
+            let color = config.get_string(key).ok().and_then(|v| git_color(&v));
+            if let (Some(color), Some(slot)) = (color, self.element(element)) {
+                *slot = color;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, status: Status) -> Color {
+        self.statuses
+            .iter()
+            .find(|(s, _)| *s == status)
+            .map_or(self.text, |(_, c)| *c)
+    }
+
+    // How an entry of the list with `status` is drawn: in its color, and dimmed if it's ignored.
+    pub fn entry(&self, status: Status) -> Style {
+        let style = Style::default().fg(self.status(status));
+        match status {
+            Status::Ignored => style.add_modifier(Modifier::DIM),
+            _ => style,
+        }
+    }
+
+    pub fn selected(&self) -> Style {
+        match self.no_color {
+            true => Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+            false => Style::default()
+                .bg(self.selection)
+                .add_modifier(Modifier::BOLD),
+        }
+    }
+
+    // The status letter an entry starts with, e.g. "M", or "[M]" when there's no color to go by.
+    pub fn status_marker(&self, status: Status) -> String {
+        match self.no_color {
+            true => format!("[{}]", char::from(status)),
+            false => char::from(status).to_string(),
+        }
+    }
+
+    // What a diff line starts with: "+" or "-" if it's added or removed, or "[+] " and "[-] ", which
+    // read out better, when there's no color to go by.
+    pub fn line_marker(&self, kind: LineKind) -> &'static str {
+        match (kind, self.no_color) {
+            (LineKind::Added, false) => "+",
+            (LineKind::Removed, false) => "-",
+            (_, false) => " ",
+            (LineKind::Added, true) => "[+] ",
+            (LineKind::Removed, true) => "[-] ",
+            (_, true) => "    ",
+        }
+    }
+
+    // The color of a token in a line otherwise drawn in `plain`.
+    pub fn token(&self, token: Token, plain: Color) -> Color {
+        match token {
+            Token::Plain => plain,
+            Token::Keyword => self.keyword,
+            Token::String => self.string,
+            Token::Comment => self.comment,
+            Token::Number => self.number,
+        }
+    }
+
+    pub fn border(&self, focused: bool) -> Style {
+        match focused {
+            true => Style::default().fg(self.focused_border),
+            false => Style::default().fg(self.border),
+        }
+    }
+
+    // The color a `[theme.colors]` key names, e.g. `status-added` or `selection`.
+    fn element(&mut self, name: &str) -> Option<&mut Color> {
+        if let Some(status) = name.strip_prefix("status-") {
+            return self
+                .statuses
+                .iter_mut()
+                .find(|(s, _)| status_name(*s) == status)
+                .map(|(_, c)| c);
+        }
+        match name {
+            "text" => Some(&mut self.text),
+            "diff-added" => Some(&mut self.diff_added),
+            "diff-removed" => Some(&mut self.diff_removed),
+            "word-added" => Some(&mut self.word_added),
+            "word-removed" => Some(&mut self.word_removed),
+            "whitespace-error" => Some(&mut self.whitespace_error),
+            "hunk-header" => Some(&mut self.hunk_header),
+            "selection" => Some(&mut self.selection),
+            "border" => Some(&mut self.border),
+            "focused-border" => Some(&mut self.focused_border),
+            "matched" => Some(&mut self.matched),
+            "marked" => Some(&mut self.marked),
+            "syntax-keyword" => Some(&mut self.keyword),
+            "syntax-string" => Some(&mut self.string),
+            "syntax-comment" => Some(&mut self.comment),
+            "syntax-number" => Some(&mut self.number),
+            _ => None,
+        }
+    }
+}
+
+// Drawn over the whole of a frame without colors: takes the colors off everything drawn, and shows
+// what its background set apart, like changed words or selected lines, in reverse video instead.
+pub struct Uncolored;
+
+impl Widget for Uncolored {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let cell = buf.get_mut(x, y);
+                if cell.bg != Color::Reset {
+                    cell.modifier.insert(Modifier::REVERSED);
+                }
+                cell.fg = Color::Reset;
+                cell.bg = Color::Reset;
+            }
+        }
+    }
+}
+
+// The git color settings the theme follows, and the elements they color. Git colors staged and
+// unstaged changes rather than statuses, which comes closest to added and modified files. Later
+// keys win, so `color.diff.context` overrides its old name `color.diff.plain`.
+const GIT_COLORS: [(&str, &str); 10] = [
+    ("color.status.added", "status-added"),
+    ("color.status.updated", "status-added"),
+    ("color.status.changed", "status-modified"),
+    ("color.status.untracked", "status-untracked"),
+    ("color.status.unmerged", "status-conflicted"),
+    ("color.diff.new", "diff-added"),
+    ("color.diff.old", "diff-removed"),
+    ("color.diff.frag", "hunk-header"),
+    ("color.diff.plain", "text"),
+    ("color.diff.context", "text"),
+];
+
+// The foreground of a git color value like `bold red`, `brightgreen black` or `#ff8800`: the first
+// color in it, ignoring attributes. `normal` keeps the theme's color, as it does git's.
+fn git_color(value: &str) -> Option<Color> {
+    for word in value.split_whitespace() {
+        let word = word.to_lowercase();
+        if word == "normal" {
+            return None;
+        }
+        if let Some(hex) = word.strip_prefix('#').filter(|h| h.len() == 3) {
+            // `#f80` is short for `#ff8800`.
+            let long: String = hex.chars().flat_map(|c| [c, c]).collect();
+            return parse_color(&format!("#{}", long));
+        }
+        let color = match word.strip_prefix("bright") {
+            Some(base) => match base {
+                "black" => Some(Color::DarkGray),
+                "red" => Some(Color::LightRed),
+                "green" => Some(Color::LightGreen),
+                "yellow" => Some(Color::LightYellow),
+                "blue" => Some(Color::LightBlue),
+                "magenta" => Some(Color::LightMagenta),
+                "cyan" => Some(Color::LightCyan),
+                "white" => Some(Color::White),
+                _ => None,
+            },
+            // Git's `white` is the terminal's plain white, which is tui's `Gray`.
+            None if word == "white" => Some(Color::Gray),
+            None => parse_color(&word),
+        };
+        if color.is_some() {
+            return color;
+        }
+    }
+    None
+}
+
+fn status_name(status: Status) -> &'static str {
+    match status {
+        Status::Unmodified => "unmodified",
+        Status::Added => "added",
+        Status::Deleted => "deleted",
+        Status::Modified => "modified",
+        Status::Renamed => "renamed",
+        Status::Copied => "copied",
+        Status::Ignored => "ignored",
+        Status::Untracked => "untracked",
+        Status::Conflicted => "conflicted",
+        Status::Typechange => "typechange",
+        Status::Unreadable => "unreadable",
+    }
+}
+
+// A color name (`red`, `light-green`, `default` for the terminal's own), `#rrggbb`, or an index
+// into the 256-color palette.
+pub fn parse_color(s: &str) -> Option<Color> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    if let Ok(index) = s.parse::<u8>() {
+        return Some(Color::Indexed(index));
+    }
+    let color = match s.to_lowercase().replace(['_', ' '], "-").as_str() {
+        "default" | "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "dark-gray" | "dark-grey" => Color::DarkGray,
+        "light-red" => Color::LightRed,
+        "light-green" => Color::LightGreen,
+        "light-yellow" => Color::LightYellow,
+        "light-blue" => Color::LightBlue,
+        "light-magenta" => Color::LightMagenta,
+        "light-cyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
@@ -0,0 +1,156 @@
+use std::{env, fs, path::PathBuf};
+
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    Frame,
+};
+
+use crate::{
+    keymap::{Action, Keymap},
+    popup,
+};
+
+// A step of the tour: what it's about, and how it's done, with a `{}` for the keys of each of its
+// actions in turn.
+struct Step {
+    title: &'static str,
+    text: &'static str,
+    actions: &'static [Action],
+}
+
+const STEPS: [Step; 5] = [
+    Step {
+        title: "Move between the changed files",
+        text:
+            "{} and {} select the next and the previous file. The files with unstaged changes are \
+               listed first.",
+        actions: &[Action::Down, Action::Up],
+    },
+    Step {
+        title: "Look at a file's diff",
+        text:
+            "The selected file's diff is shown beside the list. {} moves into it, where {} and {} \
+               go from hunk to hunk, and {} goes back to the list.",
+        actions: &[
+            Action::FocusDiff,
+            Action::NextHunk,
+            Action::PreviousHunk,
+            Action::Unselect,
+        ],
+    },
+    Step {
+        title: "Stage a hunk",
+        text: "In the diff, {} stages the selected hunk on its own. In the list, it stages the \
+               whole file, and {} unstages it.",
+        actions: &[Action::Stage, Action::Unstage],
+    },
+    Step {
+        title: "Look over what's staged",
+        text: "{} switches between the unstaged changes and the staged ones, which are what the \
+               next commit holds.",
+        actions: &[Action::ToggleView],
+    },
+    Step {
+        title: "Commit",
+        text:
+            "{} opens the editor for the commit message, and ctrl-s commits. {} lists every key, \
+               and {} finds any action by name, this tour's `show-tour` included.",
+        actions: &[Action::Commit, Action::ShowHelp, Action::CommandPalette],
+    },
+];
+
+pub enum TourEvent {
+    // The tour was finished or skipped.
+    Close,
+    Pending,
+}
+
+// The walk through the core workflow shown over the lists on the first launch, and with
+// `show-tour`: one step at a time, with the keys that do each thing as they're bound.
+pub struct Tour {
+    step: usize,
+}
+
+impl Tour {
+    pub fn new() -> Tour {
+        Tour { step: 0 }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> TourEvent {
+        match code {
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char(' ') => {
+                if self.step + 1 == STEPS.len() {
+                    return TourEvent::Close;
+                }
+                self.step += 1;
+            }
+            KeyCode::Left | KeyCode::Backspace => self.step = self.step.saturating_sub(1),
+            KeyCode::Esc | KeyCode::Char('q') => return TourEvent::Close,
+            _ => {}
+        }
+        TourEvent::Pending
+    }
+}
+
+// In the user's state directory rather than the repository's, as it's the user who's been shown
+// around: `$XDG_STATE_HOME/git-istage`, or `~/.local/state/git-istage`.
+fn seen_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(base.join("git-istage").join("tour-seen"))
+}
+
+// Whether the tour was shown on an earlier launch. Without a home to keep that in, it's taken
+// as seen, rather than shown every time.
+pub fn seen() -> bool {
+    seen_path().is_none_or(|path| path.exists())
+}
+
+pub fn mark_seen() -> anyhow::Result<()> {
+    if let Some(path) = seen_path() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, "")?;
+    }
+    Ok(())
+}
+
+pub fn render_tour<B: Backend>(f: &mut Frame<B>, tour: &Tour, keymap: &Keymap) {
+    let step = &STEPS[tour.step];
+    let key_style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    for (i, part) in step.text.split("{}").enumerate() {
+        if let Some(&action) = i.checked_sub(1).and_then(|i| step.actions.get(i)) {
+            let key = match keymap.keys(action).first() {
+                Some(key) => key.to_string(),
+                // Unbound in the config, but still there to run by name.
+                None => format!(":{}", action.name()),
+            };
+            spans.push(Span::styled(key, key_style));
+        }
+        spans.push(Span::raw(part));
+    }
+    let lines = vec![
+        Spans::from(Span::styled(
+            format!("{}/{}  {}", tour.step + 1, STEPS.len(), step.title),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::default(),
+        Spans::from(spans),
+    ];
+    let footer = match tour.step + 1 == STEPS.len() {
+        true => "[enter] done   [left] back",
+        false => "[enter] next   [left] back   [esc] skip the tour",
+    };
+    popup::render_lines(f, " Welcome to git-istage ", lines, footer);
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeSet, HashMap};
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use git_istage_rs::{
+    paths::RepoPath,
+    status::{Status, StatusEntry},
+};
+
+use crate::{filter, highlighted, mouse, push_line_counts, theme::Theme, StatefulList};
+
+// A line of the tree: a directory, or an entry as an index into the list's items.
+pub enum Row {
+    Dir {
+        path: RepoPath,
+        depth: usize,
+        collapsed: bool,
+        // How many entries there are beneath it.
+        count: usize,
+    },
+    Entry {
+        index: usize,
+        depth: usize,
+    },
+}
+
+impl Row {
+    fn key<'a>(&'a self, entries: &'a [StatusEntry]) -> &'a RepoPath {
+        match self {
+            Row::Dir { path, .. } => path,
+            Row::Entry { index, .. } => &entries[*index].new_file,
+        }
+    }
+}
+
+// The file list grouped into its directories, which can be collapsed to hide what's beneath them.
+// Entries are selected through the list as usual; a selected directory leaves the list without a
+// selection.
+pub struct Tree {
+    collapsed: BTreeSet<RepoPath>,
+    // The path of the selected directory, if it's a directory rather than an entry.
+    dir: Option<RepoPath>,
+    pub state: ListState,
+    // The row at the top, as it was last drawn.
+    pub offset: usize,
+}
+
+impl Tree {
+    pub fn new() -> Tree {
+        Tree {
+            collapsed: BTreeSet::new(),
+            dir: None,
+            state: ListState::default(),
+            offset: 0,
+        }
+    }
+
+    // A tree with `collapsed` collapsed, as the last session left it.
+    pub fn with_collapsed(collapsed: Vec<RepoPath>) -> Tree {
+        Tree {
+            collapsed: collapsed.into_iter().collect(),
+            ..Tree::new()
+        }
+    }
+
+    pub fn collapsed(&self) -> Vec<RepoPath> {
+        self.collapsed.iter().cloned().collect()
+    }
+
+    // The rows for the entries shown in `list`, with the selected one found again (and the list's
+    // selection brought in line with it, as a refresh may have moved it).
+    pub fn rows(&mut self, list: &mut StatefulList<StatusEntry>) -> Vec<Row> {
+        let rows = rows(&list.items, &self.collapsed);
+        let key = match (&self.dir, list.current()) {
+            (Some(dir), _) => Some(dir),
+            (None, Some(entry)) => Some(&entry.new_file),
+            (None, None) => None,
+        };
+        let found = key.and_then(|k| rows.iter().position(|r| r.key(&list.items) == k));
+        match (found, self.state.selected()) {
+            (Some(i), _) => self.select(list, &rows, i),
+            // What was selected went away: stay where it was.
+            (None, Some(i)) if !rows.is_empty() => self.select(list, &rows, i.min(rows.len() - 1)),
+            _ => self.state.select(None),
+        }
+        rows
+    }
+
+    // The directory selected, if it's a directory rather than an entry.
+    pub fn current_dir(&mut self, list: &mut StatefulList<StatusEntry>) -> Option<RepoPath> {
+        let rows = self.rows(list);
+        match self.state.selected().and_then(|i| rows.get(i)) {
+            Some(Row::Dir { path, .. }) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn next(&mut self, list: &mut StatefulList<StatusEntry>) {
+        let rows = self.rows(list);
+        if rows.is_empty() {
+            return;
+        }
+        let i = self.state.selected().map_or(0, |i| (i + 1) % rows.len());
+        self.select(list, &rows, i);
+    }
+
+    pub fn previous(&mut self, list: &mut StatefulList<StatusEntry>) {
+        let rows = self.rows(list);
+        if rows.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(0) | None => rows.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.select(list, &rows, i);
+    }
+
+    // Selects the row at `index`, as when it's clicked. Returns whether there is one.
+    pub fn select_row(&mut self, list: &mut StatefulList<StatusEntry>, index: usize) -> bool {
+        let rows = self.rows(list);
+        if index >= rows.len() {
+            return false;
+        }
+        self.select(list, &rows, index);
+        true
+    }
+
+    // Collapses or expands the selected directory.
+    pub fn toggle(&mut self, list: &mut StatefulList<StatusEntry>) {
+        if let Some(dir) = self.current_dir(list) {
+            if !self.collapsed.remove(&dir) {
+                self.collapsed.insert(dir);
+            }
+        }
+    }
+
+    // Collapses the selected directory, or selects the directory of the selected entry.
+    pub fn collapse(&mut self, list: &mut StatefulList<StatusEntry>) {
+        if let Some(dir) = self.current_dir(list) {
+            self.collapsed.insert(dir);
+            return;
+        }
+        let parent = match list.current() {
+            Some(entry) if !entry.dir().is_empty() => entry.dir(),
+            _ => return,
+        };
+        self.dir = Some(parent);
+        list.unselect();
+    }
+
+    fn select(&mut self, list: &mut StatefulList<StatusEntry>, rows: &[Row], i: usize) {
+        self.state.select(Some(i));
+        match &rows[i] {
+            Row::Dir { path, .. } => {
+                self.dir = Some(path.clone());
+                list.unselect();
+            }
+            Row::Entry { index, .. } => {
+                self.dir = None;
+                list.state.select(Some(*index));
+            }
+        }
+    }
+}
+
+// The directories of `entries` each followed by what's beneath them, in the order of their paths,
+// leaving out what's under a collapsed directory.
+fn rows(entries: &[StatusEntry], collapsed: &BTreeSet<RepoPath>) -> Vec<Row> {
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by(|&a, &b| entries[a].new_file.cmp(&entries[b].new_file));
+
+    // A collapsed untracked directory, "dir/", is an entry in its parent rather than a directory.
+    fn path(entry: &StatusEntry) -> &[u8] {
+        let path = entry.new_file.as_bytes();
+        path.strip_suffix(b"/").unwrap_or(path)
+    }
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for entry in entries {
+        let path = path(entry);
+        for (i, _) in path.iter().enumerate().filter(|&(_, &b)| b == b'/') {
+            *counts.entry(&path[..i]).or_default() += 1;
+        }
+    }
+
+    let mut rows = Vec::new();
+    // The directories the previous entry was in, and how deep the collapsed one among them is.
+    let mut open: Vec<&[u8]> = Vec::new();
+    let mut hidden_at: Option<usize> = None;
+    for i in order {
+        let dirs: Vec<&[u8]> = path(&entries[i]).split(|&b| b == b'/').collect();
+        let dirs = &dirs[..dirs.len() - 1];
+        let common = open.iter().zip(dirs).take_while(|(a, b)| a == b).count();
+        open.truncate(common);
+        if hidden_at.is_some_and(|d| d >= open.len()) {
+            hidden_at = None;
+        }
+        for dir in &dirs[common..] {
+            open.push(dir);
+            if hidden_at.is_some() {
+                continue;
+            }
+            let dir_path = open.join(&b'/');
+            let dir_path = RepoPath::from(dir_path);
+            let is_collapsed = collapsed.contains(&dir_path);
+            rows.push(Row::Dir {
+                count: counts.get(dir_path.as_bytes()).copied().unwrap_or(0),
+                depth: open.len() - 1,
+                collapsed: is_collapsed,
+                path: dir_path,
+            });
+            if is_collapsed {
+                hidden_at = Some(open.len() - 1);
+            }
+        }
+        if hidden_at.is_none() {
+            rows.push(Row::Entry {
+                index: i,
+                depth: open.len(),
+            });
+        }
+    }
+    rows
+}
+
+pub fn render_tree<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    list: &mut StatefulList<StatusEntry>,
+    tree: &mut Tree,
+    theme: &Theme,
+) {
+    let rows = tree.rows(list);
+    let marking = !list.marked.is_empty();
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|row| {
+            let mut spans = Vec::new();
+            if marking {
+                let marked = match row {
+                    Row::Entry { index, .. } => list.is_marked(&list.items[*index]),
+                    Row::Dir { .. } => false,
+                };
+                spans.push(match marked {
+                    true => Span::styled("* ", Style::default().fg(theme.marked)),
+                    false => Span::raw("  "),
+                });
+            }
+            match row {
+                Row::Dir {
+                    path,
+                    depth,
+                    collapsed,
+                    count,
+                } => {
+                    let path = path.as_bytes();
+                    let name = RepoPath::from(path.rsplit(|&b| b == b'/').next().unwrap_or(path));
+                    let (arrow, suffix) = match collapsed {
+                        true => ("▸", format!(" ({})", count)),
+                        false => ("▾", String::new()),
+                    };
+                    spans.push(Span::styled(
+                        format!("{}{} {}/{}", "  ".repeat(*depth), arrow, name, suffix),
+                        Style::default().fg(Color::LightBlue),
+                    ));
+                }
+                Row::Entry { index, depth } => {
+                    let entry = &list.items[*index];
+                    let path = entry.new_file.escaped();
+                    let dir = entry.dir();
+                    let name_start = dir.escaped().len() + usize::from(!dir.is_empty());
+                    let marker = theme.status_marker(entry.status);
+                    let text = format!("{} {}", marker, &path[name_start..]);
+                    // Only matches within the name are shown, as that's all of the path there is.
+                    let matched: Vec<usize> = filter::matches(&list.filter, &path)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|&i| i >= name_start)
+                        .map(|i| i - name_start + marker.len() + 1)
+                        .collect();
+                    spans.push(Span::raw("  ".repeat(*depth)));
+                    spans.extend(highlighted(
+                        &text,
+                        &matched,
+                        theme.entry(entry.status),
+                        theme,
+                    ));
+                    if let Status::Renamed = entry.status {
+                        spans.push(Span::styled(
+                            format!(" <- {}", entry.old_file),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    if entry.submodule {
+                        spans.push(Span::styled(
+                            " [submodule]",
+                            Style::default().fg(Color::Magenta),
+                        ));
+                    }
+                    push_line_counts(&mut spans, entry, area.width, theme);
+                }
+            }
+            ListItem::new(Spans::from(spans)).style(Style::default().fg(theme.text))
+        })
+        .collect();
+
+    let height = area.height as usize;
+    mouse::fit(&mut tree.state, &mut tree.offset, items.len(), height);
+    let widget = List::new(items).highlight_style(theme.selected());
+    f.render_stateful_widget(widget, area, &mut tree.state);
+    crate::show_selection(f, area, tree.state.selected(), tree.offset, theme);
+}
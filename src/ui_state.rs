@@ -0,0 +1,52 @@
+use std::{fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use git_istage_rs::{config::ListView, git::Location, paths::RepoPath};
+
+// In the repository's git directory, so that each clone keeps its own.
+fn state_path(location: &Location) -> PathBuf {
+    location.git_dir().join("istage-state.json")
+}
+
+// How the last session left the lists, written to `.git/istage-state.json` on quitting and put
+// back on the next launch if `list.restore` is set.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct UiState {
+    pub view: ListView,
+    pub combined: bool,
+    pub unstaged: ListPosition,
+    pub staged: ListPosition,
+    pub filter: String,
+    // The collapsed directories of the tree, if the lists were shown as one.
+    pub tree: Option<Vec<RepoPath>>,
+    // The collapsed untracked directories that were expanded.
+    pub expanded_dirs: Vec<RepoPath>,
+    pub diff_focused: bool,
+    // The hunk selected in the diff of the selected entry, which it opens at again.
+    pub hunk: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ListPosition {
+    // The path of the selected entry.
+    pub selected: Option<RepoPath>,
+    // The row at the top.
+    pub offset: usize,
+}
+
+// The state the last session left, or the default one if there's none, or none that can be read.
+pub fn load(location: &Location) -> UiState {
+    fs::read_to_string(state_path(location))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(location: &Location, state: &UiState) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(state)?;
+    fs::write(state_path(location), json + "\n")?;
+    Ok(())
+}
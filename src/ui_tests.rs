@@ -0,0 +1,1016 @@
+// Renders the app headlessly on a `TestBackend` after a script of keys and clicks, and compares the
+// last frame with a snapshot in `src/snapshots`. Run with `UPDATE_SNAPSHOTS=1` to accept new frames.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use git2::{IndexAddOption, Repository, RepositoryInitOptions, Signature, Time};
+use tui::{
+    backend::TestBackend,
+    buffer::Buffer,
+    style::{Color, Modifier},
+    Terminal,
+};
+
+use git_istage_rs::{
+    config::{Config, ListConfig, ListView, ShowUntracked},
+    git::Location,
+};
+
+use crate::{
+    events::{EventSource, ScriptedEvents, Unattended},
+    exec::Exec,
+    external_diff::{self, ExternalDiff},
+    opening::Opening,
+    run_app, App,
+};
+
+const WIDTH: u16 = 80;
+const HEIGHT: u16 = 16;
+
+struct Fixture {
+    dir: PathBuf,
+}
+
+impl Fixture {
+    // A repository with `committed` files in its only commit, and then `changed` in the worktree.
+    fn new(name: &str, committed: &[(&str, &str)], changed: &[(&str, &str)]) -> Fixture {
+        let fixture = Fixture::unborn(name, committed);
+        let dir = &fixture.dir;
+        let repo = Repository::open(dir).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        // A fixed time keeps the commit id the same from run to run.
+        let sig = Signature::new("Test", "test@example.com", &Time::new(0, 0)).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+
+        for (path, content) in changed {
+            write(dir, path, content);
+        }
+        fixture
+    }
+
+    // A repository with no commits yet, and `files` in the worktree.
+    fn unborn(name: &str, files: &[(&str, &str)]) -> Fixture {
+        // Named after the test, as the status bar shows the repository's name.
+        let dir = env::temp_dir()
+            .join(format!("git-istage-ui-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        Repository::init_opts(&dir, RepositoryInitOptions::new().initial_head("main")).unwrap();
+        for (path, content) in files {
+            write(&dir, path, content);
+        }
+        Fixture { dir }
+    }
+
+    // The repository, as the app finds it from its directory.
+    fn location(&self) -> Location {
+        Location::discover(&self.dir).unwrap()
+    }
+
+    // The last frame after typing `keys`.
+    fn render(&self, keys: &str) -> String {
+        self.render_in(keys, WIDTH, HEIGHT)
+    }
+
+    // The last frame after typing `keys`, in a terminal `width` columns by `height` rows.
+    fn render_in(&self, keys: &str, width: u16, height: u16) -> String {
+        self.render_with(Config::default(), keys, width, height)
+    }
+
+    // Like `render_in`, with `config` rather than the defaults.
+    fn render_with(&self, config: Config, keys: &str, width: u16, height: u16) -> String {
+        let terminal = self.run(config, keys, width, height);
+        frame_text(terminal.backend().buffer())
+    }
+
+    // The terminal the app was drawn on, once `keys` are typed.
+    fn run(&self, config: Config, keys: &str, width: u16, height: u16) -> Terminal<TestBackend> {
+        run_at(self.location(), config, keys, width, height)
+    }
+
+    // Runs the `--exec` commands in `script` as `main` does, which must end with `quit`.
+    fn exec(&self, config: Config, script: &str) -> anyhow::Result<()> {
+        self.exec_with(config, Exec::parse(script).unwrap())
+    }
+
+    // Like `exec`, with the commands already parsed, e.g. with `yes` set as `--yes` does.
+    fn exec_with(&self, mut config: Config, exec: Exec) -> anyhow::Result<()> {
+        config.refresh.watch = false;
+        let mut app = App::new(self.location(), config).unwrap();
+        app.exec = exec;
+        assert!(app.exec.unattended);
+        let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+        run_app(&mut terminal, app, Duration::ZERO, &mut Unattended)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// The terminal the app on the repository at `location` was drawn on, once `keys` are typed.
+fn run_at(
+    location: Location,
+    mut config: Config,
+    keys: &str,
+    width: u16,
+    height: u16,
+) -> Terminal<TestBackend> {
+    config.refresh.watch = false;
+    let app = App::new(location, config).unwrap();
+
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    let mut events = ScriptedEvents::new(keys).unwrap();
+    run_app(&mut terminal, app, Duration::ZERO, &mut events).unwrap();
+    terminal
+}
+
+// Scripted keys, with `change` made to the repository behind the app's back before the first.
+struct BehindTheBack<F: FnOnce()> {
+    events: ScriptedEvents,
+    change: Option<F>,
+}
+
+impl<F: FnOnce()> EventSource for BehindTheBack<F> {
+    fn next(&mut self, timeout: Duration) -> anyhow::Result<Option<crossterm::event::Event>> {
+        if let Some(change) = self.change.take() {
+            change();
+        }
+        self.events.next(timeout)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.events.is_closed()
+    }
+
+    fn waits_for_loading(&self) -> bool {
+        true
+    }
+}
+
+fn write(dir: &Path, path: &str, content: &str) {
+    let path = dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn frame_text(buffer: &Buffer) -> String {
+    let mut text = String::new();
+    for y in 0..buffer.area.height {
+        let line: String = (0..buffer.area.width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+fn assert_snapshot(name: &str, frame: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/snapshots")
+        .join(format!("{}.txt", name));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() || !path.exists() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, frame).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap();
+    assert!(
+        expected == frame,
+        "the frame differs from {}:\n{}",
+        path.display(),
+        frame
+    );
+}
+
+const LINES: &str = "one\ntwo\nthree\n";
+
+#[test]
+fn file_list_and_diff() {
+    let fixture = Fixture::new(
+        "file-list",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("new.txt", "new\n")],
+    );
+    assert_snapshot("file_list_and_diff", &fixture.render(""));
+}
+
+#[test]
+fn staged_view_after_staging() {
+    let fixture = Fixture::new(
+        "staged",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("new.txt", "new\n")],
+    );
+    assert_snapshot("staged_view_after_staging", &fixture.render("s t"));
+}
+
+#[test]
+fn discard_confirmation() {
+    let fixture = Fixture::new("discard", &[("a.txt", LINES)], &[("a.txt", "changed\n")]);
+    assert_snapshot("discard_confirmation", &fixture.render("r"));
+}
+
+#[test]
+fn help_overlay() {
+    let fixture = Fixture::new("help", &[("a.txt", LINES)], &[]);
+    assert_snapshot("help_overlay", &fixture.render("?"));
+}
+
+#[test]
+fn tree_with_a_collapsed_directory() {
+    let fixture = Fixture::new(
+        "tree",
+        &[("a.txt", LINES)],
+        &[
+            ("a.txt", "changed\n"),
+            ("src/main.rs", "main\n"),
+            ("src/ui/list.rs", "list\n"),
+            ("src/ui/view.rs", "view\n"),
+        ],
+    );
+    assert_snapshot(
+        "tree_with_a_collapsed_directory",
+        &fixture.render("` down down down enter"),
+    );
+}
+
+#[test]
+fn combined_view_after_staging() {
+    let fixture = Fixture::new(
+        "combined",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("new.txt", "new\n")],
+    );
+    assert_snapshot("combined_view_after_staging", &fixture.render("g s"));
+}
+
+#[test]
+fn mouse_stages_a_hunk_and_shows_the_staged_list() {
+    let fixture = Fixture::new(
+        "mouse",
+        &[("a.txt", LINES), ("b.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("b.txt", "1\ntwo\nthree\n")],
+    );
+    // Selects b.txt, clicks the header of its hunk, then the count of staged files.
+    assert_snapshot(
+        "mouse_stages_a_hunk_and_shows_the_staged_list",
+        &fixture.render("click@2,2 click@40,2 click@14,0"),
+    );
+}
+
+#[test]
+fn too_small_terminal() {
+    let fixture = Fixture::new("small", &[("a.txt", LINES)], &[("a.txt", "changed\n")]);
+    assert_snapshot("too_small_terminal", &fixture.render_in("", 30, 6));
+}
+
+#[test]
+fn smallest_terminal_with_popups() {
+    let fixture = Fixture::new("smallest", &[("a.txt", LINES)], &[("a.txt", "changed\n")]);
+    for keys in ["", "?", "r", ":"] {
+        fixture.render_in(keys, 40, 8);
+    }
+}
+
+#[test]
+fn ignore_menu() {
+    let fixture = Fixture::new(
+        "ignore-menu",
+        &[("a.txt", LINES)],
+        &[("logs/run.log", "x\n")],
+    );
+    assert_snapshot("ignore_menu", &fixture.render("G down"));
+}
+
+#[test]
+fn ignoring_by_extension() {
+    let fixture = Fixture::new(
+        "ignore",
+        &[("a.txt", LINES)],
+        &[("logs/run.log", "x\n"), ("other.log", "y\n")],
+    );
+    assert_snapshot("ignoring_by_extension", &fixture.render("G down enter"));
+    assert_eq!(
+        fs::read_to_string(fixture.dir.join(".gitignore")).unwrap(),
+        "*.log\n"
+    );
+}
+
+#[test]
+fn showing_ignored_files() {
+    let fixture = Fixture::new(
+        "show-ignored",
+        &[(".gitignore", "*.log\n")],
+        &[("logs/run.log", "x\n"), ("new.txt", "y\n")],
+    );
+    assert_snapshot("showing_ignored_files", &fixture.render("ctrl-g"));
+}
+
+#[test]
+fn blame_overlay() {
+    let fixture = Fixture::new(
+        "blame",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n")],
+    );
+    assert_snapshot("blame_overlay", &fixture.render("b"));
+}
+
+#[test]
+fn hunk_discard_confirmation() {
+    let fixture = Fixture::new(
+        "hunk-discard",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n")],
+    );
+    assert_snapshot("hunk_discard_confirmation", &fixture.render("right r"));
+}
+
+#[test]
+fn jumping_back_a_change_wraps_to_the_last_hunk() {
+    let lines: Vec<String> = (1..=12).map(|i| format!("line {}\n", i)).collect();
+    let changed = |name: &str| {
+        let mut changed = lines.clone();
+        changed[1] = format!("{} 2\n", name);
+        changed[10] = format!("{} 11\n", name);
+        changed.concat()
+    };
+    let fixture = Fixture::new(
+        "jump",
+        &[("a.txt", &lines.concat()), ("b.txt", &lines.concat())],
+        &[("a.txt", &changed("a")), ("b.txt", &changed("b"))],
+    );
+    assert_snapshot(
+        "jumping_back_a_change_wraps_to_the_last_hunk",
+        &fixture.render("["),
+    );
+}
+
+// A repository whose commits start from a template and show the staged diff, with a.txt changed.
+fn commit_template_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(
+        name,
+        &[("a.txt", LINES), ("template.txt", "Subject\n\n# Why?\n")],
+        &[("a.txt", "one\n2\nthree\n")],
+    );
+    let mut config = Repository::open(&fixture.dir).unwrap().config().unwrap();
+    config.set_str("commit.template", "template.txt").unwrap();
+    config.set_bool("commit.verbose", true).unwrap();
+    config.set_str("user.name", "Test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    fixture
+}
+
+#[test]
+fn commit_editor_with_template_and_diff() {
+    let fixture = commit_template_fixture("commit-template");
+    assert_snapshot(
+        "commit_editor_with_template_and_diff",
+        &fixture.render("s c"),
+    );
+}
+
+#[test]
+fn commit_message_is_cleaned_up() {
+    let fixture = commit_template_fixture("commit-cleanup");
+    fixture.render("s c N e w space end ctrl-s");
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("New Subject\n"));
+}
+
+#[test]
+fn fixup_of_a_recent_commit() {
+    let fixture = commit_template_fixture("fixup");
+    assert_snapshot("fixup_picker", &fixture.render("s ctrl-f"));
+    let screen = fixture.render("s ctrl-f enter");
+    assert!(screen.contains("Fold it in"));
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("fixup! initial\n"));
+}
+
+#[test]
+fn count_before_a_key_repeats_it() {
+    let lines: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+    let changed = lines
+        .replace("line 2\n", "two\n")
+        .replace("line 10\n", "ten\n")
+        .replace("line 18\n", "eighteen\n");
+    let files: Vec<(String, String)> = (1..=5)
+        .map(|i| (format!("{}.txt", i), format!("file {}\n", i)))
+        .collect();
+    let committed: Vec<(&str, &str)> = files
+        .iter()
+        .map(|(path, content)| (path.as_str(), content.as_str()))
+        .chain([("a.txt", lines.as_str())])
+        .collect();
+    let changed: Vec<(String, String)> = files
+        .iter()
+        .map(|(path, content)| (path.clone(), content.replace("file", "changed")))
+        .chain([(String::from("a.txt"), changed)])
+        .collect();
+    let changed: Vec<(&str, &str)> = changed
+        .iter()
+        .map(|(path, content)| (path.as_str(), content.as_str()))
+        .collect();
+    let fixture = Fixture::new("count", &committed, &changed);
+
+    let screen = fixture.render("3 down");
+    assert!(screen.contains("+changed 4"));
+    // Moving wraps around, as many times as it takes.
+    let screen = fixture.render("1 4 down");
+    assert!(screen.contains("+changed 3"));
+    assert_snapshot("count_of_hunks", &fixture.render("5 down right 2 j"));
+    // `esc` drops the count rather than repeating anything.
+    let screen = fixture.render("3 esc down");
+    assert!(screen.contains("+changed 2"));
+}
+
+#[test]
+fn split_hunk() {
+    let lines: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+    let changed = lines
+        .replace("line 3\n", "three\n")
+        .replace("line 6\n", "six\n");
+    let fixture = Fixture::new("split", &[("a.txt", &lines)], &[("a.txt", &changed)]);
+    assert_snapshot("split_hunk", &fixture.render("right | down"));
+}
+
+#[test]
+fn status_bar_in_a_linked_worktree() {
+    let fixture = Fixture::new("app", &[("a.txt", LINES)], &[]);
+    let dir = fixture.dir.join("hotfix");
+    let repo = Repository::open(&fixture.dir).unwrap();
+    repo.worktree("hotfix", &dir, None).unwrap();
+    write(&dir, "a.txt", "one\n2\nthree\n");
+    let location = Location::discover(&dir).unwrap();
+    let terminal = run_at(location, Config::default(), "", WIDTH, HEIGHT);
+    assert_snapshot(
+        "status_bar_in_a_linked_worktree",
+        &frame_text(terminal.backend().buffer()),
+    );
+}
+
+#[test]
+fn large_diff_is_only_shown_once_asked_for() {
+    let lines: String = (1..=20_001).map(|i| format!("line {}\n", i)).collect();
+    let fixture = Fixture::new("large", &[], &[("big.txt", &lines)]);
+    assert_snapshot("large_diff_placeholder", &fixture.render(""));
+    assert_snapshot("large_diff_loaded_anyway", &fixture.render("enter"));
+}
+
+#[test]
+fn repository_config_overrides_the_users() {
+    let fixture = Fixture::new(
+        "layered",
+        &[("a.txt", LINES)],
+        &[
+            ("a.txt", "one\n2\nthree\n"),
+            (
+                ".git-istage.toml",
+                "[list]\nview = \"staged\"\n[layout]\nfooter = false\n",
+            ),
+        ],
+    );
+    let user = fixture.dir.join("user.toml");
+    write(
+        &fixture.dir,
+        "user.toml",
+        "[list]\nview = \"unstaged\"\n[layout]\nstatus-bar = false\nfooter = true\n",
+    );
+    let overrides = [String::from("list.combined=true")];
+    let config = Config::load(Some(&fixture.location()), Some(&user), &overrides).unwrap();
+    assert!(config.list.view == ListView::Staged);
+    assert!(!config.layout.footer && !config.layout.status_bar && config.list.combined);
+    assert_snapshot(
+        "repository_config_overrides_the_users",
+        &fixture.render_with(config, "", WIDTH, HEIGHT),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn entry_info_of_a_mode_change() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = Fixture::new("mode", &[("run.sh", "echo hi\n")], &[]);
+    let path = fixture.dir.join("run.sh");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_snapshot("entry_info_of_a_mode_change", &fixture.render("E"));
+}
+
+#[cfg(unix)]
+#[test]
+fn diff_of_a_symlink() {
+    let fixture = Fixture::new("symlink", &[("a.txt", "a\n")], &[]);
+    std::os::unix::fs::symlink("a.txt", fixture.dir.join("link")).unwrap();
+    assert_snapshot("diff_of_a_symlink", &fixture.render(""));
+}
+
+#[test]
+fn state_restored_from_the_last_session() {
+    let fixture = Fixture::new(
+        "restore",
+        &[("a.txt", LINES), ("b.txt", LINES), ("src/c.txt", LINES)],
+        &[("a.txt", "a\n"), ("b.txt", "b\n"), ("src/c.txt", "c\n")],
+    );
+    fixture.render("` down down down q");
+    assert!(fixture.dir.join(".git/istage-state.json").exists());
+
+    let mut config = Config::default();
+    config.refresh.watch = false;
+    let app = App::restored(fixture.location(), config).unwrap();
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    let mut events = ScriptedEvents::new("").unwrap();
+    run_app(&mut terminal, app, Duration::ZERO, &mut events).unwrap();
+    assert_snapshot(
+        "state_restored_from_the_last_session",
+        &frame_text(terminal.backend().buffer()),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn formatter_arguments_are_quoted_like_a_shell_would() {
+    let fixture = Fixture::new("formatter", &[("a.txt", LINES)], &[("a.txt", "b\na\n")]);
+    let mut config = Config::default();
+    config.formatters.insert(
+        String::from("*.txt"),
+        String::from(r#"sh -c 'sort -o "$0" "$0"'"#),
+    );
+    fixture.run(config, "s", WIDTH, HEIGHT);
+
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let index = repo.index().unwrap();
+    let entry = index.get_path(Path::new("a.txt"), 0).unwrap();
+    let blob = repo.find_blob(entry.id).unwrap();
+    assert_eq!(blob.content(), b"a\nb\n");
+}
+
+#[test]
+fn repository_opened_on_a_thread_of_its_own() {
+    let fixture = Fixture::new("opening", &[("src/a.txt", LINES)], &[("src/a.txt", "a\n")]);
+    let opening = Opening {
+        dir: fixture.dir.join("src"),
+        config_file: None,
+        overrides: vec![String::from("list.combined=true")],
+        pathspecs: vec![(String::from("."), Some(fixture.dir.join("src")))],
+        file: Some(fixture.dir.join("src/a.txt")),
+    };
+
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    let mut events = ScriptedEvents::new("").unwrap();
+    let opened = opening.open_showing(&mut terminal, &mut events).unwrap();
+    let opened = opened.expect("opened before quitting").unwrap();
+    assert!(opened.config.list.combined);
+    assert_eq!(opened.config.pathspecs, ["src"]);
+    assert_eq!(opened.file.as_deref(), Some("src/a.txt"));
+    assert!(frame_text(terminal.backend().buffer()).contains("Opening "));
+}
+
+#[test]
+fn clean_working_tree() {
+    let fixture = Fixture::new("clean", &[("a.txt", LINES)], &[]);
+    assert_snapshot("clean_working_tree", &fixture.render(""));
+}
+
+#[test]
+fn staging_the_first_commit_of_an_empty_repository() {
+    let fixture = Fixture::unborn("unborn", &[("a.txt", LINES)]);
+    assert_snapshot("nothing_left_to_stage", &fixture.render("s"));
+    assert_snapshot("staged_before_the_first_commit", &fixture.render("t"));
+}
+
+#[test]
+fn command_palette() {
+    let fixture = Fixture::new(
+        "palette",
+        &[("a.txt", LINES), ("b.rs", LINES)],
+        &[("a.txt", "changed\n"), ("b.rs", "changed\n")],
+    );
+    assert_snapshot(
+        "command_palette",
+        &fixture.render_in("ctrl-p s t a l", 80, 20),
+    );
+    assert_snapshot(
+        "command_palette_runs_a_command",
+        &fixture.render_in("ctrl-p s t a g e space * . r s enter", 80, 20),
+    );
+}
+
+#[test]
+fn pathspecs_limit_the_lists() {
+    let fixture = Fixture::new(
+        "pathspecs",
+        &[("src/a.rs", LINES), ("docs/b.md", LINES)],
+        &[("src/a.rs", "changed\n"), ("docs/b.md", "changed\n")],
+    );
+    let config = Config {
+        pathspecs: vec![String::from("src")],
+        ..Config::default()
+    };
+    assert_snapshot(
+        "pathspecs_limit_the_lists",
+        &fixture.render_with(config, "", WIDTH, HEIGHT),
+    );
+}
+
+#[test]
+fn untracked_directories_are_collapsed_and_expanded_with_enter() {
+    let fixture = Fixture::new(
+        "collapsed_untracked",
+        &[("a.txt", LINES)],
+        &[("new/b.txt", "b\n"), ("new/sub/c.txt", "c\n")],
+    );
+    let config = || Config {
+        list: ListConfig {
+            untracked: ShowUntracked::Normal,
+            ..ListConfig::default()
+        },
+        ..Config::default()
+    };
+    assert_snapshot(
+        "untracked_directory_collapsed",
+        &fixture.render_with(config(), "", WIDTH, HEIGHT),
+    );
+    assert_snapshot(
+        "untracked_directory_expanded",
+        &fixture.render_with(config(), "enter", WIDTH, HEIGHT),
+    );
+}
+
+#[test]
+fn patch_exported_and_applied_to_the_index() {
+    let fixture = Fixture::new(
+        "patch",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n")],
+    );
+    fixture.render("alt-e a . p a t c h enter");
+    let patch = fs::read_to_string(fixture.dir.join("a.patch")).unwrap();
+    assert!(patch.starts_with("diff --git a/a.txt b/a.txt\n"));
+    assert!(patch.contains("\n-two\n+2\n"));
+
+    assert_snapshot(
+        "patch_applied_to_the_index",
+        &fixture.render("t alt-i a . p a t c h enter"),
+    );
+    assert_snapshot(
+        "patch_that_doesnt_apply",
+        &fixture.render("t alt-i a . p a t c h enter"),
+    );
+}
+
+#[test]
+fn changes_since_a_revision() {
+    let fixture = Fixture::new(
+        "compare",
+        &[("a.txt", LINES), ("b.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("b.txt", "one\ntwo\n")],
+    );
+    fixture.render("s");
+    assert_snapshot(
+        "changes_since_a_revision",
+        &fixture.render("alt-d H E A D enter down"),
+    );
+    let screen = fixture.render("alt-d H E A D enter t");
+    assert!(screen.contains(" HEAD (") && screen.contains(")..index "));
+    assert!(screen.contains("a.txt") && !screen.contains("b.txt"));
+}
+
+#[test]
+fn staged_changes_reviewed_before_committing() {
+    let fixture = Fixture::new(
+        "review",
+        &[("a.txt", LINES), ("b.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("b.txt", "one\ntwo\n")],
+    );
+    let config = || {
+        let mut config = Config::default();
+        config.commit.review = true;
+        config
+    };
+    fixture.render("s s");
+    assert_snapshot(
+        "staged_changes_review",
+        &fixture.render_with(config(), "c", WIDTH, HEIGHT),
+    );
+    let screen = fixture.render_with(config(), "c enter", WIDTH, HEIGHT);
+    assert!(screen.contains("Commit message"));
+
+    let screen = fixture.render_with(config(), "c down u", WIDTH, HEIGHT);
+    assert!(screen.contains("Staged (1)") && !screen.contains("b.txt"));
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let status = repo.status_file(Path::new("b.txt")).unwrap();
+    assert_eq!(status, git2::Status::WT_MODIFIED);
+}
+
+#[test]
+fn partly_staged_files_warned_about_when_committing() {
+    let fixture = Fixture::new(
+        "partial",
+        &[("a.txt", LINES), ("b.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("b.txt", "one\ntwo\n")],
+    );
+    let mut config = Repository::open(&fixture.dir).unwrap().config().unwrap();
+    config.set_str("user.name", "Test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    fixture.render("s s");
+    write(&fixture.dir, "a.txt", "one\n2\n3\n");
+    let confirming = || {
+        let mut config = Config::default();
+        config.commit.confirm_partial = true;
+        config
+    };
+    assert_snapshot(
+        "partly_staged_files_before_commit",
+        &fixture.render_with(confirming(), "c", WIDTH, HEIGHT),
+    );
+    let screen = fixture.render_with(confirming(), "c y", WIDTH, HEIGHT);
+    assert!(screen.contains("Commit message"));
+    let screen = fixture.render_with(confirming(), "c enter", WIDTH, HEIGHT);
+    // The unstaged list, with a.txt's unstaged change shown.
+    assert!(screen.contains("+3") && !screen.contains("Partly staged"));
+
+    let screen = fixture.render("c M s g ctrl-s");
+    assert!(screen.contains("Msg, leaving out the") && screen.contains("> a.txt"));
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("Msg\n"));
+}
+
+#[test]
+fn commands_run_unattended_with_exec() {
+    let fixture = Fixture::new(
+        "exec",
+        &[("a.txt", LINES), ("b.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("b.txt", "one\ntwo\n")],
+    );
+    let mut config = Repository::open(&fixture.dir).unwrap().config().unwrap();
+    config.set_str("user.name", "Test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+
+    let e = fixture.exec(Config::default(), "bogus; stage-all; quit");
+    assert_eq!(
+        e.unwrap_err().to_string(),
+        "--exec `bogus`: unknown command: bogus"
+    );
+    // Discarding is only confirmed unattended with --yes.
+    let e = fixture
+        .exec(Config::default(), "discard; quit")
+        .unwrap_err();
+    assert!(e
+        .to_string()
+        .contains("left a confirmation waiting for an answer, which it can't get when ending in `quit` (--yes answers it)"));
+    assert_eq!(
+        fs::read_to_string(fixture.dir.join("a.txt")).unwrap(),
+        "one\n2\nthree\n"
+    );
+
+    // Staging by glob is confirmed by default, which happens by itself.
+    let script = r#"stage a.txt; commit --message "Two; \"2\""; quit"#;
+    fixture.exec(Config::default(), script).unwrap();
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("Two; \"2\"\n"));
+    let status = repo.status_file(Path::new("b.txt")).unwrap();
+    assert_eq!(status, git2::Status::WT_MODIFIED);
+
+    let mut exec = Exec::parse("discard; quit").unwrap();
+    exec.yes = true;
+    fixture.exec_with(Config::default(), exec).unwrap();
+    assert_eq!(
+        fs::read_to_string(fixture.dir.join("b.txt")).unwrap(),
+        LINES
+    );
+}
+
+#[test]
+fn tour_of_the_workflow() {
+    let fixture = Fixture::new("tour", &[("a.txt", LINES)], &[("a.txt", "one\n2\nthree\n")]);
+    assert_snapshot(
+        "tour_first_step",
+        &fixture.render_in("ctrl-p s h o w t o u r enter", 80, 20),
+    );
+    let screen = fixture.render_in("ctrl-p s h o w t o u r enter enter enter", 80, 20);
+    assert!(screen.contains("3/5  Stage a hunk"));
+    let screen = fixture.render_in("ctrl-p s h o w t o u r enter esc", 80, 20);
+    assert!(!screen.contains("Welcome"));
+}
+
+#[test]
+fn commits_behind_the_upstream_once_fetched() {
+    let fixture = Fixture::new("fetch", &[("a.txt", LINES)], &[]);
+    let repo = Repository::open(&fixture.dir).unwrap();
+    // The repository is its own remote, whose `incoming` branch, a commit ahead of `main`, is
+    // fetched as the `origin/main` that `main` tracks.
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let sig = Signature::new("Test", "test@example.com", &Time::new(0, 0)).unwrap();
+    repo.commit(
+        Some("refs/heads/incoming"),
+        &sig,
+        &sig,
+        "Upstream change",
+        &head.tree().unwrap(),
+        &[&head],
+    )
+    .unwrap();
+    repo.remote_with_fetch(
+        "origin",
+        ".",
+        "+refs/heads/incoming:refs/remotes/origin/main",
+    )
+    .unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("branch.main.remote", "origin").unwrap();
+    config
+        .set_str("branch.main.merge", "refs/heads/incoming")
+        .unwrap();
+
+    fixture.exec(Config::default(), "fetch; quit").unwrap();
+    let frame = fixture.render_in("alt-l", 120, HEIGHT);
+    assert!(frame.contains("main -> origin/main ↑0 ↓1  1 commit behind origin/main"));
+    assert!(frame.contains("Incoming from origin/main"));
+    assert!(frame.contains("Upstream change"));
+}
+
+#[test]
+fn tags_on_the_commit_listed_once_tagged() {
+    let fixture = Fixture::new("tags", &[("a.txt", LINES)], &[]);
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.tag_lightweight("v1.0-rc1", head.as_object(), false)
+        .unwrap();
+
+    // Lightweight, with the message left empty.
+    let frame = fixture.render_in("T v 1 . 0 enter ctrl-s", 100, HEIGHT);
+    assert!(frame.contains("Created tag v1.0"));
+    assert!(frame.contains(": v1.0, v1.0-rc1"));
+    assert!(fixture.render("I").contains("Tags: v1.0, v1.0-rc1"));
+}
+
+#[test]
+fn external_diff_of_two_files() {
+    let fixture = Fixture::unborn(
+        "external",
+        &[("old/a.txt", LINES), ("new/a.txt", "one\n2\nthree\n")],
+    );
+    let args = [
+        "a.txt".into(),
+        fixture.dir.join("old/a.txt").into_os_string(),
+        "4cb29ea3d2f1e6a1e7c8b5f3a2e9d0c1b4a5f6e7".into(),
+        "100644".into(),
+        fixture.dir.join("new/a.txt").into_os_string(),
+        "0000000000000000000000000000000000000000".into(),
+        "100755".into(),
+    ];
+    assert!(crate::cli::is_external_diff(&args));
+    let external = ExternalDiff::parse(&args).unwrap();
+
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    let mut events = ScriptedEvents::new("").unwrap();
+    external_diff::run(
+        &mut terminal,
+        None,
+        &external,
+        &Config::default(),
+        &mut events,
+    )
+    .unwrap();
+    assert_snapshot(
+        "external_diff_of_two_files",
+        &frame_text(terminal.backend().buffer()),
+    );
+}
+
+#[test]
+fn without_colors() {
+    let fixture = Fixture::new(
+        "no-color",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n"), ("new.txt", "new\n")],
+    );
+    let mut config = Config::default();
+    config.theme.no_color = true;
+    let mut terminal = fixture.run(config, "down", WIDTH, HEIGHT);
+    let buffer = terminal.backend().buffer();
+    assert_snapshot("without_colors", &frame_text(buffer));
+    assert!(buffer
+        .content
+        .iter()
+        .all(|c| c.fg == Color::Reset && c.bg == Color::Reset));
+    // The selected entry is in reverse video, with the cursor at its start for screen readers.
+    assert!(buffer.get(0, 2).modifier.contains(Modifier::REVERSED));
+    assert_eq!(terminal.get_cursor().unwrap(), (0, 2));
+}
+
+#[test]
+fn line_endings_that_changed_are_shown() {
+    let fixture = Fixture::new(
+        "line-endings",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\r\ntwo\nthree\n")],
+    );
+    assert_snapshot("changed_line_ending", &fixture.render(""));
+    // Those that didn't are only shown when asked for.
+    fixture.render("s");
+    write(&fixture.dir, "a.txt", "one\r\ntwo\r\nthree\n");
+    let screen = fixture.render("alt-w");
+    assert_eq!(screen.matches('␍').count(), 2);
+}
+
+#[test]
+fn index_changed_by_another_program_is_reloaded_before_staging() {
+    let fixture = Fixture::new(
+        "index-changed",
+        &[("a.txt", LINES), ("b.txt", LINES)],
+        &[("a.txt", "a\n"), ("b.txt", "b\n")],
+    );
+    // Long enough for the error to outlast the second key.
+    let mut config = Config {
+        error_timeout: 60,
+        ..Config::default()
+    };
+    config.refresh.watch = false;
+    let app = App::new(fixture.location(), config).unwrap();
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    // As `git add b.txt` from another terminal would, once the lists have loaded. The second `s`,
+    // on the reloaded lists, stages a.txt along with it.
+    let mut events = BehindTheBack {
+        events: ScriptedEvents::new("s s").unwrap(),
+        change: Some(|| {
+            let repo = Repository::open(&fixture.dir).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("b.txt")).unwrap();
+            index.write().unwrap();
+        }),
+    };
+    run_app(&mut terminal, app, Duration::ZERO, &mut events).unwrap();
+    assert_snapshot(
+        "index_changed_by_another_program",
+        &frame_text(terminal.backend().buffer()),
+    );
+
+    // a.txt, selected, wasn't staged on top of the change the first time, nor was the change
+    // undone by staging it the second time.
+    let repo = Repository::open(&fixture.dir).unwrap();
+    let status = |path| repo.status_file(Path::new(path)).unwrap();
+    assert!(status("a.txt").is_index_modified() && !status("a.txt").is_wt_modified());
+    assert!(status("b.txt").is_index_modified());
+}
+
+#[test]
+fn line_counts_of_the_list_entries_and_hunks() {
+    let fixture = Fixture::new(
+        "line-counts",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\nfour\n"), ("new.txt", "new\n")],
+    );
+    let mut config = Config::default();
+    config.theme.line_counts = true;
+    assert_snapshot(
+        "line_counts",
+        &fixture.render_with(config, "", WIDTH, HEIGHT),
+    );
+}
+
+#[test]
+fn diff_rendered_by_a_diff_filter() {
+    let fixture = Fixture::new(
+        "diff-filter",
+        &[("a.txt", LINES)],
+        &[("a.txt", "one\n2\nthree\n")],
+    );
+    let mut config = Config::default();
+    config.preview.diff_filter = String::from("sed s/^/>/");
+    assert_snapshot(
+        "diff_filter",
+        &fixture.render_with(config, "", WIDTH, HEIGHT),
+    );
+
+    // A filter that doesn't print a line for each line of the diff is left out.
+    let mut config = Config::default();
+    config.preview.diff_filter = String::from("sed 1d");
+    let frame = fixture.render_with(config, "", WIDTH, HEIGHT);
+    assert!(
+        frame.contains("printed 7 lines for the 8 of the diff"),
+        "{}",
+        frame
+    );
+    assert!(frame.contains("│▌@@ -1,3 +1,3 @@"), "{}", frame);
+}
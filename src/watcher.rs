@@ -0,0 +1,108 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use git_istage_rs::paths::RepoPath;
+
+// Editors typically write a file in several steps (truncate, write, rename), so changes are only
+// reported once no new events have arrived for this long.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// How long after the app writes the index itself that the writes are ignored.
+const OWN_WRITES: Duration = Duration::from_millis(500);
+
+pub struct Watcher {
+    root: PathBuf,
+    // Dropping the inner watcher stops the background thread, so it must be kept alive.
+    _inner: RecommendedWatcher,
+    // The paths changed, with when they were.
+    rx: Receiver<(PathBuf, Instant)>,
+    pending: HashSet<PathBuf>,
+    last_event: Instant,
+    ignore_index_until: Option<Instant>,
+}
+
+impl Watcher {
+    pub fn new(root: &Path) -> anyhow::Result<Watcher> {
+        let (tx, rx) = mpsc::channel();
+        let mut inner = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                // Reads (including our own `git` invocations reading files) aren't changes.
+                if event.kind.is_access() {
+                    return;
+                }
+                for path in event.paths {
+                    // The receiver going away just means the app is shutting down.
+                    let _ = tx.send((path, Instant::now()));
+                }
+            }
+        })?;
+        inner.watch(root, RecursiveMode::Recursive)?;
+
+        Ok(Watcher {
+            root: root.to_path_buf(),
+            _inner: inner,
+            rx,
+            pending: HashSet::new(),
+            last_event: Instant::now(),
+            ignore_index_until: None,
+        })
+    }
+
+    // Ignores git's index being written for a moment, for when the app wrote it and has already
+    // brought the lists up to date.
+    pub fn ignore_index_writes(&mut self) {
+        self.ignore_index_until = Some(Instant::now() + OWN_WRITES);
+    }
+
+    // The paths changed since the last call, relative to the root, once events have settled.
+    fn settled(&mut self) -> Vec<PathBuf> {
+        let git = self.root.join(".git");
+        for (path, at) in self.rx.try_iter() {
+            let index = path == git.join("index") || path == git.join("index.lock");
+            if index && self.ignore_index_until.is_some_and(|until| at < until) {
+                continue;
+            }
+            self.pending.insert(path);
+            self.last_event = Instant::now();
+        }
+
+        if self.pending.is_empty() || self.last_event.elapsed() < DEBOUNCE {
+            return Vec::new();
+        }
+
+        let root = &self.root;
+        self.pending
+            .drain()
+            .filter_map(|p| p.strip_prefix(root).ok().map(Path::to_path_buf))
+            .collect()
+    }
+
+    // Returns the repo-relative paths (with `/` separators) of worktree files that changed since
+    // the last call, once events have settled. Changes inside `.git` are not reported.
+    pub fn changed_paths(&mut self) -> Vec<RepoPath> {
+        self.settled()
+            .into_iter()
+            .filter(|rel| !rel.starts_with(".git"))
+            .map(|rel| RepoPath::from(rel.as_path()))
+            .collect()
+    }
+
+    // Whether the statuses may have changed since the last call, once events have settled: a
+    // worktree file changed, or git's index, HEAD or refs did (e.g. after `git add` elsewhere).
+    pub fn statuses_changed(&mut self) -> bool {
+        self.settled()
+            .iter()
+            .any(|rel| match rel.strip_prefix(".git") {
+                Ok(git) => {
+                    git == Path::new("index") || git == Path::new("HEAD") || git.starts_with("refs")
+                }
+                Err(_) => true,
+            })
+    }
+}
@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+// Lines with more tokens than this between them aren't compared, as the comparison takes time in
+// proportion to the product of their lengths.
+const MAX_TOKENS: usize = 40_000;
+
+// Byte ranges into a line.
+pub type Words = Vec<Range<usize>>;
+
+// The words that changed between a removed line and the added line that replaced it, as byte
+// ranges into each, like `git diff --word-diff` shows them. None when the lines have too little in
+// common for the words they share to be more than a coincidence, in which case the whole lines are
+// the change.
+pub fn changes(old: &str, new: &str) -> Option<(Words, Words)> {
+    let old_tokens = tokens(old);
+    let new_tokens = tokens(new);
+    if old_tokens.len() * new_tokens.len() > MAX_TOKENS {
+        return None;
+    }
+    let (old_kept, new_kept) = common(old, &old_tokens, new, &new_tokens);
+
+    let old_changed = changed(old, &old_tokens, &old_kept);
+    let new_changed = changed(new, &new_tokens, &new_kept);
+    let changed_len: usize = old_changed
+        .iter()
+        .chain(&new_changed)
+        .map(|r| r.len())
+        .sum();
+    // At least half of the two lines must be left as it was.
+    if changed_len * 2 > old.trim().len() + new.trim().len() {
+        return None;
+    }
+    Some((old_changed, new_changed))
+}
+
+// Splits a line into runs of word characters, runs of whitespace, and single other characters.
+fn tokens(line: &str) -> Vec<Range<usize>> {
+    let class = |c: char| match c {
+        c if c.is_alphanumeric() || c == '_' => 0,
+        c if c.is_whitespace() => 1,
+        _ => 2,
+    };
+    let mut tokens: Vec<Range<usize>> = Vec::new();
+    let mut previous = None;
+    for (i, c) in line.char_indices() {
+        let kind = class(c);
+        match tokens.last_mut() {
+            Some(last) if previous == Some(kind) && kind != 2 => last.end = i + c.len_utf8(),
+            _ => tokens.push(i..i + c.len_utf8()),
+        }
+        previous = Some(kind);
+    }
+    tokens
+}
+
+// Which tokens of each line are part of their longest common subsequence.
+fn common(
+    old: &str,
+    old_tokens: &[Range<usize>],
+    new: &str,
+    new_tokens: &[Range<usize>],
+) -> (Vec<bool>, Vec<bool>) {
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+    let same = |i: usize, j: usize| old[old_tokens[i].clone()] == new[new_tokens[j].clone()];
+    // lengths[i][j] is the length of the longest common subsequence of the tokens from i and j on.
+    let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = match same(i, j) {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut old_kept = vec![false; n];
+    let mut new_kept = vec![false; m];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if same(i, j) {
+            old_kept[i] = true;
+            new_kept[j] = true;
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (old_kept, new_kept)
+}
+
+// The byte ranges of the tokens not kept, with neighbouring ones joined, also across the
+// whitespace between them, so that a changed phrase is highlighted as one.
+fn changed(line: &str, tokens: &[Range<usize>], kept: &[bool]) -> Words {
+    let mut ranges: Words = Vec::new();
+    for (token, _) in tokens.iter().zip(kept).filter(|(_, &kept)| !kept) {
+        match ranges.last_mut() {
+            Some(last) if line[last.end..token.start].trim().is_empty() => last.end = token.end,
+            _ => ranges.push(token.clone()),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,70 @@
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use git_istage_rs::{backend, config::Config, diff::DiffSettings, git::Location};
+
+// How long work may take before the footer says it's going on, so that quick work doesn't flash.
+const SHOW_AFTER: Duration = Duration::from_millis(150);
+
+const SPINNER: [char; 8] = ['⠋', '⠙', '⠸', '⠴', '⠦', '⠇', '⠏', '⠛'];
+
+// Git work (statuses, diffs) done on a thread of its own, so that a big repository doesn't freeze
+// the UI. The thread opens the repository at `location` again, as the backend can't be shared with
+// it, with the diff settings and pathspecs of `config`. Dropping a worker abandons its result.
+pub struct Worker<T> {
+    started: Instant,
+    done: Receiver<anyhow::Result<T>>,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    pub fn start<F>(location: &Location, config: &Config, work: F) -> Worker<T>
+    where
+        F: FnOnce(&dyn backend::GitBackend) -> anyhow::Result<T> + Send + 'static,
+    {
+        let location = location.clone();
+        let kind = config.backend;
+        let settings = DiffSettings::from(config);
+        let pathspecs = config.pathspecs.clone();
+        let expanded_dirs = config.expanded_dirs.clone();
+        let (tx, done) = mpsc::channel();
+        thread::spawn(move || {
+            let result = backend::open(kind, &location).and_then(|b| {
+                b.set_diff_settings(settings);
+                b.set_pathspecs(&pathspecs);
+                b.set_expanded_dirs(&expanded_dirs);
+                work(b.as_ref())
+            });
+            // The receiver going away just means nobody wants the result any more.
+            let _ = tx.send(result);
+        });
+        Worker {
+            started: Instant::now(),
+            done,
+        }
+    }
+
+    // The outcome, once the work is done.
+    pub fn poll(&self) -> Option<anyhow::Result<T>> {
+        match self.done.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow::anyhow!("git worker died"))),
+        }
+    }
+
+    // A spinner frame, once the work has taken long enough to be worth showing.
+    pub fn spinner(&self) -> Option<char> {
+        spinner(self.started.elapsed())
+    }
+}
+
+// The spinner frame for work that's been going on for `elapsed`, if that's long enough to show.
+pub fn spinner(elapsed: Duration) -> Option<char> {
+    if elapsed < SHOW_AFTER {
+        return None;
+    }
+    Some(SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()])
+}
@@ -0,0 +1,99 @@
+mod testutil;
+
+use std::process::{Command, Output};
+
+use git_istage_rs::FileStatusKind;
+
+use testutil::TestRepo;
+
+// Runs the binary in `repo` with these arguments, away from the user's config and state.
+fn run(repo: &TestRepo, args: &[&str]) -> Output {
+    let home = repo.dir.join(".git").join("test-home");
+    Command::new(env!("CARGO_BIN_EXE_git-istage-rs"))
+        .args(args)
+        .current_dir(&repo.dir)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_STATE_HOME", home.join("state"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn staging_all_and_committing_runs_under_the_default_confirmations() {
+    let repo = TestRepo::with_commit(&[("a.rs", "a\n"), ("b.txt", "b\n")]);
+    repo.write("a.rs", "changed\n");
+    repo.write("b.txt", "changed\n");
+
+    let output = run(
+        &repo,
+        &["--exec", r#"stage-all; commit --message "wip"; quit"#],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let head = repo.repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("wip\n"));
+    assert_eq!(repo.head("b.txt").as_deref(), Some("changed\n"));
+}
+
+#[test]
+fn the_readme_example_stages_by_glob_under_the_default_confirmations() {
+    let repo = TestRepo::with_commit(&[("a.rs", "a\n"), ("b.txt", "b\n")]);
+    repo.write("a.rs", "changed\n");
+    repo.write("b.txt", "changed\n");
+
+    let output = run(
+        &repo,
+        &["--exec", r#"stage *.rs; commit --message "wip"; quit"#],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.head("a.rs").as_deref(), Some("changed\n"));
+    assert_eq!(repo.statuses(FileStatusKind::Unstaged), ["M b.txt"]);
+}
+
+#[test]
+fn discarding_unattended_takes_yes() {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]);
+    repo.write("a.txt", "changed\n");
+
+    let output = run(&repo, &["--exec", "discard; quit"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--yes answers it"), "{}", stderr);
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("changed\n"));
+
+    let output = run(&repo, &["--exec", "discard; quit", "--yes"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("a\n"));
+}
+
+#[test]
+fn emitting_a_patch_stages_into_a_copy_of_the_index() {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]);
+    repo.write("a.txt", "changed\n");
+    repo.write("b.txt", "changed\n");
+
+    let output = run(&repo, &["--emit-patch", "--exec", "stage a.txt; quit"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let patch = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        patch.contains("+++ b/a.txt") && !patch.contains("b.txt"),
+        "{}",
+        patch
+    );
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+}
@@ -0,0 +1,49 @@
+use std::path::{Path, PathBuf};
+
+use git_istage_rs::paths;
+
+#[test]
+fn verbatim_prefixes_are_dropped_where_the_path_has_another_form() {
+    let simplified = |path: &str| paths::simplified(PathBuf::from(path));
+    assert_eq!(
+        simplified(r"\\?\C:\repo\src"),
+        PathBuf::from(r"C:\repo\src")
+    );
+    assert_eq!(
+        simplified(r"\\?\UNC\server\share\repo"),
+        PathBuf::from(r"\\server\share\repo")
+    );
+    // A volume has no path but the verbatim one.
+    let volume = r"\\?\Volume{0b4e9c3a-0000-0000-0000-100000000000}\repo";
+    assert_eq!(simplified(volume), PathBuf::from(volume));
+    assert_eq!(simplified("/home/me/repo"), PathBuf::from("/home/me/repo"));
+}
+
+#[test]
+fn canonical_paths_are_absolute_and_never_verbatim() {
+    let dir = std::env::temp_dir();
+    let canonical = paths::canonicalize(&dir).unwrap();
+    assert!(canonical.is_absolute());
+    assert!(!canonical.to_string_lossy().starts_with(r"\\?\"));
+    assert_eq!(canonical, paths::canonicalize(dir.join(".")).unwrap());
+}
+
+#[test]
+fn repository_paths_are_written_with_slashes() {
+    let path: PathBuf = ["src", "ui", "main.rs"].iter().collect();
+    assert_eq!(paths::to_slashes(&path), "src/ui/main.rs");
+    assert_eq!(paths::to_slashes(Path::new("")), "");
+    let root: PathBuf = ["repo"].iter().collect();
+    assert_eq!(
+        paths::relative_to(&root.join(&path), &root).as_deref(),
+        Some("src/ui/main.rs")
+    );
+    assert_eq!(paths::relative_to(&root, &root).as_deref(), Some(""));
+    assert_eq!(paths::relative_to(Path::new("elsewhere"), &root), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_backslash_in_a_name_is_kept_where_it_isnt_a_separator() {
+    assert_eq!(paths::to_slashes(Path::new(r"dir/a\b.txt")), r"dir/a\b.txt");
+}
@@ -0,0 +1,1170 @@
+mod testutil;
+
+use std::fs;
+
+use git_istage_rs::{
+    backend,
+    config::{BackendKind, DiscardUntracked, RenamesConfig, ShowUntracked, Whitespace},
+    console,
+    diff::{self, DiffSettings},
+    paths::RepoPath,
+    FileStatusKind, LineKind, Repo,
+};
+
+use testutil::TestRepo;
+
+// For each test function taking the `BackendKind` to run against, a module of the same name with
+// a test for each backend there is.
+macro_rules! backend_tests {
+    ($($(#[$attr:meta])* $test:ident,)*) => {$(
+        $(#[$attr])*
+        mod $test {
+            use git_istage_rs::config::BackendKind;
+
+            #[test]
+            fn git2() {
+                super::$test(BackendKind::Git2)
+            }
+
+            #[cfg(feature = "gix")]
+            #[test]
+            fn gix() {
+                super::$test(BackendKind::Gix)
+            }
+        }
+    )*};
+}
+
+const LINES: &str = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n";
+
+fn statuses_list_unstaged_changes(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]).on(backend);
+    repo.write("a.txt", "changed\n");
+    repo.remove("b.txt");
+    repo.write("dir/new.txt", "new\n");
+
+    assert_eq!(
+        repo.statuses(FileStatusKind::Unstaged),
+        ["M a.txt", "D b.txt", "U dir/new.txt"]
+    );
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+}
+
+fn statuses_of_an_unborn_branch(backend: BackendKind) {
+    let repo = TestRepo::new().on(backend);
+    repo.write("a.txt", "a\n");
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["A a.txt"]);
+    assert!(repo.statuses(FileStatusKind::Unstaged).is_empty());
+}
+
+fn stage_modified_deleted_and_untracked(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]).on(backend);
+    repo.write("a.txt", "changed\n");
+    repo.remove("b.txt");
+    repo.write("c.txt", "new\n");
+
+    let session = repo.open();
+    session
+        .stage(&repo.entries(FileStatusKind::Unstaged))
+        .unwrap();
+
+    assert!(repo.statuses(FileStatusKind::Unstaged).is_empty());
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        ["M a.txt", "D b.txt", "A c.txt"]
+    );
+    assert_eq!(repo.index("a.txt").as_deref(), Some("changed\n"));
+    assert_eq!(repo.index("b.txt"), None);
+    assert_eq!(repo.index("c.txt").as_deref(), Some("new\n"));
+}
+
+// Stages `path` as `git add` in another terminal would, through a repository of its own.
+fn add_elsewhere(repo: &TestRepo, path: &str) {
+    let other = git2::Repository::open(&repo.dir).unwrap();
+    let mut index = other.index().unwrap();
+    index.add_path(std::path::Path::new(path)).unwrap();
+    index.write().unwrap();
+}
+
+fn staging_keeps_what_another_program_staged_meanwhile(backend: BackendKind) {
+    let repo =
+        TestRepo::with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")]).on(backend);
+    repo.write("a.txt", "changed\n");
+    repo.write("b.txt", "changed\n");
+    repo.write("c.txt", "changed\n");
+    let session = repo.open();
+    // Reads the index, which the session's repository then keeps.
+    session.statuses(FileStatusKind::Unstaged).unwrap();
+
+    add_elsewhere(&repo, "b.txt");
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        ["M a.txt", "M b.txt"]
+    );
+
+    add_elsewhere(&repo, "c.txt");
+    session
+        .unstage(&[repo.entry(FileStatusKind::Staged, "a.txt")])
+        .unwrap();
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        ["M b.txt", "M c.txt"]
+    );
+}
+
+fn unstage_restores_the_head_version_in_the_index(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    repo.write("a.txt", "changed\n");
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+
+    session
+        .unstage(&[repo.entry(FileStatusKind::Staged, "a.txt")])
+        .unwrap();
+
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+    assert_eq!(repo.index("a.txt").as_deref(), Some("a\n"));
+    // The worktree keeps the change.
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("changed\n"));
+}
+
+fn stage_and_unstage_a_rename(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("old.txt", LINES)]).on(backend);
+    repo.rename("old.txt", "new.txt");
+
+    assert_eq!(
+        repo.statuses(FileStatusKind::Unstaged),
+        ["R old.txt -> new.txt"]
+    );
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "new.txt")])
+        .unwrap();
+
+    assert!(repo.statuses(FileStatusKind::Unstaged).is_empty());
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        ["R old.txt -> new.txt"]
+    );
+    assert_eq!(repo.index("old.txt"), None);
+    assert_eq!(repo.index("new.txt").as_deref(), Some(LINES));
+
+    // Both sides of the rename go back to how HEAD has them.
+    session
+        .unstage(&[repo.entry(FileStatusKind::Staged, "new.txt")])
+        .unwrap();
+
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+    assert_eq!(repo.index("old.txt").as_deref(), Some(LINES));
+    assert_eq!(repo.index("new.txt"), None);
+}
+
+fn stage_and_unstage_part_of_a_rename(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("old.txt", LINES)]).on(backend);
+    repo.remove("old.txt");
+    let renamed = LINES
+        .replacen("2\n", "two\n", 1)
+        .replace("14\n", "fourteen\n");
+    repo.write("new.txt", &renamed);
+    let session = repo.open();
+
+    // The rename is staged along with just the selected hunk.
+    session
+        .apply_hunks(
+            &repo.entry(FileStatusKind::Unstaged, "new.txt"),
+            FileStatusKind::Unstaged,
+            &[1],
+        )
+        .unwrap();
+    assert_eq!(repo.index("old.txt"), None);
+    assert_eq!(
+        repo.index("new.txt").as_deref(),
+        Some(LINES.replace("14\n", "fourteen\n").as_str())
+    );
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        ["R old.txt -> new.txt"]
+    );
+    assert_eq!(repo.statuses(FileStatusKind::Unstaged), ["M new.txt"]);
+
+    // Unstaging the hunk again leaves the rename staged.
+    session
+        .apply_hunks(
+            &repo.entry(FileStatusKind::Staged, "new.txt"),
+            FileStatusKind::Staged,
+            &[0],
+        )
+        .unwrap();
+    assert_eq!(repo.index("old.txt"), None);
+    assert_eq!(repo.index("new.txt").as_deref(), Some(LINES));
+}
+
+fn stage_lines_of_a_rename(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("old.txt", LINES)]).on(backend);
+    repo.remove("old.txt");
+    repo.write("new.txt", &LINES.replacen("2\n", "two\n", 1));
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "new.txt");
+    let diff = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    let removed = diff.hunks[0]
+        .lines
+        .iter()
+        .position(|l| l.kind == LineKind::Removed)
+        .unwrap();
+
+    // Only the removal of "2" is staged, on top of the rename.
+    session
+        .apply_lines(&entry, FileStatusKind::Unstaged, 0, removed..removed + 1)
+        .unwrap();
+    assert_eq!(repo.index("old.txt"), None);
+    assert_eq!(
+        repo.index("new.txt").as_deref(),
+        Some(LINES.replacen("2\n", "", 1).as_str())
+    );
+}
+
+fn renames_are_found_as_configured(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("old.txt", LINES)]).on(backend);
+    repo.remove("old.txt");
+    repo.write("new.txt", &LINES.replacen("1\n", "one\n", 1));
+    let session = repo.open();
+    let statuses = |renames: RenamesConfig| {
+        session.set_diff_settings(DiffSettings {
+            renames,
+            ..DiffSettings::default()
+        });
+        session
+            .statuses(FileStatusKind::Unstaged)
+            .unwrap()
+            .iter()
+            .map(|e| e.pretty_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(statuses(RenamesConfig::default()), ["R old.txt -> new.txt"]);
+    // One line in fifteen changed is too much for a rename that must be 95% alike.
+    let strict = RenamesConfig {
+        threshold: 95,
+        ..RenamesConfig::default()
+    };
+    assert_eq!(statuses(strict), ["U new.txt", "D old.txt"]);
+    let disabled = RenamesConfig {
+        enabled: false,
+        ..RenamesConfig::default()
+    };
+    assert_eq!(statuses(disabled), ["U new.txt", "D old.txt"]);
+}
+
+fn ignored_files_are_listed_on_demand_and_staged_anyway(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[(".gitignore", "*.log\nbuild/\n")]).on(backend);
+    repo.write("debug.log", "log\n");
+    repo.write("build/out.txt", "out\n");
+    repo.write("new.txt", "new\n");
+    let session = repo.open();
+    assert_eq!(repo.statuses(FileStatusKind::Unstaged), ["U new.txt"]);
+
+    session.set_diff_settings(DiffSettings {
+        ignored: true,
+        ..DiffSettings::default()
+    });
+    let entries = session.statuses(FileStatusKind::Unstaged).unwrap();
+    let listed: Vec<_> = entries.iter().map(|e| e.pretty_string()).collect();
+    assert_eq!(listed, ["! build/out.txt", "! debug.log", "U new.txt"]);
+
+    let log = entries.iter().find(|e| e.new_file == "debug.log").unwrap();
+    let diff = session.diff(log, FileStatusKind::Unstaged).unwrap();
+    assert_eq!(diff.hunks.len(), 1);
+    session.stage(std::slice::from_ref(log)).unwrap();
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["A debug.log"]);
+    assert_eq!(repo.index("debug.log").as_deref(), Some("log\n"));
+}
+
+fn untracked_directories_are_collapsed_until_expanded(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    repo.write("new/b.txt", "b\n");
+    repo.write("new/sub/c.txt", "c\n");
+    repo.write("top.txt", "top\n");
+    let session = repo.open();
+    let listed = |untracked, max_untracked| {
+        session.set_diff_settings(DiffSettings {
+            untracked,
+            max_untracked,
+            ..DiffSettings::default()
+        });
+        session
+            .statuses(FileStatusKind::Unstaged)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.new_file)
+            .collect::<Vec<_>>()
+    };
+    let all = ["new/b.txt", "new/sub/c.txt", "top.txt"];
+    assert_eq!(listed(ShowUntracked::All, 0), all);
+    assert_eq!(listed(ShowUntracked::Normal, 0), ["new/", "top.txt"]);
+    assert!(listed(ShowUntracked::No, 0).is_empty());
+    // The directory is only looked into while there's room for more untracked entries.
+    assert_eq!(listed(ShowUntracked::All, 2), ["new/", "top.txt"]);
+    assert_eq!(listed(ShowUntracked::All, 3), all);
+
+    session.set_expanded_dirs(&[RepoPath::from("new/")]);
+    assert_eq!(listed(ShowUntracked::Normal, 0), all);
+    session.set_expanded_dirs(&[]);
+
+    let dir = session
+        .statuses(FileStatusKind::Unstaged)
+        .unwrap()
+        .into_iter()
+        .find(|e| e.is_dir())
+        .unwrap();
+    assert!(session.diff(&dir, FileStatusKind::Unstaged).is_err());
+    session.stage(&[dir]).unwrap();
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        ["A new/b.txt", "A new/sub/c.txt"]
+    );
+}
+
+#[test]
+fn staged_copies_are_found_with_copies_enabled() {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]);
+    let copy = LINES.replace("14\n", "fourteen\n");
+    repo.write("b.txt", &copy);
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "b.txt")])
+        .unwrap();
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["A b.txt"]);
+
+    session.set_diff_settings(DiffSettings {
+        renames: RenamesConfig {
+            copies: true,
+            ..RenamesConfig::default()
+        },
+        ..DiffSettings::default()
+    });
+    let entries = session.statuses(FileStatusKind::Staged).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].pretty_string(), "C a.txt -> b.txt");
+    // The diff is against the file it was copied from.
+    let diff = session.diff(&entries[0], FileStatusKind::Staged).unwrap();
+    assert_eq!(diff.hunks.len(), 1);
+
+    session
+        .apply_hunks(&entries[0], FileStatusKind::Staged, &[0])
+        .unwrap();
+    assert_eq!(repo.index("b.txt").as_deref(), Some(LINES));
+    assert_eq!(repo.index("a.txt").as_deref(), Some(LINES));
+
+    let entries = session.statuses(FileStatusKind::Staged).unwrap();
+    session.unstage(&entries).unwrap();
+    assert_eq!(repo.index("b.txt"), None);
+    assert_eq!(repo.index("a.txt").as_deref(), Some(LINES));
+}
+
+fn discard_restores_the_index_version_and_deletes_untracked_files(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]).on(backend);
+    repo.write("a.txt", "changed\n");
+    repo.remove("b.txt");
+    repo.write("c.txt", "new\n");
+
+    let session = repo.open();
+    session
+        .discard(
+            &repo.entries(FileStatusKind::Unstaged),
+            DiscardUntracked::Delete,
+        )
+        .unwrap();
+
+    assert!(repo.statuses(FileStatusKind::Unstaged).is_empty());
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("a\n"));
+    assert_eq!(repo.worktree("b.txt").as_deref(), Some("b\n"));
+    assert_eq!(repo.worktree("c.txt"), None);
+}
+
+fn discard_keeps_staged_changes(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    repo.write("a.txt", "staged\n");
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+    repo.write("a.txt", "unstaged\n");
+
+    session
+        .discard(
+            &[repo.entry(FileStatusKind::Unstaged, "a.txt")],
+            DiscardUntracked::Delete,
+        )
+        .unwrap();
+
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("staged\n"));
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["M a.txt"]);
+}
+
+fn apply_hunks_stages_and_unstages_only_the_selected_hunks(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]).on(backend);
+    repo.write(
+        "a.txt",
+        &LINES
+            .replacen("2\n", "two\n", 1)
+            .replace("14\n", "fourteen\n"),
+    );
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    assert_eq!(
+        session
+            .diff(&entry, FileStatusKind::Unstaged)
+            .unwrap()
+            .hunks
+            .len(),
+        2
+    );
+
+    session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[1])
+        .unwrap();
+    assert_eq!(
+        repo.index("a.txt").as_deref(),
+        Some(LINES.replace("14\n", "fourteen\n").as_str())
+    );
+
+    session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .unwrap();
+    assert!(repo.statuses(FileStatusKind::Unstaged).is_empty());
+
+    let staged = repo.entry(FileStatusKind::Staged, "a.txt");
+    session
+        .apply_hunks(&staged, FileStatusKind::Staged, &[1])
+        .unwrap();
+    assert_eq!(
+        repo.index("a.txt").as_deref(),
+        Some(LINES.replacen("2\n", "two\n", 1).as_str())
+    );
+}
+
+fn apply_hunks_counts_hunks_with_the_diff_settings(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]).on(backend);
+    repo.write(
+        "a.txt",
+        &LINES.replacen("2\n", "two\n", 1).replace("6\n", "six\n"),
+    );
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    // With the usual 3 lines of context the changes share a hunk; without any, they don't.
+    session.set_diff_settings(DiffSettings {
+        context: 0,
+        ..DiffSettings::default()
+    });
+    let diff = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    assert_eq!(diff.hunks.len(), 2);
+
+    session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[1])
+        .unwrap();
+    assert_eq!(
+        repo.index("a.txt").as_deref(),
+        Some(LINES.replace("6\n", "six\n").as_str())
+    );
+}
+
+fn hunks_are_not_applied_while_whitespace_changes_are_hidden(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\nb\n")]).on(backend);
+    repo.write("a.txt", "a \nc\n");
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    session.set_diff_settings(DiffSettings {
+        whitespace: Whitespace::IgnoreChange,
+        ..DiffSettings::default()
+    });
+    let diff = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    // Only the change from b to c is left.
+    let changed = diff.hunks[0]
+        .lines
+        .iter()
+        .filter(|l| l.kind != LineKind::Context)
+        .count();
+    assert_eq!(changed, 2);
+    // Lines that compare the same are shown as the worktree has them.
+    assert_eq!(diff.hunks[0].lines[0].content, "a ");
+
+    assert!(session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .is_err());
+    assert_eq!(repo.index("a.txt").as_deref(), Some("a\nb\n"));
+}
+
+fn apply_lines_stages_only_the_selected_lines(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "1\n2\n3\n")]).on(backend);
+    repo.write("a.txt", "1\n2\nx\ny\n3\n");
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    let hunk = &session
+        .diff(&entry, FileStatusKind::Unstaged)
+        .unwrap()
+        .hunks[0];
+    let x = hunk.lines.iter().position(|l| l.content == "x").unwrap();
+
+    session
+        .apply_lines(&entry, FileStatusKind::Unstaged, 0, x..x + 1)
+        .unwrap();
+
+    assert_eq!(repo.index("a.txt").as_deref(), Some("1\n2\nx\n3\n"));
+}
+
+fn discard_hunks_from_the_worktree_only(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]).on(backend);
+    let session = repo.open();
+    // The first change is staged, and stays so; the worktree changes two more lines after it.
+    repo.write("a.txt", &LINES.replacen("2\n", "two\n", 1));
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+    repo.write(
+        "a.txt",
+        &LINES
+            .replacen("2\n", "two\n", 1)
+            .replace("6\n", "six\n")
+            .replace("14\n", "fourteen\n"),
+    );
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    assert_eq!(
+        session
+            .diff(&entry, FileStatusKind::Unstaged)
+            .unwrap()
+            .hunks
+            .len(),
+        2
+    );
+
+    session.discard_hunks(&entry, &[1]).unwrap();
+    assert_eq!(
+        repo.worktree("a.txt").as_deref(),
+        Some(
+            LINES
+                .replacen("2\n", "two\n", 1)
+                .replace("6\n", "six\n")
+                .as_str()
+        )
+    );
+    assert_eq!(
+        repo.index("a.txt").as_deref(),
+        Some(LINES.replacen("2\n", "two\n", 1).as_str())
+    );
+
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    session.discard_hunks(&entry, &[0]).unwrap();
+    assert!(repo.statuses(FileStatusKind::Unstaged).is_empty());
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["M a.txt"]);
+}
+
+fn discard_lines_keeps_the_rest_of_the_hunk(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "1\n2\n3\n")]).on(backend);
+    repo.write("a.txt", "1\n2\nx\ny\n3\n");
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    let hunk = &session
+        .diff(&entry, FileStatusKind::Unstaged)
+        .unwrap()
+        .hunks[0];
+    let y = hunk.lines.iter().position(|l| l.content == "y").unwrap();
+
+    session.discard_lines(&entry, 0, y..y + 1).unwrap();
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("1\n2\nx\n3\n"));
+    assert_eq!(repo.index("a.txt").as_deref(), Some("1\n2\n3\n"));
+}
+
+fn hunks_of_untracked_files_are_not_discarded(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    repo.write("new.txt", "new\n");
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "new.txt");
+
+    assert!(session.discard_hunks(&entry, &[0]).is_err());
+    assert_eq!(repo.worktree("new.txt").as_deref(), Some("new\n"));
+}
+
+fn stage_hunks_of_an_untracked_file(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    repo.write("new.txt", "new\n");
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "new.txt");
+
+    session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .unwrap();
+
+    assert_eq!(repo.index("new.txt").as_deref(), Some("new\n"));
+}
+
+fn intent_to_add_tracks_the_file_without_staging_it(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    repo.write("new.txt", "new\n");
+    let session = repo.open();
+
+    session
+        .intent_to_add(&[repo.entry(FileStatusKind::Unstaged, "new.txt")])
+        .unwrap();
+
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+    let entry = repo.entry(FileStatusKind::Unstaged, "new.txt");
+    let diff = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    assert_eq!(diff.hunks.len(), 1);
+}
+
+fn commit_the_index(backend: BackendKind) {
+    let repo = TestRepo::new().on(backend);
+    repo.write("a.txt", "a\n");
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+
+    session.commit("first").unwrap();
+    repo.write("a.txt", "b\n");
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+    let id = session.commit("second").unwrap();
+
+    let commit = repo.repo.find_commit(id).unwrap();
+    assert_eq!(commit.message(), Some("second"));
+    assert_eq!(commit.parent_count(), 1);
+    assert_eq!(repo.head("a.txt").as_deref(), Some("b\n"));
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+}
+
+// A repository set up to sign commits with an ssh key, and with a.txt staged.
+fn signing_repo(key: &str) -> TestRepo {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]);
+    let mut config = repo.repo.config().unwrap();
+    config.set_bool("commit.gpgsign", true).unwrap();
+    config.set_str("gpg.format", "ssh").unwrap();
+    config
+        .set_str("user.signingkey", &repo.dir.join(key).to_string_lossy())
+        .unwrap();
+    repo.write("a.txt", "b\n");
+    repo.open()
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "a.txt")])
+        .unwrap();
+    repo
+}
+
+#[test]
+fn commits_are_signed_when_configured() {
+    let repo = signing_repo(".git/key");
+    let key = repo.dir.join(".git/key");
+    let status = std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(&key)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let id = repo.open().commit("signed").unwrap();
+
+    let (signature, _) = repo.repo.extract_signature(&id, None).unwrap();
+    assert!(signature.as_str().unwrap().contains("BEGIN SSH SIGNATURE"));
+    assert_eq!(repo.repo.head().unwrap().target(), Some(id));
+    assert_eq!(repo.head("a.txt").as_deref(), Some("b\n"));
+}
+
+#[test]
+fn committing_without_the_signing_key_fails() {
+    let repo = signing_repo(".git/missing-key");
+    let head = repo.repo.head().unwrap().target();
+
+    let error = repo.open().commit("unsigned").unwrap_err();
+
+    assert!(
+        error.to_string().contains("signing the commit"),
+        "{}",
+        error
+    );
+    assert_eq!(repo.repo.head().unwrap().target(), head);
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["M a.txt"]);
+}
+
+#[test]
+fn signing_is_logged_in_the_console() {
+    let repo = signing_repo(".git/missing-key");
+    let key = repo.dir.join(".git/missing-key");
+
+    repo.open().commit("unsigned").unwrap_err();
+
+    let run = console::with_runs(|runs| {
+        runs.iter()
+            .find(|r| r.command.contains(&*key.to_string_lossy()))
+            .cloned()
+    })
+    .expect("the signer wasn't logged");
+    assert!(run.command.starts_with("ssh-keygen -Y sign -n git -f "));
+    assert!(!run.output.is_empty());
+    assert!(run.exit.is_some_and(|e| e != "exit 0"));
+}
+
+fn split_hunk_pieces_stage_on_their_own(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]).on(backend);
+    let changed = |lines: &[&str]| {
+        let mut content: Vec<String> = LINES.lines().map(|l| format!("{}\n", l)).collect();
+        for &line in lines {
+            let i: usize = line.parse().unwrap();
+            content[i - 1] = format!("changed {}\n", line);
+        }
+        content.concat()
+    };
+    repo.write("a.txt", &changed(&["5", "8"]));
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    let file = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    assert_eq!(file.hunks.len(), 1);
+
+    let pieces = diff::split_hunk(&file.hunks[0]);
+    let headers: Vec<_> = pieces.iter().map(|(_, h)| h.header.as_str()).collect();
+    assert_eq!(headers, ["@@ -2,6 +2,6 @@", "@@ -6,6 +6,6 @@"]);
+    assert_eq!(diff::split_hunk(&pieces[0].1).len(), 1);
+
+    session
+        .apply_lines(&entry, FileStatusKind::Unstaged, 0, pieces[1].0.clone())
+        .unwrap();
+    assert_eq!(repo.index("a.txt"), Some(changed(&["8"])));
+}
+
+fn staging_in_a_linked_worktree(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    let dir = repo.dir.join("wt");
+    repo.repo.worktree("wt", &dir, None).unwrap();
+    fs::write(dir.join("a.txt"), "changed\n").unwrap();
+
+    let session = Repo::open_with(backend, &dir).unwrap();
+    let entries = session.statuses(FileStatusKind::Unstaged).unwrap();
+    session.stage(&entries).unwrap();
+
+    let staged = session.statuses(FileStatusKind::Staged).unwrap();
+    assert_eq!(staged.len(), 1);
+    assert_eq!(
+        staged[0].abs_path_new(),
+        fs::canonicalize(&dir).unwrap().join("a.txt")
+    );
+    // The main working tree's index is its own.
+    assert!(repo.statuses(FileStatusKind::Staged).is_empty());
+}
+
+fn bare_repositories_are_refused(backend: BackendKind) {
+    let repo = TestRepo::new().on(backend);
+    let bare = repo.dir.join("bare.git");
+    git2::Repository::init_bare(&bare).unwrap();
+
+    let error = Repo::open_with(backend, &bare).err().unwrap();
+
+    assert!(
+        error.to_string().contains("is a bare repository"),
+        "{}",
+        error
+    );
+}
+
+fn staging_a_file_whose_name_is_not_utf8(backend: BackendKind) {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    let name = b"caf\xe9 \"menu\".txt";
+    fs::write(repo.dir.join(OsStr::from_bytes(name)), "one\ntwo\n").unwrap();
+
+    assert_eq!(
+        repo.statuses(FileStatusKind::Unstaged),
+        [r#"U "caf\351 \"menu\".txt""#]
+    );
+    let entry = repo.entries(FileStatusKind::Unstaged).remove(0);
+    assert_eq!(entry.new_file.as_bytes(), name);
+    assert_eq!(entry.new_path().as_os_str().as_bytes(), name);
+    repo.open()
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .unwrap();
+
+    let mut index = repo.repo.index().unwrap();
+    index.read(true).unwrap();
+    let staged = index.get_path(&entry.new_path(), 0).unwrap();
+    assert_eq!(staged.path, name);
+    let blob = repo.repo.find_blob(staged.id).unwrap();
+    assert_eq!(blob.content(), b"one\ntwo\n");
+    assert_eq!(
+        repo.statuses(FileStatusKind::Staged),
+        [r#"A "caf\351 \"menu\".txt""#]
+    );
+}
+
+#[test]
+fn escaped_paths_round_trip() {
+    for path in [
+        &b"plain/path.rs"[..],
+        b"caf\xc3\xa9",
+        b"tab\there",
+        b"back\\slash",
+        b"\xff\x01",
+    ] {
+        let escaped = RepoPath::from(path).escaped();
+        assert_eq!(
+            RepoPath::from_escaped(&escaped).as_bytes(),
+            path,
+            "{}",
+            escaped
+        );
+        assert_eq!(RepoPath::from(path).to_string(), escaped);
+    }
+    assert_eq!(
+        RepoPath::from(&b"caf\xe9.txt"[..]).quoted(),
+        r#""caf\351.txt""#
+    );
+    assert_eq!(RepoPath::from("café").escaped(), "café");
+    assert_eq!(RepoPath::from("café").quoted(), "café");
+    assert_eq!(RepoPath::from("a\nb").quoted(), r#""a\nb""#);
+}
+
+fn diff_over_the_line_limit_is_held_back(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]).on(backend);
+    repo.write("a.txt", "changed\n");
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+
+    // A header, 15 removed lines and one added.
+    let diff = session
+        .backend()
+        .diff_up_to(&entry, FileStatusKind::Unstaged, 16)
+        .unwrap();
+    assert!(diff.hunks.is_empty());
+    assert_eq!(diff.held_back, Some(17));
+
+    let diff = session
+        .backend()
+        .diff_up_to(&entry, FileStatusKind::Unstaged, 17)
+        .unwrap();
+    assert_eq!(diff.hunks.len(), 1);
+    assert_eq!(diff.held_back, None);
+}
+
+fn a_type_change_is_staged_and_unstaged_as_a_whole(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n")]).on(backend);
+    std::os::unix::fs::symlink("a.txt", repo.dir.join("link")).unwrap();
+    repo.commit_all("link");
+    repo.remove("link");
+    repo.write("link", "now a file\n");
+    assert_eq!(repo.statuses(FileStatusKind::Unstaged), ["T link"]);
+
+    // The diff has both sides: the symlink's target removed and the file's content added.
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "link");
+    let file = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    assert_eq!(file.symlink, Some((Some(String::from("a.txt")), None)));
+    let lines: Vec<_> = file
+        .hunks
+        .iter()
+        .flat_map(|h| &h.lines)
+        .filter(|l| l.kind != LineKind::NoNewlineAtEof)
+        .map(|l| (l.kind, l.content.as_str()))
+        .collect();
+    assert_eq!(lines, [(LineKind::Added, "now a file")]);
+
+    let err = session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .unwrap_err();
+    assert!(err.to_string().contains("changed type"), "{}", err);
+    assert_eq!(repo.statuses(FileStatusKind::Staged), Vec::<String>::new());
+
+    session.stage(&[entry]).unwrap();
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["T link"]);
+    assert_eq!(
+        repo.statuses(FileStatusKind::Unstaged),
+        Vec::<String>::new()
+    );
+    let mut index = repo.repo.index().unwrap();
+    index.read(true).unwrap();
+    assert_eq!(
+        index
+            .get_path(std::path::Path::new("link"), 0)
+            .unwrap()
+            .mode,
+        0o100644
+    );
+    assert_eq!(repo.index("link").as_deref(), Some("now a file\n"));
+
+    let staged = repo.entry(FileStatusKind::Staged, "link");
+    session.unstage(&[staged]).unwrap();
+    assert_eq!(repo.statuses(FileStatusKind::Unstaged), ["T link"]);
+    assert_eq!(repo.index("link").as_deref(), Some("a.txt"));
+}
+
+fn a_symlink_is_diffed_staged_and_discarded_as_a_link(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", "a\n"), ("b.txt", "b\n")]).on(backend);
+    std::os::unix::fs::symlink("a.txt", repo.dir.join("link")).unwrap();
+    repo.commit_all("link");
+    repo.remove("link");
+    std::os::unix::fs::symlink("b.txt", repo.dir.join("link")).unwrap();
+
+    // The diff says where the link points on each side, not what's in the files it points to.
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "link");
+    let file = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    let targets = (Some(String::from("a.txt")), Some(String::from("b.txt")));
+    assert_eq!(file.symlink, Some(targets));
+    assert!(file.hunks.is_empty());
+
+    let err = session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .unwrap_err();
+    assert!(err.to_string().contains("is a symlink"), "{}", err);
+    let err = session.discard_hunks(&entry, &[0]).unwrap_err();
+    assert!(err.to_string().contains("is a symlink"), "{}", err);
+    assert_eq!(repo.worktree("b.txt").as_deref(), Some("b\n"));
+
+    session.stage(&[entry]).unwrap();
+    assert_eq!(repo.statuses(FileStatusKind::Staged), ["M link"]);
+    let mut index = repo.repo.index().unwrap();
+    index.read(true).unwrap();
+    let mode = index
+        .get_path(std::path::Path::new("link"), 0)
+        .unwrap()
+        .mode;
+    assert_eq!(mode, 0o120000);
+    assert_eq!(repo.index("link").as_deref(), Some("b.txt"));
+
+    // Discarding puts the link back as it was, and leaves the files it pointed to alone.
+    let staged = repo.entry(FileStatusKind::Staged, "link");
+    session.unstage(&[staged]).unwrap();
+    let entry = repo.entry(FileStatusKind::Unstaged, "link");
+    session.discard(&[entry], DiscardUntracked::Delete).unwrap();
+    let target = fs::read_link(repo.dir.join("link")).unwrap();
+    assert_eq!(target, std::path::Path::new("a.txt"));
+    assert_eq!(repo.worktree("a.txt").as_deref(), Some("a\n"));
+    assert_eq!(repo.worktree("b.txt").as_deref(), Some("b\n"));
+}
+
+fn statuses_are_limited_to_the_pathspecs(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("src/a.rs", "a\n"), ("docs/b.md", "b\n")]).on(backend);
+    repo.write("src/a.rs", "changed\n");
+    repo.write("docs/b.md", "changed\n");
+    repo.write("src/new.rs", "new\n");
+    repo.write("notes.md", "new\n");
+    let session = repo.open();
+    session
+        .stage(&[repo.entry(FileStatusKind::Unstaged, "docs/b.md")])
+        .unwrap();
+
+    session.set_pathspecs(&[String::from("src")]);
+    assert_eq!(
+        session
+            .statuses(FileStatusKind::Unstaged)
+            .unwrap()
+            .iter()
+            .map(|e| e.pretty_string())
+            .collect::<Vec<_>>(),
+        ["M src/a.rs", "U src/new.rs"]
+    );
+    assert!(session.statuses(FileStatusKind::Staged).unwrap().is_empty());
+
+    session.set_pathspecs(&[String::from("*.md")]);
+    let paths = |kind| {
+        session
+            .statuses(kind)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.new_file)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(paths(FileStatusKind::Unstaged), ["notes.md"]);
+    assert_eq!(paths(FileStatusKind::Staged), ["docs/b.md"]);
+
+    session.set_pathspecs(&[]);
+    assert_eq!(paths(FileStatusKind::Unstaged).len(), 3);
+}
+
+fn mode_change_is_staged_and_unstaged_without_the_content(backend: BackendKind) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = TestRepo::with_commit(&[("run.sh", LINES), ("a.txt", "a\n")]).on(backend);
+    repo.write("run.sh", &LINES.replace("8\n", "eight\n"));
+    repo.write("a.txt", "changed\n");
+    let script = repo.dir.join("run.sh");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let session = repo.open();
+
+    let entry = repo.entry(FileStatusKind::Unstaged, "run.sh");
+    let diff = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    assert_eq!(diff.modes, Some((0o100644, 0o100755)));
+    assert_eq!(diff.hunks.len(), 1);
+    session
+        .apply_mode(&entry, FileStatusKind::Unstaged)
+        .unwrap();
+
+    assert_eq!(repo.index("run.sh").as_deref(), Some(LINES));
+    let staged = repo.entry(FileStatusKind::Staged, "run.sh");
+    let diff = session.diff(&staged, FileStatusKind::Staged).unwrap();
+    assert_eq!(diff.modes, Some((0o100644, 0o100755)));
+    assert!(diff.hunks.is_empty());
+    let unstaged = repo.entry(FileStatusKind::Unstaged, "run.sh");
+    let diff = session.diff(&unstaged, FileStatusKind::Unstaged).unwrap();
+    assert_eq!((diff.modes, diff.hunks.len()), (None, 1));
+
+    session.apply_mode(&staged, FileStatusKind::Staged).unwrap();
+    assert_eq!(repo.statuses(FileStatusKind::Staged), Vec::<String>::new());
+
+    let other = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    let err = session.apply_mode(&other, FileStatusKind::Unstaged);
+    assert!(err.unwrap_err().to_string().contains("no mode change"));
+}
+
+#[test]
+fn two_contents_are_diffed_with_their_modes() {
+    let settings = DiffSettings::default();
+    let file = diff::buffers_diff(
+        &RepoPath::from("a.txt"),
+        b"one\ntwo\n",
+        b"one\n2\n",
+        Some((0o100644, 0o100755)),
+        settings,
+    )
+    .unwrap();
+    assert_eq!(file.new_path, "a.txt");
+    assert_eq!(file.modes, Some((0o100644, 0o100755)));
+    let lines: Vec<_> = file.hunks[0].lines.iter().map(|l| l.kind).collect();
+    assert_eq!(
+        lines,
+        [LineKind::Context, LineKind::Removed, LineKind::Added]
+    );
+
+    // What a new symlink points to is its content, and it has no hunks.
+    let file = diff::buffers_diff(
+        &RepoPath::from("link"),
+        b"",
+        b"a.txt",
+        Some((0, 0o120000)),
+        settings,
+    )
+    .unwrap();
+    assert_eq!(file.symlink, Some((None, Some(String::from("a.txt")))));
+    assert!(file.hunks.is_empty());
+
+    let file = diff::buffers_diff(
+        &RepoPath::from("a.txt"),
+        b"same\n",
+        b"same\n",
+        None,
+        settings,
+    )
+    .unwrap();
+    assert!(file.hunks.is_empty() && file.modes.is_none());
+}
+
+fn lines_of_a_crlf_file_are_staged_with_their_line_endings(backend: BackendKind) {
+    let crlf = LINES.replace('\n', "\r\n");
+    let repo = TestRepo::with_commit(&[("a.txt", &crlf)]).on(backend);
+    let changed = crlf
+        .replacen("2\r\n", "two\r\n", 1)
+        .replace("14\r\n", "fourteen\r\n");
+    repo.write("a.txt", &changed);
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    let diff = session.diff(&entry, FileStatusKind::Unstaged).unwrap();
+    assert!(diff.hunks[0]
+        .lines
+        .iter()
+        .all(|l| l.content.ends_with('\r')));
+
+    session
+        .apply_lines(
+            &entry,
+            FileStatusKind::Unstaged,
+            0,
+            0..diff.hunks[0].lines.len(),
+        )
+        .unwrap();
+    assert_eq!(
+        repo.index("a.txt").as_deref(),
+        Some(crlf.replacen("2\r\n", "two\r\n", 1).as_str())
+    );
+    // What's left is now the first hunk.
+    session
+        .apply_hunks(&entry, FileStatusKind::Unstaged, &[0])
+        .unwrap();
+    assert_eq!(repo.index("a.txt"), Some(changed));
+}
+
+fn only_hunks_changing_more_than_whitespace_are_staged(backend: BackendKind) {
+    let repo = TestRepo::with_commit(&[("a.txt", LINES)]).on(backend);
+    // A reformatted line at the top, and a real change at the bottom.
+    let changed = LINES
+        .replacen("2\n", "  2\n", 1)
+        .replace("14\n", "fourteen\n");
+    repo.write("a.txt", &changed);
+    let session = repo.open();
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+
+    let settings = DiffSettings::default();
+    let staged = backend::stage_ignoring_whitespace(session.backend(), &entry, settings).unwrap();
+    assert_eq!(staged, (1, 1));
+    assert_eq!(
+        repo.index("a.txt").as_deref(),
+        Some(LINES.replace("14\n", "fourteen\n").as_str())
+    );
+
+    // What's left only changes whitespace.
+    let entry = repo.entry(FileStatusKind::Unstaged, "a.txt");
+    let error = backend::stage_ignoring_whitespace(session.backend(), &entry, settings)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("only changes whitespace"), "{}", error);
+}
+
+// Each of these runs against every backend, as `<test>::git2` and `<test>::gix`. The rest run
+// against libgit2 alone: gitoxide doesn't find a copy that's the only change staged, the signing
+// tests are of the libgit2 backend's own signing, and the others don't open a repository.
+backend_tests! {
+    statuses_list_unstaged_changes,
+    statuses_of_an_unborn_branch,
+    stage_modified_deleted_and_untracked,
+    staging_keeps_what_another_program_staged_meanwhile,
+    unstage_restores_the_head_version_in_the_index,
+    stage_and_unstage_a_rename,
+    stage_and_unstage_part_of_a_rename,
+    stage_lines_of_a_rename,
+    renames_are_found_as_configured,
+    ignored_files_are_listed_on_demand_and_staged_anyway,
+    untracked_directories_are_collapsed_until_expanded,
+    discard_restores_the_index_version_and_deletes_untracked_files,
+    discard_keeps_staged_changes,
+    apply_hunks_stages_and_unstages_only_the_selected_hunks,
+    apply_hunks_counts_hunks_with_the_diff_settings,
+    hunks_are_not_applied_while_whitespace_changes_are_hidden,
+    apply_lines_stages_only_the_selected_lines,
+    discard_hunks_from_the_worktree_only,
+    discard_lines_keeps_the_rest_of_the_hunk,
+    hunks_of_untracked_files_are_not_discarded,
+    stage_hunks_of_an_untracked_file,
+    intent_to_add_tracks_the_file_without_staging_it,
+    commit_the_index,
+    split_hunk_pieces_stage_on_their_own,
+    staging_in_a_linked_worktree,
+    bare_repositories_are_refused,
+    #[cfg(unix)]
+    staging_a_file_whose_name_is_not_utf8,
+    diff_over_the_line_limit_is_held_back,
+    #[cfg(unix)]
+    a_type_change_is_staged_and_unstaged_as_a_whole,
+    #[cfg(unix)]
+    a_symlink_is_diffed_staged_and_discarded_as_a_link,
+    statuses_are_limited_to_the_pathspecs,
+    #[cfg(unix)]
+    mode_change_is_staged_and_unstaged_without_the_content,
+    lines_of_a_crlf_file_are_staged_with_their_line_endings,
+    only_hunks_changing_more_than_whitespace_are_staged,
+}
@@ -0,0 +1,141 @@
+//! Throwaway repositories for the integration tests.
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use git2::{IndexAddOption, Repository, Signature};
+
+use git_istage_rs::{config::BackendKind, FileStatusKind, Repo, StatusEntry};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// A repository in a fresh directory under the system's temporary one, removed again on drop.
+// It's opened as a `Repo` staging through `backend`, libgit2 unless told otherwise.
+pub struct TestRepo {
+    pub dir: PathBuf,
+    pub repo: Repository,
+    pub backend: BackendKind,
+}
+
+impl TestRepo {
+    pub fn new() -> TestRepo {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let dir =
+            std::env::temp_dir().join(format!("git-istage-test-{}-{}", std::process::id(), id));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dir = fs::canonicalize(dir).unwrap();
+
+        let repo = Repository::init(&dir).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        TestRepo {
+            dir,
+            repo,
+            backend: BackendKind::default(),
+        }
+    }
+
+    // A repository with the given files committed.
+    pub fn with_commit(files: &[(&str, &str)]) -> TestRepo {
+        let repo = TestRepo::new();
+        for (path, content) in files {
+            repo.write(path, content);
+        }
+        repo.commit_all("initial");
+        repo
+    }
+
+    // The repository, opened to stage through `backend`.
+    pub fn on(mut self, backend: BackendKind) -> TestRepo {
+        self.backend = backend;
+        self
+    }
+
+    pub fn open(&self) -> Repo {
+        Repo::open_with(self.backend, &self.dir).unwrap()
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, content).unwrap();
+    }
+
+    pub fn remove(&self, path: &str) {
+        fs::remove_file(self.dir.join(path)).unwrap();
+    }
+
+    pub fn rename(&self, from: &str, to: &str) {
+        fs::rename(self.dir.join(from), self.dir.join(to)).unwrap();
+    }
+
+    // Stages everything, deletions included, and commits it.
+    pub fn commit_all(&self, message: &str) {
+        let mut index = self.repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("Test", "test@example.com").unwrap();
+        let parent = self.repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo
+            .commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap();
+    }
+
+    pub fn worktree(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.dir.join(path)).ok()
+    }
+
+    // The staged content of `path`, read from the index on disk.
+    pub fn index(&self, path: &str) -> Option<String> {
+        let mut index = self.repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new(path), 0)?;
+        let blob = self.repo.find_blob(entry.id).unwrap();
+        Some(String::from_utf8_lossy(blob.content()).into_owned())
+    }
+
+    pub fn head(&self, path: &str) -> Option<String> {
+        let tree = self.repo.head().ok()?.peel_to_tree().unwrap();
+        let entry = tree.get_path(Path::new(path)).ok()?;
+        let blob = self.repo.find_blob(entry.id()).unwrap();
+        Some(String::from_utf8_lossy(blob.content()).into_owned())
+    }
+
+    // The entries of the given kind, as "<status letter> <path>" like the TUI lists them.
+    pub fn statuses(&self, kind: FileStatusKind) -> Vec<String> {
+        self.entries(kind)
+            .iter()
+            .map(|e| e.pretty_string())
+            .collect()
+    }
+
+    pub fn entries(&self, kind: FileStatusKind) -> Vec<StatusEntry> {
+        self.open().statuses(kind).unwrap()
+    }
+
+    // The entry of the given kind for `path`, which must have one.
+    pub fn entry(&self, kind: FileStatusKind, path: &str) -> StatusEntry {
+        self.entries(kind)
+            .into_iter()
+            .find(|e| e.new_file == path)
+            .unwrap_or_else(|| panic!("no entry for {}", path))
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}